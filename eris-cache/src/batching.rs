@@ -0,0 +1,132 @@
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    num::NonZeroUsize,
+    time::Duration,
+};
+
+use futures_util::{future::ready, FutureExt};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        mpsc::{error::SendError, unbounded_channel, UnboundedReceiver},
+        oneshot::{channel as oneshot_channel, Sender as OneShotSender},
+    },
+    time::timeout,
+};
+use tower::{layer::layer_fn, service_fn, Layer, Service, ServiceExt};
+
+/// The error that might be returned by a service that batches its requests.
+#[derive(Debug, Error)]
+pub enum BatchingServiceError<Req, E: Debug + Display> {
+    /// The batching task has stopped, so the request was not attempted
+    #[error("The batching task is offline, request not attempted")]
+    NotSent(Req),
+    /// The batch was processed, but it did not include a response for this
+    /// request
+    #[error("The batched service did not send a response")]
+    NoResponse,
+    /// The batched service returned an error for this request
+    #[error("{0}")]
+    InnerError(E),
+}
+
+/// A queued request, paired with the channel its result should be sent back on.
+type BatchedRequest<Req, T, E> = (Req, OneShotSender<Result<T, E>>);
+
+/// Collects requests into batches, using the same windowing as
+/// `eris_lib::scheduling::batch_forward`: a batch is sent once max_size
+/// requests have arrived, or once batch_duration has elapsed since the first.
+async fn batching_loop<S, Req, T, E>(
+    service: S,
+    mut receiver: UnboundedReceiver<BatchedRequest<Req, T, E>>,
+    batch_duration: Duration,
+    max_size: usize,
+) where
+    S: Service<Vec<Req>, Response = Vec<Result<T, E>>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: Send + 'static,
+    T: Send + 'static,
+    E: Send + 'static,
+{
+    // Wait for the first item
+    while let Some(item) = receiver.recv().await {
+        let mut batch = Vec::with_capacity(max_size);
+        batch.push(item);
+
+        // Either the batch fills or the window closes; both are fine
+        let _ = timeout(batch_duration, async {
+            while batch.len() < max_size {
+                if let Some(item) = receiver.recv().await {
+                    batch.push(item);
+                } else {
+                    break;
+                }
+            }
+        })
+        .await;
+
+        // Process the batch in the background so the next one can be collected
+        let (requests, callbacks): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+        let service = service.clone();
+        tokio::spawn(async move {
+            let responses = match service.oneshot(requests).await {
+                Ok(responses) => responses,
+                Err(infallible) => match infallible {},
+            };
+
+            // Any callbacks without a matching response are dropped, which
+            // their callers will see as NoResponse
+            for (callback_tx, response) in callbacks.into_iter().zip(responses) {
+                // The caller may have stopped waiting, which is not an error
+                let _ = callback_tx.send(response);
+            }
+        });
+    }
+}
+
+/// Returns a [tower::Layer] which converts a batched [tower::Service], such
+/// as [crate::moka::cache_aside_layer] or [crate::redis::cache_aside_layer],
+/// into one which takes a single request at a time. Requests made within
+/// `batch_duration` of each other are sent to the inner service together,
+/// up to `max_size` at a time, like a dataloader.
+///
+/// The batches are collected by a background task, so this must be called
+/// from within a [tokio] runtime.
+pub fn batching_layer<S, Req, T, E>(
+    batch_duration: Duration,
+    max_size: NonZeroUsize,
+) -> impl Layer<S, Service = impl Service<Req, Response = T, Error = BatchingServiceError<Req, E>> + Clone>
+where
+    S: Service<Vec<Req>, Response = Vec<Result<T, E>>, Error = Infallible> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: Send + 'static,
+    T: Send + 'static,
+    E: Debug + Display + Send + 'static,
+{
+    layer_fn(move |service: S| {
+        let (queue_tx, queue_rx) = unbounded_channel();
+        tokio::spawn(batching_loop(
+            service,
+            queue_rx,
+            batch_duration,
+            max_size.get(),
+        ));
+
+        service_fn(move |request: Req| {
+            let (callback_tx, callback_rx) = oneshot_channel();
+            match queue_tx.send((request, callback_tx)) {
+                Ok(_) => callback_rx
+                    .map(|result_result| match result_result {
+                        Ok(Ok(response)) => Ok(response),
+                        Ok(Err(e)) => Err(BatchingServiceError::InnerError(e)),
+                        Err(_recv_error) => Err(BatchingServiceError::NoResponse),
+                    })
+                    .left_future(),
+                Err(SendError((request, _))) => {
+                    ready(Err(BatchingServiceError::NotSent(request))).right_future()
+                }
+            }
+        })
+    })
+}
@@ -0,0 +1,145 @@
+use std::fmt::{Debug, Display};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use serde::{de::DeserializeOwned, Serialize};
+use thiserror::Error;
+
+/// A serialization format used to store keys and responses in a cache.
+/// Every layer sharing a cache must use the same codec, otherwise lookups
+/// will miss or fail to deserialize.
+pub trait CacheCodec: Clone + Send + Sync + 'static {
+    /// The error returned when a value cannot be encoded.
+    type EncodeError: Debug + Display + Send;
+    /// The error returned when bytes cannot be decoded.
+    type DecodeError: Debug + Display + Send;
+
+    /// Encodes a value into bytes.
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Bytes, Self::EncodeError>;
+
+    /// Decodes a value from bytes.
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Self::DecodeError>;
+}
+
+/// Compact binary encoding using [rmp_serde]. This is the default codec.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MessagePack;
+
+impl CacheCodec for MessagePack {
+    type EncodeError = rmp_serde::encode::Error;
+    type DecodeError = rmp_serde::decode::Error;
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Bytes, Self::EncodeError> {
+        let mut writer = BytesMut::with_capacity(128).writer();
+        rmp_serde::encode::write(&mut writer, value)?;
+        Ok(writer.into_inner().into())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Self::DecodeError> {
+        rmp_serde::from_slice(bytes)
+    }
+}
+
+/// Human-readable encoding using [serde_json], so that cache contents can be
+/// inspected with standard tools such as `redis-cli`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Json;
+
+impl CacheCodec for Json {
+    type EncodeError = serde_json::Error;
+    type DecodeError = serde_json::Error;
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Bytes, Self::EncodeError> {
+        serde_json::to_vec(value).map(Bytes::from)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Self::DecodeError> {
+        serde_json::from_slice(bytes)
+    }
+}
+
+/// Fixed-layout binary encoding using [bincode]. Smaller and faster than
+/// [MessagePack], but does not support self-describing types such as
+/// `serde_json::Value` or `#[serde(flatten)]`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Bincode;
+
+impl CacheCodec for Bincode {
+    type EncodeError = bincode::Error;
+    type DecodeError = bincode::Error;
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Bytes, Self::EncodeError> {
+        bincode::serialize(value).map(Bytes::from)
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Self::DecodeError> {
+        bincode::deserialize(bytes)
+    }
+}
+
+/// The error returned when decoding with a [Versioned] codec.
+#[derive(Debug, Error)]
+pub enum VersionedDecodeError<E: Debug + Display> {
+    /// The bytes were written with a different version
+    #[error("Expected cache payload version {expected}, found {found:?}")]
+    VersionMismatch {
+        /// The version of this codec.
+        expected: u32,
+        /// The version found in the payload, if it had one.
+        found: Option<u32>,
+    },
+    /// The version matched, but the payload could not be decoded
+    #[error("{0}")]
+    InnerError(E),
+}
+
+/// Wraps another codec, prefixing every key and payload with a version
+/// number. Bumping the version when a cached type changes shape means old
+/// entries are never looked up, rather than failing to deserialize.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Versioned<C> {
+    version: u32,
+    inner: C,
+}
+
+impl<C: CacheCodec> Versioned<C> {
+    /// Creates a new codec which writes this version before every payload
+    /// encoded by the inner codec.
+    pub fn new(version: u32, inner: C) -> Self {
+        Self { version, inner }
+    }
+}
+
+impl<C: CacheCodec> CacheCodec for Versioned<C> {
+    type EncodeError = C::EncodeError;
+    type DecodeError = VersionedDecodeError<C::DecodeError>;
+
+    fn encode<T: Serialize>(&self, value: &T) -> Result<Bytes, Self::EncodeError> {
+        let payload = self.inner.encode(value)?;
+        let mut bytes = BytesMut::with_capacity(4 + payload.len());
+        bytes.put_u32(self.version);
+        bytes.put(payload);
+        Ok(bytes.freeze())
+    }
+
+    fn decode<T: DeserializeOwned>(&self, bytes: &[u8]) -> Result<T, Self::DecodeError> {
+        if bytes.len() < 4 {
+            return Err(VersionedDecodeError::VersionMismatch {
+                expected: self.version,
+                found: None,
+            });
+        }
+        let (version, payload) = bytes.split_at(4);
+        let version = u32::from_be_bytes(version.try_into().expect("split at 4 bytes"));
+
+        if version != self.version {
+            return Err(VersionedDecodeError::VersionMismatch {
+                expected: self.version,
+                found: Some(version),
+            });
+        }
+
+        self.inner
+            .decode(payload)
+            .map_err(VersionedDecodeError::InnerError)
+    }
+}
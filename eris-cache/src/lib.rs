@@ -1,15 +1,37 @@
 #![warn(missing_docs)]
 //! eris-cache provides [tower::Service]s that abstract over exact cache
 //! implementation. It currently supports two different backends: [moka]
-//! and [redis]
+//! and [redis], which can also be layered using [tiered]
+
+/// Adaptors which batch single requests together, so that single lookups
+/// can share a batched cache-aside service.
+pub mod batching;
+
+/// Serialization formats for keys and responses stored in a cache.
+pub mod codec;
 
 /// A cache implementation using [moka], an in-memory concurrent hashmap.
 pub mod moka;
 
+/// A cache implementation using [redis], a shared key-value store which can
+/// be used by multiple processes at once.
+pub mod redis;
+
+/// Counters for hits, misses and failures in the cache-aside layers.
+pub mod stats;
+
+/// A two-tier cache, with a [moka] cache in each process in front of a
+/// shared [redis] cache, kept consistent by publishing invalidations.
+pub mod tiered;
 
-use std::fmt::{Debug, Display};
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    time::Duration,
+};
 
-use serde::Serialize;
+use codec::{CacheCodec, MessagePack};
+use serde::{de::DeserializeOwned, Serialize};
 use thiserror::Error;
 use tower::Service;
 
@@ -31,22 +53,93 @@ impl<K: Serialize> From<K> for CacheKey<K> {
 
 /// The error that might be returned by a service that has been wrapped in a
 /// cache-aside layer.
-#[derive(Debug, Error)]
-pub enum CacheServiceError<E, S, Req>
-where S: Service<Req>, E: Debug + Display, S::Error: Debug + Display
+#[derive(Error)]
+pub enum CacheServiceError<E, S, Req, C = MessagePack>
+where
+    S: Service<Req>,
+    E: Debug + Display,
+    S::Error: Debug + Display,
+    C: CacheCodec,
 {
     /// The request's key, or its response, could not be serialized
     #[error("Serialization error: {0}")]
-    SerializeError(rmp_serde::encode::Error),
+    SerializeError(C::EncodeError),
     /// The cache failed to produce a response
     #[error("Error executing cache command: {0}")]
     CacheError(E),
     /// The response could not be deserialized
     #[error("Deserialization error: {0}")]
-    DeserializeError(rmp_serde::decode::Error),
+    DeserializeError(C::DecodeError),
     /// The inner service was queried and failed to respond
     #[error("{0}")]
     InnerError(S::Error),
+    /// The same key appeared earlier in the batch, and that request failed.
+    /// The inner service is only called once per key in a batch, so the
+    /// error is reported on the first occurrence.
+    #[error("An identical request earlier in the batch failed")]
+    DuplicateRequestFailed,
+}
+
+// Implemented by hand so that the service and request types do not need to
+// be Debug themselves, which allows cache-aside layers to be stacked.
+impl<E, S, Req, C> Debug for CacheServiceError<E, S, Req, C>
+where
+    S: Service<Req>,
+    E: Debug + Display,
+    S::Error: Debug + Display,
+    C: CacheCodec,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CacheServiceError::SerializeError(e) => {
+                f.debug_tuple("SerializeError").field(e).finish()
+            }
+            CacheServiceError::CacheError(e) => f.debug_tuple("CacheError").field(e).finish(),
+            CacheServiceError::DeserializeError(e) => {
+                f.debug_tuple("DeserializeError").field(e).finish()
+            }
+            CacheServiceError::InnerError(e) => f.debug_tuple("InnerError").field(e).finish(),
+            CacheServiceError::DuplicateRequestFailed => write!(f, "DuplicateRequestFailed"),
+        }
+    }
+}
+
+/// The result for a single request within a batch handled by a cache-aside
+/// layer.
+pub type CacheResult<E, S, Req, C = MessagePack> =
+    Result<<S as Service<Req>>::Response, CacheServiceError<E, S, Req, C>>;
+
+/// The response of a batched cache-aside service, with one result for each
+/// request in the batch.
+pub type BatchCacheResponse<E, S, Req, C = MessagePack> = Vec<CacheResult<E, S, Req, C>>;
+
+/// The output of a batched cache-aside service. Errors are reported for each
+/// request, so the batch as a whole never fails.
+pub type BatchCacheOutput<E, S, Req, C = MessagePack> =
+    Result<BatchCacheResponse<E, S, Req, C>, Infallible>;
+
+/// The error that might be returned by a cache invalidation service.
+#[derive(Debug, Error)]
+pub enum CacheInvalidationError<E: Debug + Display, C: CacheCodec = MessagePack> {
+    /// The key to invalidate could not be serialized
+    #[error("Serialization error: {0}")]
+    SerializeError(C::EncodeError),
+    /// The cache failed to evict the entries
+    #[error("Error executing cache command: {0}")]
+    CacheError(E),
+}
+
+/// A request to evict entries from a cache, so that the next query for them
+/// goes to the inner service. Used when the underlying data changes, such as
+/// when a post is updated or deleted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Invalidation<K> {
+    /// Evict the entry cached under this key, as returned by
+    /// [CacheableQuery::cache_key].
+    Key(K),
+    /// Evict every entry whose query returned this tag from
+    /// [CacheableQuery::cache_tags].
+    Tag(String),
 }
 
 /// Trait that indicates a query is cacheable, and what key
@@ -57,4 +150,111 @@ pub trait CacheableQuery {
 
     /// Returns the key for this request.
     fn cache_key(&self) -> Self::Key;
+
+    /// How long a response to this request may stay in the cache. Defaults
+    /// to None, which keeps the response until it is evicted or invalidated.
+    fn time_to_live(&self) -> Option<Duration> {
+        None
+    }
+
+    /// How long a "not found" response to this request may stay in the
+    /// cache (see [CacheableResponse::is_not_found]). Defaults to None, which
+    /// does not cache "not found" responses at all.
+    fn not_found_time_to_live(&self) -> Option<Duration> {
+        None
+    }
+
+    /// Tags to associate with the cached response, so that it can be
+    /// evicted together with every other entry sharing that tag using
+    /// [Invalidation::Tag]. Defaults to no tags.
+    fn cache_tags(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// How long after being cached a response should be refreshed. A read
+    /// after this still returns the cached response immediately, but also
+    /// fetches a new one from the inner service in the background, so that
+    /// hot entries are replaced before they expire rather than making a
+    /// reader wait. Defaults to None, which never refreshes ahead.
+    fn refresh_after(&self) -> Option<Duration> {
+        None
+    }
+}
+
+/// Trait that indicates a request changes cached data, and that its response
+/// is the new response to a [CacheableQuery]. Used with the write-through
+/// layers, such as when an Update activity replaces a cached post.
+pub trait CacheableWrite {
+    /// The query whose cached response is replaced by this write's response.
+    type Query: CacheableQuery;
+
+    /// Returns the query whose cached response should be replaced. Its key,
+    /// time to live and tags are used exactly as if it had been read through
+    /// a cache-aside layer.
+    fn query(&self) -> Self::Query;
+}
+
+/// Trait that indicates a response can be stored in a cache.
+pub trait CacheableResponse: Serialize + DeserializeOwned {
+    /// Returns true if this response means the requested entity does not
+    /// exist. Such responses are only cached if the query provides a
+    /// [CacheableQuery::not_found_time_to_live]. Defaults to false.
+    fn is_not_found(&self) -> bool {
+        false
+    }
+}
+
+impl<T: Serialize + DeserializeOwned> CacheableResponse for Option<T> {
+    fn is_not_found(&self) -> bool {
+        self.is_none()
+    }
+}
+
+/// How long a cached response should live.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Expiration {
+    /// The response never expires, but may still be evicted or invalidated.
+    Never,
+    /// The response expires after the given duration.
+    After(Duration),
+}
+
+impl From<Option<Duration>> for Expiration {
+    fn from(time_to_live: Option<Duration>) -> Self {
+        match time_to_live {
+            Some(duration) => Self::After(duration),
+            None => Self::Never,
+        }
+    }
+}
+
+/// How the response to a query should be stored. This is captured before the
+/// query is moved into the inner service.
+#[derive(Debug, Clone)]
+pub(crate) struct CachePolicy {
+    time_to_live: Option<Duration>,
+    not_found_time_to_live: Option<Duration>,
+    pub(crate) tags: Vec<String>,
+    pub(crate) refresh_after: Option<Duration>,
+}
+
+impl CachePolicy {
+    pub(crate) fn of<Q: CacheableQuery>(query: &Q) -> Self {
+        Self {
+            time_to_live: query.time_to_live(),
+            not_found_time_to_live: query.not_found_time_to_live(),
+            tags: query.cache_tags(),
+            refresh_after: query.refresh_after(),
+        }
+    }
+
+    /// Returns how long the response should be cached for, or None if it
+    /// should not be cached at all.
+    pub(crate) fn expiration<R: CacheableResponse>(&self, response: &R) -> Option<Expiration> {
+        if response.is_not_found() {
+            self.not_found_time_to_live.map(Expiration::After)
+        } else {
+            Some(self.time_to_live.into())
+        }
+    }
 }
\ No newline at end of file
@@ -1,100 +1,581 @@
 use std::{
+    any::type_name,
+    collections::{hash_map::Entry as HashMapEntry, HashMap},
     convert::Infallible,
     fmt::{Debug, Display},
+    future::Future,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use bytes::{BufMut, Bytes, BytesMut};
-use moka::future::Cache;
-use serde::{de::DeserializeOwned, Serialize};
+use bytes::Bytes;
+use futures_util::{
+    future::{ready, Either},
+    stream, StreamExt,
+};
+use moka::{
+    future::{Cache, CacheBuilder},
+    Expiry, PredicateError,
+};
+use serde::Serialize;
 use tower::{layer::layer_fn, service_fn, Layer, Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::{
+    codec::CacheCodec, stats::CacheStats, BatchCacheOutput, BatchCacheResponse,
+    CacheInvalidationError, CacheKey, CachePolicy, CacheResult, CacheServiceError, CacheableQuery,
+    CacheableResponse, CacheableWrite, Expiration, Invalidation,
+};
+
+/// A serialized response stored in a [moka] cache, along with how long it
+/// should live, when it should be refreshed and the tags it can be
+/// invalidated by.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    bytes: Bytes,
+    expiration: Expiration,
+    tags: Arc<[String]>,
+    refresh_at: Option<Instant>,
+    /// Shared between clones of the entry, so that only one reader starts a
+    /// refresh.
+    refreshing: Arc<AtomicBool>,
+}
+
+impl CacheEntry {
+    fn new(bytes: Bytes, expiration: Expiration, policy: &CachePolicy) -> Self {
+        Self {
+            bytes,
+            expiration,
+            tags: policy.tags.clone().into(),
+            refresh_at: policy
+                .refresh_after
+                .map(|refresh_after| Instant::now() + refresh_after),
+            refreshing: Arc::new(AtomicBool::new(false)),
+        }
+    }
 
-use crate::{CacheKey, CacheServiceError, CacheableQuery};
+    /// Returns true if this entry is due to be refreshed and no other reader
+    /// has already started refreshing it.
+    fn claim_refresh(&self) -> bool {
+        self.refresh_at
+            .is_some_and(|refresh_at| Instant::now() >= refresh_at)
+            && self
+                .refreshing
+                .compare_exchange(false, true, Ordering::AcqRel, Ordering::Acquire)
+                .is_ok()
+    }
 
-/// Returns a [tower::Layer] which converts a
-pub fn cache_aside_layer<S, Req>(
-    moka_cache: Cache<Bytes, Bytes>,
+    /// The serialized response.
+    pub fn bytes(&self) -> &Bytes {
+        &self.bytes
+    }
+
+    /// The tags this entry can be invalidated by.
+    pub fn tags(&self) -> &[String] {
+        &self.tags
+    }
+}
+
+/// Expires each [CacheEntry] according to its own time to live, rather than
+/// a single policy for the whole cache.
+struct CacheEntryExpiry;
+
+impl Expiry<Bytes, CacheEntry> for CacheEntryExpiry {
+    fn expire_after_create(
+        &self,
+        _key: &Bytes,
+        value: &CacheEntry,
+        _current_time: Instant,
+    ) -> Option<Duration> {
+        match value.expiration {
+            Expiration::Never => None,
+            Expiration::After(duration) => Some(duration),
+        }
+    }
+
+    fn expire_after_update(
+        &self,
+        key: &Bytes,
+        value: &CacheEntry,
+        current_time: Instant,
+        _current_duration: Option<Duration>,
+    ) -> Option<Duration> {
+        self.expire_after_create(key, value, current_time)
+    }
+}
+
+/// Returns a [CacheBuilder] for a [moka] cache that can be used with
+/// [cache_aside_layer] and [invalidation_service]. Entries expire according
+/// to [CacheableQuery::time_to_live], and tag invalidation is enabled.
+/// Other settings, such as the maximum capacity, can be added before
+/// building.
+pub fn cache_builder() -> CacheBuilder<Bytes, CacheEntry, Cache<Bytes, CacheEntry>> {
+    Cache::builder()
+        .expire_after(CacheEntryExpiry)
+        .support_invalidation_closures()
+}
+
+/// Calls the inner service, returning its response alongside the serialized
+/// form of the response.
+async fn fetch<S, Req, Codec>(
+    service: &mut S,
+    codec: &Codec,
+    stats: &CacheStats,
+    request: Req,
+) -> Result<(S::Response, Bytes), CacheServiceError<Infallible, S, Req, Codec>>
+where
+    S: Service<Req>,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    Codec: CacheCodec,
+{
+    let response = match service.ready().await {
+        Ok(service) => service.call(request).await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        stats.record_inner_error(&e);
+        CacheServiceError::InnerError(e)
+    })?;
+    let response_bytes = codec.encode(&response).map_err(|e| {
+        stats.record_serialize_error(&e);
+        CacheServiceError::SerializeError(e)
+    })?;
+    Ok((response, response_bytes))
+}
+
+/// Replaces a cached response with a new one from the inner service, or
+/// evicts it if the new response is not cacheable. If the inner service
+/// fails, the old entry is kept until it expires, and the next reader may try
+/// again.
+async fn refresh<S, Req, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+    stats: CacheStats,
+    mut service: S,
+    cache_key: Bytes,
+    request: Req,
+    refreshing: Arc<AtomicBool>,
+) where
+    S: Service<Req>,
+    Req: CacheableQuery,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    Codec: CacheCodec,
+{
+    let policy = CachePolicy::of(&request);
+    stats.record_refresh();
+    match fetch(&mut service, &codec, &stats, request).await {
+        Ok((response, response_bytes)) => match policy.expiration(&response) {
+            Some(expiration) => {
+                stats.record_insert(response_bytes.len());
+                let entry = CacheEntry::new(response_bytes, expiration, &policy);
+                moka_cache.insert(cache_key, entry).await;
+            }
+            None => moka_cache.invalidate(&cache_key).await,
+        },
+        Err(_) => refreshing.store(false, Ordering::Release),
+    }
+}
+
+/// Looks up a single key, calling the inner service on a miss. Concurrent
+/// lookups of the same key, including from other batches, are coalesced so
+/// that only one of them calls the inner service. Hits on entries due for
+/// refresh start a [refresh] in the background.
+async fn get_or_fetch<S, Req, Codec>(
+    moka_cache: &Cache<Bytes, CacheEntry>,
+    codec: &Codec,
+    stats: &CacheStats,
+    mut service: S,
+    cache_key: Bytes,
+    request: Req,
+) -> Result<(S::Response, Bytes), CacheServiceError<Infallible, S, Req, Codec>>
+where
+    S: Service<Req> + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    Codec: CacheCodec,
+{
+    let policy = CachePolicy::of(&request);
+    let mut request = Some(request);
+    let mut fetched = None;
+
+    let init = async {
+        let request = request.take().expect("init future only runs once");
+        stats.record_miss();
+        let result = fetch(&mut service, codec, stats, request).await;
+
+        // Errors and uncacheable responses are kept out of the cache, but the
+        // result is still kept for this caller
+        let entry = match &result {
+            Ok((response, response_bytes)) => policy.expiration(response).map(|expiration| {
+                stats.record_insert(response_bytes.len());
+                CacheEntry::new(response_bytes.clone(), expiration, &policy)
+            }),
+            Err(_) => None,
+        };
+        fetched = Some(result);
+        entry.ok_or(())
+    };
+
+    let cached = moka_cache.try_get_with(cache_key.clone(), init).await;
+
+    if let Some(result) = fetched {
+        // This caller fetched the response itself
+        return result;
+    }
+
+    match cached {
+        // Cache hit, or another caller fetched it for us
+        Ok(entry) => match codec.decode(&entry.bytes) {
+            Ok(response) => {
+                stats.record_hit();
+                if entry.claim_refresh() {
+                    let request = request.take().expect("init future did not run");
+                    tokio::spawn(
+                        refresh(
+                            moka_cache.clone(),
+                            codec.clone(),
+                            stats.clone(),
+                            service,
+                            cache_key,
+                            request,
+                            entry.refreshing.clone(),
+                        )
+                        .in_current_span(),
+                    );
+                }
+                Ok((response, entry.bytes))
+            }
+            Err(e) => {
+                stats.record_deserialize_error(&e);
+                Err(CacheServiceError::DeserializeError(e))
+            }
+        },
+        // Another caller's fetch failed or was not cacheable, so try again
+        // without coalescing
+        Err(_) => {
+            let request = request.take().expect("init future did not run");
+            stats.record_miss();
+            fetch(&mut service, codec, stats, request).await
+        }
+    }
+}
+
+/// Returns a [tower::Layer] which converts a [tower::Service] taking a
+/// [CacheableQuery] into one that takes a batch of them, checking the [moka]
+/// cache for each before falling back to the inner service and caching its
+/// response. The cache should be created with [cache_builder].
+///
+/// Up to `concurrency_limit` cache misses are fetched from the inner service
+/// at once. Requests with the same key in a batch only call the inner
+/// service once, and concurrent misses for the same key across batches are
+/// coalesced so that a cold cache does not stampede the inner service.
+///
+/// Entries read after their [CacheableQuery::refresh_after] are refreshed in
+/// a background task, so this must be called from within a [tokio] runtime.
+///
+/// Hits, misses and failures are counted in `stats`, and each batch is
+/// traced in a `cache_aside` span naming the query type.
+pub fn cache_aside_layer<S, Req, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+    stats: CacheStats,
+    concurrency_limit: NonZeroUsize,
 ) -> impl Layer<
     S,
     Service = impl Service<
         Vec<Req>,
-        Response = Vec<Result<S::Response, CacheServiceError<Infallible, S, Req>>>,
+        Response = BatchCacheResponse<Infallible, S, Req, Codec>,
         Error = Infallible,
+        Future = impl Future<Output = BatchCacheOutput<Infallible, S, Req, Codec>> + Send,
     > + Clone,
 >
 where
-    S: Service<Req> + Clone,
-    Req: CacheableQuery,
-    S::Response: Serialize + DeserializeOwned,
-    S::Error: Debug + Display,
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    Codec: CacheCodec,
 {
     layer_fn(move |service: S| {
         let moka_cache = moka_cache.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
         service_fn(move |veq_request: Vec<Req>| {
             let moka_cache = moka_cache.clone();
-            let mut service = service.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let service = service.clone();
+            let span = tracing::debug_span!(
+                "cache_aside",
+                backend = "moka",
+                query = type_name::<Req>(),
+                batch_size = veq_request.len(),
+            );
             async move {
-                let mut responses = Vec::with_capacity(veq_request.len());
-
-                for request in veq_request.into_iter() {
-                    let cache_key: Bytes = {
-                        let mut writer = BytesMut::with_capacity(128).writer();
-                        if let Err(e) = rmp_serde::encode::write(
-                            &mut writer,
-                            &CacheKey::from(request.cache_key()),
-                        ) {
-                            responses.push(Err(CacheServiceError::SerializeError(e)));
+                let mut responses: Vec<Option<CacheResult<Infallible, S, Req, Codec>>> =
+                    veq_request.iter().map(|_| None).collect();
+
+                // Group requests by key, keeping only the first request for each
+                let mut unique_requests: Vec<(Bytes, Req, Vec<usize>)> = Vec::new();
+                let mut unique_indices: HashMap<Bytes, usize> = HashMap::new();
+                for (position, request) in veq_request.into_iter().enumerate() {
+                    let cache_key = match codec.encode(&CacheKey::from(request.cache_key())) {
+                        Ok(cache_key) => cache_key,
+                        Err(e) => {
+                            stats.record_serialize_error(&e);
+                            responses[position] = Some(Err(CacheServiceError::SerializeError(e)));
                             continue;
                         }
-                        writer.into_inner().into()
                     };
 
-                    let response = if let Some(response_bytes) = moka_cache.get(&cache_key) {
-                        // Cache hit, deserialize it and don't do any extra reads or writes
-                        match rmp_serde::from_slice(&response_bytes) {
-                            Ok(response) => response,
-                            Err(e) => {
-                                responses.push(Err(CacheServiceError::DeserializeError(e)));
-                                continue;
-                            }
+                    match unique_indices.entry(cache_key.clone()) {
+                        HashMapEntry::Occupied(occupied) => {
+                            unique_requests[*occupied.get()].2.push(position);
                         }
-                    } else {
-                        // Cache miss, get the value from the inner service
-                        // Make sure the service is ready
-                        let ready_service = match service.ready().await {
-                            Ok(ready_service) => ready_service,
-                            Err(e) => {
-                                responses.push(Err(CacheServiceError::InnerError(e)));
-                                continue;
-                            }
-                        };
-
-                        // Call the service and see if it succeeds
-                        let response = match ready_service.call(request).await {
-                            Ok(response) => response,
-                            Err(e) => {
-                                responses.push(Err(CacheServiceError::InnerError(e)));
-                                continue;
+                        HashMapEntry::Vacant(vacant) => {
+                            vacant.insert(unique_requests.len());
+                            unique_requests.push((cache_key, request, vec![position]));
+                        }
+                    }
+                }
+
+                // Look up each unique key concurrently, each with its own
+                // clone of the inner service
+                let fetches: Vec<_> = unique_requests
+                    .into_iter()
+                    .map(|(cache_key, request, positions)| {
+                        (cache_key, request, positions, service.clone())
+                    })
+                    .collect();
+                let results: Vec<_> = stream::iter(fetches)
+                    .map(|(cache_key, request, positions, service)| {
+                        let get_or_fetch =
+                            get_or_fetch(&moka_cache, &codec, &stats, service, cache_key, request);
+                        async move { (positions, get_or_fetch.await) }
+                    })
+                    .buffer_unordered(concurrency_limit.get())
+                    .collect()
+                    .await;
+
+                // Copy each result to every position that requested it
+                for (positions, result) in results {
+                    let (first, duplicates) = positions
+                        .split_first()
+                        .expect("every unique request has a position");
+                    match result {
+                        Ok((response, response_bytes)) => {
+                            // Duplicates are answered without calling the
+                            // inner service, so they count as hits
+                            for duplicate in duplicates {
+                                responses[*duplicate] = Some(
+                                    codec
+                                        .decode(&response_bytes)
+                                        .inspect(|_| stats.record_hit())
+                                        .map_err(|e| {
+                                            stats.record_deserialize_error(&e);
+                                            CacheServiceError::DeserializeError(e)
+                                        }),
+                                );
                             }
-                        };
-
-                        // Serialize response and insert into the cache
-                        let response_bytes: Bytes = {
-                            let mut writer = BytesMut::with_capacity(128).writer();
-                            if let Err(e) = rmp_serde::encode::write(&mut writer, &response) {
-                                responses.push(Err(CacheServiceError::SerializeError(e)));
-                                continue;
+                            responses[*first] = Some(Ok(response));
+                        }
+                        Err(e) => {
+                            for duplicate in duplicates {
+                                responses[*duplicate] =
+                                    Some(Err(CacheServiceError::DuplicateRequestFailed));
                             }
-                            writer.into_inner().into()
-                        };
-                        moka_cache.insert(cache_key, response_bytes).await;
-                        response
+                            responses[*first] = Some(Err(e));
+                        }
+                    }
+                }
+
+                Ok(responses
+                    .into_iter()
+                    .map(|response| response.expect("every position has a response"))
+                    .collect())
+            }
+            .instrument(span)
+        })
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableQuery], checking the [moka] cache before falling back to the
+/// inner service and caching its response. This behaves like
+/// [cache_aside_layer] for a single request at a time, including coalescing
+/// concurrent misses for the same key, refreshing ahead and counting into
+/// `stats`.
+pub fn single_cache_aside_layer<S, Req, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<Infallible, S, Req, Codec>,
+        Future = impl Future<Output = CacheResult<Infallible, S, Req, Codec>> + Send,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let moka_cache = moka_cache.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |request: Req| {
+            let moka_cache = moka_cache.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let service = service.clone();
+            let span =
+                tracing::debug_span!("cache_aside", backend = "moka", query = type_name::<Req>());
+            async move {
+                let cache_key =
+                    codec
+                        .encode(&CacheKey::from(request.cache_key()))
+                        .map_err(|e| {
+                            stats.record_serialize_error(&e);
+                            CacheServiceError::SerializeError(e)
+                        })?;
+                get_or_fetch(&moka_cache, &codec, &stats, service, cache_key, request)
+                    .await
+                    .map(|(response, _response_bytes)| response)
+            }
+            .instrument(span)
+        })
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableWrite], storing each successful response in the [moka] cache as
+/// the new response to [CacheableWrite::query]. Readers using
+/// [cache_aside_layer] with the same cache and codec see the new response
+/// without calling their inner service. Responses which are not cacheable,
+/// such as "not found" after a delete, evict the entry instead.
+///
+/// If the response cannot be serialized, the write has still happened, but
+/// the entry is evicted and a [CacheServiceError::SerializeError] is
+/// returned.
+pub fn write_through_layer<S, Req, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<Infallible, S, Req, Codec>,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone,
+    Req: CacheableWrite,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let moka_cache = moka_cache.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |request: Req| {
+            let moka_cache = moka_cache.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let mut service = service.clone();
+            let span = tracing::debug_span!(
+                "write_through",
+                backend = "moka",
+                query = type_name::<Req::Query>(),
+            );
+            async move {
+                // Find the entry before writing, so a bad key fails early
+                let query = request.query();
+                let policy = CachePolicy::of(&query);
+                let cache_key = codec
+                    .encode(&CacheKey::from(query.cache_key()))
+                    .map_err(|e| {
+                        stats.record_serialize_error(&e);
+                        CacheServiceError::SerializeError(e)
+                    })?;
+
+                let (response, response_bytes) =
+                    match fetch(&mut service, &codec, &stats, request).await {
+                        Ok(fetched) => fetched,
+                        Err(CacheServiceError::SerializeError(e)) => {
+                            moka_cache.invalidate(&cache_key).await;
+                            return Err(CacheServiceError::SerializeError(e));
+                        }
+                        Err(e) => return Err(e),
                     };
 
-                    responses.push(Ok(response));
+                match policy.expiration(&response) {
+                    Some(expiration) => {
+                        stats.record_insert(response_bytes.len());
+                        let entry = CacheEntry::new(response_bytes, expiration, &policy);
+                        moka_cache.insert(cache_key, entry).await;
+                    }
+                    None => moka_cache.invalidate(&cache_key).await,
                 }
 
-                Ok(responses)
+                Ok(response)
             }
+            .instrument(span)
         })
     })
 }
+
+/// Returns a [tower::Service] which evicts entries from a [moka] cache.
+/// Tag invalidation requires the cache to have been created with
+/// [cache_builder]; otherwise it will fail with a [PredicateError].
+pub fn invalidation_service<K, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+) -> impl Service<
+    Invalidation<K>,
+    Response = (),
+    Error = CacheInvalidationError<PredicateError, Codec>,
+> + Clone
+where
+    K: Serialize,
+    Codec: CacheCodec,
+{
+    service_fn(move |invalidation: Invalidation<K>| {
+        let moka_cache = moka_cache.clone();
+        match invalidation {
+            Invalidation::Key(key) => {
+                let result_cache_key = codec.encode(&CacheKey::from(key));
+                Either::Left(async move {
+                    let cache_key =
+                        result_cache_key.map_err(CacheInvalidationError::SerializeError)?;
+                    moka_cache.invalidate(&cache_key).await;
+                    Ok(())
+                })
+            }
+            Invalidation::Tag(tag) => Either::Right(ready(
+                moka_cache
+                    .invalidate_entries_if(move |_key, entry| entry.tags.contains(&tag))
+                    .map(|_predicate_id| ())
+                    .map_err(CacheInvalidationError::CacheError),
+            )),
+        }
+    })
+}
@@ -0,0 +1,559 @@
+use std::{
+    any::type_name,
+    convert::Infallible,
+    fmt::{Debug, Display},
+    future::Future,
+    time::Duration,
+};
+
+use bytes::Bytes;
+use redis::{aio::ConnectionLike, AsyncCommands, RedisError};
+use serde::Serialize;
+use tower::{layer::layer_fn, service_fn, Layer, Service, ServiceExt};
+use tracing::Instrument;
+
+use crate::{
+    codec::CacheCodec, stats::CacheStats, BatchCacheOutput, BatchCacheResponse,
+    CacheInvalidationError, CacheKey, CachePolicy, CacheResult, CacheServiceError, CacheableQuery,
+    CacheableResponse, CacheableWrite, Expiration, Invalidation,
+};
+
+/// Returns the key of the redis set which holds every cache key tagged with
+/// this tag.
+fn tag_set_key(tag: &str) -> String {
+    format!("eris-cache:tag:{tag}")
+}
+
+/// Adds a cache key to a tag's set, and makes sure the set lives at least
+/// as long as the entry: KEYS[1] is the set, ARGV[1] the cache key and
+/// ARGV[2] the entry's time to live in milliseconds, or -1 if it never
+/// expires. A set with an entry which never expires never expires either.
+const TAG_SCRIPT: &str = r"
+local existed = redis.call('EXISTS', KEYS[1])
+redis.call('SADD', KEYS[1], ARGV[1])
+local ttl = tonumber(ARGV[2])
+if ttl < 0 then
+    redis.call('PERSIST', KEYS[1])
+elseif existed == 0 then
+    redis.call('PEXPIRE', KEYS[1], ttl)
+else
+    local current = redis.call('PTTL', KEYS[1])
+    if current >= 0 and current < ttl then
+        redis.call('PEXPIRE', KEYS[1], ttl)
+    end
+end
+";
+
+/// Converts a duration to the milliseconds given to PX and PSETEX, which
+/// reject 0, so anything shorter than a millisecond becomes one.
+fn millis(duration: Duration) -> usize {
+    duration.as_millis().max(1) as usize
+}
+
+/// Returns the key which exists while the entry under this cache key is
+/// fresh, or while one process is refreshing it.
+fn refresh_marker_key(cache_key: &[u8]) -> Vec<u8> {
+    [b"eris-cache:refresh:", cache_key].concat()
+}
+
+/// Calls the inner service, counting any error.
+async fn call_inner<S, Req, Codec>(
+    service: &mut S,
+    stats: &CacheStats,
+    request: Req,
+) -> CacheResult<RedisError, S, Req, Codec>
+where
+    S: Service<Req>,
+    S::Error: Debug + Display,
+    Codec: CacheCodec,
+{
+    match service.ready().await {
+        Ok(service) => service.call(request).await,
+        Err(e) => Err(e),
+    }
+    .map_err(|e| {
+        stats.record_inner_error(&e);
+        CacheServiceError::InnerError(e)
+    })
+}
+
+/// Serializes a response and stores it under the cache key, along with its
+/// tags and refresh marker. Returns the response, and false if nothing was
+/// stored because the response is not cacheable.
+async fn store<S, Req, C, Codec>(
+    redis_connection: &mut C,
+    codec: &Codec,
+    stats: &CacheStats,
+    cache_key: &[u8],
+    response: S::Response,
+    policy: &CachePolicy,
+) -> Result<(S::Response, bool), CacheServiceError<RedisError, S, Req, Codec>>
+where
+    S: Service<Req>,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    C: ConnectionLike + Send,
+    Codec: CacheCodec,
+{
+    // Some responses (such as "not found") may not be cacheable
+    let Some(expiration) = policy.expiration(&response) else {
+        return Ok((response, false));
+    };
+
+    let response_bytes = codec.encode(&response).map_err(|e| {
+        stats.record_serialize_error(&e);
+        CacheServiceError::SerializeError(e)
+    })?;
+    let mut pipeline = redis::pipe();
+    let time_to_live = match expiration {
+        Expiration::Never => {
+            pipeline.set(cache_key, &response_bytes[..]).ignore();
+            -1
+        }
+        Expiration::After(duration) => {
+            pipeline
+                .pset_ex(cache_key, &response_bytes[..], millis(duration))
+                .ignore();
+            millis(duration) as i64
+        }
+    };
+    for tag in policy.tags.iter() {
+        pipeline
+            .cmd("EVAL")
+            .arg(TAG_SCRIPT)
+            .arg(1)
+            .arg(tag_set_key(tag))
+            .arg(cache_key)
+            .arg(time_to_live)
+            .ignore();
+    }
+    if let Some(refresh_after) = policy.refresh_after {
+        pipeline
+            .pset_ex(refresh_marker_key(cache_key), 1, millis(refresh_after))
+            .ignore();
+    }
+    pipeline
+        .query_async::<_, ()>(redis_connection)
+        .await
+        .map_err(|e| {
+            stats.record_cache_error(&e);
+            CacheServiceError::CacheError(e)
+        })?;
+    stats.record_insert(response_bytes.len());
+
+    Ok((response, true))
+}
+
+/// Deletes the entry under the cache key, counting any error.
+async fn evict<C: ConnectionLike + Send>(
+    redis_connection: &mut C,
+    stats: &CacheStats,
+    cache_key: &[u8],
+) -> Result<(), RedisError> {
+    redis_connection
+        .del::<_, ()>(cache_key)
+        .await
+        .inspect_err(|e| stats.record_cache_error(e))
+}
+
+/// Replaces a cached response with a new one from the inner service, or
+/// evicts it if the new response is not cacheable. If the inner service
+/// fails, the old entry is kept until it expires, and the refresh is tried
+/// again once the refresh marker expires. Errors are only counted, as there
+/// is no caller to report them to.
+async fn refresh<S, Req, C, Codec>(
+    mut redis_connection: C,
+    codec: Codec,
+    stats: CacheStats,
+    mut service: S,
+    cache_key: Bytes,
+    request: Req,
+) where
+    S: Service<Req>,
+    Req: CacheableQuery,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    C: ConnectionLike + Send,
+    Codec: CacheCodec,
+{
+    let policy = CachePolicy::of(&request);
+    stats.record_refresh();
+    let Ok(response) = call_inner::<_, _, Codec>(&mut service, &stats, request).await else {
+        return;
+    };
+    let stored = store::<S, Req, _, _>(
+        &mut redis_connection,
+        &codec,
+        &stats,
+        &cache_key,
+        response,
+        &policy,
+    )
+    .await;
+    if !matches!(stored, Ok((_, true))) {
+        let _ = evict(&mut redis_connection, &stats, &cache_key).await;
+    }
+}
+
+/// Looks up a single request in [redis], calling the inner service and
+/// caching its response on a miss. Hits on entries due for refresh start a
+/// [refresh] in the background.
+async fn get_or_fetch<S, Req, C, Codec>(
+    redis_connection: &mut C,
+    codec: &Codec,
+    stats: &CacheStats,
+    service: &mut S,
+    request: Req,
+) -> CacheResult<RedisError, S, Req, Codec>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    C: ConnectionLike + Clone + Send + 'static,
+    Codec: CacheCodec,
+{
+    let cache_key = codec
+        .encode(&CacheKey::from(request.cache_key()))
+        .map_err(|e| {
+            stats.record_serialize_error(&e);
+            CacheServiceError::SerializeError(e)
+        })?;
+
+    let (cached, claimed_refresh): (Option<Vec<u8>>, bool) = match request.refresh_after() {
+        None => redis_connection
+            .get(&cache_key[..])
+            .await
+            .map(|cached| (cached, false)),
+        Some(refresh_after) => {
+            // The marker only exists while the entry is fresh, so whoever
+            // manages to set it is the one process which should refresh
+            redis::pipe()
+                .get(&cache_key[..])
+                .cmd("SET")
+                .arg(refresh_marker_key(&cache_key))
+                .arg(1)
+                .arg("NX")
+                .arg("PX")
+                .arg(millis(refresh_after))
+                .query_async::<_, (Option<Vec<u8>>, Option<String>)>(redis_connection)
+                .await
+                .map(|(cached, claimed)| (cached, claimed.is_some()))
+        }
+    }
+    .map_err(|e| {
+        stats.record_cache_error(&e);
+        CacheServiceError::CacheError(e)
+    })?;
+
+    if let Some(response_bytes) = cached {
+        // Cache hit, deserialize it and don't do any extra reads or writes
+        return match codec.decode(&response_bytes) {
+            Ok(response) => {
+                stats.record_hit();
+                if claimed_refresh {
+                    tokio::spawn(
+                        refresh(
+                            redis_connection.clone(),
+                            codec.clone(),
+                            stats.clone(),
+                            service.clone(),
+                            cache_key,
+                            request,
+                        )
+                        .in_current_span(),
+                    );
+                }
+                Ok(response)
+            }
+            Err(e) => {
+                stats.record_deserialize_error(&e);
+                Err(CacheServiceError::DeserializeError(e))
+            }
+        };
+    }
+
+    // Cache miss, get the value from the inner service and cache it
+    stats.record_miss();
+    let policy = CachePolicy::of(&request);
+    let response = call_inner(service, stats, request).await?;
+    store(
+        redis_connection,
+        codec,
+        stats,
+        &cache_key,
+        response,
+        &policy,
+    )
+    .await
+    .map(|(response, _stored)| response)
+}
+
+/// Returns a [tower::Layer] which converts a [tower::Service] taking a
+/// [CacheableQuery] into one that takes a batch of them, checking [redis]
+/// for each before falling back to the inner service and caching its
+/// response.
+///
+/// The connection is cloned for every batch, so it should be a cheaply
+/// clonable multiplexed connection such as
+/// [redis::aio::ConnectionManager] or [redis::aio::MultiplexedConnection].
+/// Keys and responses are encoded with the given [CacheCodec], exactly as in
+/// [crate::moka::cache_aside_layer], so the two backends can share entries
+/// when they use the same codec.
+/// Entries expire using redis' own expiry, and tags are tracked in redis sets
+/// so that [invalidation_service] can be used from any process. Each set
+/// expires along with the longest-lived entry tagged with it.
+///
+/// Entries read after their [CacheableQuery::refresh_after] are refreshed in
+/// a background task by whichever process reads them first, so this must be
+/// called from within a [tokio] runtime.
+///
+/// Hits, misses and failures are counted in `stats`, and each batch is
+/// traced in a `cache_aside` span naming the query type.
+pub fn cache_aside_layer<S, Req, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Vec<Req>,
+        Response = BatchCacheResponse<RedisError, S, Req, Codec>,
+        Error = Infallible,
+        Future = impl Future<Output = BatchCacheOutput<RedisError, S, Req, Codec>> + Send,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    C: ConnectionLike + Clone + Send + 'static,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |veq_request: Vec<Req>| {
+            let mut redis_connection = redis_connection.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let mut service = service.clone();
+            let span = tracing::debug_span!(
+                "cache_aside",
+                backend = "redis",
+                query = type_name::<Req>(),
+                batch_size = veq_request.len(),
+            );
+            async move {
+                let mut responses = Vec::with_capacity(veq_request.len());
+
+                for request in veq_request.into_iter() {
+                    responses.push(
+                        get_or_fetch(&mut redis_connection, &codec, &stats, &mut service, request)
+                            .await,
+                    );
+                }
+
+                Ok(responses)
+            }
+            .instrument(span)
+        })
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableQuery], checking [redis] before falling back to the inner
+/// service and caching its response. This behaves like [cache_aside_layer]
+/// for a single request at a time, refreshing ahead and counting into
+/// `stats`.
+pub fn single_cache_aside_layer<S, Req, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<RedisError, S, Req, Codec>,
+        Future = impl Future<Output = CacheResult<RedisError, S, Req, Codec>> + Send,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    C: ConnectionLike + Clone + Send + 'static,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |request: Req| {
+            let mut redis_connection = redis_connection.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let mut service = service.clone();
+            let span =
+                tracing::debug_span!("cache_aside", backend = "redis", query = type_name::<Req>());
+            async move {
+                get_or_fetch(&mut redis_connection, &codec, &stats, &mut service, request).await
+            }
+            .instrument(span)
+        })
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableWrite], storing each successful response in [redis] as the new
+/// response to [CacheableWrite::query]. Readers in any process using
+/// [cache_aside_layer] with the same codec see the new response without
+/// calling their inner service. Responses which are not cacheable, such as
+/// "not found" after a delete, evict the entry instead.
+///
+/// If the response cannot be serialized or stored, the write has still
+/// happened, so the entry is evicted and the error is returned to let the
+/// caller decide whether to retry the invalidation.
+pub fn write_through_layer<S, Req, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<RedisError, S, Req, Codec>,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone,
+    Req: CacheableWrite,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    C: ConnectionLike + Clone + Send,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |request: Req| {
+            let mut redis_connection = redis_connection.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            let mut service = service.clone();
+            let span = tracing::debug_span!(
+                "write_through",
+                backend = "redis",
+                query = type_name::<Req::Query>(),
+            );
+            async move {
+                // Find the entry before writing, so a bad key fails early
+                let query = request.query();
+                let policy = CachePolicy::of(&query);
+                let cache_key = codec
+                    .encode(&CacheKey::from(query.cache_key()))
+                    .map_err(|e| {
+                        stats.record_serialize_error(&e);
+                        CacheServiceError::SerializeError(e)
+                    })?;
+
+                let response = call_inner(&mut service, &stats, request).await?;
+
+                let stored = store(
+                    &mut redis_connection,
+                    &codec,
+                    &stats,
+                    &cache_key,
+                    response,
+                    &policy,
+                )
+                .await;
+                match stored {
+                    Ok((response, true)) => Ok(response),
+                    Ok((response, false)) => {
+                        evict(&mut redis_connection, &stats, &cache_key)
+                            .await
+                            .map_err(CacheServiceError::CacheError)?;
+                        Ok(response)
+                    }
+                    Err(e) => {
+                        let _ = evict(&mut redis_connection, &stats, &cache_key).await;
+                        Err(e)
+                    }
+                }
+            }
+            .instrument(span)
+        })
+    })
+}
+
+/// Deletes every entry tagged with this tag, along with the tag's set. The
+/// set is read and deleted in one transaction, so that a key tagged in
+/// between is kept in a new set rather than lost. Its keys are then deleted
+/// one command each, so that they need not be in the same hash slot when
+/// using Redis Cluster.
+pub(crate) async fn invalidate_tag<C: ConnectionLike + Send>(
+    redis_connection: &mut C,
+    tag: &str,
+) -> Result<(), RedisError> {
+    let set_key = tag_set_key(tag);
+    let (cache_keys,): (Vec<Vec<u8>>,) = redis::pipe()
+        .atomic()
+        .smembers(&set_key)
+        .del(&set_key)
+        .ignore()
+        .query_async(redis_connection)
+        .await?;
+    if cache_keys.is_empty() {
+        return Ok(());
+    }
+
+    let mut pipeline = redis::pipe();
+    for cache_key in cache_keys.iter() {
+        pipeline.del(&cache_key[..]).ignore();
+    }
+    pipeline.query_async::<_, ()>(redis_connection).await
+}
+
+/// Returns a [tower::Service] which evicts entries from [redis]. Because the
+/// entries are shared, this evicts them for every process using the cache.
+pub fn invalidation_service<K, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+) -> impl Service<Invalidation<K>, Response = (), Error = CacheInvalidationError<RedisError, Codec>>
+       + Clone
+where
+    K: Serialize,
+    C: ConnectionLike + Clone + Send,
+    Codec: CacheCodec,
+{
+    service_fn(move |invalidation: Invalidation<K>| {
+        let mut redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        async move {
+            match invalidation {
+                Invalidation::Key(key) => {
+                    let cache_key = codec
+                        .encode(&CacheKey::from(key))
+                        .map_err(CacheInvalidationError::SerializeError)?;
+                    redis_connection
+                        .del::<_, ()>(&cache_key[..])
+                        .await
+                        .map_err(CacheInvalidationError::CacheError)
+                }
+                Invalidation::Tag(tag) => invalidate_tag(&mut redis_connection, &tag)
+                    .await
+                    .map_err(CacheInvalidationError::CacheError),
+            }
+        }
+    })
+}
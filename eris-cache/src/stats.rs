@@ -0,0 +1,128 @@
+use std::sync::{
+    atomic::{AtomicU64, Ordering},
+    Arc,
+};
+
+#[derive(Debug, Default)]
+struct CacheCounters {
+    hits: AtomicU64,
+    misses: AtomicU64,
+    serialize_errors: AtomicU64,
+    deserialize_errors: AtomicU64,
+    cache_errors: AtomicU64,
+    inner_errors: AtomicU64,
+    refreshes: AtomicU64,
+    inserts: AtomicU64,
+    inserted_bytes: AtomicU64,
+}
+
+/// A handle to the counters of one or more cache-aside layers. This is
+/// cheap to clone, and all clones share the same counters, so the same
+/// handle can be given to a layer and kept for reporting. Use a separate
+/// handle for each [crate::CacheableQuery] type to track them separately.
+#[derive(Debug, Clone, Default)]
+pub struct CacheStats(Arc<CacheCounters>);
+
+impl CacheStats {
+    /// Creates a new handle with all counters at zero.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the current value of every counter.
+    pub fn snapshot(&self) -> CacheStatsSnapshot {
+        CacheStatsSnapshot {
+            hits: self.0.hits.load(Ordering::Relaxed),
+            misses: self.0.misses.load(Ordering::Relaxed),
+            serialize_errors: self.0.serialize_errors.load(Ordering::Relaxed),
+            deserialize_errors: self.0.deserialize_errors.load(Ordering::Relaxed),
+            cache_errors: self.0.cache_errors.load(Ordering::Relaxed),
+            inner_errors: self.0.inner_errors.load(Ordering::Relaxed),
+            refreshes: self.0.refreshes.load(Ordering::Relaxed),
+            inserts: self.0.inserts.load(Ordering::Relaxed),
+            inserted_bytes: self.0.inserted_bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    pub(crate) fn record_hit(&self) {
+        tracing::trace!("cache hit");
+        self.0.hits.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_miss(&self) {
+        tracing::trace!("cache miss");
+        self.0.misses.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_serialize_error(&self, e: &impl std::fmt::Display) {
+        tracing::warn!("failed to serialize cache key or response: {e}");
+        self.0.serialize_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_deserialize_error(&self, e: &impl std::fmt::Display) {
+        tracing::warn!("failed to deserialize cached response: {e}");
+        self.0.deserialize_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_cache_error(&self, e: &impl std::fmt::Display) {
+        tracing::warn!("cache command failed: {e}");
+        self.0.cache_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_inner_error(&self, e: &impl std::fmt::Display) {
+        tracing::debug!("inner service failed: {e}");
+        self.0.inner_errors.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_refresh(&self) {
+        tracing::trace!("cache refresh");
+        self.0.refreshes.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_insert(&self, size: usize) {
+        tracing::trace!(size, "cache insert");
+        self.0.inserts.fetch_add(1, Ordering::Relaxed);
+        self.0
+            .inserted_bytes
+            .fetch_add(size as u64, Ordering::Relaxed);
+    }
+}
+
+/// The value of every counter in a [CacheStats] at a point in time.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct CacheStatsSnapshot {
+    /// Requests answered without calling the inner service, including
+    /// duplicates within a batch and misses coalesced with another caller.
+    pub hits: u64,
+    /// Requests which had to wait for the inner service.
+    pub misses: u64,
+    /// Keys or responses which could not be serialized.
+    pub serialize_errors: u64,
+    /// Cached responses which could not be deserialized.
+    pub deserialize_errors: u64,
+    /// Commands which the cache backend failed to execute.
+    pub cache_errors: u64,
+    /// Calls to the inner service which returned an error.
+    pub inner_errors: u64,
+    /// Background refreshes started because an entry was read after its
+    /// [crate::CacheableQuery::refresh_after]. These call the inner service
+    /// but are not counted as misses.
+    pub refreshes: u64,
+    /// Responses written to the cache.
+    pub inserts: u64,
+    /// The total size in bytes of every response written to the cache.
+    pub inserted_bytes: u64,
+}
+
+impl CacheStatsSnapshot {
+    /// The fraction of requests answered without calling the inner service,
+    /// or None if there have been no requests.
+    pub fn hit_ratio(&self) -> Option<f64> {
+        let total = self.hits + self.misses;
+        if total == 0 {
+            None
+        } else {
+            Some(self.hits as f64 / total as f64)
+        }
+    }
+}
@@ -0,0 +1,335 @@
+use std::{
+    convert::Infallible,
+    fmt::{Debug, Display},
+    future::Future,
+    num::NonZeroUsize,
+};
+
+use bytes::Bytes;
+use futures_util::StreamExt;
+use moka::future::Cache;
+use redis::{aio::ConnectionLike, AsyncCommands, Client, RedisError};
+use serde::{Deserialize, Serialize};
+use tower::{layer::layer_fn, service_fn, Layer, Service, ServiceExt};
+
+use crate::{
+    codec::CacheCodec, moka::CacheEntry, stats::CacheStats, BatchCacheOutput, BatchCacheResponse,
+    CacheInvalidationError, CacheKey, CacheResult, CacheServiceError, CacheableQuery,
+    CacheableResponse, CacheableWrite, Invalidation,
+};
+
+/// The [redis] channel on which invalidations are published to the L1 cache
+/// of every process.
+pub const INVALIDATION_CHANNEL: &str = "eris-cache:invalidations";
+
+/// An invalidation as published to other processes. Keys are sent already
+/// encoded, so subscribers do not need to know the type of the key.
+#[derive(Debug, Serialize, Deserialize)]
+enum InvalidationNotice {
+    Key(Bytes),
+    Tag(String),
+}
+
+/// Publishes an invalidation to every process subscribed with
+/// [subscribe_to_invalidations].
+async fn publish<C, Codec>(
+    redis_connection: &mut C,
+    codec: &Codec,
+    notice: InvalidationNotice,
+) -> Result<(), CacheInvalidationError<RedisError, Codec>>
+where
+    C: ConnectionLike + Send,
+    Codec: CacheCodec,
+{
+    let notice_bytes = codec
+        .encode(&notice)
+        .map_err(CacheInvalidationError::SerializeError)?;
+    redis_connection
+        .publish::<_, _, ()>(INVALIDATION_CHANNEL, &notice_bytes[..])
+        .await
+        .map_err(CacheInvalidationError::CacheError)
+}
+
+/// Converts an error from the L1 layer, whose inner service is the L2 layer,
+/// into the error the L2 layer would have returned on its own.
+fn flatten_error<L2, S, Req, Codec>(
+    error: CacheServiceError<Infallible, L2, Req, Codec>,
+) -> CacheServiceError<RedisError, S, Req, Codec>
+where
+    L2: Service<Req, Error = CacheServiceError<RedisError, S, Req, Codec>>,
+    S: Service<Req>,
+    S::Error: Debug + Display,
+    Codec: CacheCodec,
+{
+    match error {
+        CacheServiceError::SerializeError(e) => CacheServiceError::SerializeError(e),
+        CacheServiceError::CacheError(infallible) => match infallible {},
+        CacheServiceError::DeserializeError(e) => CacheServiceError::DeserializeError(e),
+        CacheServiceError::InnerError(e) => e,
+        CacheServiceError::DuplicateRequestFailed => CacheServiceError::DuplicateRequestFailed,
+    }
+}
+
+/// Returns a [tower::Layer] which converts a [tower::Service] taking a
+/// [CacheableQuery] into one that takes a batch of them, checking this
+/// process's [moka] cache (L1), then the shared [redis] cache (L2), before
+/// falling back to the inner service. Responses are cached in every tier
+/// they were missing from on the way back up.
+///
+/// This is [crate::moka::cache_aside_layer] stacked on
+/// [crate::redis::single_cache_aside_layer], so it batches, coalesces and
+/// refreshes ahead in the same way. The L1 cache should be created with
+/// [crate::moka::cache_builder], and kept in sync with other processes by
+/// [subscribe_to_invalidations]. Hits and misses are counted separately for
+/// each tier, so `l2_stats` only sees the requests that missed L1.
+pub fn cache_aside_layer<S, Req, C, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    redis_connection: C,
+    codec: Codec,
+    l1_stats: CacheStats,
+    l2_stats: CacheStats,
+    concurrency_limit: NonZeroUsize,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Vec<Req>,
+        Response = BatchCacheResponse<RedisError, S, Req, Codec>,
+        Error = Infallible,
+        Future = impl Future<Output = BatchCacheOutput<RedisError, S, Req, Codec>> + Send,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    C: ConnectionLike + Clone + Send + 'static,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let l2 = crate::redis::single_cache_aside_layer(
+            redis_connection.clone(),
+            codec.clone(),
+            l2_stats.clone(),
+        )
+        .layer(service);
+        crate::moka::cache_aside_layer(
+            moka_cache.clone(),
+            codec.clone(),
+            l1_stats.clone(),
+            concurrency_limit,
+        )
+        .layer(l2)
+        .map_response(|responses| {
+            responses
+                .into_iter()
+                .map(|response| response.map_err(flatten_error))
+                .collect()
+        })
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableQuery], checking the L1 and then the L2 cache before falling
+/// back to the inner service. This behaves like [cache_aside_layer] for a
+/// single request at a time.
+pub fn single_cache_aside_layer<S, Req, C, Codec>(
+    moka_cache: Cache<Bytes, CacheEntry>,
+    redis_connection: C,
+    codec: Codec,
+    l1_stats: CacheStats,
+    l2_stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<RedisError, S, Req, Codec>,
+        Future = impl Future<Output = CacheResult<RedisError, S, Req, Codec>> + Send,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone + Send + 'static,
+    S::Future: Send,
+    Req: CacheableQuery + Send + 'static,
+    S::Response: CacheableResponse + Send,
+    S::Error: Debug + Display + Send,
+    C: ConnectionLike + Clone + Send + 'static,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let l2 = crate::redis::single_cache_aside_layer(
+            redis_connection.clone(),
+            codec.clone(),
+            l2_stats.clone(),
+        )
+        .layer(service);
+        crate::moka::single_cache_aside_layer(moka_cache.clone(), codec.clone(), l1_stats.clone())
+            .layer(l2)
+            .map_err(flatten_error)
+    })
+}
+
+/// Returns a [tower::Layer] which wraps a [tower::Service] taking a
+/// [CacheableWrite], storing each successful response in the L2 cache as in
+/// [crate::redis::write_through_layer], then evicting the stale entry from
+/// the L1 cache of every process. Readers fetch the new response from L2 the
+/// next time they look it up.
+pub fn write_through_layer<S, Req, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+    stats: CacheStats,
+) -> impl Layer<
+    S,
+    Service = impl Service<
+        Req,
+        Response = S::Response,
+        Error = CacheServiceError<RedisError, S, Req, Codec>,
+    > + Clone,
+>
+where
+    S: Service<Req> + Clone,
+    Req: CacheableWrite,
+    S::Response: CacheableResponse,
+    S::Error: Debug + Display,
+    C: ConnectionLike + Clone + Send,
+    Codec: CacheCodec,
+{
+    layer_fn(move |service: S| {
+        let write_through = crate::redis::write_through_layer(
+            redis_connection.clone(),
+            codec.clone(),
+            stats.clone(),
+        )
+        .layer(service);
+        let redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        let stats = stats.clone();
+        service_fn(move |request: Req| {
+            let write_through = write_through.clone();
+            let mut redis_connection = redis_connection.clone();
+            let codec = codec.clone();
+            let stats = stats.clone();
+            async move {
+                let cache_key = codec
+                    .encode(&CacheKey::from(request.query().cache_key()))
+                    .map_err(|e| {
+                        stats.record_serialize_error(&e);
+                        CacheServiceError::SerializeError(e)
+                    })?;
+
+                let result = write_through.oneshot(request).await;
+
+                // If the write itself failed, nothing changed
+                if matches!(result, Err(CacheServiceError::InnerError(_))) {
+                    return result;
+                }
+
+                let notice = InvalidationNotice::Key(cache_key);
+                match publish(&mut redis_connection, &codec, notice).await {
+                    Ok(()) => result,
+                    Err(CacheInvalidationError::SerializeError(e)) => {
+                        stats.record_serialize_error(&e);
+                        Err(CacheServiceError::SerializeError(e))
+                    }
+                    Err(CacheInvalidationError::CacheError(e)) => {
+                        stats.record_cache_error(&e);
+                        Err(CacheServiceError::CacheError(e))
+                    }
+                }
+            }
+        })
+    })
+}
+
+/// Returns a [tower::Service] which evicts entries from the L2 cache, then
+/// publishes the invalidation so that every process evicts them from its L1
+/// cache too.
+pub fn invalidation_service<K, C, Codec>(
+    redis_connection: C,
+    codec: Codec,
+) -> impl Service<Invalidation<K>, Response = (), Error = CacheInvalidationError<RedisError, Codec>>
+       + Clone
+where
+    K: Serialize,
+    C: ConnectionLike + Clone + Send,
+    Codec: CacheCodec,
+{
+    service_fn(move |invalidation: Invalidation<K>| {
+        let mut redis_connection = redis_connection.clone();
+        let codec = codec.clone();
+        async move {
+            let notice = match invalidation {
+                Invalidation::Key(key) => {
+                    let cache_key = codec
+                        .encode(&CacheKey::from(key))
+                        .map_err(CacheInvalidationError::SerializeError)?;
+                    redis_connection
+                        .del::<_, ()>(&cache_key[..])
+                        .await
+                        .map_err(CacheInvalidationError::CacheError)?;
+                    InvalidationNotice::Key(cache_key)
+                }
+                Invalidation::Tag(tag) => {
+                    crate::redis::invalidate_tag(&mut redis_connection, &tag)
+                        .await
+                        .map_err(CacheInvalidationError::CacheError)?;
+                    InvalidationNotice::Tag(tag)
+                }
+            };
+
+            // Only tell the L1 caches once L2 is gone, so that they can't be
+            // repopulated from it
+            publish(&mut redis_connection, &codec, notice).await
+        }
+    })
+}
+
+/// Listens for invalidations published by [invalidation_service] and
+/// [write_through_layer] in any process, including this one, and evicts the
+/// matching entries from this process's L1 cache. This should be spawned in
+/// every process using the tiered cache, with the same codec.
+///
+/// Returns an error if the subscription fails, or Ok once it is lost. Any
+/// invalidations published while unsubscribed are missed, so the whole L1
+/// cache is cleared in either case, and again once subscribed; the caller
+/// should subscribe again.
+pub async fn subscribe_to_invalidations<Codec: CacheCodec>(
+    redis_client: Client,
+    moka_cache: Cache<Bytes, CacheEntry>,
+    codec: Codec,
+) -> Result<(), RedisError> {
+    let subscribe = async {
+        let mut pubsub = redis_client.get_async_connection().await?.into_pubsub();
+        pubsub.subscribe(INVALIDATION_CHANNEL).await?;
+        Ok::<_, RedisError>(pubsub)
+    };
+    let pubsub = match subscribe.await {
+        Ok(pubsub) => pubsub,
+        Err(e) => {
+            moka_cache.invalidate_all();
+            return Err(e);
+        }
+    };
+    moka_cache.invalidate_all();
+
+    let mut messages = pubsub.into_on_message();
+    while let Some(message) = messages.next().await {
+        match codec.decode(message.get_payload_bytes()) {
+            Ok(InvalidationNotice::Key(cache_key)) => moka_cache.invalidate(&cache_key).await,
+            Ok(InvalidationNotice::Tag(tag)) => {
+                if let Err(e) =
+                    moka_cache.invalidate_entries_if(move |_key, entry| entry.tags().contains(&tag))
+                {
+                    tracing::error!("Failed to invalidate L1 cache by tag: {e}");
+                }
+            }
+            Err(e) => tracing::warn!("Ignoring invalidation that could not be decoded: {e}"),
+        }
+    }
+
+    tracing::warn!("Lost subscription to cache invalidations, clearing L1 cache");
+    moka_cache.invalidate_all();
+    Ok(())
+}
@@ -0,0 +1,157 @@
+//! A query, a write and an inner service which counts its calls, shared by
+//! the cache tests.
+
+#![allow(dead_code)]
+
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    sync::{Arc, Mutex},
+    task::{Context, Poll},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use eris_cache::{CacheableQuery, CacheableWrite};
+use futures_util::future::BoxFuture;
+use tower::Service;
+
+/// Ids above this have no name, so looking them up is "not found".
+pub const LAST_ID: u32 = 100;
+
+/// Looks up the name of an id. The namespace keeps the keys of each test
+/// apart, when they share a redis server.
+#[derive(Debug, Clone, Default)]
+pub struct GetName {
+    pub namespace: String,
+    pub id: u32,
+    pub time_to_live: Option<Duration>,
+    pub not_found_time_to_live: Option<Duration>,
+    pub tags: Vec<String>,
+}
+
+impl GetName {
+    pub fn new(namespace: &str, id: u32) -> Self {
+        Self {
+            namespace: namespace.to_string(),
+            id,
+            ..Default::default()
+        }
+    }
+}
+
+impl CacheableQuery for GetName {
+    type Key = (String, u32);
+
+    fn cache_key(&self) -> Self::Key {
+        (self.namespace.clone(), self.id)
+    }
+
+    fn time_to_live(&self) -> Option<Duration> {
+        self.time_to_live
+    }
+
+    fn not_found_time_to_live(&self) -> Option<Duration> {
+        self.not_found_time_to_live
+    }
+
+    fn cache_tags(&self) -> Vec<String> {
+        self.tags.clone()
+    }
+}
+
+/// Renames an id, answering with its new name.
+#[derive(Debug, Clone)]
+pub struct SetName {
+    pub query: GetName,
+    pub name: String,
+}
+
+impl CacheableWrite for SetName {
+    type Query = GetName;
+
+    fn query(&self) -> GetName {
+        self.query.clone()
+    }
+}
+
+/// The service behind the cache, which names every id up to [LAST_ID] and
+/// counts how many times each id was looked up.
+#[derive(Debug, Clone, Default)]
+pub struct Names {
+    renamed: Arc<Mutex<HashMap<u32, String>>>,
+    calls: Arc<Mutex<HashMap<u32, usize>>>,
+    delay: Duration,
+}
+
+impl Names {
+    /// Names which take this long to look up.
+    pub fn slow(delay: Duration) -> Self {
+        Self {
+            delay,
+            ..Default::default()
+        }
+    }
+
+    /// How many times this id was looked up.
+    pub fn calls(&self, id: u32) -> usize {
+        self.calls.lock().unwrap().get(&id).copied().unwrap_or(0)
+    }
+}
+
+impl Service<GetName> for Names {
+    type Response = Option<String>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Option<String>, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: GetName) -> Self::Future {
+        *self.calls.lock().unwrap().entry(request.id).or_default() += 1;
+        let name = match self.renamed.lock().unwrap().get(&request.id) {
+            Some(name) => Some(name.clone()),
+            None if request.id <= LAST_ID => Some(format!("name {}", request.id)),
+            None => None,
+        };
+        let delay = self.delay;
+        Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            Ok(name)
+        })
+    }
+}
+
+impl Service<SetName> for Names {
+    type Response = Option<String>;
+    type Error = Infallible;
+    type Future = BoxFuture<'static, Result<Option<String>, Infallible>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Infallible>> {
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, request: SetName) -> Self::Future {
+        self.renamed
+            .lock()
+            .unwrap()
+            .insert(request.query.id, request.name.clone());
+        Box::pin(async move { Ok(Some(request.name)) })
+    }
+}
+
+/// A namespace which no earlier run of the test has used.
+pub fn namespace(test_name: &str) -> String {
+    let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap();
+    format!("{test_name}:{}", now.as_nanos())
+}
+
+/// Connects to the redis server given by REDIS_URL, such as
+/// `redis://127.0.0.1/`.
+pub async fn redis_client() -> (redis::Client, redis::aio::ConnectionManager) {
+    let redis_url =
+        std::env::var("REDIS_URL").expect("REDIS_URL must be set to run the redis tests");
+    let client = redis::Client::open(redis_url).unwrap();
+    let connection = client.get_connection_manager().await.unwrap();
+    (client, connection)
+}
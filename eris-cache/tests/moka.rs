@@ -0,0 +1,187 @@
+//! Runs the moka cache-aside and write-through layers against an inner
+//! service which counts its calls.
+
+mod common;
+
+use std::{num::NonZeroUsize, time::Duration};
+
+use common::{GetName, Names, SetName, LAST_ID};
+use eris_cache::{
+    codec::MessagePack,
+    moka::{
+        cache_aside_layer, cache_builder, invalidation_service, single_cache_aside_layer,
+        write_through_layer,
+    },
+    stats::CacheStats,
+    Invalidation,
+};
+use futures_util::future::join_all;
+use tower::{Layer, ServiceExt};
+
+#[tokio::test]
+async fn duplicate_keys_in_a_batch_call_the_inner_service_once() {
+    let names = Names::default();
+    let stats = CacheStats::new();
+    let service = cache_aside_layer(
+        cache_builder().build(),
+        MessagePack,
+        stats.clone(),
+        NonZeroUsize::new(4).unwrap(),
+    )
+    .layer(names.clone());
+
+    let batch = [1, 2, 1, 1].map(|id| GetName::new("batch", id)).to_vec();
+    let responses: Vec<_> = service
+        .oneshot(batch)
+        .await
+        .unwrap()
+        .into_iter()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        responses,
+        ["name 1", "name 2", "name 1", "name 1"].map(|name| Some(name.to_string()))
+    );
+    assert_eq!(names.calls(1), 1);
+    assert_eq!(names.calls(2), 1);
+}
+
+#[tokio::test]
+async fn a_stampede_on_a_cold_cache_is_coalesced() {
+    let names = Names::slow(Duration::from_millis(100));
+    let stats = CacheStats::new();
+    let service = single_cache_aside_layer(cache_builder().build(), MessagePack, stats.clone())
+        .layer(names.clone());
+
+    let responses =
+        join_all((0..10).map(|_| service.clone().oneshot(GetName::new("stampede", 1)))).await;
+    for response in responses {
+        assert_eq!(response.unwrap().as_deref(), Some("name 1"));
+    }
+    assert_eq!(names.calls(1), 1);
+    let snapshot = stats.snapshot();
+    assert_eq!((snapshot.hits, snapshot.misses), (9, 1));
+}
+
+#[tokio::test]
+async fn entries_expire_after_their_time_to_live() {
+    let names = Names::default();
+    let service = single_cache_aside_layer(cache_builder().build(), MessagePack, CacheStats::new())
+        .layer(names.clone());
+    let found = GetName {
+        time_to_live: Some(Duration::from_millis(200)),
+        ..GetName::new("ttl", 1)
+    };
+    let not_found = GetName {
+        not_found_time_to_live: Some(Duration::from_millis(200)),
+        ..GetName::new("ttl", LAST_ID + 1)
+    };
+    let never_cached = GetName::new("ttl", LAST_ID + 2);
+
+    for _ in 0..2 {
+        assert_eq!(
+            service
+                .clone()
+                .oneshot(found.clone())
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("name 1")
+        );
+        assert_eq!(
+            service.clone().oneshot(not_found.clone()).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            service.clone().oneshot(never_cached.clone()).await.unwrap(),
+            None
+        );
+    }
+    assert_eq!(names.calls(found.id), 1);
+    assert_eq!(names.calls(not_found.id), 1);
+    // "Not found" is only cached with its own time to live
+    assert_eq!(names.calls(never_cached.id), 2);
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    service.clone().oneshot(found.clone()).await.unwrap();
+    service.clone().oneshot(not_found.clone()).await.unwrap();
+    assert_eq!(names.calls(found.id), 2);
+    assert_eq!(names.calls(not_found.id), 2);
+}
+
+#[tokio::test]
+async fn invalidating_a_tag_evicts_only_its_entries() {
+    let names = Names::default();
+    let moka_cache = cache_builder().build();
+    let service = single_cache_aside_layer(moka_cache.clone(), MessagePack, CacheStats::new())
+        .layer(names.clone());
+    let tagged = |id, tag: &str| GetName {
+        tags: vec![tag.to_string()],
+        ..GetName::new("tags", id)
+    };
+    let queries = [tagged(1, "herons"), tagged(2, "herons"), tagged(3, "crows")];
+
+    for query in queries.iter() {
+        service.clone().oneshot(query.clone()).await.unwrap();
+    }
+    invalidation_service::<(String, u32), _>(moka_cache, MessagePack)
+        .oneshot(Invalidation::Tag("herons".to_string()))
+        .await
+        .unwrap();
+    for query in queries.iter() {
+        service.clone().oneshot(query.clone()).await.unwrap();
+    }
+
+    assert_eq!(names.calls(1), 2);
+    assert_eq!(names.calls(2), 2);
+    assert_eq!(names.calls(3), 1);
+}
+
+#[tokio::test]
+async fn hit_ratio_counts_every_lookup() {
+    let names = Names::default();
+    let stats = CacheStats::new();
+    assert_eq!(stats.snapshot().hit_ratio(), None);
+
+    let service =
+        single_cache_aside_layer(cache_builder().build(), MessagePack, stats.clone()).layer(names);
+    for id in [1, 1, 2, 1] {
+        service
+            .clone()
+            .oneshot(GetName::new("stats", id))
+            .await
+            .unwrap();
+    }
+
+    let snapshot = stats.snapshot();
+    assert_eq!((snapshot.hits, snapshot.misses), (2, 2));
+    assert_eq!(snapshot.inserts, 2);
+    assert_eq!(snapshot.inner_errors, 0);
+    assert_eq!(snapshot.hit_ratio(), Some(0.5));
+}
+
+#[tokio::test]
+async fn reads_after_a_write_through_see_the_new_response() {
+    let names = Names::default();
+    let moka_cache = cache_builder().build();
+    let stats = CacheStats::new();
+    let reads = single_cache_aside_layer(moka_cache.clone(), MessagePack, stats.clone())
+        .layer(names.clone());
+    let writes = write_through_layer(moka_cache, MessagePack, stats).layer(names.clone());
+    let query = GetName::new("write_through", 1);
+
+    let read = reads.clone().oneshot(query.clone()).await.unwrap();
+    assert_eq!(read.as_deref(), Some("name 1"));
+    let written = writes
+        .oneshot(SetName {
+            query: query.clone(),
+            name: "heron".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(written.as_deref(), Some("heron"));
+
+    let read = reads.oneshot(query).await.unwrap();
+    assert_eq!(read.as_deref(), Some("heron"));
+    assert_eq!(names.calls(1), 1);
+}
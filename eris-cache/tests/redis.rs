@@ -0,0 +1,129 @@
+//! Runs the redis cache-aside and write-through layers against the redis
+//! server given by REDIS_URL, such as `redis://127.0.0.1/`. Every key is
+//! namespaced by the test and the time it ran, so tests can share a server
+//! and run in parallel. Run them with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{namespace, redis_client, GetName, Names, SetName, LAST_ID};
+use eris_cache::{
+    codec::MessagePack,
+    redis::{invalidation_service, single_cache_aside_layer, write_through_layer},
+    stats::CacheStats,
+    Invalidation,
+};
+use tower::{Layer, ServiceExt};
+
+#[tokio::test]
+#[ignore = "needs a redis server at REDIS_URL"]
+async fn entries_expire_after_their_time_to_live() {
+    let (_client, connection) = redis_client().await;
+    let names = Names::default();
+    let service =
+        single_cache_aside_layer(connection, MessagePack, CacheStats::new()).layer(names.clone());
+    let namespace = namespace("ttl");
+    let found = GetName {
+        time_to_live: Some(Duration::from_millis(200)),
+        ..GetName::new(&namespace, 1)
+    };
+    let not_found = GetName {
+        not_found_time_to_live: Some(Duration::from_millis(200)),
+        ..GetName::new(&namespace, LAST_ID + 1)
+    };
+    let never_cached = GetName::new(&namespace, LAST_ID + 2);
+
+    for _ in 0..2 {
+        assert_eq!(
+            service
+                .clone()
+                .oneshot(found.clone())
+                .await
+                .unwrap()
+                .as_deref(),
+            Some("name 1")
+        );
+        assert_eq!(
+            service.clone().oneshot(not_found.clone()).await.unwrap(),
+            None
+        );
+        assert_eq!(
+            service.clone().oneshot(never_cached.clone()).await.unwrap(),
+            None
+        );
+    }
+    assert_eq!(names.calls(found.id), 1);
+    assert_eq!(names.calls(not_found.id), 1);
+    // "Not found" is only cached with its own time to live
+    assert_eq!(names.calls(never_cached.id), 2);
+
+    tokio::time::sleep(Duration::from_millis(400)).await;
+    service.clone().oneshot(found.clone()).await.unwrap();
+    service.clone().oneshot(not_found.clone()).await.unwrap();
+    assert_eq!(names.calls(found.id), 2);
+    assert_eq!(names.calls(not_found.id), 2);
+}
+
+#[tokio::test]
+#[ignore = "needs a redis server at REDIS_URL"]
+async fn invalidating_a_tag_evicts_only_its_entries() {
+    let (_client, connection) = redis_client().await;
+    let names = Names::default();
+    let service = single_cache_aside_layer(connection.clone(), MessagePack, CacheStats::new())
+        .layer(names.clone());
+    let namespace = namespace("tags");
+    let herons = format!("{namespace}:herons");
+    let crows = format!("{namespace}:crows");
+    let tagged = |id, tag: &str| GetName {
+        time_to_live: Some(Duration::from_secs(60)),
+        tags: vec![tag.to_string()],
+        ..GetName::new(&namespace, id)
+    };
+    let queries = [tagged(1, &herons), tagged(2, &herons), tagged(3, &crows)];
+
+    for query in queries.iter() {
+        service.clone().oneshot(query.clone()).await.unwrap();
+    }
+    invalidation_service::<(String, u32), _, _>(connection, MessagePack)
+        .oneshot(Invalidation::Tag(herons.clone()))
+        .await
+        .unwrap();
+    for query in queries.iter() {
+        service.clone().oneshot(query.clone()).await.unwrap();
+    }
+
+    assert_eq!(names.calls(1), 2);
+    assert_eq!(names.calls(2), 2);
+    assert_eq!(names.calls(3), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a redis server at REDIS_URL"]
+async fn reads_after_a_write_through_see_the_new_response() {
+    let (_client, connection) = redis_client().await;
+    let names = Names::default();
+    let stats = CacheStats::new();
+    let reads = single_cache_aside_layer(connection.clone(), MessagePack, stats.clone())
+        .layer(names.clone());
+    let writes = write_through_layer(connection, MessagePack, stats).layer(names.clone());
+    let query = GetName {
+        time_to_live: Some(Duration::from_secs(60)),
+        ..GetName::new(&namespace("write_through"), 1)
+    };
+
+    let read = reads.clone().oneshot(query.clone()).await.unwrap();
+    assert_eq!(read.as_deref(), Some("name 1"));
+    let written = writes
+        .oneshot(SetName {
+            query: query.clone(),
+            name: "heron".to_string(),
+        })
+        .await
+        .unwrap();
+    assert_eq!(written.as_deref(), Some("heron"));
+
+    let read = reads.oneshot(query).await.unwrap();
+    assert_eq!(read.as_deref(), Some("heron"));
+    assert_eq!(names.calls(1), 1);
+}
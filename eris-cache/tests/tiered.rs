@@ -0,0 +1,89 @@
+//! Runs the tiered cache against the redis server given by REDIS_URL, such
+//! as `redis://127.0.0.1/`, with an L1 cache for each of two processes. Run
+//! them with `cargo test -- --ignored`.
+
+mod common;
+
+use std::time::Duration;
+
+use common::{namespace, redis_client, GetName, Names};
+use eris_cache::{
+    codec::MessagePack,
+    moka::cache_builder,
+    stats::CacheStats,
+    tiered::{
+        invalidation_service, single_cache_aside_layer, subscribe_to_invalidations,
+        INVALIDATION_CHANNEL,
+    },
+    CacheableQuery, Invalidation,
+};
+use tokio::time::{sleep, timeout};
+use tower::{Layer, ServiceExt};
+
+#[tokio::test]
+#[ignore = "needs a redis server at REDIS_URL"]
+async fn deleting_from_l2_evicts_l1_in_other_processes() {
+    let (client, mut connection) = redis_client().await;
+    let names = Names::default();
+    let l1_caches = [cache_builder().build(), cache_builder().build()];
+    for l1_cache in l1_caches.iter() {
+        tokio::spawn(subscribe_to_invalidations(
+            client.clone(),
+            l1_cache.clone(),
+            MessagePack,
+        ));
+    }
+    // Subscribing clears the L1 cache, so wait until both have
+    timeout(Duration::from_secs(5), async {
+        loop {
+            let (_channel, subscribers): (String, usize) = redis::cmd("PUBSUB")
+                .arg("NUMSUB")
+                .arg(INVALIDATION_CHANNEL)
+                .query_async(&mut connection)
+                .await
+                .unwrap();
+            if subscribers >= l1_caches.len() {
+                break;
+            }
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+
+    let [first, second] = l1_caches.map(|l1_cache| {
+        let service = single_cache_aside_layer(
+            l1_cache.clone(),
+            connection.clone(),
+            MessagePack,
+            CacheStats::new(),
+            CacheStats::new(),
+        )
+        .layer(names.clone());
+        (l1_cache, service)
+    });
+    let query = GetName {
+        time_to_live: Some(Duration::from_secs(60)),
+        ..GetName::new(&namespace("tiered"), 1)
+    };
+    for (_l1_cache, service) in [&first, &second] {
+        service.clone().oneshot(query.clone()).await.unwrap();
+    }
+    // The second process found it in L2
+    assert_eq!(names.calls(1), 1);
+    assert_eq!(second.0.iter().count(), 1);
+
+    invalidation_service::<(String, u32), _, _>(connection.clone(), MessagePack)
+        .oneshot(Invalidation::Key(query.cache_key()))
+        .await
+        .unwrap();
+    timeout(Duration::from_secs(5), async {
+        while second.0.iter().count() > 0 {
+            sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .unwrap();
+    second.1.clone().oneshot(query).await.unwrap();
+    assert_eq!(names.calls(1), 2);
+}
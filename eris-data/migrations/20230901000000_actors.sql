@@ -0,0 +1,38 @@
+-- Discord users who have joined the instance with /join
+CREATE TABLE users (
+    id BIGSERIAL PRIMARY KEY,
+    discord_user_id BIGINT NOT NULL UNIQUE,
+    handle TEXT NOT NULL,
+    display_name TEXT,
+    summary TEXT,
+    manually_approves_followers BOOLEAN NOT NULL DEFAULT FALSE,
+    public_key_pem TEXT NOT NULL,
+    private_key_pem TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- WebFinger handles are case-insensitive
+CREATE UNIQUE INDEX users_handle ON users (lower(handle));
+
+-- Discord text channels registered with the instance
+CREATE TABLE channels (
+    channel_id BIGINT PRIMARY KEY,
+    guild_id BIGINT NOT NULL,
+    name TEXT NOT NULL,
+    public_key_pem TEXT NOT NULL,
+    private_key_pem TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- Actors on other servers, keyed by their ActivityPub id
+CREATE TABLE foreign_actors (
+    id TEXT PRIMARY KEY,
+    kind TEXT NOT NULL,
+    preferred_username TEXT,
+    name TEXT,
+    url TEXT,
+    inbox TEXT NOT NULL,
+    shared_inbox TEXT,
+    public_key_pem TEXT NOT NULL,
+    last_refreshed_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,35 @@
+-- Posts, local and foreign, keyed by their ActivityPub id. The numeric id
+-- is part of the ActivityPub id of local posts.
+CREATE TABLE posts (
+    id BIGSERIAL PRIMARY KEY,
+    ap_id TEXT NOT NULL UNIQUE,
+    -- The local author, or NULL for foreign posts
+    user_id BIGINT REFERENCES users (id) ON DELETE CASCADE,
+    attributed_to TEXT NOT NULL,
+    content TEXT NOT NULL,
+    summary TEXT,
+    sensitive BOOLEAN NOT NULL DEFAULT FALSE,
+    url TEXT,
+    in_reply_to TEXT,
+    published TIMESTAMPTZ,
+    updated TIMESTAMPTZ,
+    to_audience TEXT[] NOT NULL DEFAULT '{}',
+    cc_audience TEXT[] NOT NULL DEFAULT '{}'
+);
+
+CREATE INDEX posts_attributed_to ON posts (attributed_to);
+
+-- The image and video attached to a post. Foreign attachments without an
+-- ActivityPub id use their URL instead.
+CREATE TABLE attachments (
+    id BIGSERIAL PRIMARY KEY,
+    ap_id TEXT NOT NULL,
+    post_id BIGINT NOT NULL REFERENCES posts (id) ON DELETE CASCADE,
+    kind TEXT NOT NULL CHECK (kind IN ('image', 'video')),
+    url TEXT NOT NULL,
+    media_type TEXT,
+    name TEXT,
+    UNIQUE (post_id, kind)
+);
+
+CREATE INDEX attachments_ap_id ON attachments (ap_id);
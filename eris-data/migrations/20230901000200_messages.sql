@@ -0,0 +1,11 @@
+-- Discord messages showing a post, found by the post's ActivityPub id so
+-- they can be updated or deleted along with it
+CREATE TABLE messages (
+    message_id BIGINT PRIMARY KEY,
+    channel_id BIGINT NOT NULL,
+    guild_id BIGINT NOT NULL,
+    post_id TEXT NOT NULL,
+    sent_at TIMESTAMPTZ NOT NULL
+);
+
+CREATE INDEX messages_post_id ON messages (post_id);
@@ -0,0 +1,44 @@
+-- Relations between actors and objects, keyed by ActivityPub ids so that
+-- they can hold local and foreign actors alike. Each keeps the id of the
+-- activity which created it, so that an Undo can be matched to it.
+
+CREATE TABLE follows (
+    follower TEXT NOT NULL,
+    followed TEXT NOT NULL,
+    activity_id TEXT NOT NULL UNIQUE,
+    accepted BOOLEAN NOT NULL DEFAULT FALSE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (follower, followed)
+);
+
+CREATE INDEX follows_followed ON follows (followed);
+
+CREATE TABLE likes (
+    actor TEXT NOT NULL,
+    object TEXT NOT NULL,
+    activity_id TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (actor, object)
+);
+
+CREATE INDEX likes_object ON likes (object);
+
+CREATE TABLE shares (
+    actor TEXT NOT NULL,
+    object TEXT NOT NULL,
+    activity_id TEXT NOT NULL UNIQUE,
+    published TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (actor, object)
+);
+
+CREATE INDEX shares_object ON shares (object);
+
+CREATE TABLE blocks (
+    actor TEXT NOT NULL,
+    blocked TEXT NOT NULL,
+    activity_id TEXT NOT NULL UNIQUE,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    PRIMARY KEY (actor, blocked)
+);
+
+CREATE INDEX blocks_blocked ON blocks (blocked);
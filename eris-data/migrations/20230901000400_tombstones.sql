@@ -0,0 +1,7 @@
+-- What is left of deleted objects, keyed by their ActivityPub id, so that
+-- the id is never reused and keeps answering that it was deleted
+CREATE TABLE tombstones (
+    id TEXT PRIMARY KEY,
+    former_type TEXT,
+    deleted TIMESTAMPTZ
+);
@@ -0,0 +1,31 @@
+-- Every activity accepted by the instance, local or foreign, in the order
+-- it was accepted. This is the write model: follows, likes, shares, blocks
+-- and posts are projections of it, which can be rebuilt from it.
+CREATE TABLE activity_log (
+    sequence BIGSERIAL PRIMARY KEY,
+    activity_id TEXT NOT NULL UNIQUE,
+    kind TEXT NOT NULL,
+    actor TEXT NOT NULL,
+    object TEXT NOT NULL,
+    activity JSONB NOT NULL,
+    recorded_at TIMESTAMPTZ NOT NULL DEFAULT now()
+);
+
+-- The log is only ever appended to
+CREATE FUNCTION reject_activity_log_change() RETURNS trigger AS $$
+BEGIN
+    RAISE EXCEPTION 'activity_log is append-only';
+END;
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER activity_log_append_only
+    BEFORE UPDATE OR DELETE OR TRUNCATE ON activity_log
+    FOR EACH STATEMENT EXECUTE FUNCTION reject_activity_log_change();
+
+-- The sequence of the last activity applied to the projections
+CREATE TABLE projection_position (
+    only_row BOOLEAN PRIMARY KEY DEFAULT TRUE CHECK (only_row),
+    sequence BIGINT NOT NULL
+);
+
+INSERT INTO projection_position (sequence) VALUES (0);
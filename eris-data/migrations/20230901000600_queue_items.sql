@@ -0,0 +1,14 @@
+-- The items of every durable queue in eris_lib::services::postgres_queue,
+-- kept until the subscriber acknowledges them. Earlier versions created
+-- this table at runtime, so it may already exist.
+CREATE TABLE IF NOT EXISTS eris_queue_items (
+    id BIGSERIAL PRIMARY KEY,
+    queue TEXT NOT NULL,
+    payload JSONB NOT NULL,
+    enqueued_at TIMESTAMPTZ NOT NULL DEFAULT now(),
+    claimed_at TIMESTAMPTZ,
+    attempts INTEGER NOT NULL DEFAULT 0
+);
+
+CREATE INDEX IF NOT EXISTS eris_queue_items_unclaimed
+    ON eris_queue_items (queue, id) WHERE claimed_at IS NULL;
@@ -0,0 +1,11 @@
+-- The actions eris_lib::services::dead_letter_queue could not send to
+-- Discord, kept for an operator to replay or discard. Earlier versions
+-- created this table at runtime, so it may already exist.
+CREATE TABLE IF NOT EXISTS eris_dead_letters (
+    id BIGSERIAL PRIMARY KEY,
+    action JSONB NOT NULL,
+    error_kind JSONB NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    failed_at TIMESTAMPTZ NOT NULL
+);
@@ -0,0 +1,212 @@
+use std::future::Future;
+
+use activitypub_federation::traits::ActivityHandler;
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::model::{activity::InboxActivity, federation::ModelError, urls::UrlLayout};
+use sqlx::{types::Json, Connection, PgConnection};
+use tower::{Service, ServiceExt};
+use url::Url;
+
+use crate::{DataError, Repository, RepositoryRequest};
+
+/// An activity, as recorded in the log.
+#[derive(Debug, Clone)]
+pub struct LoggedActivity {
+    /// The position of the activity in the log, counting from 1
+    pub sequence: i64,
+    /// The activity
+    pub activity: InboxActivity,
+    /// When the activity was appended to the log
+    pub recorded_at: DateTime<Utc>,
+}
+
+/// A row of the activity_log table.
+#[derive(Debug, sqlx::FromRow)]
+struct LoggedActivityRow {
+    sequence: i64,
+    activity: Json<InboxActivity>,
+    recorded_at: DateTime<Utc>,
+}
+
+impl From<LoggedActivityRow> for LoggedActivity {
+    fn from(row: LoggedActivityRow) -> Self {
+        Self {
+            sequence: row.sequence,
+            activity: row.activity.0,
+            recorded_at: row.recorded_at,
+        }
+    }
+}
+
+/// Reads the activities following the one at `after`, in order.
+pub(crate) async fn read_activities(
+    conn: &mut PgConnection,
+    after: i64,
+    limit: i64,
+) -> Result<Vec<LoggedActivity>, DataError> {
+    let rows: Vec<LoggedActivityRow> = sqlx::query_as(
+        "SELECT sequence, activity, recorded_at FROM activity_log
+            WHERE sequence > $1
+            ORDER BY sequence
+            LIMIT $2",
+    )
+    .bind(after)
+    .bind(limit)
+    .fetch_all(&mut *conn)
+    .await?;
+    Ok(rows.into_iter().map(LoggedActivity::from).collect())
+}
+
+/// Appends an accepted activity to the log. Returns it as logged, or None
+/// if an activity with the same id was already logged, so that an activity
+/// received twice is only applied once. The projections are only updated
+/// by [crate::projections::CatchUpProjections].
+#[derive(Debug, Clone)]
+pub struct AppendActivity(pub InboxActivity);
+
+/// Locks the log until the end of the transaction. Appends are made one at
+/// a time, so that activities are committed in the order of their sequence
+/// and catching up never skips one.
+async fn lock_log(conn: &mut PgConnection) -> Result<(), DataError> {
+    sqlx::query("LOCK TABLE activity_log IN SHARE ROW EXCLUSIVE MODE")
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Inserts an activity at the end of the log, or at `sequence` if it is
+/// given. Returns None if an activity with the same id was already logged.
+async fn insert_activity(
+    conn: &mut PgConnection,
+    activity: &InboxActivity,
+    sequence: Option<i64>,
+) -> Result<Option<(i64, DateTime<Utc>)>, DataError> {
+    let logged = sqlx::query_as(
+        "INSERT INTO activity_log (sequence, activity_id, kind, actor, object, activity)
+            VALUES (COALESCE($1, nextval('activity_log_sequence_seq')), $2, $3, $4, $5, $6)
+            ON CONFLICT (activity_id) DO NOTHING
+            RETURNING sequence, recorded_at",
+    )
+    .bind(sequence)
+    .bind(activity.id().as_str())
+    .bind(activity.kind())
+    .bind(activity.actor().as_str())
+    .bind(activity.object_id().as_str())
+    .bind(Json(activity))
+    .fetch_optional(&mut *conn)
+    .await?;
+    Ok(logged)
+}
+
+/// Appends an activity performed by a local actor, within the transaction
+/// of the write it records, so that the projections rebuilt from the log
+/// keep that write. The activity is built from the id it is given, which
+/// comes from its sequence. Returns the activity.
+pub(crate) async fn append_local_activity(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    activity: impl FnOnce(Url) -> InboxActivity,
+) -> Result<InboxActivity, DataError> {
+    lock_log(conn).await?;
+    let sequence: i64 = sqlx::query_scalar("SELECT nextval('activity_log_sequence_seq')")
+        .fetch_one(&mut *conn)
+        .await?;
+    let activity = activity(urls.activity(sequence));
+    insert_activity(conn, &activity, Some(sequence))
+        .await?
+        .ok_or(DataError::Conflict("an activity has this id"))?;
+    Ok(activity)
+}
+
+#[async_trait]
+impl RepositoryRequest for AppendActivity {
+    type Response = Option<LoggedActivity>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<LoggedActivity>, DataError> {
+        let activity = self.0;
+        let mut conn = repository.pool().acquire().await?;
+        let mut transaction = conn.begin().await?;
+        lock_log(&mut transaction).await?;
+        let logged = insert_activity(&mut transaction, &activity, None).await?;
+        transaction.commit().await?;
+
+        Ok(logged.map(|(sequence, recorded_at)| LoggedActivity {
+            sequence,
+            activity,
+            recorded_at,
+        }))
+    }
+}
+
+/// Reads up to `limit` activities from the log, in order, starting after
+/// the one at `after`. Use 0 to start from the beginning.
+#[derive(Debug, Clone, Copy)]
+pub struct ReadActivities {
+    /// The sequence of the last activity already read
+    pub after: i64,
+    /// How many activities to read at most
+    pub limit: i64,
+}
+
+#[async_trait]
+impl RepositoryRequest for ReadActivities {
+    type Response = Vec<LoggedActivity>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<LoggedActivity>, DataError> {
+        let mut conn = repository.pool().acquire().await?;
+        read_activities(&mut conn, self.after, self.limit).await
+    }
+}
+
+/// Whether an activity with this id is in the log.
+#[derive(Debug, Clone)]
+pub struct IsLogged(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for IsLogged {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let logged =
+            sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM activity_log WHERE activity_id = $1)")
+                .bind(self.0.as_str())
+                .fetch_one(repository.pool())
+                .await?;
+        Ok(logged)
+    }
+}
+
+/// A service which answers whether an activity id is not in the log yet,
+/// to be used to deduplicate activities with
+/// [eris_lib::services::activity_router::activity_router].
+pub fn deduplicate_service(
+    repository: Repository,
+) -> impl Service<
+    Url,
+    Response = bool,
+    Error = ModelError,
+    Future = impl Future<Output = Result<bool, ModelError>> + Send,
+> + Clone {
+    repository
+        .map_request(IsLogged)
+        .map_response(|logged: bool| !logged)
+        .map_err(ModelError::from)
+}
+
+/// A service which appends each activity to the log, once it has been
+/// handled by [eris_lib::services::activity_router::activity_router], and
+/// answers whether it was the first with its id.
+pub fn record_service(
+    repository: Repository,
+) -> impl Service<
+    InboxActivity,
+    Response = bool,
+    Error = ModelError,
+    Future = impl Future<Output = Result<bool, ModelError>> + Send,
+> + Clone {
+    repository
+        .map_request(AppendActivity)
+        .map_response(|logged: Option<LoggedActivity>| logged.is_some())
+        .map_err(ModelError::from)
+}
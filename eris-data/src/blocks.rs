@@ -0,0 +1,94 @@
+use async_trait::async_trait;
+use eris_lib::model::block::Block;
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{parse_urls, DataError, Repository, RepositoryRequest};
+
+/// Stores a block. Blocking an actor again keeps the first block.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveBlock(pub Block);
+
+impl SaveBlock {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO blocks (actor, blocked, activity_id) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+        )
+        .bind(self.0.actor.as_str())
+        .bind(self.0.object.as_str())
+        .bind(self.0.id.as_str())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Deletes a block. Returns false if there was no such block.
+#[derive(Debug, Clone)]
+pub(crate) struct DeleteBlock {
+    /// The actor doing the blocking
+    pub actor: Url,
+    /// The actor blocked
+    pub blocked: Url,
+}
+
+impl DeleteBlock {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM blocks WHERE actor = $1 AND blocked = $2")
+            .bind(self.actor.as_str())
+            .bind(self.blocked.as_str())
+            .execute(&mut *conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Checks whether either actor blocks the other, in which case nothing
+/// should pass between them.
+#[derive(Debug, Clone)]
+pub struct IsBlocked {
+    /// One actor
+    pub actor: Url,
+    /// The other actor
+    pub other: Url,
+}
+
+#[async_trait]
+impl RepositoryRequest for IsBlocked {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let blocked: bool = sqlx::query_scalar(
+            "SELECT EXISTS (
+                SELECT 1 FROM blocks
+                    WHERE (actor = $1 AND blocked = $2) OR (actor = $2 AND blocked = $1)
+            )",
+        )
+        .bind(self.actor.as_str())
+        .bind(self.other.as_str())
+        .fetch_one(repository.pool())
+        .await?;
+        Ok(blocked)
+    }
+}
+
+/// Lists the actors an actor blocks, oldest first.
+#[derive(Debug, Clone)]
+pub struct GetBlocked(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetBlocked {
+    type Response = Vec<Url>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Url>, DataError> {
+        let blocked: Vec<String> =
+            sqlx::query_scalar("SELECT blocked FROM blocks WHERE actor = $1 ORDER BY created_at")
+                .bind(self.0.as_str())
+                .fetch_all(repository.pool())
+                .await?;
+        parse_urls(&blocked)
+    }
+}
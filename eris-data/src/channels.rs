@@ -0,0 +1,153 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use eris_lib::model::{channel::Channel, urls::UrlLayout};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+
+use crate::{
+    tombstones::{bury, check_not_deleted},
+    DataError, Repository, RepositoryRequest,
+};
+
+const CHANNEL_COLUMNS: &str = "channel_id, guild_id, name, public_key_pem, private_key_pem";
+
+/// A row of the channels table.
+#[derive(Debug, sqlx::FromRow)]
+struct ChannelRow {
+    channel_id: i64,
+    guild_id: i64,
+    name: String,
+    public_key_pem: String,
+    private_key_pem: String,
+}
+
+impl ChannelRow {
+    fn into_channel(self, urls: &UrlLayout) -> Channel {
+        let guild_id = Id::new(self.guild_id as u64);
+        let channel_id = Id::new(self.channel_id as u64);
+        Channel {
+            id: urls.channel(guild_id, channel_id),
+            guild_id,
+            channel_id,
+            name: self.name,
+            inbox: urls.channel_collection(guild_id, channel_id, "inbox"),
+            outbox: urls.channel_collection(guild_id, channel_id, "outbox"),
+            following: urls.channel_collection(guild_id, channel_id, "following"),
+            shared_inbox: urls.shared_inbox(),
+            public_key_pem: self.public_key_pem,
+            private_key_pem: Some(self.private_key_pem),
+        }
+    }
+}
+
+/// Stores a Discord channel which has just been registered. Fails with
+/// [DataError::Conflict] if it is already registered, or with
+/// [DataError::Deleted] if it was registered before and then deleted, as
+/// the id of its actor cannot be used again.
+#[derive(Debug, Clone)]
+pub struct RegisterChannel {
+    /// The guild the channel is in
+    pub guild_id: Id<GuildMarker>,
+    /// The channel being registered
+    pub channel_id: Id<ChannelMarker>,
+    /// The name of the channel
+    pub name: String,
+    /// The public key of the channel's new actor
+    pub public_key_pem: String,
+    /// The private key of the channel's new actor
+    pub private_key_pem: String,
+}
+
+#[async_trait]
+impl RepositoryRequest for RegisterChannel {
+    type Response = Channel;
+
+    async fn execute(self, repository: &Repository) -> Result<Channel, DataError> {
+        let mut conn = repository.pool().acquire().await?;
+        let id = repository.urls().channel(self.guild_id, self.channel_id);
+        check_not_deleted(&mut conn, &id).await?;
+        let row: ChannelRow = sqlx::query_as(&format!(
+            "INSERT INTO channels (channel_id, guild_id, name, public_key_pem, private_key_pem)
+                VALUES ($1, $2, $3, $4, $5)
+                RETURNING {CHANNEL_COLUMNS}"
+        ))
+        .bind(self.channel_id.get() as i64)
+        .bind(self.guild_id.get() as i64)
+        .bind(&self.name)
+        .bind(&self.public_key_pem)
+        .bind(&self.private_key_pem)
+        .fetch_one(&mut *conn)
+        .await
+        .map_err(DataError::conflict_on_unique("channel already registered"))?;
+        Ok(row.into_channel(repository.urls()))
+    }
+}
+
+/// Finds a registered channel.
+#[derive(Debug, Clone, Copy)]
+pub struct GetChannel(pub Id<ChannelMarker>);
+
+#[async_trait]
+impl RepositoryRequest for GetChannel {
+    type Response = Option<Channel>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Channel>, DataError> {
+        let row: Option<ChannelRow> = sqlx::query_as(&format!(
+            "SELECT {CHANNEL_COLUMNS} FROM channels WHERE channel_id = $1"
+        ))
+        .bind(self.0.get() as i64)
+        .fetch_optional(repository.pool())
+        .await?;
+        Ok(row.map(|row| row.into_channel(repository.urls())))
+    }
+}
+
+/// Lists the channels registered in a guild.
+#[derive(Debug, Clone, Copy)]
+pub struct GetGuildChannels(pub Id<GuildMarker>);
+
+#[async_trait]
+impl RepositoryRequest for GetGuildChannels {
+    type Response = Vec<Channel>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Channel>, DataError> {
+        let rows: Vec<ChannelRow> = sqlx::query_as(&format!(
+            "SELECT {CHANNEL_COLUMNS} FROM channels WHERE guild_id = $1 ORDER BY name"
+        ))
+        .bind(self.0.get() as i64)
+        .fetch_all(repository.pool())
+        .await?;
+        Ok(rows
+            .into_iter()
+            .map(|row| row.into_channel(repository.urls()))
+            .collect())
+    }
+}
+
+/// Deletes a registered channel, leaving a tombstone for its actor.
+/// Returns false if it was not registered.
+#[derive(Debug, Clone, Copy)]
+pub struct DeleteChannel(pub Id<ChannelMarker>);
+
+#[async_trait]
+impl RepositoryRequest for DeleteChannel {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let mut transaction = repository.pool().begin().await?;
+        let guild_id: Option<i64> =
+            sqlx::query_scalar("DELETE FROM channels WHERE channel_id = $1 RETURNING guild_id")
+                .bind(self.0.get() as i64)
+                .fetch_optional(&mut *transaction)
+                .await?;
+        let Some(guild_id) = guild_id.and_then(|id| Id::new_checked(id as u64)) else {
+            return Ok(false);
+        };
+        let id = repository.urls().channel(guild_id, self.0);
+        bury(&mut transaction, &id, Some("Service"), Utc::now()).await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
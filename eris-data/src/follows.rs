@@ -0,0 +1,183 @@
+use async_trait::async_trait;
+use eris_lib::model::{
+    activity::InboxActivity,
+    follow::{Accept, Follow},
+    urls::{LocalUrl, UrlLayout},
+};
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{
+    activity_log::append_local_activity, parse_url, parse_urls, DataError, Repository,
+    RepositoryRequest,
+};
+
+/// Stores a follow, pending until it is accepted. A follow which is already
+/// stored, accepted or not, is kept, as Follows can be sent again.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveFollow(pub Follow);
+
+impl SaveFollow {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO follows (follower, followed, activity_id, accepted)
+                VALUES ($1, $2, $3, FALSE)
+                ON CONFLICT (follower, followed) DO NOTHING",
+        )
+        .bind(self.0.actor.as_str())
+        .bind(self.0.object.as_str())
+        .bind(self.0.id.as_str())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Marks the follow of an accepted Follow as accepted. Unless the Accept is
+/// the instance's own, the follower must be a local actor, so that other
+/// servers can only accept what was asked of them. Fails with
+/// [DataError::Conflict] if it is not, or if no follow has the Follow's id.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveAccept(pub Follow);
+
+impl SaveAccept {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(
+        self,
+        conn: &mut PgConnection,
+        urls: &UrlLayout,
+    ) -> Result<(), DataError> {
+        let follow = self.0;
+        let is_local_actor = |id: &Url| {
+            matches!(
+                urls.parse(id),
+                Some(LocalUrl::User(_) | LocalUrl::Channel(..))
+            )
+        };
+        if !is_local_actor(&follow.actor) && !is_local_actor(&follow.object) {
+            return Err(DataError::Conflict(
+                "only follows by local actors can be accepted",
+            ));
+        }
+        let result = sqlx::query(
+            "UPDATE follows SET accepted = TRUE
+                WHERE follower = $1 AND followed = $2 AND activity_id = $3",
+        )
+        .bind(follow.actor.as_str())
+        .bind(follow.object.as_str())
+        .bind(follow.id.as_str())
+        .execute(&mut *conn)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Err(DataError::Conflict("no follow has this id"));
+        }
+        Ok(())
+    }
+}
+
+/// Marks a follow as accepted, and appends the Accept to the log. Returns
+/// false if there is no such follow.
+#[derive(Debug, Clone)]
+pub struct AcceptFollow {
+    /// The follower
+    pub follower: Url,
+    /// The actor followed
+    pub followed: Url,
+}
+
+#[async_trait]
+impl RepositoryRequest for AcceptFollow {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let mut transaction = repository.pool().begin().await?;
+        let activity_id: Option<String> = sqlx::query_scalar(
+            "UPDATE follows SET accepted = TRUE WHERE follower = $1 AND followed = $2
+                RETURNING activity_id",
+        )
+        .bind(self.follower.as_str())
+        .bind(self.followed.as_str())
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some(activity_id) = activity_id else {
+            return Ok(false);
+        };
+        let follow = Follow::new(
+            parse_url(&activity_id)?,
+            self.follower,
+            self.followed.clone(),
+        );
+        append_local_activity(&mut transaction, repository.urls(), |id| {
+            InboxActivity::Accept(Accept::new(id, self.followed, follow))
+        })
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
+
+/// Deletes a follow, whether it was accepted or not, such as after an Undo
+/// or a Reject. Returns false if there was no such follow.
+#[derive(Debug, Clone)]
+pub(crate) struct DeleteFollow {
+    /// The follower
+    pub follower: Url,
+    /// The actor followed
+    pub followed: Url,
+}
+
+impl DeleteFollow {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM follows WHERE follower = $1 AND followed = $2")
+            .bind(self.follower.as_str())
+            .bind(self.followed.as_str())
+            .execute(&mut *conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Lists the accepted followers of an actor, oldest first.
+#[derive(Debug, Clone)]
+pub struct GetFollowers(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetFollowers {
+    type Response = Vec<Url>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Url>, DataError> {
+        let followers: Vec<String> = sqlx::query_scalar(
+            "SELECT follower FROM follows
+                WHERE followed = $1 AND accepted
+                ORDER BY created_at",
+        )
+        .bind(self.0.as_str())
+        .fetch_all(repository.pool())
+        .await?;
+        parse_urls(&followers)
+    }
+}
+
+/// Lists the actors an actor follows, once they have accepted, oldest
+/// first.
+#[derive(Debug, Clone)]
+pub struct GetFollowing(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetFollowing {
+    type Response = Vec<Url>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Url>, DataError> {
+        let following: Vec<String> = sqlx::query_scalar(
+            "SELECT followed FROM follows
+                WHERE follower = $1 AND accepted
+                ORDER BY created_at",
+        )
+        .bind(self.0.as_str())
+        .fetch_all(repository.pool())
+        .await?;
+        parse_urls(&following)
+    }
+}
@@ -0,0 +1,143 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::model::foreign_actor::{ActorType, ForeignActor};
+use url::Url;
+
+use crate::{parse_url, tombstones::check_not_deleted, DataError, Repository, RepositoryRequest};
+
+const FOREIGN_ACTOR_COLUMNS: &str = "id, kind, preferred_username, name, url, inbox,
+    shared_inbox, public_key_pem, last_refreshed_at";
+
+/// How an [ActorType] is stored.
+fn actor_type_name(kind: ActorType) -> &'static str {
+    match kind {
+        ActorType::Service => "Service",
+        ActorType::Application => "Application",
+        ActorType::Group => "Group",
+        ActorType::Organization => "Organization",
+        ActorType::Person => "Person",
+    }
+}
+
+fn parse_actor_type(name: &str) -> Result<ActorType, DataError> {
+    match name {
+        "Service" => Ok(ActorType::Service),
+        "Application" => Ok(ActorType::Application),
+        "Group" => Ok(ActorType::Group),
+        "Organization" => Ok(ActorType::Organization),
+        "Person" => Ok(ActorType::Person),
+        _ => Err(DataError::InvalidValue(format!("actor type {name}"))),
+    }
+}
+
+/// A row of the foreign_actors table.
+#[derive(Debug, sqlx::FromRow)]
+struct ForeignActorRow {
+    id: String,
+    kind: String,
+    preferred_username: Option<String>,
+    name: Option<String>,
+    url: Option<String>,
+    inbox: String,
+    shared_inbox: Option<String>,
+    public_key_pem: String,
+    last_refreshed_at: DateTime<Utc>,
+}
+
+impl TryFrom<ForeignActorRow> for ForeignActor {
+    type Error = DataError;
+
+    fn try_from(row: ForeignActorRow) -> Result<Self, Self::Error> {
+        Ok(ForeignActor {
+            id: parse_url(&row.id)?,
+            kind: parse_actor_type(&row.kind)?,
+            preferred_username: row.preferred_username,
+            name: row.name,
+            url: row.url.as_deref().map(parse_url).transpose()?,
+            inbox: parse_url(&row.inbox)?,
+            shared_inbox: row.shared_inbox.as_deref().map(parse_url).transpose()?,
+            public_key_pem: row.public_key_pem,
+            last_refreshed_at: row.last_refreshed_at.naive_utc(),
+        })
+    }
+}
+
+/// Stores a foreign actor, replacing what was stored about it before, such
+/// as after it has been fetched again. Fails with [DataError::Deleted] if
+/// the actor was deleted.
+#[derive(Debug, Clone)]
+pub struct SaveForeignActor(pub ForeignActor);
+
+#[async_trait]
+impl RepositoryRequest for SaveForeignActor {
+    type Response = ();
+
+    async fn execute(self, repository: &Repository) -> Result<(), DataError> {
+        let actor = self.0;
+        let mut conn = repository.pool().acquire().await?;
+        check_not_deleted(&mut conn, &actor.id).await?;
+        sqlx::query(
+            "INSERT INTO foreign_actors (id, kind, preferred_username, name, url, inbox,
+                    shared_inbox, public_key_pem, last_refreshed_at)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+                ON CONFLICT (id) DO UPDATE SET
+                    kind = EXCLUDED.kind,
+                    preferred_username = EXCLUDED.preferred_username,
+                    name = EXCLUDED.name,
+                    url = EXCLUDED.url,
+                    inbox = EXCLUDED.inbox,
+                    shared_inbox = EXCLUDED.shared_inbox,
+                    public_key_pem = EXCLUDED.public_key_pem,
+                    last_refreshed_at = EXCLUDED.last_refreshed_at",
+        )
+        .bind(actor.id.as_str())
+        .bind(actor_type_name(actor.kind))
+        .bind(&actor.preferred_username)
+        .bind(&actor.name)
+        .bind(actor.url.as_ref().map(Url::as_str))
+        .bind(actor.inbox.as_str())
+        .bind(actor.shared_inbox.as_ref().map(Url::as_str))
+        .bind(&actor.public_key_pem)
+        .bind(actor.last_refreshed_at.and_utc())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Finds a foreign actor by its id.
+#[derive(Debug, Clone)]
+pub struct GetForeignActor(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetForeignActor {
+    type Response = Option<ForeignActor>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<ForeignActor>, DataError> {
+        let row: Option<ForeignActorRow> = sqlx::query_as(&format!(
+            "SELECT {FOREIGN_ACTOR_COLUMNS} FROM foreign_actors WHERE id = $1"
+        ))
+        .bind(self.0.as_str())
+        .fetch_optional(repository.pool())
+        .await?;
+        row.map(ForeignActor::try_from).transpose()
+    }
+}
+
+/// Deletes a foreign actor, such as after it deleted itself. Returns false
+/// if it was not stored.
+#[derive(Debug, Clone)]
+pub struct DeleteForeignActor(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for DeleteForeignActor {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM foreign_actors WHERE id = $1")
+            .bind(self.0.as_str())
+            .execute(repository.pool())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
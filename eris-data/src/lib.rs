@@ -1,14 +1,171 @@
-pub fn add(left: usize, right: usize) -> usize {
-    left + right
+#![warn(missing_docs)]
+//! eris-data stores the entities of [eris_lib::model] in Postgres. Each
+//! query is a typed request, and a [Repository] is a [tower::Service] for
+//! every one of them, so that other services can depend on exactly the
+//! queries they use.
+//!
+//! Every accepted activity is appended to the [activity_log], which is the
+//! write model. Follows, likes, shares, blocks and posts are
+//! [projections] of the log, and can be rebuilt from it.
+
+/// The append-only log of accepted activities.
+pub mod activity_log;
+
+/// Blocks between actors.
+pub mod blocks;
+
+/// Discord text channels registered with the instance.
+pub mod channels;
+
+/// Follows between actors.
+pub mod follows;
+
+/// Actors on other servers.
+pub mod foreign_actors;
+
+/// Likes of objects.
+pub mod likes;
+
+/// Discord messages showing posts.
+pub mod messages;
+
+/// A [tower::Service] finding any stored object by its id, for
+/// [eris_lib::model::federation::ErisData].
+pub mod object_store;
+
+/// Posts and their attachments.
+pub mod posts;
+
+/// The current state of follows, likes, shares, blocks and posts, derived
+/// from the activity log.
+pub mod projections;
+
+/// Shares (Announces) of objects.
+pub mod shares;
+
+/// Tombstones of deleted objects.
+pub mod tombstones;
+
+/// Users who have joined the instance.
+pub mod users;
+
+use std::task::{Context, Poll};
+
+use async_trait::async_trait;
+use eris_lib::model::urls::UrlLayout;
+use futures_util::future::BoxFuture;
+use sqlx::{migrate::Migrator, PgPool};
+use thiserror::Error;
+use tower::Service;
+use url::Url;
+
+/// The migrations creating the schema, from the `migrations` directory.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
+
+/// The error that might be returned by a [Repository].
+#[derive(Debug, Error)]
+pub enum DataError {
+    /// The database failed to execute a query
+    #[error("Database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    /// The schema could not be migrated
+    #[error("Migration error: {0}")]
+    MigrateError(#[from] sqlx::migrate::MigrateError),
+    /// The entity conflicts with one already stored, such as a user with a
+    /// handle which is already taken
+    #[error("Conflicts with a stored entity: {0}")]
+    Conflict(&'static str),
+    /// The id is that of a deleted object, which is never used again
+    #[error("Object was deleted: {0}")]
+    Deleted(Url),
+    /// A stored value could not be read, such as a URL which does not parse
+    #[error("Invalid stored value: {0}")]
+    InvalidValue(String),
+}
+
+impl DataError {
+    /// Maps a unique violation to [DataError::Conflict], and anything else
+    /// to [DataError::DatabaseError].
+    pub(crate) fn conflict_on_unique(what: &'static str) -> impl FnOnce(sqlx::Error) -> Self {
+        move |e| match e.as_database_error() {
+            Some(db_error) if db_error.is_unique_violation() => Self::Conflict(what),
+            _ => Self::DatabaseError(e),
+        }
+    }
+}
+
+/// Parses a URL read from the database.
+pub(crate) fn parse_url(value: &str) -> Result<Url, DataError> {
+    Url::parse(value).map_err(|e| DataError::InvalidValue(format!("{value}: {e}")))
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Parses a list of URLs read from the database.
+pub(crate) fn parse_urls(values: &[String]) -> Result<Vec<Url>, DataError> {
+    values.iter().map(|value| parse_url(value)).collect()
+}
+
+/// Converts URLs to strings to be stored.
+pub(crate) fn url_strings(urls: &[Url]) -> Vec<String> {
+    urls.iter().map(Url::to_string).collect()
+}
+
+/// A query or command against the database, which a [Repository] can
+/// execute as a [tower::Service].
+#[async_trait]
+pub trait RepositoryRequest: Send + Sized + 'static {
+    /// What the request returns
+    type Response: Send + 'static;
+
+    /// Executes the request against the repository's database.
+    async fn execute(self, repository: &Repository) -> Result<Self::Response, DataError>;
+}
+
+/// The entities of an instance, stored in Postgres. Call
+/// [Repository::migrate] before using it.
+#[derive(Debug, Clone)]
+pub struct Repository {
+    pool: PgPool,
+    urls: UrlLayout,
+}
+
+impl Repository {
+    /// Creates a repository of the instance with this URL layout, stored in
+    /// the given pool.
+    pub fn new(pool: PgPool, urls: UrlLayout) -> Self {
+        Self { pool, urls }
+    }
+
+    /// Runs any migrations which have not yet been run.
+    pub async fn migrate(&self) -> Result<(), DataError> {
+        MIGRATOR.run(&self.pool).await?;
+        Ok(())
+    }
+
+    /// The pool the repository is stored in.
+    pub fn pool(&self) -> &PgPool {
+        &self.pool
+    }
+
+    /// The URL layout of the instance, used to build the ids of local
+    /// entities.
+    pub fn urls(&self) -> &UrlLayout {
+        &self.urls
+    }
+}
+
+impl<R: RepositoryRequest> Service<R> for Repository {
+    type Response = R::Response;
+
+    type Error = DataError;
+
+    type Future = BoxFuture<'static, Result<R::Response, DataError>>;
+
+    fn poll_ready(&mut self, _cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        Poll::Ready(Ok(()))
+    }
 
-    #[test]
-    fn it_works() {
-        let result = add(2, 2);
-        assert_eq!(result, 4);
+    fn call(&mut self, request: R) -> Self::Future {
+        let repository = self.clone();
+        Box::pin(async move { request.execute(&repository).await })
     }
 }
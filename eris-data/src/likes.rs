@@ -0,0 +1,65 @@
+use async_trait::async_trait;
+use eris_lib::model::like::Like;
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{parse_urls, DataError, Repository, RepositoryRequest};
+
+/// Stores a like. Liking an object again keeps the first like.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveLike(pub Like);
+
+impl SaveLike {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO likes (actor, object, activity_id) VALUES ($1, $2, $3)
+                ON CONFLICT DO NOTHING",
+        )
+        .bind(self.0.actor.as_str())
+        .bind(self.0.object.as_str())
+        .bind(self.0.id.as_str())
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Deletes a like. Returns false if there was no such like.
+#[derive(Debug, Clone)]
+pub(crate) struct DeleteLike {
+    /// The actor who liked the object
+    pub actor: Url,
+    /// The object liked
+    pub object: Url,
+}
+
+impl DeleteLike {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM likes WHERE actor = $1 AND object = $2")
+            .bind(self.actor.as_str())
+            .bind(self.object.as_str())
+            .execute(&mut *conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Lists the actors who liked an object, oldest first.
+#[derive(Debug, Clone)]
+pub struct GetLikes(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetLikes {
+    type Response = Vec<Url>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Url>, DataError> {
+        let actors: Vec<String> =
+            sqlx::query_scalar("SELECT actor FROM likes WHERE object = $1 ORDER BY created_at")
+                .bind(self.0.as_str())
+                .fetch_all(repository.pool())
+                .await?;
+        parse_urls(&actors)
+    }
+}
@@ -0,0 +1,119 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::{model::message::Message, payloads::MessageLocation};
+use twilight_model::id::{marker::MessageMarker, Id};
+use url::Url;
+
+use crate::{parse_url, DataError, Repository, RepositoryRequest};
+
+const MESSAGE_COLUMNS: &str = "message_id, channel_id, guild_id, post_id, sent_at";
+
+/// A row of the messages table.
+#[derive(Debug, sqlx::FromRow)]
+struct MessageRow {
+    message_id: i64,
+    channel_id: i64,
+    guild_id: i64,
+    post_id: String,
+    sent_at: DateTime<Utc>,
+}
+
+impl TryFrom<MessageRow> for Message {
+    type Error = DataError;
+
+    fn try_from(row: MessageRow) -> Result<Self, Self::Error> {
+        Ok(Message {
+            location: MessageLocation {
+                channel_id: Id::new(row.channel_id as u64),
+                message_id: Id::new(row.message_id as u64),
+            },
+            guild_id: Id::new(row.guild_id as u64),
+            post_id: parse_url(&row.post_id)?,
+            sent_at: row.sent_at,
+        })
+    }
+}
+
+/// Stores a message which has just been sent to show a post.
+#[derive(Debug, Clone)]
+pub struct SaveMessage(pub Message);
+
+#[async_trait]
+impl RepositoryRequest for SaveMessage {
+    type Response = ();
+
+    async fn execute(self, repository: &Repository) -> Result<(), DataError> {
+        let message = self.0;
+        sqlx::query(
+            "INSERT INTO messages (message_id, channel_id, guild_id, post_id, sent_at)
+                VALUES ($1, $2, $3, $4, $5)
+                ON CONFLICT (message_id) DO NOTHING",
+        )
+        .bind(message.location.message_id.get() as i64)
+        .bind(message.location.channel_id.get() as i64)
+        .bind(message.guild_id.get() as i64)
+        .bind(message.post_id.as_str())
+        .bind(message.sent_at)
+        .execute(repository.pool())
+        .await?;
+        Ok(())
+    }
+}
+
+/// Lists the messages showing a post, oldest first, such as to update or
+/// delete them along with it.
+#[derive(Debug, Clone)]
+pub struct GetPostMessages(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetPostMessages {
+    type Response = Vec<Message>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Message>, DataError> {
+        let rows: Vec<MessageRow> = sqlx::query_as(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE post_id = $1 ORDER BY sent_at"
+        ))
+        .bind(self.0.as_str())
+        .fetch_all(repository.pool())
+        .await?;
+        rows.into_iter().map(Message::try_from).collect()
+    }
+}
+
+/// Finds the post a message shows, such as when a message command is used
+/// on it.
+#[derive(Debug, Clone, Copy)]
+pub struct GetMessage(pub Id<MessageMarker>);
+
+#[async_trait]
+impl RepositoryRequest for GetMessage {
+    type Response = Option<Message>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Message>, DataError> {
+        let row: Option<MessageRow> = sqlx::query_as(&format!(
+            "SELECT {MESSAGE_COLUMNS} FROM messages WHERE message_id = $1"
+        ))
+        .bind(self.0.get() as i64)
+        .fetch_optional(repository.pool())
+        .await?;
+        row.map(Message::try_from).transpose()
+    }
+}
+
+/// Forgets a message, such as after it was deleted. Returns false if it
+/// was not stored.
+#[derive(Debug, Clone, Copy)]
+pub struct DeleteMessage(pub Id<MessageMarker>);
+
+#[async_trait]
+impl RepositoryRequest for DeleteMessage {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM messages WHERE message_id = $1")
+            .bind(self.0.get() as i64)
+            .execute(repository.pool())
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
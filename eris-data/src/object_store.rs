@@ -0,0 +1,87 @@
+use eris_lib::model::{
+    application::Application,
+    federation::{ModelError, StoredObject},
+    urls::LocalUrl,
+};
+use tower::{service_fn, Service, ServiceExt};
+use url::Url;
+
+use crate::{
+    channels::GetChannel,
+    foreign_actors::GetForeignActor,
+    posts::{Attachment, GetAttachment, GetPost},
+    tombstones::GetTombstone,
+    users::GetUser,
+    DataError, Repository,
+};
+
+impl From<DataError> for ModelError {
+    fn from(e: DataError) -> Self {
+        ModelError::StorageError(Box::new(e))
+    }
+}
+
+async fn find_object(
+    repository: Repository,
+    application: Application,
+    id: Url,
+) -> Result<Option<StoredObject>, DataError> {
+    // A deleted object only leaves its tombstone
+    if let Some(tombstone) = repository.clone().oneshot(GetTombstone(id.clone())).await? {
+        return Ok(Some(StoredObject::Tombstone(tombstone)));
+    }
+
+    let object = match repository.urls().parse(&id) {
+        Some(LocalUrl::Instance) => Some(StoredObject::Application(application)),
+        Some(LocalUrl::User(user_id)) => repository
+            .oneshot(GetUser(user_id))
+            .await?
+            .map(StoredObject::User),
+        // The guild is checked by comparing the whole id
+        Some(LocalUrl::Channel(_, channel_id)) => repository
+            .oneshot(GetChannel(channel_id))
+            .await?
+            .filter(|channel| channel.id == id)
+            .map(StoredObject::Channel),
+        Some(LocalUrl::Post { .. }) => repository
+            .oneshot(GetPost(id))
+            .await?
+            .map(StoredObject::Post),
+        Some(LocalUrl::Image { .. } | LocalUrl::Video { .. }) => repository
+            .oneshot(GetAttachment(id))
+            .await?
+            .map(|attachment| match attachment {
+                Attachment::Image(image) => StoredObject::Image(image),
+                Attachment::Video(video) => StoredObject::Video(video),
+            }),
+        // Activities are not stored here
+        Some(LocalUrl::Activity(_)) => None,
+        None => match repository
+            .clone()
+            .oneshot(GetForeignActor(id.clone()))
+            .await?
+        {
+            Some(actor) => Some(StoredObject::ForeignActor(actor)),
+            None => repository
+                .oneshot(GetPost(id))
+                .await?
+                .map(StoredObject::Post),
+        },
+    };
+    Ok(object)
+}
+
+/// A service which finds any stored object by its id, to be used as the
+/// object store of [eris_lib::model::federation::ErisData]. Deleted objects
+/// are found as their tombstones. The instance's own actor is not stored,
+/// so it is given here.
+pub fn object_store_service(
+    repository: Repository,
+    application: Application,
+) -> impl Service<Url, Response = Option<StoredObject>, Error = ModelError> + Clone {
+    service_fn(move |id: Url| {
+        let repository = repository.clone();
+        let application = application.clone();
+        async move { Ok(find_object(repository, application, id).await?) }
+    })
+}
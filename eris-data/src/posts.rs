@@ -0,0 +1,553 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::model::{
+    activity::InboxActivity,
+    create::Create,
+    delete::{Delete, DeletedObject},
+    image::Image,
+    post::Post,
+    tombstone::Tombstone,
+    update::Update,
+    urls::{LocalUrl, UrlLayout},
+    video::Video,
+};
+use sqlx::{Connection, PgConnection};
+use url::Url;
+
+use crate::{
+    activity_log::append_local_activity,
+    parse_url, parse_urls,
+    tombstones::{bury, check_not_deleted},
+    url_strings, DataError, Repository, RepositoryRequest,
+};
+
+const POST_COLUMNS: &str = "id, ap_id, attributed_to, content, summary, sensitive, url,
+    in_reply_to, published, updated, to_audience, cc_audience";
+
+const ATTACHMENT_COLUMNS: &str = "ap_id, kind, url, media_type, name";
+
+/// A row of the posts table.
+#[derive(Debug, sqlx::FromRow)]
+struct PostRow {
+    id: i64,
+    ap_id: String,
+    attributed_to: String,
+    content: String,
+    summary: Option<String>,
+    sensitive: bool,
+    url: Option<String>,
+    in_reply_to: Option<String>,
+    published: Option<DateTime<Utc>>,
+    updated: Option<DateTime<Utc>>,
+    to_audience: Vec<String>,
+    cc_audience: Vec<String>,
+}
+
+/// A row of the attachments table.
+#[derive(Debug, sqlx::FromRow)]
+struct AttachmentRow {
+    ap_id: String,
+    kind: String,
+    url: String,
+    media_type: Option<String>,
+    name: Option<String>,
+}
+
+/// An image or video attached to a post.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Attachment {
+    /// An image
+    Image(Image),
+    /// A video
+    Video(Video),
+}
+
+impl TryFrom<AttachmentRow> for Attachment {
+    type Error = DataError;
+
+    fn try_from(row: AttachmentRow) -> Result<Self, Self::Error> {
+        let id = parse_url(&row.ap_id)?;
+        let url = parse_url(&row.url)?;
+        match row.kind.as_str() {
+            "image" => Ok(Attachment::Image(Image {
+                kind: Default::default(),
+                id,
+                url,
+                media_type: row.media_type,
+                name: row.name,
+            })),
+            "video" => Ok(Attachment::Video(Video {
+                kind: Default::default(),
+                id,
+                url,
+                media_type: row.media_type,
+                name: row.name,
+            })),
+            kind => Err(DataError::InvalidValue(format!("attachment kind {kind}"))),
+        }
+    }
+}
+
+/// Reads a post and its attachments.
+async fn fetch_post(conn: &mut PgConnection, ap_id: &str) -> Result<Option<Post>, DataError> {
+    let row: Option<PostRow> = sqlx::query_as(&format!(
+        "SELECT {POST_COLUMNS} FROM posts WHERE ap_id = $1"
+    ))
+    .bind(ap_id)
+    .fetch_optional(&mut *conn)
+    .await?;
+    let Some(row) = row else {
+        return Ok(None);
+    };
+
+    let attachments: Vec<AttachmentRow> = sqlx::query_as(&format!(
+        "SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE post_id = $1"
+    ))
+    .bind(row.id)
+    .fetch_all(&mut *conn)
+    .await?;
+
+    let mut post = Post {
+        id: parse_url(&row.ap_id)?,
+        attributed_to: parse_url(&row.attributed_to)?,
+        content: row.content,
+        summary: row.summary,
+        sensitive: row.sensitive,
+        url: row.url.as_deref().map(parse_url).transpose()?,
+        in_reply_to: row.in_reply_to.as_deref().map(parse_url).transpose()?,
+        published: row.published,
+        updated: row.updated,
+        to: parse_urls(&row.to_audience)?,
+        cc: parse_urls(&row.cc_audience)?,
+        image: None,
+        video: None,
+    };
+    for attachment in attachments {
+        match Attachment::try_from(attachment)? {
+            Attachment::Image(image) => post.image = Some(image),
+            Attachment::Video(video) => post.video = Some(video),
+        }
+    }
+    Ok(Some(post))
+}
+
+/// Stores an attachment of a post.
+async fn insert_attachment(
+    conn: &mut PgConnection,
+    post_id: i64,
+    id: Option<i64>,
+    attachment: Attachment,
+) -> Result<(), DataError> {
+    let (kind, ap_id, url, media_type, name) = match attachment {
+        Attachment::Image(image) => ("image", image.id, image.url, image.media_type, image.name),
+        Attachment::Video(video) => ("video", video.id, video.url, video.media_type, video.name),
+    };
+    sqlx::query(
+        "INSERT INTO attachments (id, ap_id, post_id, kind, url, media_type, name)
+            VALUES (COALESCE($1, nextval('attachments_id_seq')), $2, $3, $4, $5, $6, $7)",
+    )
+    .bind(id)
+    .bind(ap_id.as_str())
+    .bind(post_id)
+    .bind(kind)
+    .bind(url.as_str())
+    .bind(media_type)
+    .bind(name)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// A file to attach to a new local post. Eris only stores its URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NewAttachment {
+    /// Where the file is
+    pub url: Url,
+    /// The MIME type of the file
+    pub media_type: Option<String>,
+    /// A description of the file
+    pub name: Option<String>,
+}
+
+/// Stores a new post by a local user, published now, giving it and its
+/// attachments their ids, and appends its Create to the log. Returns the
+/// stored post.
+#[derive(Debug, Clone)]
+pub struct CreateLocalPost {
+    /// The author of the post
+    pub user_id: i64,
+    /// The body of the post, as HTML
+    pub content: String,
+    /// The content warning of the post
+    pub summary: Option<String>,
+    /// Whether the post should be hidden behind its summary
+    pub sensitive: bool,
+    /// The post this one replies to
+    pub in_reply_to: Option<Url>,
+    /// The primary audience of the post
+    pub to: Vec<Url>,
+    /// The secondary audience of the post
+    pub cc: Vec<Url>,
+    /// The image to attach
+    pub image: Option<NewAttachment>,
+    /// The video to attach
+    pub video: Option<NewAttachment>,
+}
+
+#[async_trait]
+impl RepositoryRequest for CreateLocalPost {
+    type Response = Post;
+
+    async fn execute(self, repository: &Repository) -> Result<Post, DataError> {
+        let urls = repository.urls();
+        let mut transaction = repository.pool().begin().await?;
+
+        // The id is needed to build the ActivityPub id stored with the post
+        let post_id: i64 = sqlx::query_scalar("SELECT nextval('posts_id_seq')")
+            .fetch_one(&mut *transaction)
+            .await?;
+        let ap_id = urls.post(self.user_id, post_id);
+        sqlx::query(
+            "INSERT INTO posts (id, ap_id, user_id, attributed_to, content, summary, sensitive,
+                    url, in_reply_to, published, to_audience, cc_audience)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $2, $8, now(), $9, $10)",
+        )
+        .bind(post_id)
+        .bind(ap_id.as_str())
+        .bind(self.user_id)
+        .bind(urls.user(self.user_id).as_str())
+        .bind(&self.content)
+        .bind(&self.summary)
+        .bind(self.sensitive)
+        .bind(self.in_reply_to.as_ref().map(Url::as_str))
+        .bind(url_strings(&self.to))
+        .bind(url_strings(&self.cc))
+        .execute(&mut *transaction)
+        .await?;
+
+        if let Some(image) = self.image {
+            let image_id: i64 = sqlx::query_scalar("SELECT nextval('attachments_id_seq')")
+                .fetch_one(&mut *transaction)
+                .await?;
+            let image = Image {
+                kind: Default::default(),
+                id: urls.image(self.user_id, post_id, image_id),
+                url: image.url,
+                media_type: image.media_type,
+                name: image.name,
+            };
+            insert_attachment(
+                &mut transaction,
+                post_id,
+                Some(image_id),
+                Attachment::Image(image),
+            )
+            .await?;
+        }
+        if let Some(video) = self.video {
+            let video_id: i64 = sqlx::query_scalar("SELECT nextval('attachments_id_seq')")
+                .fetch_one(&mut *transaction)
+                .await?;
+            let video = Video {
+                kind: Default::default(),
+                id: urls.video(self.user_id, post_id, video_id),
+                url: video.url,
+                media_type: video.media_type,
+                name: video.name,
+            };
+            insert_attachment(
+                &mut transaction,
+                post_id,
+                Some(video_id),
+                Attachment::Video(video),
+            )
+            .await?;
+        }
+
+        let post = fetch_post(&mut transaction, ap_id.as_str())
+            .await?
+            .ok_or_else(|| DataError::InvalidValue(format!("{ap_id} was not stored")))?;
+        append_local_activity(&mut transaction, urls, |id| {
+            InboxActivity::Create(Create::new(
+                id,
+                urls.user(self.user_id),
+                post.clone().into(),
+            ))
+        })
+        .await?;
+        transaction.commit().await?;
+        Ok(post)
+    }
+}
+
+/// Stores a post from another server, replacing what was stored about it
+/// before, such as after it has been updated. Fails with
+/// [DataError::Conflict] if the id is that of a local post or of a post
+/// attributed to anyone else, or with [DataError::Deleted] if the post was
+/// deleted.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveForeignPost(pub Post);
+
+impl SaveForeignPost {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<(), DataError> {
+        let post = self.0;
+        let mut transaction = conn.begin().await?;
+        check_not_deleted(&mut transaction, &post.id).await?;
+
+        let post_id: Option<i64> = sqlx::query_scalar(
+            "INSERT INTO posts (ap_id, attributed_to, content, summary, sensitive, url,
+                    in_reply_to, published, updated, to_audience, cc_audience)
+                VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+                ON CONFLICT (ap_id) DO UPDATE SET
+                    content = EXCLUDED.content,
+                    summary = EXCLUDED.summary,
+                    sensitive = EXCLUDED.sensitive,
+                    url = EXCLUDED.url,
+                    in_reply_to = EXCLUDED.in_reply_to,
+                    published = EXCLUDED.published,
+                    updated = EXCLUDED.updated,
+                    to_audience = EXCLUDED.to_audience,
+                    cc_audience = EXCLUDED.cc_audience
+                    WHERE posts.user_id IS NULL
+                        AND posts.attributed_to = EXCLUDED.attributed_to
+                RETURNING id",
+        )
+        .bind(post.id.as_str())
+        .bind(post.attributed_to.as_str())
+        .bind(&post.content)
+        .bind(&post.summary)
+        .bind(post.sensitive)
+        .bind(post.url.as_ref().map(Url::as_str))
+        .bind(post.in_reply_to.as_ref().map(Url::as_str))
+        .bind(post.published)
+        .bind(post.updated)
+        .bind(url_strings(&post.to))
+        .bind(url_strings(&post.cc))
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let post_id = post_id.ok_or(DataError::Conflict("another author's post has this id"))?;
+
+        sqlx::query("DELETE FROM attachments WHERE post_id = $1")
+            .bind(post_id)
+            .execute(&mut *transaction)
+            .await?;
+        if let Some(image) = post.image {
+            insert_attachment(&mut transaction, post_id, None, Attachment::Image(image)).await?;
+        }
+        if let Some(video) = post.video {
+            insert_attachment(&mut transaction, post_id, None, Attachment::Video(video)).await?;
+        }
+
+        transaction.commit().await?;
+        Ok(())
+    }
+}
+
+/// Stores a post by a local user, as it was created or last updated,
+/// keeping the ids it and its attachments were given by [CreateLocalPost].
+/// This is how the projections of the activity log restore local posts.
+/// Returns false if the user no longer exists, in which case nothing is
+/// stored, and fails with [DataError::Deleted] if the post was deleted.
+pub(crate) async fn save_local_post(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    user_id: i64,
+    post_id: i64,
+    post: Post,
+) -> Result<bool, DataError> {
+    let mut transaction = conn.begin().await?;
+    check_not_deleted(&mut transaction, &post.id).await?;
+
+    let stored: Option<i64> = sqlx::query_scalar(
+        "INSERT INTO posts (id, ap_id, user_id, attributed_to, content, summary, sensitive,
+                url, in_reply_to, published, updated, to_audience, cc_audience)
+            SELECT $1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11, $12, $13
+                WHERE EXISTS (SELECT 1 FROM users WHERE id = $3)
+            ON CONFLICT (ap_id) DO UPDATE SET
+                content = EXCLUDED.content,
+                summary = EXCLUDED.summary,
+                sensitive = EXCLUDED.sensitive,
+                updated = EXCLUDED.updated,
+                to_audience = EXCLUDED.to_audience,
+                cc_audience = EXCLUDED.cc_audience
+            RETURNING id",
+    )
+    .bind(post_id)
+    .bind(post.id.as_str())
+    .bind(user_id)
+    .bind(post.attributed_to.as_str())
+    .bind(&post.content)
+    .bind(&post.summary)
+    .bind(post.sensitive)
+    .bind(post.url.as_ref().map(Url::as_str))
+    .bind(post.in_reply_to.as_ref().map(Url::as_str))
+    .bind(post.published)
+    .bind(post.updated)
+    .bind(url_strings(&post.to))
+    .bind(url_strings(&post.cc))
+    .fetch_optional(&mut *transaction)
+    .await?;
+    if stored.is_none() {
+        return Ok(false);
+    }
+
+    sqlx::query("DELETE FROM attachments WHERE post_id = $1")
+        .bind(post_id)
+        .execute(&mut *transaction)
+        .await?;
+    if let Some(image) = post.image {
+        let image_id = match urls.parse(&image.id) {
+            Some(LocalUrl::Image { image_id, .. }) => Some(image_id),
+            _ => None,
+        };
+        insert_attachment(
+            &mut transaction,
+            post_id,
+            image_id,
+            Attachment::Image(image),
+        )
+        .await?;
+    }
+    if let Some(video) = post.video {
+        let video_id = match urls.parse(&video.id) {
+            Some(LocalUrl::Video { video_id, .. }) => Some(video_id),
+            _ => None,
+        };
+        insert_attachment(
+            &mut transaction,
+            post_id,
+            video_id,
+            Attachment::Video(video),
+        )
+        .await?;
+    }
+
+    transaction.commit().await?;
+    Ok(true)
+}
+
+/// Finds a post, local or foreign, by its id.
+#[derive(Debug, Clone)]
+pub struct GetPost(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetPost {
+    type Response = Option<Post>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Post>, DataError> {
+        let mut conn = repository.pool().acquire().await?;
+        fetch_post(&mut conn, self.0.as_str()).await
+    }
+}
+
+/// Edits the text of a post, marking it as updated now, and appends its
+/// Update to the log. Returns the updated post, or None if there is no such
+/// post.
+#[derive(Debug, Clone)]
+pub struct EditPost {
+    /// The post to edit
+    pub id: Url,
+    /// The new body of the post
+    pub content: String,
+    /// The new content warning of the post
+    pub summary: Option<String>,
+    /// Whether the post should now be hidden behind its summary
+    pub sensitive: bool,
+}
+
+#[async_trait]
+impl RepositoryRequest for EditPost {
+    type Response = Option<Post>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Post>, DataError> {
+        let mut transaction = repository.pool().begin().await?;
+        let result = sqlx::query(
+            "UPDATE posts SET content = $2, summary = $3, sensitive = $4, updated = now()
+                WHERE ap_id = $1",
+        )
+        .bind(self.id.as_str())
+        .bind(&self.content)
+        .bind(&self.summary)
+        .bind(self.sensitive)
+        .execute(&mut *transaction)
+        .await?;
+        if result.rows_affected() == 0 {
+            return Ok(None);
+        }
+        let Some(post) = fetch_post(&mut transaction, self.id.as_str()).await? else {
+            return Ok(None);
+        };
+        append_local_activity(&mut transaction, repository.urls(), |id| {
+            InboxActivity::Update(Update::new(
+                id,
+                post.attributed_to.clone(),
+                post.clone().into(),
+            ))
+        })
+        .await?;
+        transaction.commit().await?;
+        Ok(Some(post))
+    }
+}
+
+/// Deletes a post and its attachments, leaving tombstones for them, and
+/// appends its Delete to the log. Returns false if there was no such post.
+#[derive(Debug, Clone)]
+pub struct DeletePost(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for DeletePost {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let deleted = Utc::now();
+        let mut transaction = repository.pool().begin().await?;
+        // Buried first, while the attachments are still there. If there is
+        // no such post, the transaction is rolled back.
+        bury(&mut transaction, &self.0, Some("Note"), deleted).await?;
+        let post: Option<(String, Vec<String>)> = sqlx::query_as(
+            "DELETE FROM posts WHERE ap_id = $1 RETURNING attributed_to, to_audience",
+        )
+        .bind(self.0.as_str())
+        .fetch_optional(&mut *transaction)
+        .await?;
+        let Some((attributed_to, to)) = post else {
+            return Ok(false);
+        };
+        let attributed_to = parse_url(&attributed_to)?;
+        let to = parse_urls(&to)?;
+        append_local_activity(&mut transaction, repository.urls(), |id| {
+            let tombstone = Tombstone::new(self.0.clone(), "Note", deleted);
+            InboxActivity::Delete(Delete::new(
+                id,
+                attributed_to,
+                DeletedObject::Tombstone(tombstone),
+                to,
+            ))
+        })
+        .await?;
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
+
+/// Finds an attachment by its id. Foreign attachments without an id of
+/// their own are found by their URL.
+#[derive(Debug, Clone)]
+pub struct GetAttachment(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetAttachment {
+    type Response = Option<Attachment>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Attachment>, DataError> {
+        let row: Option<AttachmentRow> = sqlx::query_as(&format!(
+            "SELECT {ATTACHMENT_COLUMNS} FROM attachments WHERE ap_id = $1 LIMIT 1"
+        ))
+        .bind(self.0.as_str())
+        .fetch_optional(repository.pool())
+        .await?;
+        row.map(Attachment::try_from).transpose()
+    }
+}
@@ -0,0 +1,335 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::model::{
+    activity::InboxActivity,
+    delete::DeletedObject,
+    post::Post,
+    undo::UndoneActivity,
+    urls::{LocalUrl, UrlLayout},
+};
+use sqlx::{Connection, PgConnection};
+use url::Url;
+
+use crate::{
+    activity_log::{read_activities, LoggedActivity},
+    blocks::{DeleteBlock, SaveBlock},
+    follows::{DeleteFollow, SaveAccept, SaveFollow},
+    likes::{DeleteLike, SaveLike},
+    posts::{save_local_post, SaveForeignPost},
+    shares::{DeleteShare, SaveShare},
+    tombstones::bury,
+    DataError, Repository, RepositoryRequest,
+};
+
+/// How many activities are read from the log at a time.
+const BATCH_SIZE: i64 = 500;
+
+/// The tables which only hold projections of the log. Attachments are
+/// deleted along with their posts. Tombstones are kept, so that deleted
+/// posts are not restored.
+const PROJECTED_TABLES: [&str; 5] = ["follows", "likes", "shares", "blocks", "posts"];
+
+/// The type of a stored object, to keep in its tombstone.
+async fn stored_type(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    id: &Url,
+) -> Result<Option<String>, DataError> {
+    let stored: Option<String> = sqlx::query_scalar(
+        "SELECT 'Note' FROM posts WHERE ap_id = $1
+            UNION ALL
+            SELECT kind FROM foreign_actors WHERE id = $1
+            LIMIT 1",
+    )
+    .bind(id.as_str())
+    .fetch_optional(&mut *conn)
+    .await?;
+    let local = match urls.parse(id) {
+        Some(LocalUrl::User(_)) => Some("Person"),
+        Some(LocalUrl::Channel(..)) => Some("Service"),
+        Some(LocalUrl::Post { .. }) => Some("Note"),
+        Some(LocalUrl::Image { .. }) => Some("Image"),
+        Some(LocalUrl::Video { .. }) => Some("Video"),
+        _ => None,
+    };
+    Ok(stored.or(local.map(str::to_string)))
+}
+
+/// The author of a stored post, or None if no post has this id.
+async fn stored_author(conn: &mut PgConnection, id: &Url) -> Result<Option<String>, DataError> {
+    let author = sqlx::query_scalar("SELECT attributed_to FROM posts WHERE ap_id = $1")
+        .bind(id.as_str())
+        .fetch_optional(&mut *conn)
+        .await?;
+    Ok(author)
+}
+
+/// Deletes an object deleted by an actor, and everything relating to it,
+/// leaving tombstones. If the object is the actor, that is its posts,
+/// follows, likes, shares and blocks. Any other object must be one of the
+/// actor's posts: fails with [DataError::Conflict] if it is anyone else's,
+/// and does nothing if no post has its id.
+async fn delete_object(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    actor: &Url,
+    object: DeletedObject,
+    recorded_at: DateTime<Utc>,
+) -> Result<(), DataError> {
+    let (id, former_type, deleted) = match object {
+        DeletedObject::Id(id) => (id, None, recorded_at),
+        DeletedObject::Tombstone(tombstone) => (
+            tombstone.id,
+            tombstone.former_type,
+            tombstone.deleted.unwrap_or(recorded_at),
+        ),
+    };
+    if id != *actor {
+        match stored_author(conn, &id).await? {
+            Some(author) if author == actor.as_str() => {}
+            Some(_) => return Err(DataError::Conflict("only its author can delete a post")),
+            None => return Ok(()),
+        }
+    }
+    let former_type = match former_type {
+        Some(former_type) => Some(former_type),
+        None => stored_type(conn, urls, &id).await?,
+    };
+    bury(conn, &id, former_type.as_deref(), deleted).await?;
+
+    let statements = [
+        "DELETE FROM foreign_actors WHERE id = $1",
+        "DELETE FROM posts WHERE ap_id = $1 OR attributed_to = $1",
+        "DELETE FROM follows WHERE follower = $1 OR followed = $1",
+        "DELETE FROM likes WHERE actor = $1 OR object = $1",
+        "DELETE FROM shares WHERE actor = $1 OR object = $1",
+        "DELETE FROM blocks WHERE actor = $1 OR blocked = $1",
+    ];
+    for statement in statements {
+        sqlx::query(statement)
+            .bind(id.as_str())
+            .execute(&mut *conn)
+            .await?;
+    }
+    Ok(())
+}
+
+/// Stores a created or updated post, keeping the ids of local posts. A
+/// post which was deleted stays deleted. Fails with [DataError::Conflict]
+/// if the id is that of a local post, but the post is attributed to anyone
+/// else.
+async fn save_post(conn: &mut PgConnection, urls: &UrlLayout, post: Post) -> Result<(), DataError> {
+    let saved = match urls.parse(&post.id) {
+        Some(LocalUrl::Post { user_id, .. }) if post.attributed_to != urls.user(user_id) => {
+            Err(DataError::Conflict("a local post has this id"))
+        }
+        Some(LocalUrl::Post { user_id, post_id }) => {
+            save_local_post(conn, urls, user_id, post_id, post)
+                .await
+                .map(drop)
+        }
+        _ => SaveForeignPost(post).execute_on(conn).await,
+    };
+    match saved {
+        Err(DataError::Deleted(_)) => Ok(()),
+        saved => saved,
+    }
+}
+
+/// Applies an activity to the projections.
+async fn project(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    logged: LoggedActivity,
+) -> Result<(), DataError> {
+    match logged.activity {
+        InboxActivity::Create(create) => save_post(conn, urls, create.object.into()).await,
+        InboxActivity::Update(update) => save_post(conn, urls, update.object.into()).await,
+        InboxActivity::Delete(delete) => {
+            delete_object(conn, urls, &delete.actor, delete.object, logged.recorded_at).await
+        }
+        InboxActivity::Follow(follow) => SaveFollow(follow).execute_on(conn).await,
+        InboxActivity::Accept(accept) => SaveAccept(accept.object).execute_on(conn, urls).await,
+        InboxActivity::Reject(reject) => {
+            let follow = reject.object;
+            DeleteFollow {
+                follower: follow.actor,
+                followed: follow.object,
+            }
+            .execute_on(conn)
+            .await?;
+            Ok(())
+        }
+        InboxActivity::Undo(undo) => {
+            match undo.object {
+                UndoneActivity::Follow(follow) => {
+                    DeleteFollow {
+                        follower: follow.actor,
+                        followed: follow.object,
+                    }
+                    .execute_on(conn)
+                    .await?
+                }
+                UndoneActivity::Like(like) => {
+                    DeleteLike {
+                        actor: like.actor,
+                        object: like.object,
+                    }
+                    .execute_on(conn)
+                    .await?
+                }
+                UndoneActivity::Announce(announce) => {
+                    DeleteShare {
+                        actor: announce.actor,
+                        object: announce.object,
+                    }
+                    .execute_on(conn)
+                    .await?
+                }
+                UndoneActivity::Block(block) => {
+                    DeleteBlock {
+                        actor: block.actor,
+                        blocked: block.object,
+                    }
+                    .execute_on(conn)
+                    .await?
+                }
+            };
+            Ok(())
+        }
+        InboxActivity::Like(like) => SaveLike(like).execute_on(conn).await,
+        InboxActivity::Announce(announce) => SaveShare(announce).execute_on(conn).await,
+        InboxActivity::Block(block) => SaveBlock(block).execute_on(conn).await,
+    }
+}
+
+/// Whether an error applying an activity would happen again, so that the
+/// activity should be skipped rather than retried. Losing the connection,
+/// deadlocks and serialization failures are transient.
+fn is_permanent(error: &DataError) -> bool {
+    match error {
+        DataError::DatabaseError(sqlx::Error::Database(db_error)) => {
+            !db_error.code().is_some_and(|code| code.starts_with("40"))
+        }
+        DataError::DatabaseError(_) | DataError::MigrateError(_) => false,
+        DataError::Conflict(_) | DataError::Deleted(_) | DataError::InvalidValue(_) => true,
+    }
+}
+
+/// Applies the next batch of activities after `position`. Each activity is
+/// applied within a savepoint, and one which cannot be applied is logged and
+/// skipped, so that it does not stop the projections from advancing.
+/// Returns the position of the last activity read and how many were
+/// applied, or None if there were none to apply.
+async fn project_batch(
+    conn: &mut PgConnection,
+    urls: &UrlLayout,
+    position: i64,
+) -> Result<Option<(i64, u64)>, DataError> {
+    let batch = read_activities(conn, position, BATCH_SIZE).await?;
+    let Some(last) = batch.last().map(|logged| logged.sequence) else {
+        return Ok(None);
+    };
+    let mut applied = 0;
+    for logged in batch {
+        let sequence = logged.sequence;
+        let mut savepoint = conn.begin().await?;
+        match project(&mut savepoint, urls, logged).await {
+            Ok(()) => {
+                savepoint.commit().await?;
+                applied += 1;
+            }
+            Err(e) if is_permanent(&e) => {
+                savepoint.rollback().await?;
+                tracing::error!("Skipped activity {sequence} of the log: {e}");
+            }
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(Some((last, applied)))
+}
+
+/// Locks the position of the projections until the end of the
+/// transaction, so that only one catch up or rebuild runs at a time, and
+/// returns it.
+async fn lock_position(conn: &mut PgConnection) -> Result<i64, DataError> {
+    let position = sqlx::query_scalar("SELECT sequence FROM projection_position FOR UPDATE")
+        .fetch_one(&mut *conn)
+        .await?;
+    Ok(position)
+}
+
+/// Saves how far the projections have applied the log.
+async fn save_position(conn: &mut PgConnection, position: i64) -> Result<(), DataError> {
+    sqlx::query("UPDATE projection_position SET sequence = $1")
+        .bind(position)
+        .execute(&mut *conn)
+        .await?;
+    Ok(())
+}
+
+/// Applies the activities appended to the log since the projections were
+/// last brought up to date, in batches which are each committed along with
+/// the new position of the projections. Activities which cannot be applied
+/// are skipped. Returns how many activities were applied.
+#[derive(Debug, Clone, Copy)]
+pub struct CatchUpProjections;
+
+#[async_trait]
+impl RepositoryRequest for CatchUpProjections {
+    type Response = u64;
+
+    async fn execute(self, repository: &Repository) -> Result<u64, DataError> {
+        let mut conn = repository.pool().acquire().await?;
+        let mut applied = 0;
+        loop {
+            let mut transaction = conn.begin().await?;
+            let position = lock_position(&mut transaction).await?;
+            let Some((position, batch_applied)) =
+                project_batch(&mut transaction, repository.urls(), position).await?
+            else {
+                return Ok(applied);
+            };
+            save_position(&mut transaction, position).await?;
+            transaction.commit().await?;
+            applied += batch_applied;
+        }
+    }
+}
+
+/// Empties the projections and applies the whole log to them again, in a
+/// single transaction, such as to recover from a corrupted or restored
+/// database. Local writes append their activities to the log, so they are
+/// kept. Posts which were fetched rather than received in an activity are
+/// not, and will be fetched again when needed. Returns how many activities
+/// were applied.
+#[derive(Debug, Clone, Copy)]
+pub struct RebuildProjections;
+
+#[async_trait]
+impl RepositoryRequest for RebuildProjections {
+    type Response = u64;
+
+    async fn execute(self, repository: &Repository) -> Result<u64, DataError> {
+        let mut conn = repository.pool().acquire().await?;
+        let mut transaction = conn.begin().await?;
+        lock_position(&mut transaction).await?;
+        for table in PROJECTED_TABLES {
+            sqlx::query(&format!("DELETE FROM {table}"))
+                .execute(&mut *transaction)
+                .await?;
+        }
+
+        let mut position = 0;
+        let mut applied = 0;
+        while let Some((last, batch_applied)) =
+            project_batch(&mut transaction, repository.urls(), position).await?
+        {
+            position = last;
+            applied += batch_applied;
+        }
+        save_position(&mut transaction, position).await?;
+        transaction.commit().await?;
+        Ok(applied)
+    }
+}
@@ -0,0 +1,67 @@
+use async_trait::async_trait;
+use eris_lib::model::announce::Announce;
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{parse_urls, DataError, Repository, RepositoryRequest};
+
+/// Stores a share. Sharing an object again keeps the first share.
+#[derive(Debug, Clone)]
+pub(crate) struct SaveShare(pub Announce);
+
+impl SaveShare {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO shares (actor, object, activity_id, published)
+                VALUES ($1, $2, $3, COALESCE($4, now()))
+                ON CONFLICT DO NOTHING",
+        )
+        .bind(self.0.actor.as_str())
+        .bind(self.0.object.as_str())
+        .bind(self.0.id.as_str())
+        .bind(self.0.published)
+        .execute(&mut *conn)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Deletes a share. Returns false if there was no such share.
+#[derive(Debug, Clone)]
+pub(crate) struct DeleteShare {
+    /// The actor who shared the object
+    pub actor: Url,
+    /// The object shared
+    pub object: Url,
+}
+
+impl DeleteShare {
+    /// Executes the request on a connection, such as within a transaction.
+    pub(crate) async fn execute_on(self, conn: &mut PgConnection) -> Result<bool, DataError> {
+        let result = sqlx::query("DELETE FROM shares WHERE actor = $1 AND object = $2")
+            .bind(self.actor.as_str())
+            .bind(self.object.as_str())
+            .execute(&mut *conn)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+}
+
+/// Lists the actors who shared an object, oldest first.
+#[derive(Debug, Clone)]
+pub struct GetShares(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetShares {
+    type Response = Vec<Url>;
+
+    async fn execute(self, repository: &Repository) -> Result<Vec<Url>, DataError> {
+        let actors: Vec<String> =
+            sqlx::query_scalar("SELECT actor FROM shares WHERE object = $1 ORDER BY published")
+                .bind(self.0.as_str())
+                .fetch_all(repository.pool())
+                .await?;
+        parse_urls(&actors)
+    }
+}
@@ -0,0 +1,105 @@
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use eris_lib::model::tombstone::Tombstone;
+use sqlx::PgConnection;
+use url::Url;
+
+use crate::{parse_url, DataError, Repository, RepositoryRequest};
+
+/// Fails with [DataError::Deleted] if the id is that of a deleted object,
+/// so that it is never used again.
+pub(crate) async fn check_not_deleted(conn: &mut PgConnection, id: &Url) -> Result<(), DataError> {
+    let deleted: bool =
+        sqlx::query_scalar("SELECT EXISTS (SELECT 1 FROM tombstones WHERE id = $1)")
+            .bind(id.as_str())
+            .fetch_one(&mut *conn)
+            .await?;
+    if deleted {
+        return Err(DataError::Deleted(id.clone()));
+    }
+    Ok(())
+}
+
+/// Stores the tombstones of an object which is about to be deleted, and of
+/// the posts and attachments deleted along with it: the attachments of a
+/// post, or the posts and attachments of an actor. Objects which already
+/// have a tombstone keep it.
+pub(crate) async fn bury(
+    conn: &mut PgConnection,
+    id: &Url,
+    former_type: Option<&str>,
+    deleted: DateTime<Utc>,
+) -> Result<(), DataError> {
+    sqlx::query(
+        "INSERT INTO tombstones (id, former_type, deleted) VALUES ($1, $2, $3)
+            ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(id.as_str())
+    .bind(former_type)
+    .bind(deleted)
+    .execute(&mut *conn)
+    .await?;
+    sqlx::query(
+        "INSERT INTO tombstones (id, former_type, deleted)
+            SELECT ap_id, 'Note', $2 FROM posts WHERE attributed_to = $1
+            UNION ALL
+            SELECT attachments.ap_id, initcap(attachments.kind), $2
+                FROM attachments JOIN posts ON posts.id = attachments.post_id
+                WHERE posts.ap_id = $1 OR posts.attributed_to = $1
+            ON CONFLICT (id) DO NOTHING",
+    )
+    .bind(id.as_str())
+    .bind(deleted)
+    .execute(&mut *conn)
+    .await?;
+    Ok(())
+}
+
+/// Stores the tombstone of a deleted object. If the object was already
+/// deleted, the first tombstone is kept.
+#[derive(Debug, Clone)]
+pub struct SaveTombstone(pub Tombstone);
+
+#[async_trait]
+impl RepositoryRequest for SaveTombstone {
+    type Response = ();
+
+    async fn execute(self, repository: &Repository) -> Result<(), DataError> {
+        sqlx::query(
+            "INSERT INTO tombstones (id, former_type, deleted) VALUES ($1, $2, $3)
+                ON CONFLICT (id) DO NOTHING",
+        )
+        .bind(self.0.id.as_str())
+        .bind(&self.0.former_type)
+        .bind(self.0.deleted)
+        .execute(repository.pool())
+        .await?;
+        Ok(())
+    }
+}
+
+/// Finds the tombstone of a deleted object, by the object's id.
+#[derive(Debug, Clone)]
+pub struct GetTombstone(pub Url);
+
+#[async_trait]
+impl RepositoryRequest for GetTombstone {
+    type Response = Option<Tombstone>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<Tombstone>, DataError> {
+        let row: Option<(String, Option<String>, Option<DateTime<Utc>>)> =
+            sqlx::query_as("SELECT id, former_type, deleted FROM tombstones WHERE id = $1")
+                .bind(self.0.as_str())
+                .fetch_optional(repository.pool())
+                .await?;
+        row.map(|(id, former_type, deleted)| {
+            Ok(Tombstone {
+                kind: Default::default(),
+                id: parse_url(&id)?,
+                former_type,
+                deleted,
+            })
+        })
+        .transpose()
+    }
+}
@@ -0,0 +1,198 @@
+use async_trait::async_trait;
+use chrono::Utc;
+use eris_lib::model::{urls::UrlLayout, user::User};
+use twilight_model::id::{marker::UserMarker, Id};
+
+use crate::{tombstones::bury, DataError, Repository, RepositoryRequest};
+
+const USER_COLUMNS: &str = "id, discord_user_id, handle, display_name, summary,
+    manually_approves_followers, public_key_pem, private_key_pem";
+
+/// A row of the users table.
+#[derive(Debug, sqlx::FromRow)]
+struct UserRow {
+    id: i64,
+    discord_user_id: i64,
+    handle: String,
+    display_name: Option<String>,
+    summary: Option<String>,
+    manually_approves_followers: bool,
+    public_key_pem: String,
+    private_key_pem: String,
+}
+
+impl UserRow {
+    fn into_user(self, urls: &UrlLayout) -> User {
+        User {
+            id: urls.user(self.id),
+            discord_user_id: Id::new(self.discord_user_id as u64),
+            handle: self.handle,
+            display_name: self.display_name,
+            summary: self.summary,
+            manually_approves_followers: self.manually_approves_followers,
+            inbox: urls.user_collection(self.id, "inbox"),
+            outbox: urls.user_collection(self.id, "outbox"),
+            followers: urls.user_collection(self.id, "followers"),
+            following: urls.user_collection(self.id, "following"),
+            liked: urls.user_collection(self.id, "liked"),
+            shared_inbox: urls.shared_inbox(),
+            public_key_pem: self.public_key_pem,
+            private_key_pem: Some(self.private_key_pem),
+        }
+    }
+}
+
+/// Stores a Discord user who has just joined. Fails with
+/// [DataError::Conflict] if they have already joined, or their handle is
+/// taken, ignoring case.
+#[derive(Debug, Clone)]
+pub struct CreateUser {
+    /// The Discord user joining
+    pub discord_user_id: Id<UserMarker>,
+    /// The handle they picked
+    pub handle: String,
+    /// The public key of their new actor
+    pub public_key_pem: String,
+    /// The private key of their new actor
+    pub private_key_pem: String,
+}
+
+#[async_trait]
+impl RepositoryRequest for CreateUser {
+    type Response = User;
+
+    async fn execute(self, repository: &Repository) -> Result<User, DataError> {
+        let row: UserRow = sqlx::query_as(&format!(
+            "INSERT INTO users (discord_user_id, handle, public_key_pem, private_key_pem)
+                VALUES ($1, $2, $3, $4)
+                RETURNING {USER_COLUMNS}"
+        ))
+        .bind(self.discord_user_id.get() as i64)
+        .bind(&self.handle)
+        .bind(&self.public_key_pem)
+        .bind(&self.private_key_pem)
+        .fetch_one(repository.pool())
+        .await
+        .map_err(DataError::conflict_on_unique(
+            "user or handle already exists",
+        ))?;
+        Ok(row.into_user(repository.urls()))
+    }
+}
+
+/// Finds a user by their id.
+#[derive(Debug, Clone, Copy)]
+pub struct GetUser(pub i64);
+
+#[async_trait]
+impl RepositoryRequest for GetUser {
+    type Response = Option<User>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<User>, DataError> {
+        let row: Option<UserRow> =
+            sqlx::query_as(&format!("SELECT {USER_COLUMNS} FROM users WHERE id = $1"))
+                .bind(self.0)
+                .fetch_optional(repository.pool())
+                .await?;
+        Ok(row.map(|row| row.into_user(repository.urls())))
+    }
+}
+
+/// Finds the user a Discord user joined as.
+#[derive(Debug, Clone, Copy)]
+pub struct GetUserByDiscordId(pub Id<UserMarker>);
+
+#[async_trait]
+impl RepositoryRequest for GetUserByDiscordId {
+    type Response = Option<User>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<User>, DataError> {
+        let row: Option<UserRow> = sqlx::query_as(&format!(
+            "SELECT {USER_COLUMNS} FROM users WHERE discord_user_id = $1"
+        ))
+        .bind(self.0.get() as i64)
+        .fetch_optional(repository.pool())
+        .await?;
+        Ok(row.map(|row| row.into_user(repository.urls())))
+    }
+}
+
+/// Finds a user by their handle, ignoring case, such as for WebFinger.
+#[derive(Debug, Clone)]
+pub struct GetUserByHandle(pub String);
+
+#[async_trait]
+impl RepositoryRequest for GetUserByHandle {
+    type Response = Option<User>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<User>, DataError> {
+        let row: Option<UserRow> = sqlx::query_as(&format!(
+            "SELECT {USER_COLUMNS} FROM users WHERE lower(handle) = lower($1)"
+        ))
+        .bind(&self.0)
+        .fetch_optional(repository.pool())
+        .await?;
+        Ok(row.map(|row| row.into_user(repository.urls())))
+    }
+}
+
+/// Changes the profile of a user. Returns the updated user, or None if
+/// there is no such user.
+#[derive(Debug, Clone)]
+pub struct UpdateProfile {
+    /// The user to update
+    pub user_id: i64,
+    /// The new name shown for the user
+    pub display_name: Option<String>,
+    /// The new profile of the user
+    pub summary: Option<String>,
+    /// Whether the user now approves follow requests themselves
+    pub manually_approves_followers: bool,
+}
+
+#[async_trait]
+impl RepositoryRequest for UpdateProfile {
+    type Response = Option<User>;
+
+    async fn execute(self, repository: &Repository) -> Result<Option<User>, DataError> {
+        let row: Option<UserRow> = sqlx::query_as(&format!(
+            "UPDATE users SET display_name = $2, summary = $3, manually_approves_followers = $4
+                WHERE id = $1
+                RETURNING {USER_COLUMNS}"
+        ))
+        .bind(self.user_id)
+        .bind(&self.display_name)
+        .bind(&self.summary)
+        .bind(self.manually_approves_followers)
+        .fetch_optional(repository.pool())
+        .await?;
+        Ok(row.map(|row| row.into_user(repository.urls())))
+    }
+}
+
+/// Deletes a user, along with their posts, leaving tombstones for them and
+/// their posts and attachments. Returns false if there was no such user.
+#[derive(Debug, Clone, Copy)]
+pub struct DeleteUser(pub i64);
+
+#[async_trait]
+impl RepositoryRequest for DeleteUser {
+    type Response = bool;
+
+    async fn execute(self, repository: &Repository) -> Result<bool, DataError> {
+        let mut transaction = repository.pool().begin().await?;
+        // Buried first, while the user's posts are still there. If there is
+        // no such user, the transaction is rolled back.
+        let id = repository.urls().user(self.0);
+        bury(&mut transaction, &id, Some("Person"), Utc::now()).await?;
+        let result = sqlx::query("DELETE FROM users WHERE id = $1")
+            .bind(self.0)
+            .execute(&mut *transaction)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Ok(false);
+        }
+        transaction.commit().await?;
+        Ok(true)
+    }
+}
@@ -0,0 +1,45 @@
+//! The database fixture shared by the tests which need Postgres. Each test
+//! creates its own database, so tests can run in parallel.
+
+#![allow(dead_code)]
+
+use std::str::FromStr;
+
+use eris_data::{Repository, MIGRATOR};
+use eris_lib::model::urls::UrlLayout;
+use sqlx::{postgres::PgConnectOptions, PgPool};
+
+/// Connects to a new, empty database named after the test, with every
+/// migration run. Panics if DATABASE_URL is not set.
+pub async fn pool(test_name: &str) -> PgPool {
+    let database_url = std::env::var("DATABASE_URL")
+        .expect("DATABASE_URL should be set to run the tests which need Postgres");
+    let database = format!("eris_data_test_{test_name}");
+
+    let admin = PgPool::connect(&database_url).await.unwrap();
+    sqlx::query(&format!("DROP DATABASE IF EXISTS {database} WITH (FORCE)"))
+        .execute(&admin)
+        .await
+        .unwrap();
+    sqlx::query(&format!("CREATE DATABASE {database}"))
+        .execute(&admin)
+        .await
+        .unwrap();
+    admin.close().await;
+
+    let options = PgConnectOptions::from_str(&database_url)
+        .unwrap()
+        .database(&database);
+    let pool = PgPool::connect_with(options).await.unwrap();
+    MIGRATOR.run(&pool).await.unwrap();
+    pool
+}
+
+/// A repository of local urls on `eris.example`, in a new database named
+/// after the test.
+pub async fn repository(test_name: &str) -> Repository {
+    Repository::new(
+        pool(test_name).await,
+        UrlLayout::new("eris.example").unwrap(),
+    )
+}
@@ -0,0 +1,175 @@
+//! Runs the durable queues of eris_lib against a local Postgres migrated
+//! with the eris-data migrations, given by DATABASE_URL. Run them with
+//! `cargo test -- --ignored`.
+
+mod common;
+
+use std::{
+    sync::{Arc, Mutex},
+    time::{Duration, SystemTime},
+};
+
+use common::pool;
+use eris_lib::{
+    payloads::{
+        DeleteMessage, DiscordClientAction, DiscordServerAction, MessageLocation, PostAction,
+    },
+    services::{
+        dead_letter_queue::{DeadLetter, DeadLetterErrorKind, DeadLetterQueue, ReplayError},
+        postgres_queue::{postgres_queue, subscribe_to_postgres_queue},
+    },
+};
+use tower::{service_fn, ServiceExt};
+use twilight_model::{application::interaction::Interaction, id::Id};
+
+fn component_interaction() -> Interaction {
+    serde_json::from_value(serde_json::json!({
+        "id": "1",
+        "application_id": "2",
+        "type": 3,
+        "token": "token",
+        "version": 1,
+        "channel_id": "3",
+        "guild_id": "4",
+        "data": { "custom_id": "follow_author", "component_type": 2 },
+    }))
+    .unwrap()
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn server_actions_are_queued_and_malformed_items_deleted() {
+    let pool = pool("queues").await;
+    // Queued by an older version, and never deliverable
+    sqlx::query("INSERT INTO eris_queue_items (queue, payload) VALUES ('server', '{\"old\": 1}')")
+        .execute(&pool)
+        .await
+        .unwrap();
+
+    let (queue, subscription) = postgres_queue::<DiscordServerAction>(pool.clone(), "server");
+    let received = Arc::new(Mutex::new(Vec::new()));
+    let recorded = received.clone();
+    let service = service_fn(move |action: DiscordServerAction| {
+        recorded.lock().unwrap().push(action);
+        async { Ok::<_, std::convert::Infallible>(()) }
+    });
+    subscribe_to_postgres_queue(service, subscription)
+        .await
+        .unwrap();
+    queue
+        .oneshot(DiscordServerAction::PostComponentInteraction {
+            interaction: component_interaction(),
+            action: PostAction::FollowAuthor,
+        })
+        .await
+        .unwrap();
+
+    for _ in 0..50 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    let received = received.lock().unwrap().clone();
+    assert!(matches!(
+        received.as_slice(),
+        [DiscordServerAction::PostComponentInteraction {
+            interaction,
+            action: PostAction::FollowAuthor,
+        }] if interaction.token == "token"
+    ));
+
+    let remaining: i64 = sqlx::query_scalar("SELECT count(*) FROM eris_queue_items")
+        .fetch_one(&pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining, 0);
+}
+
+fn dead_letter() -> DeadLetter {
+    DeadLetter {
+        action: DiscordClientAction::DeleteMessage(DeleteMessage {
+            message_location: MessageLocation {
+                channel_id: Id::new(1),
+                message_id: Id::new(2),
+            },
+            reason: None,
+        }),
+        error_kind: DeadLetterErrorKind::Status { status: 403 },
+        error: "Missing Permissions".to_string(),
+        attempts: 1,
+        failed_at: SystemTime::now(),
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn dead_letters_are_stored_and_discarded() {
+    let pool = pool("dead_letters").await;
+    let dead_letters = DeadLetterQueue::new(pool);
+    let dead_letter = dead_letter();
+    dead_letters
+        .clone()
+        .oneshot(dead_letter.clone())
+        .await
+        .unwrap();
+
+    let listed = dead_letters.list(None, 10).await.unwrap();
+    assert_eq!(listed.len(), 1);
+    let (id, stored) = &listed[0];
+    assert_eq!(stored.action, dead_letter.action);
+    assert_eq!(stored.error_kind, dead_letter.error_kind);
+    assert!(dead_letters.discard(*id).await.unwrap());
+    assert!(dead_letters.inspect(*id).await.unwrap().is_none());
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn dead_letters_are_replayed_once() {
+    let pool = pool("replays").await;
+    let dead_letters = DeadLetterQueue::new(pool);
+    let dead_letter = dead_letter();
+    dead_letters
+        .clone()
+        .oneshot(dead_letter.clone())
+        .await
+        .unwrap();
+    let id = dead_letters.list(None, 10).await.unwrap()[0].0;
+
+    // Kept under its id if the service fails
+    let failed = dead_letters
+        .replay(
+            id,
+            service_fn(|_| async { Err::<(), _>("Discord is down".to_string()) }),
+        )
+        .await;
+    assert!(matches!(failed, Err(ReplayError::ServiceError(_))));
+    assert!(dead_letters.inspect(id).await.unwrap().is_some());
+
+    // Not found by another replay while it is being replayed
+    let replayed = Arc::new(Mutex::new(Vec::new()));
+    let service = service_fn({
+        let dead_letters = dead_letters.clone();
+        let replayed = replayed.clone();
+        move |action: DiscordClientAction| {
+            let dead_letters = dead_letters.clone();
+            let replayed = replayed.clone();
+            async move {
+                let concurrent = dead_letters
+                    .replay(id, service_fn(|_| async { Ok::<_, String>(()) }))
+                    .await;
+                assert!(matches!(concurrent, Err(ReplayError::NotFound(_))));
+                replayed.lock().unwrap().push(action);
+                Ok::<_, String>(())
+            }
+        }
+    });
+    dead_letters.replay(id, service).await.unwrap();
+    assert_eq!(*replayed.lock().unwrap(), vec![dead_letter.action]);
+    assert!(dead_letters.inspect(id).await.unwrap().is_none());
+
+    let again = dead_letters
+        .replay(id, service_fn(|_| async { Ok::<_, String>(()) }))
+        .await;
+    assert!(matches!(again, Err(ReplayError::NotFound(_))));
+}
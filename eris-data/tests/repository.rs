@@ -0,0 +1,1304 @@
+//! Runs the repository requests against a local Postgres, given by
+//! DATABASE_URL, such as `postgres://postgres@localhost/postgres`. Run them
+//! with `cargo test -- --ignored`.
+
+mod common;
+
+use common::repository;
+use eris_data::{
+    activity_log::{deduplicate_service, record_service, AppendActivity, ReadActivities},
+    blocks::{GetBlocked, IsBlocked},
+    channels::{DeleteChannel, GetChannel, GetGuildChannels, RegisterChannel},
+    follows::{AcceptFollow, GetFollowers, GetFollowing},
+    foreign_actors::{DeleteForeignActor, GetForeignActor, SaveForeignActor},
+    likes::GetLikes,
+    messages::{DeleteMessage, GetMessage, GetPostMessages, SaveMessage},
+    object_store::object_store_service,
+    posts::{
+        Attachment, CreateLocalPost, DeletePost, EditPost, GetAttachment, GetPost, NewAttachment,
+    },
+    projections::{CatchUpProjections, RebuildProjections},
+    shares::GetShares,
+    tombstones::{GetTombstone, SaveTombstone},
+    users::{CreateUser, DeleteUser, GetUser, GetUserByDiscordId, GetUserByHandle, UpdateProfile},
+    DataError, Repository,
+};
+use eris_lib::{
+    model::{
+        activity::InboxActivity,
+        announce::Announce,
+        application::Application,
+        block::Block,
+        create::Create,
+        delete::{Delete, DeletedObject},
+        federation::StoredObject,
+        follow::{Accept, Follow},
+        foreign_actor::{ActorType, ForeignActor},
+        image::Image,
+        like::Like,
+        message::Message,
+        post::Post,
+        tombstone::Tombstone,
+        undo::{Undo, UndoneActivity},
+        update::Update,
+    },
+    payloads::MessageLocation,
+};
+use tower::ServiceExt;
+use twilight_model::id::Id;
+use url::Url;
+
+fn url(value: &str) -> Url {
+    Url::parse(value).unwrap()
+}
+
+/// Appends activities to the log and applies them to the projections, as
+/// the inbox does. Returns how many activities were applied.
+async fn apply(
+    repository: &Repository,
+    activities: impl IntoIterator<Item = InboxActivity>,
+) -> u64 {
+    for activity in activities {
+        repository
+            .clone()
+            .oneshot(AppendActivity(activity))
+            .await
+            .unwrap();
+    }
+    repository
+        .clone()
+        .oneshot(CatchUpProjections)
+        .await
+        .unwrap()
+}
+
+fn create_user(discord_user_id: u64, handle: &str) -> CreateUser {
+    CreateUser {
+        discord_user_id: Id::new(discord_user_id),
+        handle: handle.to_string(),
+        public_key_pem: "public".to_string(),
+        private_key_pem: "private".to_string(),
+    }
+}
+
+fn foreign_actor() -> ForeignActor {
+    ForeignActor {
+        id: url("https://mastodon.example/users/alice"),
+        kind: ActorType::Person,
+        preferred_username: Some("alice".to_string()),
+        name: Some("Alice".to_string()),
+        url: Some(url("https://mastodon.example/@alice")),
+        inbox: url("https://mastodon.example/users/alice/inbox"),
+        shared_inbox: Some(url("https://mastodon.example/inbox")),
+        public_key_pem: "alice's key".to_string(),
+        last_refreshed_at: "2023-08-01T12:00:00".parse().unwrap(),
+    }
+}
+
+fn foreign_post() -> Post {
+    Post {
+        id: url("https://mastodon.example/users/alice/statuses/1"),
+        attributed_to: url("https://mastodon.example/users/alice"),
+        content: "<p>A heron</p>".to_string(),
+        summary: None,
+        sensitive: false,
+        url: Some(url("https://mastodon.example/@alice/1")),
+        in_reply_to: None,
+        published: Some("2023-08-01T12:00:00Z".parse().unwrap()),
+        updated: None,
+        to: vec![url("https://www.w3.org/ns/activitystreams#Public")],
+        cc: vec![url("https://mastodon.example/users/alice/followers")],
+        image: Some(Image {
+            kind: Default::default(),
+            id: url("https://files.mastodon.example/heron.jpg"),
+            url: url("https://files.mastodon.example/heron.jpg"),
+            media_type: Some("image/jpeg".to_string()),
+            name: Some("A grey heron".to_string()),
+        }),
+        video: None,
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn users_are_created_found_and_deleted() {
+    let repository = repository("users").await;
+
+    let user = repository
+        .clone()
+        .oneshot(create_user(10, "Alice"))
+        .await
+        .unwrap();
+    assert_eq!(user.id.as_str(), "https://eris.example/users/1");
+    assert_eq!(
+        user.followers.as_str(),
+        "https://eris.example/users/1/followers"
+    );
+    assert_eq!(user.private_key_pem.as_deref(), Some("private"));
+
+    // Handles are unique regardless of case, and users can only join once
+    let taken = repository.clone().oneshot(create_user(11, "alice")).await;
+    assert!(matches!(taken, Err(DataError::Conflict(_))));
+    let rejoined = repository.clone().oneshot(create_user(10, "bob")).await;
+    assert!(matches!(rejoined, Err(DataError::Conflict(_))));
+
+    let by_handle = repository
+        .clone()
+        .oneshot(GetUserByHandle("ALICE".to_string()))
+        .await
+        .unwrap();
+    assert_eq!(by_handle.as_ref(), Some(&user));
+    let by_discord_id = repository
+        .clone()
+        .oneshot(GetUserByDiscordId(Id::new(10)))
+        .await
+        .unwrap();
+    assert_eq!(by_discord_id.as_ref(), Some(&user));
+
+    let updated = repository
+        .clone()
+        .oneshot(UpdateProfile {
+            user_id: 1,
+            display_name: Some("Alice A.".to_string()),
+            summary: Some("Birds".to_string()),
+            manually_approves_followers: true,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(updated.display_name.as_deref(), Some("Alice A."));
+    assert!(updated.manually_approves_followers);
+
+    assert!(repository.clone().oneshot(DeleteUser(1)).await.unwrap());
+    assert_eq!(repository.oneshot(GetUser(1)).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn channels_are_registered_once() {
+    let repository = repository("channels").await;
+
+    let register = RegisterChannel {
+        guild_id: Id::new(100),
+        channel_id: Id::new(200),
+        name: "birds".to_string(),
+        public_key_pem: "public".to_string(),
+        private_key_pem: "private".to_string(),
+    };
+    let channel = repository.clone().oneshot(register.clone()).await.unwrap();
+    assert_eq!(channel.id.as_str(), "https://eris.example/channels/100/200");
+    assert!(matches!(
+        repository.clone().oneshot(register.clone()).await,
+        Err(DataError::Conflict(_))
+    ));
+
+    let found = repository
+        .clone()
+        .oneshot(GetChannel(Id::new(200)))
+        .await
+        .unwrap();
+    assert_eq!(found.as_ref(), Some(&channel));
+    let in_guild = repository
+        .clone()
+        .oneshot(GetGuildChannels(Id::new(100)))
+        .await
+        .unwrap();
+    assert_eq!(in_guild, [channel]);
+
+    assert!(repository
+        .clone()
+        .oneshot(DeleteChannel(Id::new(200)))
+        .await
+        .unwrap());
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetChannel(Id::new(200)))
+            .await
+            .unwrap(),
+        None
+    );
+
+    // The channel's actor cannot come back
+    assert!(matches!(
+        repository.oneshot(register).await,
+        Err(DataError::Deleted(id)) if id.as_str() == "https://eris.example/channels/100/200"
+    ));
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn foreign_actors_are_replaced_when_saved_again() {
+    let repository = repository("foreign_actors").await;
+
+    let mut actor = foreign_actor();
+    repository
+        .clone()
+        .oneshot(SaveForeignActor(actor.clone()))
+        .await
+        .unwrap();
+    actor.name = Some("Alice (moved)".to_string());
+    actor.shared_inbox = None;
+    repository
+        .clone()
+        .oneshot(SaveForeignActor(actor.clone()))
+        .await
+        .unwrap();
+
+    let found = repository
+        .clone()
+        .oneshot(GetForeignActor(actor.id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(found, Some(actor.clone()));
+
+    assert!(repository
+        .clone()
+        .oneshot(DeleteForeignActor(actor.id.clone()))
+        .await
+        .unwrap());
+    assert_eq!(
+        repository.oneshot(GetForeignActor(actor.id)).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn local_posts_get_ids_for_them_and_their_attachments() {
+    let repository = repository("local_posts").await;
+    repository
+        .clone()
+        .oneshot(create_user(10, "alice"))
+        .await
+        .unwrap();
+
+    let post = repository
+        .clone()
+        .oneshot(CreateLocalPost {
+            user_id: 1,
+            content: "<p>Hello</p>".to_string(),
+            summary: None,
+            sensitive: false,
+            in_reply_to: None,
+            to: vec![url("https://www.w3.org/ns/activitystreams#Public")],
+            cc: vec![url("https://eris.example/users/1/followers")],
+            image: Some(NewAttachment {
+                url: url("https://cdn.example/heron.png"),
+                media_type: Some("image/png".to_string()),
+                name: Some("A heron".to_string()),
+            }),
+            video: None,
+        })
+        .await
+        .unwrap();
+    assert_eq!(post.id.as_str(), "https://eris.example/users/1/posts/1");
+    assert_eq!(post.attributed_to.as_str(), "https://eris.example/users/1");
+    assert!(post.published.is_some());
+    let image = post.image.clone().unwrap();
+    assert_eq!(
+        image.id.as_str(),
+        "https://eris.example/users/1/posts/1/attachments/images/1"
+    );
+
+    let found = repository
+        .clone()
+        .oneshot(GetPost(post.id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(found.as_ref(), Some(&post));
+    let attachment = repository
+        .clone()
+        .oneshot(GetAttachment(image.id.clone()))
+        .await
+        .unwrap();
+    assert_eq!(attachment, Some(Attachment::Image(image.clone())));
+
+    let edited = repository
+        .clone()
+        .oneshot(EditPost {
+            id: post.id.clone(),
+            content: "<p>Hello again</p>".to_string(),
+            summary: Some("Birds".to_string()),
+            sensitive: true,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(edited.content, "<p>Hello again</p>");
+    assert!(edited.updated.is_some());
+
+    // A foreign post cannot overwrite a local one, and is skipped without
+    // stopping the activities after it from being applied
+    let alice = url("https://mastodon.example/users/alice");
+    let mut impostor = foreign_post();
+    impostor.id = post.id.clone();
+    let create = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity"),
+        alice.clone(),
+        impostor.into(),
+    );
+    let like = Like::new(
+        url("https://mastodon.example/likes/1"),
+        alice.clone(),
+        post.id.clone(),
+    );
+    let applied = apply(
+        &repository,
+        [InboxActivity::Create(create), InboxActivity::Like(like)],
+    )
+    .await;
+    // The Create and Update of the local post, and the Like
+    assert_eq!(applied, 3);
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(post.id.clone()))
+            .await
+            .unwrap(),
+        Some(edited)
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetLikes(post.id.clone()))
+            .await
+            .unwrap(),
+        vec![alice]
+    );
+    assert_eq!(apply(&repository, []).await, 0);
+
+    assert!(repository
+        .clone()
+        .oneshot(DeletePost(post.id.clone()))
+        .await
+        .unwrap());
+    assert_eq!(
+        repository.oneshot(GetAttachment(image.id)).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn foreign_posts_are_replaced_with_their_attachments() {
+    let repository = repository("foreign_posts").await;
+
+    let alice = url("https://mastodon.example/users/alice");
+    let mut post = foreign_post();
+    let create = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity"),
+        alice.clone(),
+        post.clone().into(),
+    );
+    apply(&repository, [InboxActivity::Create(create)]).await;
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(post.id.clone()))
+            .await
+            .unwrap()
+            .as_ref(),
+        Some(&post)
+    );
+
+    post.content = "<p>A grey heron</p>".to_string();
+    post.updated = Some("2023-08-01T12:30:00Z".parse().unwrap());
+    post.image = None;
+    let update = Update::new(
+        url("https://mastodon.example/users/alice/statuses/1#updates/1"),
+        alice,
+        post.clone().into(),
+    );
+    apply(&repository, [InboxActivity::Update(update)]).await;
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(post.id.clone()))
+            .await
+            .unwrap()
+            .as_ref(),
+        Some(&post)
+    );
+
+    // Another actor on the same server cannot take the post over
+    let mallory = url("https://mastodon.example/users/mallory");
+    let mut taken = post.clone();
+    taken.attributed_to = mallory.clone();
+    taken.content = "<p>Mine now</p>".to_string();
+    let create = Create::new(
+        url("https://mastodon.example/users/mallory/statuses/2/activity"),
+        mallory.clone(),
+        taken.clone().into(),
+    );
+    let update = Update::new(
+        url("https://mastodon.example/users/mallory/statuses/2#updates/1"),
+        mallory,
+        taken.into(),
+    );
+    let applied = apply(
+        &repository,
+        [InboxActivity::Create(create), InboxActivity::Update(update)],
+    )
+    .await;
+    assert_eq!(applied, 0);
+    assert_eq!(
+        repository.oneshot(GetPost(post.id.clone())).await.unwrap(),
+        Some(post)
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn objects_are_only_deleted_by_their_authors() {
+    let repository = repository("delete_authors").await;
+    let alice = url("https://mastodon.example/users/alice");
+    let mallory = url("https://mastodon.example/users/mallory");
+    let user = url("https://eris.example/users/1");
+    let post = foreign_post();
+    let create = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity"),
+        alice.clone(),
+        post.clone().into(),
+    );
+    let follow = Follow::new(
+        url("https://mastodon.example/follows/1"),
+        alice.clone(),
+        user.clone(),
+    );
+    apply(
+        &repository,
+        [InboxActivity::Create(create), InboxActivity::Follow(follow)],
+    )
+    .await;
+    assert!(repository
+        .clone()
+        .oneshot(AcceptFollow {
+            follower: alice.clone(),
+            followed: user.clone(),
+        })
+        .await
+        .unwrap());
+
+    // Another actor on the same server can delete neither the post nor its
+    // author
+    let delete_post = Delete::new(
+        url("https://mastodon.example/users/mallory#deletes/1"),
+        mallory.clone(),
+        DeletedObject::Tombstone(Tombstone::new(
+            post.id.clone(),
+            "Note",
+            "2023-08-02T12:00:00Z".parse().unwrap(),
+        )),
+        vec![],
+    );
+    let delete_author = Delete::new(
+        url("https://mastodon.example/users/mallory#deletes/2"),
+        mallory,
+        DeletedObject::Id(alice.clone()),
+        vec![],
+    );
+    apply(
+        &repository,
+        [
+            InboxActivity::Delete(delete_post),
+            InboxActivity::Delete(delete_author),
+        ],
+    )
+    .await;
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(post.id.clone()))
+            .await
+            .unwrap(),
+        Some(post.clone())
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowers(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    for id in [&post.id, &alice] {
+        assert_eq!(
+            repository
+                .clone()
+                .oneshot(GetTombstone(id.clone()))
+                .await
+                .unwrap(),
+            None
+        );
+    }
+
+    // Its author can
+    let delete_post = Delete::new(
+        url("https://mastodon.example/users/alice/statuses/1#delete"),
+        alice.clone(),
+        DeletedObject::Id(post.id.clone()),
+        vec![],
+    );
+    assert_eq!(
+        apply(&repository, [InboxActivity::Delete(delete_post)]).await,
+        1
+    );
+    assert_eq!(repository.oneshot(GetPost(post.id)).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn messages_are_found_by_post() {
+    let repository = repository("messages").await;
+
+    let post_id = foreign_post().id;
+    let messages: Vec<Message> = (1..=2)
+        .map(|i| Message {
+            location: MessageLocation {
+                channel_id: Id::new(200 + i),
+                message_id: Id::new(300 + i),
+            },
+            guild_id: Id::new(100),
+            post_id: post_id.clone(),
+            sent_at: format!("2023-08-01T12:0{i}:00Z").parse().unwrap(),
+        })
+        .collect();
+    for message in &messages {
+        repository
+            .clone()
+            .oneshot(SaveMessage(message.clone()))
+            .await
+            .unwrap();
+    }
+
+    let found = repository
+        .clone()
+        .oneshot(GetPostMessages(post_id))
+        .await
+        .unwrap();
+    assert_eq!(found, messages);
+    let message = repository
+        .clone()
+        .oneshot(GetMessage(Id::new(301)))
+        .await
+        .unwrap();
+    assert_eq!(message.as_ref(), Some(&messages[0]));
+
+    assert!(repository
+        .clone()
+        .oneshot(DeleteMessage(Id::new(301)))
+        .await
+        .unwrap());
+    assert_eq!(
+        repository.oneshot(GetMessage(Id::new(301))).await.unwrap(),
+        None
+    );
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn relations_between_actors() {
+    let repository = repository("relations").await;
+    let alice = url("https://mastodon.example/users/alice");
+    let user = url("https://eris.example/users/1");
+    let post = url("https://eris.example/users/1/posts/1");
+
+    // Follows only count once accepted
+    let follow = Follow::new(
+        url("https://mastodon.example/follows/1"),
+        alice.clone(),
+        user.clone(),
+    );
+    apply(&repository, [InboxActivity::Follow(follow.clone())]).await;
+    assert!(repository
+        .clone()
+        .oneshot(GetFollowers(user.clone()))
+        .await
+        .unwrap()
+        .is_empty());
+    assert!(repository
+        .clone()
+        .oneshot(AcceptFollow {
+            follower: alice.clone(),
+            followed: user.clone(),
+        })
+        .await
+        .unwrap());
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowers(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowing(alice.clone()))
+            .await
+            .unwrap(),
+        vec![user.clone()]
+    );
+
+    // Following again, as Mastodon does, keeps the follow accepted
+    let follow_again = Follow::new(
+        url("https://mastodon.example/follows/2"),
+        alice.clone(),
+        user.clone(),
+    );
+    apply(&repository, [InboxActivity::Follow(follow_again)]).await;
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowers(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+
+    // Another server can only accept follows which were asked of it
+    let channel = url("https://eris.example/channels/100/200");
+    let unasked = Accept::new(
+        url("https://mastodon.example/users/alice#accepts/follows/1"),
+        alice.clone(),
+        Follow::new(
+            url("https://eris.example/activities/50"),
+            channel.clone(),
+            alice.clone(),
+        ),
+    );
+    let between_others = Accept::new(
+        url("https://mastodon.example/users/alice#accepts/follows/2"),
+        alice.clone(),
+        Follow::new(
+            url("https://other.example/follows/1"),
+            url("https://other.example/users/carol"),
+            alice.clone(),
+        ),
+    );
+    let applied = apply(
+        &repository,
+        [
+            InboxActivity::Accept(unasked),
+            InboxActivity::Accept(between_others),
+        ],
+    )
+    .await;
+    assert_eq!(applied, 0);
+    assert!(repository
+        .clone()
+        .oneshot(GetFollowing(channel))
+        .await
+        .unwrap()
+        .is_empty());
+
+    let unfollow = Undo::new(
+        url("https://mastodon.example/follows/1/undo"),
+        alice.clone(),
+        UndoneActivity::Follow(follow),
+    );
+    apply(&repository, [InboxActivity::Undo(unfollow)]).await;
+    assert!(repository
+        .clone()
+        .oneshot(GetFollowers(user.clone()))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Liking or sharing twice keeps one
+    let mut like = None;
+    for i in 1..=2 {
+        let liked = Like::new(
+            url(&format!("https://mastodon.example/likes/{i}")),
+            alice.clone(),
+            post.clone(),
+        );
+        let share = Announce::new(
+            url(&format!("https://mastodon.example/shares/{i}")),
+            alice.clone(),
+            post.clone(),
+            vec![],
+            vec![],
+        );
+        apply(
+            &repository,
+            [
+                InboxActivity::Like(liked.clone()),
+                InboxActivity::Announce(share),
+            ],
+        )
+        .await;
+        like.get_or_insert(liked);
+    }
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetLikes(post.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetShares(post.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    let unlike = Undo::new(
+        url("https://mastodon.example/likes/1/undo"),
+        alice.clone(),
+        UndoneActivity::Like(like.unwrap()),
+    );
+    apply(&repository, [InboxActivity::Undo(unlike)]).await;
+    assert!(repository
+        .clone()
+        .oneshot(GetLikes(post.clone()))
+        .await
+        .unwrap()
+        .is_empty());
+
+    // Blocks work both ways
+    let block = Block::new(
+        url("https://eris.example/activities/100"),
+        user.clone(),
+        alice.clone(),
+    );
+    apply(&repository, [InboxActivity::Block(block.clone())]).await;
+    assert!(repository
+        .clone()
+        .oneshot(IsBlocked {
+            actor: alice.clone(),
+            other: user.clone(),
+        })
+        .await
+        .unwrap());
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetBlocked(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    let unblock = Undo::new(
+        url("https://eris.example/activities/101"),
+        user.clone(),
+        UndoneActivity::Block(block),
+    );
+    apply(&repository, [InboxActivity::Undo(unblock)]).await;
+    assert!(!repository
+        .oneshot(IsBlocked {
+            actor: user,
+            other: alice,
+        })
+        .await
+        .unwrap());
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn object_store_finds_objects_and_tombstones() {
+    let repository = repository("object_store").await;
+    let urls = repository.urls().clone();
+    let application = Application {
+        id: urls.instance(),
+        name: "eris.example".to_string(),
+        summary: None,
+        inbox: urls.shared_inbox(),
+        outbox: urls.instance_outbox(),
+        public_key_pem: "public".to_string(),
+        private_key_pem: Some("private".to_string()),
+    };
+    let store = object_store_service(repository.clone(), application.clone());
+
+    repository
+        .clone()
+        .oneshot(create_user(10, "alice"))
+        .await
+        .unwrap();
+    repository
+        .clone()
+        .oneshot(SaveForeignActor(foreign_actor()))
+        .await
+        .unwrap();
+    let create = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity"),
+        foreign_actor().id,
+        foreign_post().into(),
+    );
+    apply(&repository, [InboxActivity::Create(create)]).await;
+
+    let found = |id: Url| store.clone().oneshot(id);
+    assert!(matches!(
+        found(urls.instance()).await.unwrap(),
+        Some(StoredObject::Application(found)) if found == application
+    ));
+    assert!(matches!(
+        found(urls.user(1)).await.unwrap(),
+        Some(StoredObject::User(_))
+    ));
+    assert!(found(urls.user(2)).await.unwrap().is_none());
+    assert!(matches!(
+        found(foreign_actor().id).await.unwrap(),
+        Some(StoredObject::ForeignActor(_))
+    ));
+    assert!(matches!(
+        found(foreign_post().id).await.unwrap(),
+        Some(StoredObject::Post(_))
+    ));
+
+    // Once deleted, only the tombstone is found
+    repository.clone().oneshot(DeleteUser(1)).await.unwrap();
+    assert!(matches!(
+        found(urls.user(1)).await.unwrap(),
+        Some(StoredObject::Tombstone(found))
+            if found.former_type.as_deref() == Some("Person")
+    ));
+    let tombstone = Tombstone::new(
+        foreign_post().id,
+        "Note",
+        "2023-08-02T12:00:00Z".parse().unwrap(),
+    );
+    repository
+        .clone()
+        .oneshot(SaveTombstone(tombstone.clone()))
+        .await
+        .unwrap();
+    assert!(matches!(
+        found(foreign_post().id).await.unwrap(),
+        Some(StoredObject::Tombstone(found)) if found == tombstone
+    ));
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn activity_log_is_append_only_without_duplicates() {
+    let repository = repository("activity_log").await;
+    let like = InboxActivity::Like(Like::new(
+        url("https://mastodon.example/likes/1"),
+        url("https://mastodon.example/users/alice"),
+        url("https://eris.example/users/1/posts/1"),
+    ));
+
+    let logged = repository
+        .clone()
+        .oneshot(AppendActivity(like.clone()))
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(logged.sequence, 1);
+    let again = repository
+        .clone()
+        .oneshot(AppendActivity(like))
+        .await
+        .unwrap();
+    assert!(again.is_none());
+
+    let read = repository
+        .clone()
+        .oneshot(ReadActivities {
+            after: 0,
+            limit: 10,
+        })
+        .await
+        .unwrap();
+    assert_eq!(read.len(), 1);
+    assert!(matches!(
+        &read[0].activity,
+        InboxActivity::Like(like) if like.id.as_str() == "https://mastodon.example/likes/1"
+    ));
+
+    let deduplicate = deduplicate_service(repository.clone());
+    let follow = InboxActivity::Follow(Follow::new(
+        url("https://mastodon.example/follows/1"),
+        url("https://mastodon.example/users/alice"),
+        url("https://eris.example/users/1"),
+    ));
+    let follow_id = url("https://mastodon.example/follows/1");
+    assert!(deduplicate
+        .clone()
+        .oneshot(follow_id.clone())
+        .await
+        .unwrap());
+    let record = record_service(repository.clone());
+    assert!(record.clone().oneshot(follow.clone()).await.unwrap());
+    assert!(!record.oneshot(follow).await.unwrap());
+    assert!(!deduplicate.oneshot(follow_id).await.unwrap());
+
+    for statement in [
+        "UPDATE activity_log SET kind = 'Block'",
+        "DELETE FROM activity_log",
+        "TRUNCATE activity_log",
+    ] {
+        let changed = sqlx::query(statement).execute(repository.pool()).await;
+        assert!(changed.is_err(), "{statement}");
+    }
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn projections_catch_up_with_the_log() {
+    let repository = repository("catch_up").await;
+    let alice = url("https://mastodon.example/users/alice");
+    let user = url("https://eris.example/users/1");
+    let post = foreign_post();
+
+    let follow = Follow::new(
+        url("https://mastodon.example/follows/1"),
+        alice.clone(),
+        user.clone(),
+    );
+    let like = Like::new(
+        url("https://mastodon.example/likes/1"),
+        user.clone(),
+        post.id.clone(),
+    );
+    let mut edited = post.clone();
+    edited.content = "<p>A grey heron</p>".to_string();
+    let activities = [
+        InboxActivity::Follow(follow.clone()),
+        InboxActivity::Accept(Accept::new(
+            url("https://eris.example/activities/1"),
+            user.clone(),
+            follow,
+        )),
+        InboxActivity::Create(Create::new(
+            url("https://mastodon.example/users/alice/statuses/1/activity"),
+            alice.clone(),
+            post.clone().into(),
+        )),
+        InboxActivity::Like(like.clone()),
+        InboxActivity::Update(Update::new(
+            url("https://mastodon.example/users/alice/statuses/1#updates/1"),
+            alice.clone(),
+            edited.clone().into(),
+        )),
+        InboxActivity::Undo(Undo::new(
+            url("https://eris.example/activities/2"),
+            user.clone(),
+            UndoneActivity::Like(like),
+        )),
+    ];
+    for activity in activities {
+        repository
+            .clone()
+            .oneshot(AppendActivity(activity))
+            .await
+            .unwrap();
+    }
+
+    let applied = repository
+        .clone()
+        .oneshot(CatchUpProjections)
+        .await
+        .unwrap();
+    assert_eq!(applied, 6);
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowers(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    assert!(repository
+        .clone()
+        .oneshot(GetLikes(post.id.clone()))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(post.id.clone()))
+            .await
+            .unwrap(),
+        Some(edited)
+    );
+
+    // Only what was appended since is applied
+    let applied = repository
+        .clone()
+        .oneshot(CatchUpProjections)
+        .await
+        .unwrap();
+    assert_eq!(applied, 0);
+    let delete = Delete::new(
+        url("https://mastodon.example/users/alice#delete"),
+        alice.clone(),
+        DeletedObject::Id(alice.clone()),
+        vec![],
+    );
+    repository
+        .clone()
+        .oneshot(AppendActivity(InboxActivity::Delete(delete)))
+        .await
+        .unwrap();
+    let applied = repository
+        .clone()
+        .oneshot(CatchUpProjections)
+        .await
+        .unwrap();
+    assert_eq!(applied, 1);
+    assert!(repository
+        .clone()
+        .oneshot(GetFollowers(user))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(repository.oneshot(GetPost(post.id)).await.unwrap(), None);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn projections_are_rebuilt_from_the_log() {
+    let repository = repository("rebuild").await;
+    let alice = url("https://mastodon.example/users/alice");
+    let user = url("https://eris.example/users/1");
+    repository
+        .clone()
+        .oneshot(create_user(10, "bob"))
+        .await
+        .unwrap();
+
+    let local_post = repository
+        .clone()
+        .oneshot(CreateLocalPost {
+            user_id: 1,
+            content: "<p>Hello</p>".to_string(),
+            summary: None,
+            sensitive: false,
+            in_reply_to: None,
+            to: vec![url("https://www.w3.org/ns/activitystreams#Public")],
+            cc: vec![],
+            image: Some(NewAttachment {
+                url: url("https://cdn.example/heron.png"),
+                media_type: Some("image/png".to_string()),
+                name: None,
+            }),
+            video: None,
+        })
+        .await
+        .unwrap();
+    let share = Announce::new(
+        url("https://mastodon.example/users/alice/statuses/2/activity"),
+        alice.clone(),
+        local_post.id.clone(),
+        vec![],
+        vec![],
+    );
+    let follow = Follow::new(
+        url("https://mastodon.example/follows/1"),
+        alice.clone(),
+        user.clone(),
+    );
+    let activities = [
+        InboxActivity::Announce(share),
+        InboxActivity::Follow(follow),
+        InboxActivity::Block(Block::new(
+            url("https://eris.example/activities/100"),
+            user.clone(),
+            url("https://mastodon.example/users/mallory"),
+        )),
+    ];
+    apply(&repository, activities).await;
+
+    // Local writes are logged too
+    assert!(repository
+        .clone()
+        .oneshot(AcceptFollow {
+            follower: alice.clone(),
+            followed: user.clone(),
+        })
+        .await
+        .unwrap());
+    let local_post = repository
+        .clone()
+        .oneshot(EditPost {
+            id: local_post.id.clone(),
+            content: "<p>Hello again</p>".to_string(),
+            summary: None,
+            sensitive: false,
+        })
+        .await
+        .unwrap()
+        .unwrap();
+
+    // Lose everything but the log and the users
+    for table in ["posts", "shares", "blocks", "follows"] {
+        sqlx::query(&format!("DELETE FROM {table}"))
+            .execute(repository.pool())
+            .await
+            .unwrap();
+    }
+    // Including something which is not in the log
+    sqlx::query("INSERT INTO likes (actor, object, activity_id) VALUES ($1, $2, $3)")
+        .bind(alice.as_str())
+        .bind(local_post.id.as_str())
+        .bind("https://mastodon.example/likes/1")
+        .execute(repository.pool())
+        .await
+        .unwrap();
+
+    let applied = repository
+        .clone()
+        .oneshot(RebuildProjections)
+        .await
+        .unwrap();
+    // The Create, Announce, Follow, Block, Accept and Update
+    assert_eq!(applied, 6);
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetPost(local_post.id.clone()))
+            .await
+            .unwrap(),
+        Some(local_post.clone())
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetFollowers(user.clone()))
+            .await
+            .unwrap(),
+        vec![alice.clone()]
+    );
+    assert_eq!(
+        repository
+            .clone()
+            .oneshot(GetShares(local_post.id.clone()))
+            .await
+            .unwrap(),
+        vec![alice]
+    );
+    assert!(repository
+        .clone()
+        .oneshot(GetLikes(local_post.id.clone()))
+        .await
+        .unwrap()
+        .is_empty());
+    assert_eq!(
+        repository.clone().oneshot(GetBlocked(user)).await.unwrap(),
+        vec![url("https://mastodon.example/users/mallory")]
+    );
+
+    // The next post does not reuse the ids of the restored one
+    let next_post = repository
+        .clone()
+        .oneshot(CreateLocalPost {
+            user_id: 1,
+            content: "<p>Hello again</p>".to_string(),
+            summary: None,
+            sensitive: false,
+            in_reply_to: None,
+            to: vec![],
+            cc: vec![],
+            image: None,
+            video: None,
+        })
+        .await
+        .unwrap();
+    assert_ne!(next_post.id, local_post.id);
+    // Only its Create is left to apply
+    assert_eq!(repository.oneshot(CatchUpProjections).await.unwrap(), 1);
+}
+
+#[tokio::test]
+#[ignore = "needs a Postgres database at DATABASE_URL"]
+async fn deleted_ids_are_never_reused() {
+    let repository = repository("tombstones").await;
+    let urls = repository.urls().clone();
+    repository
+        .clone()
+        .oneshot(create_user(10, "alice"))
+        .await
+        .unwrap();
+    let post = repository
+        .clone()
+        .oneshot(CreateLocalPost {
+            user_id: 1,
+            content: "<p>Hello</p>".to_string(),
+            summary: None,
+            sensitive: false,
+            in_reply_to: None,
+            to: vec![],
+            cc: vec![],
+            image: None,
+            video: Some(NewAttachment {
+                url: url("https://cdn.example/heron.mp4"),
+                media_type: Some("video/mp4".to_string()),
+                name: None,
+            }),
+        })
+        .await
+        .unwrap();
+
+    // Deleting the user buries their posts and attachments too
+    assert!(repository.clone().oneshot(DeleteUser(1)).await.unwrap());
+    let tombstone_type = |id: Url| {
+        let repository = repository.clone();
+        async move {
+            repository
+                .oneshot(GetTombstone(id))
+                .await
+                .unwrap()
+                .and_then(|tombstone| tombstone.former_type)
+        }
+    };
+    assert_eq!(
+        tombstone_type(urls.user(1)).await.as_deref(),
+        Some("Person")
+    );
+    assert_eq!(
+        tombstone_type(post.id.clone()).await.as_deref(),
+        Some("Note")
+    );
+    assert_eq!(
+        tombstone_type(post.video.unwrap().id).await.as_deref(),
+        Some("Video")
+    );
+    assert!(!repository.clone().oneshot(DeleteUser(1)).await.unwrap());
+
+    // A foreign post deleted by its author is not stored again, even if it
+    // is created again later
+    let alice = url("https://mastodon.example/users/alice");
+    let foreign = foreign_post();
+    let create = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity"),
+        alice.clone(),
+        foreign.clone().into(),
+    );
+    let delete = Delete::new(
+        url("https://mastodon.example/users/alice/statuses/1#delete"),
+        alice.clone(),
+        DeletedObject::Id(foreign.id.clone()),
+        vec![],
+    );
+    let create_again = Create::new(
+        url("https://mastodon.example/users/alice/statuses/1/activity#again"),
+        alice,
+        foreign.clone().into(),
+    );
+    // The local post's Create is applied before them
+    assert_eq!(
+        apply(
+            &repository,
+            [
+                InboxActivity::Create(create),
+                InboxActivity::Delete(delete),
+                InboxActivity::Create(create_again),
+            ],
+        )
+        .await,
+        4
+    );
+    assert_eq!(
+        tombstone_type(foreign.id.clone()).await.as_deref(),
+        Some("Note")
+    );
+    assert_eq!(repository.oneshot(GetPost(foreign.id)).await.unwrap(), None);
+}
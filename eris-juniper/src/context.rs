@@ -1,6 +1,30 @@
+use std::collections::HashMap;
+
 use juniper::Context;
+use url::Url;
+
+use crate::nodes::Tombstone;
 
 /// TODO: make this real
-pub struct MockContext;
+#[derive(Default)]
+pub struct MockContext {
+    tombstones: HashMap<Url, Tombstone>,
+}
+
+impl MockContext {
+    /// Creates a context in which these tombstones can be found.
+    pub fn new(tombstones: impl IntoIterator<Item = Tombstone>) -> Self {
+        let tombstones = tombstones
+            .into_iter()
+            .map(|tombstone| (tombstone.activitypub_id.clone(), tombstone))
+            .collect();
+        Self { tombstones }
+    }
+
+    /// Finds the tombstone of a deleted object by its former ActivityPub id.
+    pub fn tombstone(&self, activitypub_id: &Url) -> Option<&Tombstone> {
+        self.tombstones.get(activitypub_id)
+    }
+}
 
-impl Context for MockContext {}
\ No newline at end of file
+impl Context for MockContext {}
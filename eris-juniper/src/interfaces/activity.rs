@@ -2,8 +2,9 @@ use juniper::graphql_interface;
 use url::Url;
 
 use super::{ActivityPubObject, Node, actor::ActorValue, ActivityPubObjectValue};
+use crate::MockContext;
 
-#[graphql_interface]
+#[graphql_interface(context = MockContext)]
 /// An ActivityPub Activity, representing a state-affecting action taken
 /// by some Actor. Usually has an object, but may not for intransitive activities.
 pub trait Activity: ActivityPubObject {
@@ -2,8 +2,9 @@ use juniper::graphql_interface;
 use url::Url;
 
 use super::{ActivityPubObject, Node};
+use crate::MockContext;
 
-#[graphql_interface]
+#[graphql_interface(context = MockContext)]
 /// An ActivityPub Actor, capable of performing Activities.
 /// As per the spec, must have an inbox URL and an outbox URL.
 /// Eris additionally requires that all Actors have a public key to
@@ -1,7 +1,8 @@
 use juniper::graphql_interface;
 
+use crate::{nodes::Tombstone, MockContext};
 
-#[graphql_interface]
+#[graphql_interface(context = MockContext, for = Tombstone)]
 /// A node, representing any individually queryable entity.
 pub trait Node {
     /// Returns the node's Base64-encoded [NodeId], which indicates both the
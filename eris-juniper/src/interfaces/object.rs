@@ -2,8 +2,9 @@ use juniper::graphql_interface;
 use url::Url;
 
 use super::Node;
+use crate::{nodes::Tombstone, MockContext};
 
-#[graphql_interface]
+#[graphql_interface(context = MockContext, for = Tombstone)]
 /// An ActivityPub Object, with no other guarantees. May be a local Actor,
 /// a foreign Actor, a locally-created Object, an Activity, or any other
 /// item which ActivityPub recognizes.
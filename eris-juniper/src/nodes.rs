@@ -1,2 +1,4 @@
 mod instance;
-pub use instance::Instance;
\ No newline at end of file
+pub use instance::Instance;
+mod tombstone;
+pub use tombstone::Tombstone;
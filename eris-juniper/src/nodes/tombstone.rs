@@ -0,0 +1,69 @@
+use chrono::{DateTime, Utc};
+use juniper::graphql_object;
+use url::Url;
+
+use crate::{
+    interfaces::{ActivityPubObject, ActivityPubObjectValue, Node, NodeValue},
+    scalars::NodeId,
+    MockContext,
+};
+
+/// A deleted object. Its id is never given to another object.
+#[derive(Debug, Clone)]
+pub struct Tombstone {
+    pub(crate) activitypub_id: Url,
+    former_type: Option<String>,
+    deleted: Option<DateTime<Utc>>,
+}
+
+impl Tombstone {
+    /// Creates the tombstone of the object with the given ActivityPub id.
+    pub fn new(
+        activitypub_id: Url,
+        former_type: Option<String>,
+        deleted: Option<DateTime<Utc>>,
+    ) -> Self {
+        Self {
+            activitypub_id,
+            former_type,
+            deleted,
+        }
+    }
+}
+
+#[graphql_object(Context = MockContext, impl = [NodeValue, ActivityPubObjectValue])]
+impl Tombstone {
+    /// The node's Base64-encoded [NodeId].
+    fn id(&self) -> String {
+        Node::id(self)
+    }
+
+    /// The URL the object had.
+    fn activitypub_id(&self) -> Url {
+        self.activitypub_id.clone()
+    }
+
+    /// The ActivityPub type the object had, such as Note or Person, if known.
+    fn former_type(&self) -> Option<&str> {
+        self.former_type.as_deref()
+    }
+
+    /// When the object was deleted, if known.
+    fn deleted(&self) -> Option<DateTime<Utc>> {
+        self.deleted
+    }
+}
+
+#[juniper::graphql_interface]
+impl Node for Tombstone {
+    fn id(&self) -> String {
+        NodeId::Tombstone(self.activitypub_id.clone()).encode()
+    }
+}
+
+#[juniper::graphql_interface]
+impl ActivityPubObject for Tombstone {
+    fn activitypub_id(&self) -> Url {
+        self.activitypub_id.clone()
+    }
+}
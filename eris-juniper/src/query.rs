@@ -1,7 +1,9 @@
 use juniper::graphql_object;
 
-use crate::MockContext;
+use crate::interfaces::NodeValue;
 use crate::nodes::Instance;
+use crate::scalars::NodeId;
+use crate::MockContext;
 
 /// The root Query object.
 pub struct Query;
@@ -11,4 +13,14 @@ impl Query {
     fn instance(_context: &MockContext) -> Instance {
         todo!()
     }
-}
\ No newline at end of file
+
+    /// Finds any node by its [NodeId], or null if there is no such node.
+    fn node(context: &MockContext, id: String) -> Option<NodeValue> {
+        match NodeId::decode(&id)? {
+            NodeId::Tombstone(activitypub_id) => {
+                context.tombstone(&activitypub_id).cloned().map(Into::into)
+            }
+            NodeId::Instance(_) => None,
+        }
+    }
+}
@@ -0,0 +1,2 @@
+mod node_id;
+pub use node_id::NodeId;
@@ -1,4 +1,29 @@
-#[derive(Debug, Clone, Serialize, Deserialize)]
+use base64::{engine::general_purpose::URL_SAFE_NO_PAD, Engine};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+/// The globally unique id of a node, which names the type of the node
+/// along with whatever identifies it among nodes of that type.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub enum NodeId {
+    /// The instance, identified by its domain.
     Instance(Url),
+    /// A deleted object, identified by its former ActivityPub id.
+    Tombstone(Url),
+}
+
+impl NodeId {
+    /// Encodes the id as an opaque string, to be used as the `id` of a node.
+    pub fn encode(&self) -> String {
+        // Serializing an enum of URLs cannot fail
+        let json = serde_json::to_vec(self).expect("NodeId serializes to JSON");
+        URL_SAFE_NO_PAD.encode(json)
+    }
+
+    /// Decodes an id made by [NodeId::encode], or returns None if it is not
+    /// one.
+    pub fn decode(id: &str) -> Option<Self> {
+        let json = URL_SAFE_NO_PAD.decode(id).ok()?;
+        serde_json::from_slice(&json).ok()
+    }
 }
@@ -0,0 +1,52 @@
+//! Checks that nodes are found by their ids.
+
+use chrono::{TimeZone, Utc};
+use eris_juniper::{nodes::Tombstone, scalars::NodeId, MockContext, Query};
+use juniper::{graphql_value, EmptyMutation, EmptySubscription, RootNode, Value, Variables};
+use url::Url;
+
+fn node(context: &MockContext, id: &str) -> Value {
+    let schema = RootNode::new(
+        Query,
+        EmptyMutation::<MockContext>::new(),
+        EmptySubscription::<MockContext>::new(),
+    );
+    let query = format!(
+        r#"{{ node(id: "{id}") {{ id ... on Tombstone {{ activitypubId formerType }} }} }}"#
+    );
+    let (value, errors) =
+        juniper::execute_sync(&query, None, &schema, &Variables::new(), context).unwrap();
+    assert!(errors.is_empty(), "{errors:?}");
+    value
+}
+
+#[test]
+fn tombstones_are_found_by_their_node_id() {
+    let post = Url::parse("https://eris.example/users/1/posts/1").unwrap();
+    let deleted = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+    let context = MockContext::new([Tombstone::new(
+        post.clone(),
+        Some("Note".to_string()),
+        Some(deleted),
+    )]);
+
+    let id = NodeId::Tombstone(post).encode();
+    assert_eq!(
+        node(&context, &id),
+        graphql_value!({
+            "node": {
+                "id": (id.clone()),
+                "activitypubId": "https://eris.example/users/1/posts/1",
+                "formerType": "Note",
+            }
+        })
+    );
+
+    let unknown =
+        NodeId::Tombstone(Url::parse("https://eris.example/users/1/posts/2").unwrap()).encode();
+    assert_eq!(node(&context, &unknown), graphql_value!({ "node": None }));
+    assert_eq!(
+        node(&context, "not a node id"),
+        graphql_value!({ "node": None })
+    );
+}
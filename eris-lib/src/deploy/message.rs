@@ -124,7 +124,7 @@ pub fn unfollow_in_channel() -> Command {
 /// Removes a like from a post. Fails if it was not a post the user had
 /// previously liked.
 pub fn unlike() -> Command {
-    CommandBuilder::new("Like", "Stop liking this post", CommandType::Message)
+    CommandBuilder::new("Unlike", "Stop liking this post", CommandType::Message)
         .dm_permission(true)
         .validate()
         .unwrap()
@@ -14,7 +14,7 @@ pub fn block() -> Command {
     )
     .option(
         StringOptionBuilder::new("url", "The URL of the actor to block")
-            .autocomplete(false)
+            .autocomplete(true)
             .required(true),
     )
     .dm_permission(false)
@@ -35,7 +35,7 @@ pub fn follow() -> Command {
     )
     .option(
         StringOptionBuilder::new("url", "The URL of the actor to follow")
-            .autocomplete(false)
+            .autocomplete(true)
             .required(true),
     )
     .dm_permission(false)
@@ -50,6 +50,18 @@ pub fn follow() -> Command {
     .build()
 }
 
+/// Slash command for /join.
+/// Opens a modal in which the user picks their WebFinger handle, so needs no
+/// options or permissions beyond using commands.
+pub fn join() -> Command {
+    CommandBuilder::new("join", "Join this Eris instance", CommandType::ChatInput)
+        .dm_permission(true)
+        .default_member_permissions(Permissions::USE_SLASH_COMMANDS)
+        .validate()
+        .unwrap()
+        .build()
+}
+
 /// Slash command for /unblock <url>.
 /// Requires all of the permissions for both block and follow,
 /// since this will undo a block and permit new messages to appear.
@@ -61,7 +73,7 @@ pub fn unblock() -> Command {
     )
     .option(
         StringOptionBuilder::new("url", "The URL of the actor to unblock")
-            .autocomplete(false)
+            .autocomplete(true)
             .required(true),
     )
     .dm_permission(false)
@@ -88,7 +100,7 @@ pub fn unfollow() -> Command {
     )
     .option(
         StringOptionBuilder::new("url", "The URL of the actor to unfollow")
-            .autocomplete(false)
+            .autocomplete(true)
             .required(true),
     )
     .dm_permission(false)
@@ -101,8 +113,9 @@ pub fn unfollow() -> Command {
 /// An iterator that produces these slash commands:
 /// /block <url>
 /// /follow <url>
+/// /join
 /// /unblock <url>
 /// /unfollow <url>
 pub fn slash_commands() -> impl ExactSizeIterator<Item = Command> {
-    vec![block(), follow(), unblock(), unfollow()].into_iter()
+    vec![block(), follow(), join(), unblock(), unfollow()].into_iter()
 }
@@ -17,9 +17,9 @@ pub mod deserialize_json;
 pub mod callback_service;
 
 /// A [`tower::Layer`] which constructs a [tower::Service] that responds to an
-/// Interaction by queuing it and responding with DEFERRED_CHANNEL_MESSAGE as
-/// quickly as possible. Must be provided with a service that takes a
-/// [DiscordServerAction].
+/// Interaction by queuing it and responding with a deferred message, a
+/// modal, or another chosen response as quickly as possible. Must be
+/// provided with a service that takes a [DiscordServerAction].
 pub mod respond_to_interaction;
 
 /// Authentication [`tower::Layer`] to verify Discord's [`ed25519_dalek::Signature`] on incoming
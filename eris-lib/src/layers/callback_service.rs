@@ -23,9 +23,12 @@ pub enum CallbackServiceError<Req, E: Debug + Display> {
     InnerError(E),
 }
 
+/// A queued request, paired with the channel its result should be sent back on.
+type CallbackRequest<Req, Resp, E> = (Req, OneShotSender<Result<Resp, E>>);
+
 async fn callback_service_loop<S, Req>(
     mut service: S,
-    mut receiver: UnboundedReceiver<(Req, OneShotSender<Result<S::Response, S::Error>>)>,
+    mut receiver: UnboundedReceiver<CallbackRequest<Req, S::Response, S::Error>>,
 ) where
     S: Service<Req>,
     S::Error: Debug + Display,
@@ -41,7 +44,7 @@ async fn callback_service_loop<S, Req>(
             }
         };
 
-        if let Err(_) = callback_tx.send(ready_service.call(request).await) {
+        if callback_tx.send(ready_service.call(request).await).is_err() {
             tracing::error!("Callback channel closed early, service response not delivered");
         }
     }
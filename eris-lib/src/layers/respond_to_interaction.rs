@@ -6,12 +6,18 @@ use serde_json::Value as JsonValue;
 use thiserror::Error;
 use tower::{Service, ServiceBuilder, ServiceExt};
 use twilight_model::{
-    application::interaction::{Interaction, InteractionType},
-    http::interaction::{InteractionResponse, InteractionResponseType},
+    application::{
+        command::CommandOptionChoice,
+        interaction::{Interaction, InteractionData, InteractionType},
+    },
+    channel::message::MessageFlags,
+    http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
 };
 
 use crate::{
-    layers::provide_cloned_state::ClonedStateProviderLayer, payloads::DiscordServerAction,
+    layers::provide_cloned_state::ClonedStateProviderLayer,
+    payloads::{join_modal, DiscordServerAction, PostAction},
+    services::autocomplete::{autocomplete, AutocompleteRequest},
 };
 
 /// The response to a PING request.
@@ -27,6 +33,99 @@ pub const DEFER: InteractionResponse = InteractionResponse {
     data: None,
 };
 
+/// A response telling Discord that we will respond to this interaction
+/// later, and that only the user who used it can see the response.
+pub const DEFER_EPHEMERAL: InteractionResponse = InteractionResponse {
+    kind: InteractionResponseType::DeferredChannelMessageWithSource,
+    data: Some(InteractionResponseData {
+        allowed_mentions: None,
+        attachments: None,
+        choices: None,
+        components: None,
+        content: None,
+        custom_id: None,
+        embeds: None,
+        flags: Some(MessageFlags::EPHEMERAL),
+        title: None,
+        tts: None,
+    }),
+};
+
+/// A response to a component interaction telling Discord that we may update
+/// the message the component is on later, without sending a new message.
+pub const DEFER_UPDATE: InteractionResponse = InteractionResponse {
+    kind: InteractionResponseType::DeferredUpdateMessage,
+    data: None,
+};
+
+/// How to answer an interaction as soon as it is received.
+#[derive(Debug, Clone, PartialEq)]
+pub enum InitialResponse {
+    /// Answer with this response, then queue the interaction so that it can
+    /// be finished later, such as by updating a deferred response.
+    Queue(InteractionResponse),
+    /// Answer with this response, which completes the interaction, so it is
+    /// not queued.
+    Complete(InteractionResponse),
+}
+
+impl InitialResponse {
+    /// Shows the user that Eris is thinking, publicly in the channel, and
+    /// queues the interaction.
+    pub const fn defer() -> Self {
+        Self::Queue(DEFER)
+    }
+
+    /// Shows only the user that Eris is thinking, and queues the
+    /// interaction. Later responses and follow-ups should be ephemeral too.
+    pub const fn defer_ephemeral() -> Self {
+        Self::Queue(DEFER_EPHEMERAL)
+    }
+
+    /// Acknowledges a component interaction without sending a message, and
+    /// queues the interaction.
+    pub const fn defer_update() -> Self {
+        Self::Queue(DEFER_UPDATE)
+    }
+
+    /// Answers immediately with a message, such as one that does not depend
+    /// on any stored data.
+    pub fn message(data: InteractionResponseData) -> Self {
+        Self::Complete(InteractionResponse {
+            kind: InteractionResponseType::ChannelMessageWithSource,
+            data: Some(data),
+        })
+    }
+
+    /// Answers immediately with a modal. When the user submits it, Discord
+    /// sends a separate modal submit interaction.
+    pub fn modal(data: InteractionResponseData) -> Self {
+        Self::Complete(InteractionResponse {
+            kind: InteractionResponseType::Modal,
+            data: Some(data),
+        })
+    }
+}
+
+/// Chooses how to answer an interaction according to the book: /join opens
+/// the handle picker, blocking and unblocking are private to the user who
+/// did it, clicks on post components update the post rather than sending a
+/// message, and anything else is deferred publicly.
+pub fn default_initial_response(interaction: &Interaction) -> InitialResponse {
+    match &interaction.data {
+        Some(InteractionData::ApplicationCommand(command)) => match command.name.as_str() {
+            "join" => InitialResponse::modal(join_modal()),
+            "block" | "unblock" | "Block in channel" | "Unblock in channel" => {
+                InitialResponse::defer_ephemeral()
+            }
+            _ => InitialResponse::defer(),
+        },
+        Some(InteractionData::MessageComponent(_)) => InitialResponse::defer_update(),
+        Some(InteractionData::ModalSubmit(_)) => InitialResponse::defer_ephemeral(),
+        _ => InitialResponse::defer(),
+    }
+}
+
 /// An error attempting to respond to an Interaction.
 #[derive(Debug, Error)]
 pub enum InteractionResponseError<Q: Debug + Display> {
@@ -38,33 +137,73 @@ pub enum InteractionResponseError<Q: Debug + Display> {
     QueueServiceError(Q),
 }
 
-async fn respond_to_interaction<Q>(
-    (mut queue_service, interaction): (Q, Interaction),
+async fn respond_to_interaction<Q, F, A>(
+    ((mut queue_service, choose_response, autocomplete_service), interaction): (
+        (Q, F, A),
+        Interaction,
+    ),
 ) -> Result<(StatusCode, JsonValue), InteractionResponseError<Q::Error>>
 where
     Q: Service<DiscordServerAction, Response = ()>,
     Q::Error: Debug + Display,
+    F: Fn(&Interaction) -> InitialResponse,
+    A: Service<AutocompleteRequest, Response = Vec<CommandOptionChoice>>,
+    A::Error: Display,
 {
     // If the interaction is just a PING we do not need to queue it
     if interaction.kind == InteractionType::Ping {
         return Ok((StatusCode::OK, serde_json::to_value(PONG)?));
     }
 
+    // Autocomplete cannot be deferred, so it is answered now
+    if interaction.kind == InteractionType::ApplicationCommandAutocomplete {
+        let response = autocomplete(autocomplete_service, &interaction).await;
+        return Ok((StatusCode::OK, serde_json::to_value(response)?));
+    }
+
+    let response = match choose_response(&interaction) {
+        InitialResponse::Queue(response) => response,
+        InitialResponse::Complete(response) => {
+            return Ok((StatusCode::OK, serde_json::to_value(response)?));
+        }
+    };
+
+    let server_action = match &interaction.data {
+        Some(InteractionData::MessageComponent(data)) => match PostAction::from_component(data) {
+            Ok(action) => DiscordServerAction::PostComponentInteraction {
+                interaction,
+                action,
+            },
+            Err(e) => {
+                tracing::warn!("Queueing component interaction without an action: {e}");
+                DiscordServerAction::from(interaction)
+            }
+        },
+        _ => DiscordServerAction::from(interaction),
+    };
+
     queue_service
         .ready()
         .await
         .map_err(InteractionResponseError::QueueServiceError)?
-        .call(DiscordServerAction::from(interaction))
+        .call(server_action)
         .await
         .map_err(InteractionResponseError::QueueServiceError)?;
 
-    Ok((StatusCode::OK, serde_json::to_value(DEFER)?))
+    Ok((StatusCode::OK, serde_json::to_value(response)?))
 }
 
-/// Returns a service which takes an incoming Interaction, queues it, and
-/// responds as quickly as possible with 200 OK and DEFERRED_CHANNEL_MESSAGE.
-pub fn respond_to_interaction_layer_fn<Q>(
+/// Returns a service which takes an incoming Interaction, and responds as
+/// quickly as possible with 200 OK and the [InitialResponse] chosen for it,
+/// such as by [default_initial_response], queueing it first unless the
+/// response completes it. Interactions with the components under a post are
+/// queued with the [PostAction] they are for. Autocomplete interactions are
+/// answered with the choices from the autocomplete service, without being
+/// queued.
+pub fn respond_to_interaction_layer_fn<Q, F, A>(
     queue_service: Q,
+    choose_response: F,
+    autocomplete_service: A,
 ) -> impl Service<
     Interaction,
     Response = impl IntoResponse,
@@ -74,9 +213,16 @@ where
     Q: Service<DiscordServerAction, Response = ()>,
     Q: Clone,
     Q::Error: Debug + Display,
+    F: Fn(&Interaction) -> InitialResponse + Clone,
+    A: Service<AutocompleteRequest, Response = Vec<CommandOptionChoice>> + Clone,
+    A::Error: Display,
 {
     ServiceBuilder::new()
-        .layer(ClonedStateProviderLayer::new(queue_service))
+        .layer(ClonedStateProviderLayer::new((
+            queue_service,
+            choose_response,
+            autocomplete_service,
+        )))
         .service_fn(respond_to_interaction)
         .map_response(|(status_code, json)| (status_code, axum::Json(json)))
 }
@@ -1,3 +1,9 @@
+/// Every activity an inbox accepts.
+pub mod activity;
+
+/// The Announce Activity, used to share posts.
+pub mod announce;
+
 /// The base entity and actor in the system.
 pub mod application;
 
@@ -14,9 +20,13 @@ pub mod create;
 /// The Delete Activity.
 pub mod delete;
 
-/// The Follow Activity.
+/// The Follow Activity, and the Accept and Reject Activities
+/// answering it.
 pub mod follow;
 
+/// The data and errors shared by the model's ActivityPub handlers.
+pub mod federation;
+
 /// Any Actor from an external source.
 pub mod foreign_actor;
 
@@ -26,6 +36,10 @@ pub mod image;
 /// The Like activity.
 pub mod like;
 
+/// Media attached to a post, generic over its
+/// object type.
+pub mod media;
+
 /// A Discord message. This is **not** ActivityPub
 /// but does need an internal data representation.\
 pub mod message;
@@ -36,9 +50,15 @@ pub mod post;
 /// A tombstone marker for a deleted entity.
 pub mod tombstone;
 
+/// The Undo Activity.
+pub mod undo;
+
 /// The Update activity.
 pub mod update;
 
+/// The URLs at which the instance serves its objects.
+pub mod urls;
+
 /// A human app user. One-to-one with a Discord user.
 pub mod user;
 
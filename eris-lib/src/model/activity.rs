@@ -0,0 +1,79 @@
+use activitypub_federation::{config::Data, traits::ActivityHandler};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    announce::Announce,
+    block::Block,
+    create::Create,
+    delete::Delete,
+    follow::{Accept, Follow, Reject},
+    like::Like,
+    undo::Undo,
+    update::Update,
+};
+
+/// Any activity which an inbox accepts. Once verified, each is received by
+/// passing it to the activity service of
+/// [super::federation::ErisData].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(untagged)]
+#[enum_delegate::implement(ActivityHandler)]
+pub enum InboxActivity {
+    /// A new post
+    Create(Create),
+    /// An edited post
+    Update(Update),
+    /// A deleted post or actor
+    Delete(Delete),
+    /// A follow request
+    Follow(Follow),
+    /// An accepted follow request
+    Accept(Accept),
+    /// A rejected follow request
+    Reject(Reject),
+    /// An undone follow, like, share or block
+    Undo(Undo),
+    /// A liked post
+    Like(Like),
+    /// A shared post
+    Announce(Announce),
+    /// A blocked actor
+    Block(Block),
+}
+
+impl InboxActivity {
+    /// The type of the activity, such as "Create".
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Self::Create(_) => "Create",
+            Self::Update(_) => "Update",
+            Self::Delete(_) => "Delete",
+            Self::Follow(_) => "Follow",
+            Self::Accept(_) => "Accept",
+            Self::Reject(_) => "Reject",
+            Self::Undo(_) => "Undo",
+            Self::Like(_) => "Like",
+            Self::Announce(_) => "Announce",
+            Self::Block(_) => "Block",
+        }
+    }
+
+    /// The id of what the activity acts on: the post created, updated or
+    /// deleted, the actor followed or blocked, the object liked or shared,
+    /// or the activity accepted, rejected or undone.
+    pub fn object_id(&self) -> &Url {
+        match self {
+            Self::Create(create) => create.object.id.inner(),
+            Self::Update(update) => update.object.id.inner(),
+            Self::Delete(delete) => delete.object.id(),
+            Self::Follow(follow) => &follow.object,
+            Self::Accept(accept) => &accept.object.id,
+            Self::Reject(reject) => &reject.object.id,
+            Self::Undo(undo) => undo.object.id(),
+            Self::Like(like) => &like.object,
+            Self::Announce(announce) => &announce.object,
+            Self::Block(block) => &block.object,
+        }
+    }
+}
@@ -0,0 +1,74 @@
+use activitypub_federation::{
+    config::Data,
+    kinds::activity::AnnounceType,
+    protocol::{helpers::deserialize_one_or_many, verification::verify_domains_match},
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{ErisData, ModelError};
+
+/// An actor sharing an object, usually a post, with its followers.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Announce {
+    /// Always "Announce"
+    #[serde(rename = "type")]
+    pub kind: AnnounceType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor sharing the object
+    pub actor: Url,
+    /// The object shared
+    pub object: Url,
+    /// The primary audience of the share
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub to: Vec<Url>,
+    /// The secondary audience of the share
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub cc: Vec<Url>,
+    /// When the object was shared
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+}
+
+impl Announce {
+    /// A share of an object with this audience, published now.
+    pub fn new(id: Url, actor: Url, object: Url, to: Vec<Url>, cc: Vec<Url>) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+            to,
+            cc,
+            published: Some(Utc::now()),
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Announce {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
@@ -0,0 +1,132 @@
+use activitypub_federation::{
+    config::Data,
+    fetch::object_id::ObjectId,
+    kinds::actor::ApplicationType,
+    protocol::{public_key::PublicKey, verification::verify_domains_match},
+    traits::{Actor, Object},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{Endpoints, ErisData, ModelError, StoredObject};
+
+/// An Eris instance, which performs admin actions such as deleting users
+/// and channels. It is served from the root of the instance's domain, see
+/// [super::urls::UrlLayout].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Application {
+    /// The id of the instance, the root of its domain
+    pub id: Url,
+    /// The preferred username of the instance, usually its domain
+    pub name: String,
+    /// A description of the instance
+    pub summary: Option<String>,
+    /// The inbox of the instance, which is also the shared inbox
+    pub inbox: Url,
+    /// The outbox of the instance
+    pub outbox: Url,
+    /// The public key used to verify activities from the instance
+    pub public_key_pem: String,
+    /// The private key used to sign activities from the instance
+    pub private_key_pem: Option<String>,
+}
+
+/// The JSON-LD representation of an [Application].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ApplicationJson {
+    /// Always "Application"
+    #[serde(rename = "type")]
+    pub kind: ApplicationType,
+    /// The id of the instance
+    pub id: ObjectId<Application>,
+    /// The preferred username of the instance
+    pub preferred_username: String,
+    /// The display name of the instance
+    pub name: String,
+    /// A description of the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// The inbox of the instance
+    pub inbox: Url,
+    /// The outbox of the instance
+    pub outbox: Url,
+    /// Other endpoints of the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Endpoints>,
+    /// The public key of the instance
+    pub public_key: PublicKey,
+}
+
+#[async_trait]
+impl Object for Application {
+    type DataType = ErisData;
+    type Kind = ApplicationJson;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::Application(application)) => Ok(Some(application)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(ApplicationJson {
+            kind: Default::default(),
+            public_key: self.public_key(),
+            id: self.id.into(),
+            preferred_username: self.name.clone(),
+            name: self.name,
+            summary: self.summary,
+            endpoints: Some(Endpoints {
+                shared_inbox: Some(self.inbox.clone()),
+            }),
+            inbox: self.inbox,
+            outbox: self.outbox,
+        })
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        Ok(())
+    }
+
+    /// The instance is never fetched, so this only finds it in the store.
+    async fn from_json(json: Self::Kind, data: &Data<Self::DataType>) -> Result<Self, Self::Error> {
+        let id = json.id.into_inner();
+        Self::read_from_id(id.clone(), data)
+            .await?
+            .ok_or(ModelError::LocalObjectNotFound(id))
+    }
+}
+
+impl Actor for Application {
+    fn id(&self) -> Url {
+        self.id.clone()
+    }
+
+    fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    fn private_key_pem(&self) -> Option<String> {
+        self.private_key_pem.clone()
+    }
+
+    fn inbox(&self) -> Url {
+        self.inbox.clone()
+    }
+
+    fn shared_inbox(&self) -> Option<Url> {
+        Some(self.inbox.clone())
+    }
+}
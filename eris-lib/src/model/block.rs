@@ -0,0 +1,60 @@
+use activitypub_federation::{
+    config::Data, kinds::activity::BlockType, protocol::verification::verify_domains_match,
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{ErisData, ModelError};
+
+/// An actor blocking another, so that the other's posts are not shown
+/// to it and its posts are not sent to the other.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Block {
+    /// Always "Block"
+    #[serde(rename = "type")]
+    pub kind: BlockType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor doing the blocking
+    pub actor: Url,
+    /// The actor blocked
+    pub object: Url,
+}
+
+impl Block {
+    /// A block of an actor.
+    pub fn new(id: Url, actor: Url, object: Url) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Block {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
@@ -0,0 +1,149 @@
+use activitypub_federation::{
+    config::Data,
+    fetch::object_id::ObjectId,
+    kinds::actor::ServiceType,
+    protocol::{public_key::PublicKey, verification::verify_domains_match},
+    traits::{Actor, Object},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+use url::Url;
+
+use super::federation::{Endpoints, ErisData, ModelError, StoredObject};
+
+/// A Discord text channel registered with the instance. Channels follow
+/// other actors to show their posts, but cannot be followed themselves.
+/// They are served from `/channels/{guild_id}/{channel_id}`, see
+/// [super::urls::UrlLayout].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Channel {
+    /// The id of the channel
+    pub id: Url,
+    /// The Discord guild the channel is in
+    pub guild_id: Id<GuildMarker>,
+    /// The Discord channel
+    pub channel_id: Id<ChannelMarker>,
+    /// The name of the channel
+    pub name: String,
+    /// The inbox of the channel
+    pub inbox: Url,
+    /// The outbox of the channel
+    pub outbox: Url,
+    /// The actors the channel follows
+    pub following: Url,
+    /// The shared inbox of the instance
+    pub shared_inbox: Url,
+    /// The public key used to verify activities from the channel
+    pub public_key_pem: String,
+    /// The private key used to sign activities from the channel
+    pub private_key_pem: Option<String>,
+}
+
+impl Channel {
+    /// The preferred username of a channel, unique on the instance.
+    pub fn preferred_username(&self) -> String {
+        format!("{}-{}", self.guild_id, self.channel_id)
+    }
+}
+
+/// The JSON-LD representation of a [Channel].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ServiceJson {
+    /// Always "Service"
+    #[serde(rename = "type")]
+    pub kind: ServiceType,
+    /// The id of the channel
+    pub id: ObjectId<Channel>,
+    /// The unique name of the channel
+    pub preferred_username: String,
+    /// The name of the channel
+    pub name: String,
+    /// The inbox of the channel
+    pub inbox: Url,
+    /// The outbox of the channel
+    pub outbox: Url,
+    /// The actors the channel follows
+    pub following: Url,
+    /// Other endpoints of the instance
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Endpoints>,
+    /// The public key of the channel
+    pub public_key: PublicKey,
+}
+
+#[async_trait]
+impl Object for Channel {
+    type DataType = ErisData;
+    type Kind = ServiceJson;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::Channel(channel)) => Ok(Some(channel)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(ServiceJson {
+            kind: Default::default(),
+            public_key: self.public_key(),
+            preferred_username: self.preferred_username(),
+            id: self.id.into(),
+            name: self.name,
+            inbox: self.inbox,
+            outbox: self.outbox,
+            following: self.following,
+            endpoints: Some(Endpoints {
+                shared_inbox: Some(self.shared_inbox),
+            }),
+        })
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        Ok(())
+    }
+
+    /// Channels are never fetched, so this only finds them in the store.
+    async fn from_json(json: Self::Kind, data: &Data<Self::DataType>) -> Result<Self, Self::Error> {
+        let id = json.id.into_inner();
+        Self::read_from_id(id.clone(), data)
+            .await?
+            .ok_or(ModelError::LocalObjectNotFound(id))
+    }
+}
+
+impl Actor for Channel {
+    fn id(&self) -> Url {
+        self.id.clone()
+    }
+
+    fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    fn private_key_pem(&self) -> Option<String> {
+        self.private_key_pem.clone()
+    }
+
+    fn inbox(&self) -> Url {
+        self.inbox.clone()
+    }
+
+    fn shared_inbox(&self) -> Option<Url> {
+        Some(self.shared_inbox.clone())
+    }
+}
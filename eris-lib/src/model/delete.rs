@@ -0,0 +1,115 @@
+use activitypub_federation::{
+    config::Data,
+    kinds::activity::DeleteType,
+    protocol::{
+        helpers::deserialize_one_or_many,
+        verification::{verify_domains_match, verify_urls_match},
+    },
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    federation::{ErisData, ModelError},
+    tombstone::Tombstone,
+};
+
+/// The object types of actors, which only the actor itself can delete.
+const ACTOR_TYPES: [&str; 5] = ["Application", "Group", "Organization", "Person", "Service"];
+
+/// What a [Delete] deletes. Mastodon sends a [Tombstone] for deleted posts,
+/// but only the id for deleted actors.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DeletedObject {
+    /// The id of the deleted object
+    Id(Url),
+    /// What is left of the deleted object
+    Tombstone(Tombstone),
+}
+
+impl DeletedObject {
+    /// The id of the deleted object.
+    pub fn id(&self) -> &Url {
+        match self {
+            Self::Id(id) => id,
+            Self::Tombstone(tombstone) => &tombstone.id,
+        }
+    }
+
+    /// Whether the tombstone says that the object was an actor.
+    pub fn is_actor(&self) -> bool {
+        match self {
+            Self::Id(_) => false,
+            Self::Tombstone(tombstone) => tombstone
+                .former_type
+                .as_deref()
+                .is_some_and(|former_type| ACTOR_TYPES.contains(&former_type)),
+        }
+    }
+}
+
+/// An actor deleting one of its posts, or itself. The instance also deletes
+/// its users and channels. Whether a deleted post was the actor's is only
+/// known once it is found in the repository, so that is checked when the
+/// deletion is applied.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Delete {
+    /// Always "Delete"
+    #[serde(rename = "type")]
+    pub kind: DeleteType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor deleting the object
+    pub actor: Url,
+    /// The object deleted
+    pub object: DeletedObject,
+    /// The audience told of the deletion
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub to: Vec<Url>,
+}
+
+impl Delete {
+    /// The deletion of an object, told to this audience.
+    pub fn new(id: Url, actor: Url, object: DeletedObject, to: Vec<Url>) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+            to,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Delete {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        // An actor can only delete objects on its own server
+        verify_domains_match(&self.actor, self.object.id())?;
+        // and only itself of the actors
+        if self.object.is_actor() {
+            verify_urls_match(&self.actor, self.object.id())?;
+        }
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
@@ -0,0 +1,123 @@
+use std::sync::{Arc, Mutex};
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use tower::{util::BoxCloneService, Service, ServiceExt};
+use url::Url;
+
+use super::{
+    activity::InboxActivity, application::Application, channel::Channel,
+    foreign_actor::ForeignActor, image::Image, post::Post, tombstone::Tombstone, user::User,
+    video::Video,
+};
+
+/// An error from the ActivityPub model.
+#[derive(Debug, Error)]
+pub enum ModelError {
+    /// An error from [activitypub_federation], such as a failed fetch or a
+    /// bad signature
+    #[error("ActivityPub federation error: {0}")]
+    FederationError(#[from] activitypub_federation::error::Error),
+    /// Could not convert between JSON-LD and the model
+    #[error("Error while (de)serializing JSON: {0}")]
+    JsonError(#[from] serde_json::Error),
+    /// A received object or activity is not consistent with itself, such as
+    /// an activity about an object on another domain than its actor's
+    #[error("Invalid object: {0}")]
+    InvalidObject(&'static str),
+    /// An object on this instance was expected to be stored, but was not
+    #[error("Local object not found: {0}")]
+    LocalObjectNotFound(Url),
+    /// An error from the service storing objects or receiving activities
+    #[error("Storage error: {0}")]
+    StorageError(#[source] Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl From<anyhow::Error> for ModelError {
+    fn from(e: anyhow::Error) -> Self {
+        Self::FederationError(e.into())
+    }
+}
+
+/// A boxed [tower::Service] that can be shared between threads, as the
+/// [activitypub_federation] handlers require. It is cloned for each call, so
+/// the lock is only held while cloning.
+pub struct SharedService<Request, Response>(
+    Arc<Mutex<BoxCloneService<Request, Response, ModelError>>>,
+);
+
+impl<Request, Response> SharedService<Request, Response> {
+    /// Boxes a service to be shared.
+    pub fn new<S>(service: S) -> Self
+    where
+        S: Service<Request, Response = Response, Error = ModelError> + Clone + Send + 'static,
+        S::Future: Send + 'static,
+    {
+        Self(Arc::new(Mutex::new(BoxCloneService::new(service))))
+    }
+
+    /// Calls a clone of the service, waiting until it is ready.
+    pub async fn call(&self, request: Request) -> Result<Response, ModelError> {
+        let service = self
+            .0
+            .lock()
+            .expect("shared service lock should not be poisoned")
+            .clone();
+        service.oneshot(request).await
+    }
+}
+
+impl<Request, Response> Clone for SharedService<Request, Response> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+/// An object stored by this instance, as returned by the object store of
+/// [ErisData].
+#[derive(Debug, Clone)]
+pub enum StoredObject {
+    /// This instance
+    Application(Application),
+    /// A local user
+    User(User),
+    /// A local channel
+    Channel(Channel),
+    /// A post, local or foreign
+    Post(Post),
+    /// An image attached to a post
+    Image(Image),
+    /// A video attached to a post
+    Video(Video),
+    /// An actor on another server
+    ForeignActor(ForeignActor),
+    /// Something that was deleted
+    Tombstone(Tombstone),
+}
+
+/// The data shared by every [activitypub_federation] handler, available from
+/// [activitypub_federation::config::Data::app_data].
+#[derive(Clone)]
+pub struct ErisData {
+    /// Looks up a stored object by its id, so that objects on this instance
+    /// are never fetched over HTTP, and foreign ones not more than needed.
+    pub object_store: SharedService<Url, Option<StoredObject>>,
+    /// Receives every incoming activity, once it has been verified.
+    pub activity_service: SharedService<InboxActivity, ()>,
+}
+
+impl ErisData {
+    /// Looks up a stored object by its id.
+    pub async fn stored_object(&self, id: Url) -> Result<Option<StoredObject>, ModelError> {
+        self.object_store.call(id).await
+    }
+}
+
+/// The `endpoints` of an actor, used to find the shared inbox of its server.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Endpoints {
+    /// The inbox which receives activities for every actor on the server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub shared_inbox: Option<Url>,
+}
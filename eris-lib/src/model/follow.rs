@@ -0,0 +1,167 @@
+use activitypub_federation::{
+    config::Data,
+    kinds::activity::{AcceptType, FollowType, RejectType},
+    protocol::verification::{verify_domains_match, verify_urls_match},
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{ErisData, ModelError};
+
+/// A request by an actor to receive the posts of another. Users can be
+/// followed, channels follow others.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Follow {
+    /// Always "Follow"
+    #[serde(rename = "type")]
+    pub kind: FollowType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor who wants to follow
+    pub actor: Url,
+    /// The actor to be followed
+    pub object: Url,
+}
+
+impl Follow {
+    /// A follow of an actor.
+    pub fn new(id: Url, actor: Url, object: Url) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Follow {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
+
+/// The acceptance of a [Follow] by the actor it followed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Accept {
+    /// Always "Accept"
+    #[serde(rename = "type")]
+    pub kind: AcceptType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor who was followed
+    pub actor: Url,
+    /// The follow being accepted
+    pub object: Follow,
+}
+
+impl Accept {
+    /// The acceptance of a follow of this actor.
+    pub fn new(id: Url, actor: Url, object: Follow) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Accept {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        // Only the followed actor can accept
+        verify_urls_match(&self.actor, &self.object.object)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
+
+/// The refusal of a [Follow] by the actor it followed, or the removal of a
+/// follower.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Reject {
+    /// Always "Reject"
+    #[serde(rename = "type")]
+    pub kind: RejectType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor who was followed
+    pub actor: Url,
+    /// The follow being rejected
+    pub object: Follow,
+}
+
+impl Reject {
+    /// The refusal of a follow of this actor.
+    pub fn new(id: Url, actor: Url, object: Follow) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Reject {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        // Only the followed actor can reject
+        verify_urls_match(&self.actor, &self.object.object)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
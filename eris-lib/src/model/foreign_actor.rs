@@ -0,0 +1,174 @@
+use activitypub_federation::{
+    config::Data,
+    fetch::object_id::ObjectId,
+    protocol::{
+        public_key::PublicKey,
+        verification::{verify_domains_match, verify_urls_match},
+    },
+    traits::{Actor, Object},
+};
+use async_trait::async_trait;
+use chrono::{NaiveDateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{Endpoints, ErisData, ModelError, StoredObject};
+
+/// The ActivityStreams actor types, any of which a foreign actor may be.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum ActorType {
+    /// A bot or service, including Eris channels
+    Service,
+    /// A software application, including Eris instances
+    Application,
+    /// A group of actors, such as a Lemmy community
+    Group,
+    /// An organization
+    Organization,
+    /// A human user
+    Person,
+}
+
+/// An actor on another server, known because it sent an activity to the
+/// instance or was followed or blocked by a local actor. Only what is needed
+/// to verify its activities and deliver to it is kept.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignActor {
+    /// The id of the actor
+    pub id: Url,
+    /// What kind of actor it is
+    pub kind: ActorType,
+    /// The handle of the actor, used with its domain in WebFinger
+    pub preferred_username: Option<String>,
+    /// The name shown for the actor
+    pub name: Option<String>,
+    /// The page showing the actor on its server
+    pub url: Option<Url>,
+    /// The inbox of the actor
+    pub inbox: Url,
+    /// The shared inbox of the actor's server, if it has one
+    pub shared_inbox: Option<Url>,
+    /// The public key used to verify activities from the actor
+    pub public_key_pem: String,
+    /// When the actor was last fetched from its server
+    pub last_refreshed_at: NaiveDateTime,
+}
+
+/// The JSON-LD representation of a [ForeignActor], as served by Mastodon and
+/// other servers. Fields Eris does not use are ignored.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ActorJson {
+    /// What kind of actor it is
+    #[serde(rename = "type")]
+    pub kind: ActorType,
+    /// The id of the actor
+    pub id: ObjectId<ForeignActor>,
+    /// The handle of the actor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub preferred_username: Option<String>,
+    /// The name shown for the actor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The page showing the actor on its server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// The inbox of the actor
+    pub inbox: Url,
+    /// The outbox of the actor
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub outbox: Option<Url>,
+    /// Other endpoints of the actor's server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Endpoints>,
+    /// The public key of the actor
+    pub public_key: PublicKey,
+}
+
+#[async_trait]
+impl Object for ForeignActor {
+    type DataType = ErisData;
+    type Kind = ActorJson;
+    type Error = ModelError;
+
+    fn last_refreshed_at(&self) -> Option<NaiveDateTime> {
+        Some(self.last_refreshed_at)
+    }
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::ForeignActor(actor)) => Ok(Some(actor)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(ActorJson {
+            public_key: self.public_key(),
+            kind: self.kind,
+            id: self.id.into(),
+            preferred_username: self.preferred_username,
+            name: self.name,
+            url: self.url,
+            inbox: self.inbox,
+            outbox: None,
+            endpoints: self.shared_inbox.map(|shared_inbox| Endpoints {
+                shared_inbox: Some(shared_inbox),
+            }),
+        })
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        verify_domains_match(json.id.inner(), &json.inbox)?;
+        // Otherwise an actor could claim the key of another
+        verify_urls_match(json.id.inner(), &json.public_key.owner)?;
+        Ok(())
+    }
+
+    async fn from_json(
+        json: Self::Kind,
+        _data: &Data<Self::DataType>,
+    ) -> Result<Self, Self::Error> {
+        Ok(ForeignActor {
+            id: json.id.into_inner(),
+            kind: json.kind,
+            preferred_username: json.preferred_username,
+            name: json.name,
+            url: json.url,
+            inbox: json.inbox,
+            shared_inbox: json.endpoints.and_then(|endpoints| endpoints.shared_inbox),
+            public_key_pem: json.public_key.public_key_pem,
+            last_refreshed_at: Utc::now().naive_utc(),
+        })
+    }
+}
+
+impl Actor for ForeignActor {
+    fn id(&self) -> Url {
+        self.id.clone()
+    }
+
+    fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    fn private_key_pem(&self) -> Option<String> {
+        None
+    }
+
+    fn inbox(&self) -> Url {
+        self.inbox.clone()
+    }
+
+    fn shared_inbox(&self) -> Option<Url> {
+        self.shared_inbox.clone()
+    }
+}
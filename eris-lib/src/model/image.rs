@@ -0,0 +1,25 @@
+use activitypub_federation::kinds::object::ImageType;
+
+use super::{
+    federation::StoredObject,
+    media::{Media, MediaJson, MediaKind},
+    post::AttachmentType,
+};
+
+/// An image attached to a post. Local images are served from
+/// `/users/{user_id}/posts/{post_id}/attachments/images/{image_id}`.
+pub type Image = Media<ImageType>;
+
+/// The JSON-LD representation of an [Image].
+pub type ImageJson = MediaJson<ImageType>;
+
+impl MediaKind for ImageType {
+    const ATTACHMENT_TYPE: AttachmentType = AttachmentType::Image;
+
+    fn from_stored(object: StoredObject) -> Option<Image> {
+        match object {
+            StoredObject::Image(image) => Some(image),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,59 @@
+use activitypub_federation::{
+    config::Data, kinds::activity::LikeType, protocol::verification::verify_domains_match,
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{ErisData, ModelError};
+
+/// An actor liking an object, usually a post.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Like {
+    /// Always "Like"
+    #[serde(rename = "type")]
+    pub kind: LikeType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor who likes the object
+    pub actor: Url,
+    /// The object liked
+    pub object: Url,
+}
+
+impl Like {
+    /// A like of an object.
+    pub fn new(id: Url, actor: Url, object: Url) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Like {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, _data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        Ok(())
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
@@ -0,0 +1,139 @@
+use std::fmt::Debug;
+
+use activitypub_federation::{
+    config::Data, fetch::object_id::ObjectId, protocol::verification::verify_domains_match,
+    traits::Object,
+};
+use async_trait::async_trait;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    federation::{ErisData, ModelError, StoredObject},
+    post::{AttachmentJson, AttachmentType},
+};
+
+/// The object type of a kind of [Media], such as
+/// [activitypub_federation::kinds::object::ImageType].
+pub trait MediaKind:
+    Debug + Clone + Default + PartialEq + Eq + Serialize + DeserializeOwned + Send + Sync + 'static
+{
+    /// How media of this kind is attached to a post
+    const ATTACHMENT_TYPE: AttachmentType;
+
+    /// Returns the stored object if it is media of this kind.
+    fn from_stored(object: StoredObject) -> Option<Media<Self>>;
+}
+
+/// Media attached to a post, such as an [super::image::Image] or a
+/// [super::video::Video]. Eris does not host the media itself, only its
+/// URL. Local media is served from under
+/// `/users/{user_id}/posts/{post_id}/attachments/`, see
+/// [super::urls::UrlLayout]. Media attached to foreign posts usually has no
+/// id of its own, so it is identified by its URL instead.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Media<K> {
+    /// The object type of the media
+    pub kind: K,
+    /// The id of the media
+    pub id: Url,
+    /// Where the media itself is
+    pub url: Url,
+    /// The MIME type of the media, such as image/png
+    pub media_type: Option<String>,
+    /// A description of the media, for those who cannot see or hear it
+    pub name: Option<String>,
+}
+
+/// The JSON-LD representation of [Media].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", bound = "K: MediaKind")]
+pub struct MediaJson<K: MediaKind> {
+    /// The object type of the media, such as "Image"
+    #[serde(rename = "type")]
+    pub kind: K,
+    /// The id of the media
+    pub id: ObjectId<Media<K>>,
+    /// Where the media itself is
+    pub url: Url,
+    /// The MIME type of the media
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// A description of the media
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl<K: MediaKind> From<Media<K>> for AttachmentJson {
+    fn from(media: Media<K>) -> Self {
+        AttachmentJson {
+            kind: K::ATTACHMENT_TYPE,
+            id: Some(media.id),
+            url: media.url,
+            media_type: media.media_type,
+            name: media.name,
+        }
+    }
+}
+
+impl<K: MediaKind> From<AttachmentJson> for Media<K> {
+    fn from(attachment: AttachmentJson) -> Self {
+        Media {
+            kind: K::default(),
+            id: attachment.id.unwrap_or_else(|| attachment.url.clone()),
+            url: attachment.url,
+            media_type: attachment.media_type,
+            name: attachment.name,
+        }
+    }
+}
+
+#[async_trait]
+impl<K: MediaKind> Object for Media<K> {
+    type DataType = ErisData;
+    type Kind = MediaJson<K>;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        Ok(data
+            .app_data()
+            .stored_object(object_id)
+            .await?
+            .and_then(K::from_stored))
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(MediaJson {
+            kind: self.kind,
+            id: self.id.into(),
+            url: self.url,
+            media_type: self.media_type,
+            name: self.name,
+        })
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        Ok(())
+    }
+
+    async fn from_json(
+        json: Self::Kind,
+        _data: &Data<Self::DataType>,
+    ) -> Result<Self, Self::Error> {
+        Ok(Media {
+            kind: json.kind,
+            id: json.id.into_inner(),
+            url: json.url,
+            media_type: json.media_type,
+            name: json.name,
+        })
+    }
+}
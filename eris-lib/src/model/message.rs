@@ -0,0 +1,23 @@
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{marker::GuildMarker, Id};
+use url::Url;
+
+use crate::payloads::MessageLocation;
+
+/// A Discord message showing a post in a channel. A post shared to many
+/// channels has a message in each, and all of them are updated or deleted
+/// along with the post. Messages are not ActivityPub objects, so they have
+/// no id of their own.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Message {
+    /// Where the message is in Discord
+    pub location: MessageLocation,
+    /// The guild the message is in
+    pub guild_id: Id<GuildMarker>,
+    /// The id of the post the message shows
+    pub post_id: Url,
+    /// When the message was sent
+    pub sent_at: DateTime<Utc>,
+}
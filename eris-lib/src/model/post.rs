@@ -0,0 +1,262 @@
+use activitypub_federation::{
+    config::Data,
+    fetch::object_id::ObjectId,
+    protocol::{helpers::deserialize_one_or_many, verification::verify_domains_match},
+    traits::Object,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    federation::{ErisData, ModelError, StoredObject},
+    image::Image,
+    video::Video,
+};
+
+/// A post on the network, which Eris shows in Discord as an embed. Local
+/// posts are served from `/users/{user_id}/posts/{post_id}`, see
+/// [super::urls::UrlLayout]. A post has at most one image and one video.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Post {
+    /// The id of the post
+    pub id: Url,
+    /// The actor who wrote the post
+    pub attributed_to: Url,
+    /// The body of the post, as HTML
+    pub content: String,
+    /// The content warning of the post
+    pub summary: Option<String>,
+    /// Whether the post should be hidden behind its summary
+    pub sensitive: bool,
+    /// The page showing the post on its server
+    pub url: Option<Url>,
+    /// The post this one replies to
+    pub in_reply_to: Option<Url>,
+    /// When the post was first published
+    pub published: Option<DateTime<Utc>>,
+    /// When the post was last updated
+    pub updated: Option<DateTime<Utc>>,
+    /// The primary audience of the post
+    pub to: Vec<Url>,
+    /// The secondary audience of the post
+    pub cc: Vec<Url>,
+    /// The image attached to the post
+    pub image: Option<Image>,
+    /// The video attached to the post
+    pub video: Option<Video>,
+}
+
+/// The object types which Eris handles as posts. Eris only creates Notes,
+/// but shows the content of the others as best it can, such as the
+/// question of a poll.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum PostType {
+    /// A short post
+    #[default]
+    Note,
+    /// A long post, such as a blog entry
+    Article,
+    /// A web page
+    Page,
+    /// A poll
+    Question,
+}
+
+/// The object types of attachments.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum AttachmentType {
+    /// Any file, used by Mastodon for every attachment
+    Document,
+    /// An image
+    Image,
+    /// A video
+    Video,
+    /// An audio file
+    Audio,
+}
+
+/// An attachment of a post, as embedded in a [NoteJson]. Which kind of
+/// media it is may depend on its MIME type rather than its object type.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AttachmentJson {
+    /// The object type of the attachment
+    #[serde(rename = "type")]
+    pub kind: AttachmentType,
+    /// The id of the attachment, if it has one
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub id: Option<Url>,
+    /// Where the attached file is
+    pub url: Url,
+    /// The MIME type of the attached file
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub media_type: Option<String>,
+    /// A description of the attachment
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+}
+
+impl AttachmentJson {
+    fn has_media_type(&self, prefix: &str) -> bool {
+        self.media_type
+            .as_deref()
+            .is_some_and(|media_type| media_type.starts_with(prefix))
+    }
+
+    /// Whether the attachment is an image.
+    pub fn is_image(&self) -> bool {
+        match self.kind {
+            AttachmentType::Image => true,
+            AttachmentType::Document => self.has_media_type("image/"),
+            _ => false,
+        }
+    }
+
+    /// Whether the attachment is a video.
+    pub fn is_video(&self) -> bool {
+        match self.kind {
+            AttachmentType::Video => true,
+            AttachmentType::Document => self.has_media_type("video/"),
+            _ => false,
+        }
+    }
+}
+
+/// The JSON-LD representation of a [Post].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct NoteJson {
+    /// The object type, "Note" for every post Eris creates
+    #[serde(rename = "type")]
+    pub kind: PostType,
+    /// The id of the post
+    pub id: ObjectId<Post>,
+    /// The actor who wrote the post
+    pub attributed_to: Url,
+    /// The body of the post, as HTML
+    #[serde(default)]
+    pub content: String,
+    /// The content warning of the post
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Whether the post should be hidden behind its summary
+    #[serde(default)]
+    pub sensitive: bool,
+    /// The page showing the post on its server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub url: Option<Url>,
+    /// The post this one replies to
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub in_reply_to: Option<Url>,
+    /// When the post was first published
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub published: Option<DateTime<Utc>>,
+    /// When the post was last updated
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub updated: Option<DateTime<Utc>>,
+    /// The primary audience of the post
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub to: Vec<Url>,
+    /// The secondary audience of the post
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub cc: Vec<Url>,
+    /// The files attached to the post
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachment: Vec<AttachmentJson>,
+}
+
+impl From<Post> for NoteJson {
+    fn from(post: Post) -> Self {
+        let attachment = post
+            .image
+            .map(AttachmentJson::from)
+            .into_iter()
+            .chain(post.video.map(AttachmentJson::from))
+            .collect();
+
+        NoteJson {
+            kind: PostType::Note,
+            id: post.id.into(),
+            attributed_to: post.attributed_to,
+            content: post.content,
+            summary: post.summary,
+            sensitive: post.sensitive,
+            url: post.url,
+            in_reply_to: post.in_reply_to,
+            published: post.published,
+            updated: post.updated,
+            to: post.to,
+            cc: post.cc,
+            attachment,
+        }
+    }
+}
+
+/// Keeps the first image and the first video of the note, ignoring any
+/// other attachment.
+impl From<NoteJson> for Post {
+    fn from(json: NoteJson) -> Self {
+        let (images, videos): (Vec<_>, Vec<_>) = json
+            .attachment
+            .into_iter()
+            .filter(|attachment| attachment.is_image() || attachment.is_video())
+            .partition(AttachmentJson::is_image);
+
+        Post {
+            id: json.id.into_inner(),
+            attributed_to: json.attributed_to,
+            content: json.content,
+            summary: json.summary,
+            sensitive: json.sensitive,
+            url: json.url,
+            in_reply_to: json.in_reply_to,
+            published: json.published,
+            updated: json.updated,
+            to: json.to,
+            cc: json.cc,
+            image: images.into_iter().next().map(Image::from),
+            video: videos.into_iter().next().map(Video::from),
+        }
+    }
+}
+
+#[async_trait]
+impl Object for Post {
+    type DataType = ErisData;
+    type Kind = NoteJson;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::Post(post)) => Ok(Some(post)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(self.into())
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        // A post can only be written by an actor on its own server
+        verify_domains_match(json.id.inner(), &json.attributed_to)?;
+        Ok(())
+    }
+
+    async fn from_json(
+        json: Self::Kind,
+        _data: &Data<Self::DataType>,
+    ) -> Result<Self, Self::Error> {
+        Ok(json.into())
+    }
+}
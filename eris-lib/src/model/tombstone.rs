@@ -0,0 +1,77 @@
+use activitypub_federation::{
+    config::Data, kinds::object::TombstoneType, protocol::verification::verify_domains_match,
+    traits::Object,
+};
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::federation::{ErisData, ModelError, StoredObject};
+
+/// What is left of a deleted object, so that its id keeps answering that it
+/// was deleted rather than that it never existed.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Tombstone {
+    /// Always "Tombstone"
+    #[serde(rename = "type")]
+    pub kind: TombstoneType,
+    /// The id of the deleted object
+    pub id: Url,
+    /// The object type of the deleted object, such as "Note"
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub former_type: Option<String>,
+    /// When the object was deleted
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub deleted: Option<DateTime<Utc>>,
+}
+
+impl Tombstone {
+    /// A tombstone for an object of this type, deleted at this time.
+    pub fn new(id: Url, former_type: impl Into<String>, deleted: DateTime<Utc>) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            former_type: Some(former_type.into()),
+            deleted: Some(deleted),
+        }
+    }
+}
+
+#[async_trait]
+impl Object for Tombstone {
+    type DataType = ErisData;
+    type Kind = Tombstone;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::Tombstone(tombstone)) => Ok(Some(tombstone)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(self)
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(&json.id, expected_domain)?;
+        Ok(())
+    }
+
+    async fn from_json(
+        json: Self::Kind,
+        _data: &Data<Self::DataType>,
+    ) -> Result<Self, Self::Error> {
+        Ok(json)
+    }
+}
@@ -0,0 +1,84 @@
+use activitypub_federation::{
+    config::Data,
+    kinds::activity::UndoType,
+    protocol::verification::{verify_domains_match, verify_urls_match},
+    traits::ActivityHandler,
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    announce::Announce,
+    block::Block,
+    federation::{ErisData, ModelError},
+    follow::Follow,
+    like::Like,
+};
+
+/// An activity which can be undone.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+#[enum_delegate::implement(ActivityHandler)]
+pub enum UndoneActivity {
+    /// Stop following
+    Follow(Follow),
+    /// Remove a like
+    Like(Like),
+    /// Stop sharing
+    Announce(Announce),
+    /// Unblock
+    Block(Block),
+}
+
+/// An actor undoing one of its earlier activities.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Undo {
+    /// Always "Undo"
+    #[serde(rename = "type")]
+    pub kind: UndoType,
+    /// The id of the activity
+    pub id: Url,
+    /// The actor undoing the activity
+    pub actor: Url,
+    /// The activity undone
+    pub object: UndoneActivity,
+}
+
+impl Undo {
+    /// The undoing of an activity.
+    pub fn new(id: Url, actor: Url, object: UndoneActivity) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Undo {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        // Only the actor of an activity can undo it
+        verify_urls_match(&self.actor, self.object.actor())?;
+        self.object.verify(data).await
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
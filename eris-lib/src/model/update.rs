@@ -0,0 +1,77 @@
+use activitypub_federation::{
+    config::Data,
+    kinds::activity::UpdateType,
+    protocol::{
+        helpers::deserialize_one_or_many,
+        verification::{verify_domains_match, verify_urls_match},
+    },
+    traits::{ActivityHandler, Object},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use url::Url;
+
+use super::{
+    federation::{ErisData, ModelError},
+    post::{NoteJson, Post},
+};
+
+/// An actor editing one of its posts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct Update {
+    /// Always "Update"
+    #[serde(rename = "type")]
+    pub kind: UpdateType,
+    /// The id of the activity
+    pub id: Url,
+    /// The author of the post
+    pub actor: Url,
+    /// The post as edited
+    pub object: NoteJson,
+    /// The primary audience of the post
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub to: Vec<Url>,
+    /// The secondary audience of the post
+    #[serde(default, deserialize_with = "deserialize_one_or_many")]
+    pub cc: Vec<Url>,
+}
+
+impl Update {
+    /// An edit of a post, sent to the post's audience.
+    pub fn new(id: Url, actor: Url, object: NoteJson) -> Self {
+        Self {
+            kind: Default::default(),
+            id,
+            actor,
+            to: object.to.clone(),
+            cc: object.cc.clone(),
+            object,
+        }
+    }
+}
+
+#[async_trait]
+impl ActivityHandler for Update {
+    type DataType = ErisData;
+    type Error = ModelError;
+
+    fn id(&self) -> &Url {
+        &self.id
+    }
+
+    fn actor(&self) -> &Url {
+        &self.actor
+    }
+
+    async fn verify(&self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        verify_domains_match(&self.id, &self.actor)?;
+        // Only the author of a post can edit it
+        verify_urls_match(&self.actor, &self.object.attributed_to)?;
+        Post::verify(&self.object, &self.id, data).await
+    }
+
+    async fn receive(self, data: &Data<Self::DataType>) -> Result<(), Self::Error> {
+        data.app_data().activity_service.call(self.into()).await
+    }
+}
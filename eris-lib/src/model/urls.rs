@@ -0,0 +1,233 @@
+use twilight_model::id::{
+    marker::{ChannelMarker, GuildMarker},
+    Id,
+};
+use url::Url;
+
+/// An object hosted by the instance, as identified by its URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocalUrl {
+    /// The instance itself
+    Instance,
+    /// A user
+    User(i64),
+    /// A channel
+    Channel(Id<GuildMarker>, Id<ChannelMarker>),
+    /// A post
+    Post {
+        /// The user who wrote the post
+        user_id: i64,
+        /// The post
+        post_id: i64,
+    },
+    /// An image attached to a post
+    Image {
+        /// The user who wrote the post
+        user_id: i64,
+        /// The post
+        post_id: i64,
+        /// The image
+        image_id: i64,
+    },
+    /// A video attached to a post
+    Video {
+        /// The user who wrote the post
+        user_id: i64,
+        /// The post
+        post_id: i64,
+        /// The video
+        video_id: i64,
+    },
+    /// An activity
+    Activity(i64),
+}
+
+/// An inbox hosted by the instance, as identified by its URL.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LocalInbox {
+    /// The shared inbox, which is also the inbox of the instance
+    Shared,
+    /// The inbox of a user
+    User(i64),
+    /// The inbox of a channel
+    Channel(Id<GuildMarker>, Id<ChannelMarker>),
+}
+
+/// Parses a Discord id from a URL path segment.
+fn snowflake<T>(segment: &str) -> Option<Id<T>> {
+    segment.parse().ok().and_then(Id::new_checked)
+}
+
+/// Builds the ids of the objects hosted by an instance, which are also the
+/// URLs they are served from. Every id is below the instance's domain:
+///
+/// | Object                  | Path                                                             |
+/// |-------------------------|------------------------------------------------------------------|
+/// | Instance (Application)  | `/`                                                              |
+/// | Shared inbox            | `/inbox`                                                         |
+/// | Instance outbox         | `/outbox`                                                        |
+/// | User (Person)           | `/users/{user_id}`                                               |
+/// | User collections        | `/users/{user_id}/{inbox,outbox,followers,following,liked}`      |
+/// | Channel (Service)       | `/channels/{guild_id}/{channel_id}`                              |
+/// | Channel collections     | `/channels/{guild_id}/{channel_id}/{inbox,outbox,following}`     |
+/// | Post (Note)             | `/users/{user_id}/posts/{post_id}`                               |
+/// | Post collections        | `/users/{user_id}/posts/{post_id}/{likes,shares}`                |
+/// | Image attachment        | `/users/{user_id}/posts/{post_id}/attachments/images/{image_id}` |
+/// | Video attachment        | `/users/{user_id}/posts/{post_id}/attachments/videos/{video_id}` |
+/// | Activity                | `/activities/{activity_id}`                                      |
+///
+/// Each actor's public key is `{actor id}#main-key`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UrlLayout {
+    base: Url,
+}
+
+impl UrlLayout {
+    /// The layout of the instance at this domain, served over https.
+    pub fn new(domain: &str) -> Result<Self, url::ParseError> {
+        Url::parse(&format!("https://{domain}/")).map(|base| Self { base })
+    }
+
+    fn path(&self, path: &str) -> Url {
+        self.base
+            .join(path)
+            .expect("paths built from ids should always be valid URLs")
+    }
+
+    /// The URL of a request to the instance, from the path it was made to.
+    /// The path is appended to the instance rather than resolved against
+    /// it, so that a path such as `//other.example/...` stays on the
+    /// instance. None if that is not a valid URL.
+    pub fn at_path(&self, path: &str) -> Option<Url> {
+        let path = path.strip_prefix('/').unwrap_or(path);
+        Url::parse(&format!("{}{path}", self.base)).ok()
+    }
+
+    /// The instance itself.
+    pub fn instance(&self) -> Url {
+        self.base.clone()
+    }
+
+    /// The shared inbox, which is also the inbox of the instance.
+    pub fn shared_inbox(&self) -> Url {
+        self.path("inbox")
+    }
+
+    /// The outbox of the instance.
+    pub fn instance_outbox(&self) -> Url {
+        self.path("outbox")
+    }
+
+    /// A user.
+    pub fn user(&self, user_id: i64) -> Url {
+        self.path(&format!("users/{user_id}"))
+    }
+
+    /// A collection belonging to a user, such as `inbox` or `followers`.
+    pub fn user_collection(&self, user_id: i64, collection: &str) -> Url {
+        self.path(&format!("users/{user_id}/{collection}"))
+    }
+
+    /// A channel.
+    pub fn channel(&self, guild_id: Id<GuildMarker>, channel_id: Id<ChannelMarker>) -> Url {
+        self.path(&format!("channels/{guild_id}/{channel_id}"))
+    }
+
+    /// A collection belonging to a channel, such as `inbox` or `following`.
+    pub fn channel_collection(
+        &self,
+        guild_id: Id<GuildMarker>,
+        channel_id: Id<ChannelMarker>,
+        collection: &str,
+    ) -> Url {
+        self.path(&format!("channels/{guild_id}/{channel_id}/{collection}"))
+    }
+
+    /// A post, below the user who wrote it.
+    pub fn post(&self, user_id: i64, post_id: i64) -> Url {
+        self.path(&format!("users/{user_id}/posts/{post_id}"))
+    }
+
+    /// A collection belonging to a post, such as `likes` or `shares`.
+    pub fn post_collection(&self, user_id: i64, post_id: i64, collection: &str) -> Url {
+        self.path(&format!("users/{user_id}/posts/{post_id}/{collection}"))
+    }
+
+    /// An image attached to a post.
+    pub fn image(&self, user_id: i64, post_id: i64, image_id: i64) -> Url {
+        self.path(&format!(
+            "users/{user_id}/posts/{post_id}/attachments/images/{image_id}"
+        ))
+    }
+
+    /// A video attached to a post.
+    pub fn video(&self, user_id: i64, post_id: i64, video_id: i64) -> Url {
+        self.path(&format!(
+            "users/{user_id}/posts/{post_id}/attachments/videos/{video_id}"
+        ))
+    }
+
+    /// An activity performed by an actor on this instance.
+    pub fn activity(&self, activity_id: i64) -> Url {
+        self.path(&format!("activities/{activity_id}"))
+    }
+
+    /// Finds which object a URL is the id of, if it is one hosted by this
+    /// instance. Collections and other URLs which are not objects are None.
+    pub fn parse(&self, url: &Url) -> Option<LocalUrl> {
+        let path = url.as_str().strip_prefix(self.base.as_str())?;
+        if url.query().is_some() || url.fragment().is_some() {
+            return None;
+        }
+        let segments: Vec<&str> = path.split('/').collect();
+        let id = |segment: &str| segment.parse::<i64>().ok();
+
+        match segments.as_slice() {
+            [""] => Some(LocalUrl::Instance),
+            ["users", user_id] => id(user_id).map(LocalUrl::User),
+            ["channels", guild_id, channel_id] => Some(LocalUrl::Channel(
+                snowflake(guild_id)?,
+                snowflake(channel_id)?,
+            )),
+            ["users", user_id, "posts", post_id] => Some(LocalUrl::Post {
+                user_id: id(user_id)?,
+                post_id: id(post_id)?,
+            }),
+            ["users", user_id, "posts", post_id, "attachments", "images", image_id] => {
+                Some(LocalUrl::Image {
+                    user_id: id(user_id)?,
+                    post_id: id(post_id)?,
+                    image_id: id(image_id)?,
+                })
+            }
+            ["users", user_id, "posts", post_id, "attachments", "videos", video_id] => {
+                Some(LocalUrl::Video {
+                    user_id: id(user_id)?,
+                    post_id: id(post_id)?,
+                    video_id: id(video_id)?,
+                })
+            }
+            ["activities", activity_id] => id(activity_id).map(LocalUrl::Activity),
+            _ => None,
+        }
+    }
+
+    /// Finds which inbox a URL is, if it is one hosted by this instance.
+    pub fn parse_inbox(&self, url: &Url) -> Option<LocalInbox> {
+        let path = url.as_str().strip_prefix(self.base.as_str())?;
+        if url.query().is_some() || url.fragment().is_some() {
+            return None;
+        }
+        let segments: Vec<&str> = path.split('/').collect();
+
+        match segments.as_slice() {
+            ["inbox"] => Some(LocalInbox::Shared),
+            ["users", user_id, "inbox"] => user_id.parse().ok().map(LocalInbox::User),
+            ["channels", guild_id, channel_id, "inbox"] => Some(LocalInbox::Channel(
+                snowflake(guild_id)?,
+                snowflake(channel_id)?,
+            )),
+            _ => None,
+        }
+    }
+}
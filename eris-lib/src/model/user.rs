@@ -0,0 +1,163 @@
+use activitypub_federation::{
+    config::Data,
+    fetch::object_id::ObjectId,
+    kinds::actor::PersonType,
+    protocol::{public_key::PublicKey, verification::verify_domains_match},
+    traits::{Actor, Object},
+};
+use async_trait::async_trait;
+use serde::{Deserialize, Serialize};
+use twilight_model::id::{marker::UserMarker, Id};
+use url::Url;
+
+use super::federation::{Endpoints, ErisData, ModelError, StoredObject};
+
+/// A Discord user who has joined the instance with /join. Users are served
+/// from `/users/{user_id}`, see [super::urls::UrlLayout].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct User {
+    /// The id of the user
+    pub id: Url,
+    /// The Discord user this user is
+    pub discord_user_id: Id<UserMarker>,
+    /// The handle picked with /join, used in WebFinger
+    pub handle: String,
+    /// The name shown for the user
+    pub display_name: Option<String>,
+    /// The user's profile
+    pub summary: Option<String>,
+    /// Whether the user approves follow requests themselves
+    pub manually_approves_followers: bool,
+    /// The inbox of the user
+    pub inbox: Url,
+    /// The outbox of the user
+    pub outbox: Url,
+    /// The actors following the user
+    pub followers: Url,
+    /// The actors the user follows
+    pub following: Url,
+    /// The objects the user has liked
+    pub liked: Url,
+    /// The shared inbox of the instance
+    pub shared_inbox: Url,
+    /// The public key used to verify activities from the user
+    pub public_key_pem: String,
+    /// The private key used to sign activities from the user
+    pub private_key_pem: Option<String>,
+}
+
+/// The JSON-LD representation of a [User].
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct PersonJson {
+    /// Always "Person"
+    #[serde(rename = "type")]
+    pub kind: PersonType,
+    /// The id of the user
+    pub id: ObjectId<User>,
+    /// The handle of the user
+    pub preferred_username: String,
+    /// The name shown for the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    /// The user's profile, as HTML
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub summary: Option<String>,
+    /// Whether follow requests must be approved
+    #[serde(default)]
+    pub manually_approves_followers: bool,
+    /// The inbox of the user
+    pub inbox: Url,
+    /// The outbox of the user
+    pub outbox: Url,
+    /// The actors following the user
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub followers: Option<Url>,
+    /// The actors the user follows
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub following: Option<Url>,
+    /// The objects the user has liked
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub liked: Option<Url>,
+    /// Other endpoints of the user's server
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub endpoints: Option<Endpoints>,
+    /// The public key of the user
+    pub public_key: PublicKey,
+}
+
+#[async_trait]
+impl Object for User {
+    type DataType = ErisData;
+    type Kind = PersonJson;
+    type Error = ModelError;
+
+    async fn read_from_id(
+        object_id: Url,
+        data: &Data<Self::DataType>,
+    ) -> Result<Option<Self>, Self::Error> {
+        match data.app_data().stored_object(object_id).await? {
+            Some(StoredObject::User(user)) => Ok(Some(user)),
+            _ => Ok(None),
+        }
+    }
+
+    async fn into_json(self, _data: &Data<Self::DataType>) -> Result<Self::Kind, Self::Error> {
+        Ok(PersonJson {
+            kind: Default::default(),
+            public_key: self.public_key(),
+            id: self.id.into(),
+            preferred_username: self.handle,
+            name: self.display_name,
+            summary: self.summary,
+            manually_approves_followers: self.manually_approves_followers,
+            inbox: self.inbox,
+            outbox: self.outbox,
+            followers: Some(self.followers),
+            following: Some(self.following),
+            liked: Some(self.liked),
+            endpoints: Some(Endpoints {
+                shared_inbox: Some(self.shared_inbox),
+            }),
+        })
+    }
+
+    async fn verify(
+        json: &Self::Kind,
+        expected_domain: &Url,
+        _data: &Data<Self::DataType>,
+    ) -> Result<(), Self::Error> {
+        verify_domains_match(json.id.inner(), expected_domain)?;
+        Ok(())
+    }
+
+    /// Users are never fetched, so this only finds them in the store.
+    async fn from_json(json: Self::Kind, data: &Data<Self::DataType>) -> Result<Self, Self::Error> {
+        let id = json.id.into_inner();
+        Self::read_from_id(id.clone(), data)
+            .await?
+            .ok_or(ModelError::LocalObjectNotFound(id))
+    }
+}
+
+impl Actor for User {
+    fn id(&self) -> Url {
+        self.id.clone()
+    }
+
+    fn public_key_pem(&self) -> &str {
+        &self.public_key_pem
+    }
+
+    fn private_key_pem(&self) -> Option<String> {
+        self.private_key_pem.clone()
+    }
+
+    fn inbox(&self) -> Url {
+        self.inbox.clone()
+    }
+
+    fn shared_inbox(&self) -> Option<Url> {
+        Some(self.shared_inbox.clone())
+    }
+}
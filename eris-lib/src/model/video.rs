@@ -0,0 +1,25 @@
+use activitypub_federation::kinds::object::VideoType;
+
+use super::{
+    federation::StoredObject,
+    media::{Media, MediaJson, MediaKind},
+    post::AttachmentType,
+};
+
+/// A video attached to a post. Local videos are served from
+/// `/users/{user_id}/posts/{post_id}/attachments/videos/{video_id}`.
+pub type Video = Media<VideoType>;
+
+/// The JSON-LD representation of a [Video].
+pub type VideoJson = MediaJson<VideoType>;
+
+impl MediaKind for VideoType {
+    const ATTACHMENT_TYPE: AttachmentType = AttachmentType::Video;
+
+    fn from_stored(object: StoredObject) -> Option<Video> {
+        match object {
+            StoredObject::Video(video) => Some(video),
+            _ => None,
+        }
+    }
+}
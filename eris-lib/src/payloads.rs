@@ -1,10 +1,18 @@
 mod discord_client_action_response;
 mod discord_client_actions;
 mod discord_server_action;
+mod join_modal;
+mod message_components;
 
 pub use discord_client_action_response::DiscordClientActionResponse;
 pub use discord_client_actions::{
-    CreateMessage, CreateReply, DeleteMessage, DiscordClientAction, MessageLocation,
-    MessagePayload, UpdateInteractionResponse, UpdateMessage
+    CreateFollowup, CreateMessage, CreateReply, DeleteInteractionResponse, DeleteMessage,
+    DiscordClientAction, MessageAttachment, MessageLocation, MessagePayload, RichMessage,
+    UpdateInteractionResponse, UpdateMessage
 };
 pub use discord_server_action::DiscordServerAction;
+pub use join_modal::{join_modal, JOIN_HANDLE_INPUT_ID, JOIN_MODAL_ID, MAX_HANDLE_LENGTH};
+pub use message_components::{
+    open_original_button, post_actions_menu, post_components, PostAction, UnknownComponentError,
+    POST_ACTIONS_MENU_ID,
+};
@@ -1,6 +1,8 @@
+use serde::{Deserialize, Serialize};
 use twilight_model::channel::Message;
 
 /// A response to an action taken by the Discord client service.
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum DiscordClientActionResponse {
     /// A CreateMessage request was successful. Requires storing the message id
     /// and other important fields in the database in case we need to edit or
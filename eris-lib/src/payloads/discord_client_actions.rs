@@ -1,6 +1,6 @@
 use serde::{Deserialize, Serialize};
 use twilight_model::{
-    channel::message::Embed,
+    channel::message::{AllowedMentions, Component, Embed, MessageFlags},
     id::{
         marker::{ChannelMarker, MessageMarker},
         Id,
@@ -17,9 +17,14 @@ pub enum DiscordClientAction {
     CreateMessage(CreateMessage),
     /// Creates a message replying to another message in that same channel.
     CreateReply(CreateReply),
+    /// Sends another message in response to an interaction, after the
+    /// initial response.
+    CreateFollowup(CreateFollowup),
     /// Delete a message from a channel. Optionally, may include a reason, which
     /// will be stored in the audit logs for the server.
     DeleteMessage(DeleteMessage),
+    /// Deletes the initial response to an interaction.
+    DeleteInteractionResponse(DeleteInteractionResponse),
     /// Updates the initial response, changing it from the typing indicator to a
     /// proper message.
     UpdateInteractionResponse(UpdateInteractionResponse),
@@ -36,6 +41,7 @@ impl DiscordClientAction {
     ) -> Self {
         Self::CreateMessage(CreateMessage {
             channel_id: channel_id.into(),
+            idempotency_key: None,
             message: MessagePayload::Text(text.into()),
         })
     }
@@ -57,6 +63,7 @@ impl DiscordClientAction {
 
         Self::CreateMessage(CreateMessage {
             channel_id: channel_id.into(),
+            idempotency_key: None,
             message: payload,
         })
     }
@@ -72,6 +79,7 @@ impl DiscordClientAction {
                 channel_id: channel_id.into(),
                 message_id: replying_to.into(),
             },
+            idempotency_key: None,
             message: MessagePayload::Text(text.into()),
         })
     }
@@ -97,10 +105,63 @@ impl DiscordClientAction {
                 channel_id: channel_id.into(),
                 message_id: replying_to.into(),
             },
+            idempotency_key: None,
             message: payload,
         })
     }
 
+    /// Sets the idempotency key of a [CreateMessage] or [CreateReply], so that
+    /// Discord creates at most one message for it, however many times it is
+    /// sent. Other actions are already idempotent, and are returned unchanged.
+    pub fn with_idempotency_key(mut self, idempotency_key: u64) -> Self {
+        match &mut self {
+            Self::CreateMessage(CreateMessage {
+                idempotency_key: key,
+                ..
+            })
+            | Self::CreateReply(CreateReply {
+                idempotency_key: key,
+                ..
+            }) => *key = Some(idempotency_key),
+            Self::CreateFollowup(_)
+            | Self::DeleteMessage(_)
+            | Self::DeleteInteractionResponse(_)
+            | Self::UpdateInteractionResponse(_)
+            | Self::UpdateMessage(_) => {}
+        }
+        self
+    }
+
+    /// Creates a standalone message in a channel with any combination of
+    /// text, embeds and attachments.
+    pub fn create_rich_message(
+        channel_id: impl Into<Id<ChannelMarker>>,
+        message: RichMessage,
+    ) -> Self {
+        Self::CreateMessage(CreateMessage {
+            channel_id: channel_id.into(),
+            idempotency_key: None,
+            message: MessagePayload::Rich(message),
+        })
+    }
+
+    /// Replies to a message with any combination of text, embeds and
+    /// attachments.
+    pub fn create_rich_reply(
+        channel_id: impl Into<Id<ChannelMarker>>,
+        replying_to: impl Into<Id<MessageMarker>>,
+        message: RichMessage,
+    ) -> Self {
+        Self::CreateReply(CreateReply {
+            message_location: MessageLocation {
+                channel_id: channel_id.into(),
+                message_id: replying_to.into(),
+            },
+            idempotency_key: None,
+            message: MessagePayload::Rich(message),
+        })
+    }
+
     /// Deletes a message and, optionally, puts a reason into the audit log.
     pub fn delete_message(
         channel_id: impl Into<Id<ChannelMarker>>,
@@ -148,6 +209,56 @@ impl DiscordClientAction {
         })
     }
 
+    /// Responds to an interaction with any combination of text, embeds and
+    /// attachments.
+    pub fn interaction_response_rich(
+        interaction_token: impl Into<String>,
+        message: RichMessage,
+    ) -> Self {
+        Self::UpdateInteractionResponse(UpdateInteractionResponse {
+            interaction_token: interaction_token.into(),
+            message: MessagePayload::Rich(message),
+        })
+    }
+
+    /// Sends a text message in response to an interaction, after the initial
+    /// response. If ephemeral, only the user who used the interaction can see
+    /// it.
+    pub fn interaction_followup_text(
+        interaction_token: impl Into<String>,
+        text: impl Into<String>,
+        ephemeral: bool,
+    ) -> Self {
+        Self::CreateFollowup(CreateFollowup {
+            interaction_token: interaction_token.into(),
+            ephemeral,
+            message: MessagePayload::Text(text.into()),
+        })
+    }
+
+    /// Sends a message with any combination of text, embeds, attachments and
+    /// components in response to an interaction, after the initial response.
+    /// If ephemeral, only the user who used the interaction can see it.
+    pub fn interaction_followup_rich(
+        interaction_token: impl Into<String>,
+        message: RichMessage,
+        ephemeral: bool,
+    ) -> Self {
+        Self::CreateFollowup(CreateFollowup {
+            interaction_token: interaction_token.into(),
+            ephemeral,
+            message: MessagePayload::Rich(message),
+        })
+    }
+
+    /// Deletes the initial response to an interaction, such as once a
+    /// deferred response is no longer needed.
+    pub fn delete_interaction_response(interaction_token: impl Into<String>) -> Self {
+        Self::DeleteInteractionResponse(DeleteInteractionResponse {
+            interaction_token: interaction_token.into(),
+        })
+    }
+
     /// Updates an existing message's body, overwriting it with static text.
     pub fn update_text_message(
         channel_id: impl Into<Id<ChannelMarker>>,
@@ -187,6 +298,22 @@ impl DiscordClientAction {
             message: payload,
         })
     }
+
+    /// Updates an existing message, replacing its text, embeds and
+    /// attachments.
+    pub fn update_rich_message(
+        channel_id: impl Into<Id<ChannelMarker>>,
+        message_id: impl Into<Id<MessageMarker>>,
+        message: RichMessage,
+    ) -> Self {
+        Self::UpdateMessage(UpdateMessage {
+            message_location: MessageLocation {
+                channel_id: channel_id.into(),
+                message_id: message_id.into(),
+            },
+            message: MessagePayload::Rich(message),
+        })
+    }
 }
 
 /// Creates a standalone message in a channel.
@@ -195,6 +322,13 @@ impl DiscordClientAction {
 pub struct CreateMessage {
     /// The Id of the channel to send the message in.
     pub channel_id: Id<ChannelMarker>,
+    /// Sent to Discord as the message nonce, which Discord is asked to
+    /// enforce, so that if a message was already created in this channel
+    /// with the same key, that message is returned instead of creating
+    /// another. This makes it safe to retry after a timeout. Derive it from
+    /// whatever the message is about, so that redelivered actions share it.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<u64>,
     /// The payload of the message.
     #[serde(flatten)]
     pub message: MessagePayload,
@@ -207,11 +341,29 @@ pub struct CreateReply {
     /// The location of the message to be replied to.
     #[serde(flatten)]
     pub message_location: MessageLocation,
+    /// Makes the reply idempotent, as in [CreateMessage::idempotency_key].
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub idempotency_key: Option<u64>,
     /// The reply message.
     #[serde(flatten)]
     pub message: MessagePayload,
 }
 
+/// Sends another message in response to an interaction, after the initial
+/// response.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CreateFollowup {
+    /// The token for the interaction to follow up.
+    pub interaction_token: String,
+    /// Whether only the user who used the interaction can see the message.
+    #[serde(default)]
+    pub ephemeral: bool,
+    /// The follow-up message.
+    #[serde(flatten)]
+    pub message: MessagePayload,
+}
+
 /// Delete a message from a channel. Optionally, may include a reason, which
 /// will be stored in the audit logs for the server.
 #[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
@@ -224,6 +376,14 @@ pub struct DeleteMessage {
     pub reason: Option<String>,
 }
 
+/// Deletes the initial response to an interaction.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeleteInteractionResponse {
+    /// The token for the interaction whose response is deleted.
+    pub interaction_token: String,
+}
+
 /// The location of a message within Discord.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -252,6 +412,66 @@ pub enum MessagePayload {
         /// The embed.
         embed: Embed,
     },
+    /// Any combination of text, embeds and attachments, such as a foreign
+    /// post with several images.
+    Rich(RichMessage),
+}
+
+/// A message with any combination of text, embeds, attachments and
+/// components, and control over who it notifies.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct RichMessage {
+    /// The text outside any embed frame.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content: Option<String>,
+    /// The embeds, at most 10.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub embeds: Vec<Embed>,
+    /// Files uploaded with the message.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub attachments: Vec<MessageAttachment>,
+    /// Who may be pinged by mentions in the text. If not set, the client's
+    /// default is used.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub allowed_mentions: Option<AllowedMentions>,
+    /// Flags such as suppressing notifications or embeds. Discord ignores
+    /// flags it does not allow to be set, and interaction responses cannot
+    /// have their flags updated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub flags: Option<MessageFlags>,
+    /// Action rows of buttons and select menus shown under the message, at
+    /// most 5, such as from [super::post_components].
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub components: Vec<Component>,
+}
+
+/// A file uploaded with a [RichMessage].
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+#[serde(tag = "source")]
+pub enum MessageAttachment {
+    /// A file which is downloaded when the message is sent, so that it is
+    /// hosted by Discord rather than hotlinked.
+    Url {
+        /// Where to download the file from.
+        url: String,
+        /// The name of the file as shown in Discord.
+        filename: String,
+        /// Alt text for the file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
+    /// A file which is already in memory.
+    Bytes {
+        /// The contents of the file.
+        bytes: Vec<u8>,
+        /// The name of the file as shown in Discord.
+        filename: String,
+        /// Alt text for the file.
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        description: Option<String>,
+    },
 }
 
 /// Updates the initial response, changing it from the typing indicator to a
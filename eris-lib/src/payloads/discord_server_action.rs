@@ -1,10 +1,22 @@
-use crate::payloads::DiscordClientActionResponse;
+use crate::payloads::{DiscordClientActionResponse, PostAction};
+use serde::{Deserialize, Serialize};
 use twilight_model::application::interaction::Interaction;
 
 /// Actions that Discord's server might take which may require processing by Eris.
+/// They serialize to JSON, so that they can be queued durably.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[allow(clippy::large_enum_variant)]
 pub enum DiscordServerAction {
     /// Discord made a POST request to our Interactions endpoint.
     PostInteraction(Interaction),
+    /// A user clicked a button or picked from a select menu under one of our
+    /// posts. The post is the interaction's message.
+    PostComponentInteraction {
+        /// The component interaction.
+        interaction: Interaction,
+        /// The action parsed from the component.
+        action: PostAction,
+    },
     /// Discord responded to an action taken by the Discord client.
     DiscordClientActionResponse(DiscordClientActionResponse),
 }
@@ -0,0 +1,38 @@
+use twilight_model::{
+    channel::message::{
+        component::{ActionRow, TextInput, TextInputStyle},
+        Component,
+    },
+    http::interaction::InteractionResponseData,
+};
+
+/// The custom_id of the modal in which a user picks their handle after
+/// using /join.
+pub const JOIN_MODAL_ID: &str = "join";
+
+/// The custom_id of the handle field of the /join modal.
+pub const JOIN_HANDLE_INPUT_ID: &str = "handle";
+
+/// The longest handle a user can pick.
+pub const MAX_HANDLE_LENGTH: usize = 30;
+
+/// The modal in which a user picks their WebFinger handle after using /join.
+pub fn join_modal() -> InteractionResponseData {
+    InteractionResponseData {
+        custom_id: Some(JOIN_MODAL_ID.to_string()),
+        title: Some("Join Eris".to_string()),
+        components: Some(vec![Component::ActionRow(ActionRow {
+            components: vec![Component::TextInput(TextInput {
+                custom_id: JOIN_HANDLE_INPUT_ID.to_string(),
+                label: "Pick a handle, unique on this instance".to_string(),
+                max_length: Some(MAX_HANDLE_LENGTH as u16),
+                min_length: Some(1),
+                placeholder: None,
+                required: Some(true),
+                style: TextInputStyle::Short,
+                value: None,
+            })],
+        })]),
+        ..Default::default()
+    }
+}
@@ -0,0 +1,172 @@
+use std::str::FromStr;
+
+use serde::{Deserialize, Serialize};
+use thiserror::Error;
+use twilight_model::{
+    application::interaction::message_component::MessageComponentInteractionData,
+    channel::message::component::{
+        ActionRow, Button, ButtonStyle, Component, ComponentType, SelectMenu, SelectMenuOption,
+    },
+};
+
+/// The custom_id of the select menu of less common [PostAction]s. The action
+/// picked is the value of the selected option.
+pub const POST_ACTIONS_MENU_ID: &str = "post_actions";
+
+/// Something a user can do to a post by using one of the components under
+/// it, as an alternative to the message commands in
+/// [crate::deploy::message].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PostAction {
+    /// Like the post
+    Like,
+    /// Remove a like from the post
+    Unlike,
+    /// Share the post with the user's followers
+    Share,
+    /// Follow the author of the post in this channel
+    FollowAuthor,
+    /// Stop following the author of the post in this channel
+    UnfollowAuthor,
+    /// Block the author of the post from appearing in this channel
+    BlockAuthor,
+}
+
+impl PostAction {
+    /// The custom_id of the button for this action, which is also the value
+    /// of its option in the post actions select menu.
+    pub const fn custom_id(self) -> &'static str {
+        match self {
+            Self::Like => "like",
+            Self::Unlike => "unlike",
+            Self::Share => "share",
+            Self::FollowAuthor => "follow_author",
+            Self::UnfollowAuthor => "unfollow_author",
+            Self::BlockAuthor => "block_author",
+        }
+    }
+
+    /// The text shown to users for this action.
+    pub const fn label(self) -> &'static str {
+        match self {
+            Self::Like => "Like",
+            Self::Unlike => "Unlike",
+            Self::Share => "Share",
+            Self::FollowAuthor => "Follow author",
+            Self::UnfollowAuthor => "Unfollow author",
+            Self::BlockAuthor => "Block author in channel",
+        }
+    }
+
+    /// A button which performs this action.
+    pub fn button(self, style: ButtonStyle) -> Component {
+        Component::Button(Button {
+            custom_id: Some(self.custom_id().to_string()),
+            disabled: false,
+            emoji: None,
+            label: Some(self.label().to_string()),
+            style,
+            url: None,
+        })
+    }
+
+    /// Parses the action from a component interaction: the custom_id of a
+    /// button, or the value picked from the post actions select menu.
+    pub fn from_component(
+        data: &MessageComponentInteractionData,
+    ) -> Result<Self, UnknownComponentError> {
+        match data.component_type {
+            ComponentType::SelectMenu if data.custom_id == POST_ACTIONS_MENU_ID => {
+                match data.values.as_slice() {
+                    [value] => value.parse(),
+                    _ => Err(UnknownComponentError(data.custom_id.clone())),
+                }
+            }
+            _ => data.custom_id.parse(),
+        }
+    }
+}
+
+impl FromStr for PostAction {
+    type Err = UnknownComponentError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "like" => Ok(Self::Like),
+            "unlike" => Ok(Self::Unlike),
+            "share" => Ok(Self::Share),
+            "follow_author" => Ok(Self::FollowAuthor),
+            "unfollow_author" => Ok(Self::UnfollowAuthor),
+            "block_author" => Ok(Self::BlockAuthor),
+            _ => Err(UnknownComponentError(s.to_string())),
+        }
+    }
+}
+
+/// A component interaction whose custom_id or selected value is not a
+/// [PostAction], such as one from a message sent by an older version of Eris.
+#[derive(Debug, Error)]
+#[error("Unknown message component: {0}")]
+pub struct UnknownComponentError(pub String);
+
+/// A button linking to the original of a post on its home server. Link
+/// buttons open in the browser, so Discord does not send an interaction for
+/// them.
+pub fn open_original_button(url: impl Into<String>) -> Component {
+    Component::Button(Button {
+        custom_id: None,
+        disabled: false,
+        emoji: None,
+        label: Some("Open original".to_string()),
+        style: ButtonStyle::Link,
+        url: Some(url.into()),
+    })
+}
+
+/// A select menu of the less common [PostAction]s.
+pub fn post_actions_menu() -> Component {
+    let options = [
+        PostAction::Unlike,
+        PostAction::UnfollowAuthor,
+        PostAction::BlockAuthor,
+    ]
+    .into_iter()
+    .map(|action| SelectMenuOption {
+        default: false,
+        description: None,
+        emoji: None,
+        label: action.label().to_string(),
+        value: action.custom_id().to_string(),
+    })
+    .collect();
+
+    Component::SelectMenu(SelectMenu {
+        custom_id: POST_ACTIONS_MENU_ID.to_string(),
+        disabled: false,
+        max_values: Some(1),
+        min_values: Some(1),
+        options,
+        placeholder: Some("More actions".to_string()),
+    })
+}
+
+/// The components shown under a post: a row of buttons to like and share it,
+/// open the original, and follow its author, then a row with a menu of less
+/// common actions. Set these as the components of a
+/// [super::RichMessage].
+pub fn post_components(original_url: impl Into<String>) -> Vec<Component> {
+    vec![
+        Component::ActionRow(ActionRow {
+            components: vec![
+                PostAction::Like.button(ButtonStyle::Primary),
+                PostAction::Share.button(ButtonStyle::Secondary),
+                open_original_button(original_url),
+                PostAction::FollowAuthor.button(ButtonStyle::Secondary),
+            ],
+        }),
+        Component::ActionRow(ActionRow {
+            components: vec![post_actions_menu()],
+        }),
+    ]
+}
@@ -1,15 +1,48 @@
+/// A service which serves the ActivityPub objects of the instance, and the
+/// tombstones of deleted ones.
+pub mod activitypub_object;
+
+/// A service which receives signed activities POSTed to the shared inbox
+/// and to the inboxes of users and channels.
+pub mod activitypub_inbox;
+
+/// A service which deduplicates verified activities and dispatches each to
+/// the handler service for its kind.
+pub mod activity_router;
+
+/// A durable store of [DiscordClientAction]s which could not be sent to
+/// Discord, which can be inspected, replayed or discarded.
+pub mod dead_letter_queue;
+
+/// Answers autocomplete interactions with suggestions, such as actors known
+/// to the channel, within Discord's deadline.
+pub mod autocomplete;
+
 /// A service which receives [DiscordClientAction]s and sends them to Discord,
 /// and queues any responses.
 pub mod discord_client_action;
 
 /// A service which receives POST requests made by Discord,
-/// fowards them onto a handler service, and immediately responds with
-/// DEFERRED_CHANNEL_MESSAGE_WITH_SOURCE to prevent timeouts.
+/// fowards them onto a handler service, and immediately responds with a
+/// deferred message, a modal, or autocomplete choices to prevent timeouts.
 pub mod discord_endpoint;
 
-/// A service which sends requests into a [tokio::sync::mpsc::unbounded_channel].
+/// A service which queues [DiscordClientAction]s by Discord rate limit
+/// bucket, and sends them as fast as the limits allow.
+pub mod discord_scheduler;
+
+/// A service which parses queued interactions into typed commands and
+/// dispatches them to a handler service for each kind of command.
+pub mod interaction_router;
+
+/// Services which send requests into an in-memory queue, either unbounded or
+/// bounded with backpressure.
 pub mod in_memory_queue;
 
+/// A service which stores requests in a durable queue in Postgres, and
+/// delivers them to a subscriber at least once.
+pub mod postgres_queue;
+
 /// A [tower::Service] which processes [DiscordClientAction]s and
 /// sometimes returns a [DiscordClientActionResponse] for additional
 /// processing.
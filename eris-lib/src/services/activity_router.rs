@@ -0,0 +1,127 @@
+use std::future::Future;
+
+use activitypub_federation::traits::ActivityHandler;
+use tower::{service_fn, Service, ServiceExt};
+use url::Url;
+
+use crate::model::{
+    activity::InboxActivity,
+    announce::Announce,
+    block::Block,
+    create::Create,
+    delete::Delete,
+    federation::{ModelError, SharedService},
+    follow::{Accept, Follow, Reject},
+    like::Like,
+    undo::Undo,
+    update::Update,
+};
+
+/// The handler service for each kind of activity an inbox accepts. Every
+/// handler of [ActivityHandlers::default] accepts its activities and does
+/// nothing else, so that only the kinds which need handling have to be set.
+#[derive(Clone)]
+pub struct ActivityHandlers {
+    /// Handles new posts
+    pub create: SharedService<Create, ()>,
+    /// Handles edited posts
+    pub update: SharedService<Update, ()>,
+    /// Handles deleted posts and actors
+    pub delete: SharedService<Delete, ()>,
+    /// Handles follow requests
+    pub follow: SharedService<Follow, ()>,
+    /// Handles accepted follow requests
+    pub accept: SharedService<Accept, ()>,
+    /// Handles rejected follow requests
+    pub reject: SharedService<Reject, ()>,
+    /// Handles undone follows, likes, shares and blocks
+    pub undo: SharedService<Undo, ()>,
+    /// Handles liked posts
+    pub like: SharedService<Like, ()>,
+    /// Handles shared posts
+    pub announce: SharedService<Announce, ()>,
+    /// Handles blocked actors
+    pub block: SharedService<Block, ()>,
+}
+
+/// A handler which accepts any activity of its kind and does nothing.
+fn ignore<T: Send + 'static>() -> SharedService<T, ()> {
+    SharedService::new(service_fn(|_: T| async { Ok::<_, ModelError>(()) }))
+}
+
+impl Default for ActivityHandlers {
+    fn default() -> Self {
+        Self {
+            create: ignore(),
+            update: ignore(),
+            delete: ignore(),
+            follow: ignore(),
+            accept: ignore(),
+            reject: ignore(),
+            undo: ignore(),
+            like: ignore(),
+            announce: ignore(),
+            block: ignore(),
+        }
+    }
+}
+
+/// A service which receives verified activities, to be used as the activity
+/// service of [crate::model::federation::ErisData], and calls the handler
+/// for the kind of each.
+///
+/// Each activity is first passed to `deduplicate_service`, which answers
+/// whether its id has not been recorded yet. An activity recorded before,
+/// such as one redelivered by a server which did not get our response, is
+/// accepted without being handled again. Once its handler succeeds, the
+/// activity is passed to `record_service`, which records it. An activity
+/// whose handler fails is not recorded, so that it is handled again if it
+/// is redelivered. Two deliveries of an activity at the same time may both
+/// be handled.
+pub fn activity_router<D, R>(
+    deduplicate_service: D,
+    record_service: R,
+    handlers: ActivityHandlers,
+) -> impl Service<
+    InboxActivity,
+    Response = (),
+    Error = ModelError,
+    Future = impl Future<Output = Result<(), ModelError>> + Send,
+> + Clone
+where
+    D: Service<Url, Response = bool, Error = ModelError> + Clone + Send,
+    D::Future: Send,
+    R: Service<InboxActivity, Response = bool, Error = ModelError> + Clone + Send,
+    R::Future: Send,
+{
+    service_fn(move |activity: InboxActivity| {
+        let deduplicate_service = deduplicate_service.clone();
+        let record_service = record_service.clone();
+        let handlers = handlers.clone();
+        async move {
+            let id = activity.id().clone();
+            if !deduplicate_service.oneshot(id.clone()).await? {
+                tracing::debug!("Ignoring activity {id} which was already received");
+                return Ok(());
+            }
+
+            match activity.clone() {
+                InboxActivity::Create(create) => handlers.create.call(create).await,
+                InboxActivity::Update(update) => handlers.update.call(update).await,
+                InboxActivity::Delete(delete) => handlers.delete.call(delete).await,
+                InboxActivity::Follow(follow) => handlers.follow.call(follow).await,
+                InboxActivity::Accept(accept) => handlers.accept.call(accept).await,
+                InboxActivity::Reject(reject) => handlers.reject.call(reject).await,
+                InboxActivity::Undo(undo) => handlers.undo.call(undo).await,
+                InboxActivity::Like(like) => handlers.like.call(like).await,
+                InboxActivity::Announce(announce) => handlers.announce.call(announce).await,
+                InboxActivity::Block(block) => handlers.block.call(block).await,
+            }?;
+
+            if !record_service.oneshot(activity).await? {
+                tracing::debug!("Activity {id} was recorded while it was handled");
+            }
+            Ok(())
+        }
+    })
+}
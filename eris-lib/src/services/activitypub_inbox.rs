@@ -0,0 +1,157 @@
+use std::{fmt::Display, future::Future};
+
+use activitypub_federation::{
+    axum::inbox::{receive_activity, ActivityData},
+    config::{Data, FederationConfig},
+    error::Error as FederationError,
+};
+use axum::{
+    body::HttpBody,
+    extract::FromRequest,
+    response::{IntoResponse, Response},
+    BoxError,
+};
+use http::{header, Method, Request, StatusCode};
+use tower::{service_fn, Service};
+
+use crate::model::{
+    activity::InboxActivity,
+    federation::{ErisData, ModelError, StoredObject},
+    foreign_actor::ForeignActor,
+    urls::{LocalInbox, UrlLayout},
+};
+
+/// The media types an activity may be posted as.
+const ACTIVITY_MEDIA_TYPES: [&str; 2] = ["application/activity+json", "application/ld+json"];
+
+/// Whether the request body is an activity, going by its Content-Type.
+fn is_activity<B>(request: &Request<B>) -> bool {
+    request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.split(';').next())
+        .is_some_and(|media_type| ACTIVITY_MEDIA_TYPES.contains(&media_type.trim()))
+}
+
+/// Checks that the actor an inbox belongs to exists. Returns the status to
+/// answer with if it does not.
+async fn check_recipient(
+    inbox: LocalInbox,
+    urls: &UrlLayout,
+    data: &Data<ErisData>,
+) -> Result<Option<StatusCode>, ModelError> {
+    let recipient = match inbox {
+        LocalInbox::Shared => return Ok(None),
+        LocalInbox::User(user_id) => urls.user(user_id),
+        LocalInbox::Channel(guild_id, channel_id) => urls.channel(guild_id, channel_id),
+    };
+    let status = match data.stored_object(recipient).await? {
+        Some(StoredObject::User(_)) if matches!(inbox, LocalInbox::User(_)) => None,
+        Some(StoredObject::Channel(_)) if matches!(inbox, LocalInbox::Channel(..)) => None,
+        Some(StoredObject::Tombstone(_)) => Some(StatusCode::GONE),
+        _ => Some(StatusCode::NOT_FOUND),
+    };
+    Ok(status)
+}
+
+/// The response to an activity which was not received. Errors from storing
+/// or handling the activity are passed through.
+fn rejection(error: ModelError) -> Result<Response, ModelError> {
+    let status = match &error {
+        ModelError::FederationError(FederationError::ObjectDeleted) => {
+            // The actor was deleted, so its activities, such as its own
+            // Delete, can no longer be verified. Retrying will not help.
+            tracing::debug!("Dropping activity from a deleted actor");
+            return Ok(StatusCode::ACCEPTED.into_response());
+        }
+        ModelError::FederationError(
+            FederationError::ActivityBodyDigestInvalid
+            | FederationError::ActivitySignatureInvalid
+            | FederationError::WebfingerResolveFailed,
+        ) => StatusCode::UNAUTHORIZED,
+        // Fetching the sending actor failed, such as because its server is
+        // down, which may not happen when the activity is redelivered
+        ModelError::FederationError(FederationError::NotFound | FederationError::Other(_)) => {
+            StatusCode::SERVICE_UNAVAILABLE
+        }
+        ModelError::FederationError(
+            FederationError::UrlVerificationError(_)
+            | FederationError::RequestLimit
+            | FederationError::ResponseBodyLimit,
+        )
+        | ModelError::JsonError(_)
+        | ModelError::InvalidObject(_)
+        | ModelError::LocalObjectNotFound(_) => StatusCode::BAD_REQUEST,
+        ModelError::StorageError(_) => return Err(error),
+    };
+    tracing::warn!("Rejected activity with {status}: {error}");
+    Ok(status.into_response())
+}
+
+/// A service which receives activities POSTed to the shared inbox, or to
+/// the inbox of a user or a channel, as laid out by [UrlLayout]. Every
+/// activity is checked with [receive_activity]: the Digest header must
+/// match the body, the sending actor is fetched if it is not already
+/// stored, and the request must be signed with its key. The activity is
+/// then verified and passed to the activity service of [ErisData], such as
+/// [crate::services::activity_router::activity_router].
+///
+/// Received activities answer 202 Accepted, whether or not they were
+/// received before. Requests which cannot be verified answer 401
+/// Unauthorized, and activities which are not valid 400 Bad Request.
+/// Requests whose actor could not be fetched answer 503 Service
+/// Unavailable, so that they are redelivered. An inbox whose actor was
+/// deleted answers 410 Gone. Errors from the object store or the activity
+/// service are passed through.
+pub fn activitypub_inbox_service<B>(
+    config: FederationConfig<ErisData>,
+    urls: UrlLayout,
+) -> impl Service<
+    Request<B>,
+    Response = Response,
+    Error = ModelError,
+    Future = impl Future<Output = Result<Response, ModelError>> + Send,
+> + Clone
+where
+    B: HttpBody + Send + 'static,
+    B::Data: Send,
+    B::Error: Into<BoxError> + Display,
+{
+    service_fn(move |request: Request<B>| {
+        let data = config.to_request_data();
+        let urls = urls.clone();
+        async move {
+            let Some(inbox) = urls
+                .at_path(request.uri().path())
+                .and_then(|url| urls.parse_inbox(&url))
+            else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+            if request.method() != Method::POST {
+                return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+            }
+            if !is_activity(&request) {
+                return Ok(StatusCode::UNSUPPORTED_MEDIA_TYPE.into_response());
+            }
+            // Unsigned requests are turned away before fetching anything
+            if !request.headers().contains_key("Signature") {
+                return Ok(StatusCode::UNAUTHORIZED.into_response());
+            }
+            if let Some(status) = check_recipient(inbox, &urls, &data).await? {
+                return Ok(status.into_response());
+            }
+
+            let activity_data = match ActivityData::from_request(request, &()).await {
+                Ok(activity_data) => activity_data,
+                Err(response) => return Ok(response),
+            };
+            match receive_activity::<InboxActivity, ForeignActor, ErisData>(activity_data, &data)
+                .await
+            {
+                Ok(()) => Ok(StatusCode::ACCEPTED.into_response()),
+                Err(e) => rejection(e),
+            }
+        }
+    })
+}
@@ -0,0 +1,88 @@
+use std::future::Future;
+
+use activitypub_federation::{
+    axum::json::FederationJson,
+    config::{Data, FederationConfig},
+    protocol::context::WithContext,
+    traits::Object,
+};
+use axum::response::IntoResponse;
+use http::{Method, Request, StatusCode};
+use serde_json::Value;
+use tower::{service_fn, Service};
+
+use crate::model::{
+    federation::{ErisData, ModelError, StoredObject},
+    urls::UrlLayout,
+};
+
+/// Converts a stored object to its JSON-LD representation.
+async fn object_json(object: StoredObject, data: &Data<ErisData>) -> Result<Value, ModelError> {
+    let json = match object {
+        StoredObject::Application(application) => {
+            serde_json::to_value(application.into_json(data).await?)?
+        }
+        StoredObject::User(user) => serde_json::to_value(user.into_json(data).await?)?,
+        StoredObject::Channel(channel) => serde_json::to_value(channel.into_json(data).await?)?,
+        StoredObject::Post(post) => serde_json::to_value(post.into_json(data).await?)?,
+        StoredObject::Image(image) => serde_json::to_value(image.into_json(data).await?)?,
+        StoredObject::Video(video) => serde_json::to_value(video.into_json(data).await?)?,
+        StoredObject::ForeignActor(actor) => serde_json::to_value(actor.into_json(data).await?)?,
+        StoredObject::Tombstone(tombstone) => {
+            serde_json::to_value(tombstone.into_json(data).await?)?
+        }
+    };
+    Ok(json)
+}
+
+/// A service which answers GET requests for the objects of the instance,
+/// whose ids are the URLs they are served from, with their JSON-LD as
+/// `application/activity+json`. Objects are found with the object store of
+/// [ErisData].
+///
+/// A deleted object answers 410 Gone with its [Tombstone], so that other
+/// servers know to delete their copy. An unknown id, or a path which is not
+/// that of an object of the instance, answers 404 Not Found. Errors from
+/// the object store are passed through.
+///
+/// [Tombstone]: crate::model::tombstone::Tombstone
+pub fn activitypub_object_service<B>(
+    config: FederationConfig<ErisData>,
+    urls: UrlLayout,
+) -> impl Service<
+    Request<B>,
+    Response = axum::response::Response,
+    Error = ModelError,
+    Future = impl Future<Output = Result<axum::response::Response, ModelError>> + Send,
+> + Clone
+where
+    B: Send + 'static,
+{
+    service_fn(move |request: Request<B>| {
+        let data = config.to_request_data();
+        let urls = urls.clone();
+        async move {
+            if request.method() != Method::GET {
+                return Ok(StatusCode::METHOD_NOT_ALLOWED.into_response());
+            }
+            // Only the ids of objects of the instance are looked up, so that
+            // objects stored from other servers are not served as its own
+            let Some(id) = urls
+                .at_path(request.uri().path())
+                .filter(|id| urls.parse(id).is_some())
+            else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+
+            let Some(object) = data.stored_object(id).await? else {
+                return Ok(StatusCode::NOT_FOUND.into_response());
+            };
+            let status = match object {
+                StoredObject::Tombstone(_) => StatusCode::GONE,
+                _ => StatusCode::OK,
+            };
+            let json = object_json(object, &data).await?;
+            Ok((status, FederationJson(WithContext::new_default(json))).into_response())
+        }
+    })
+}
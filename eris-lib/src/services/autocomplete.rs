@@ -0,0 +1,153 @@
+use std::{fmt::Display, time::Duration};
+
+use tower::{service_fn, Service, ServiceExt};
+use twilight_model::{
+    application::{
+        command::{CommandOptionChoice, CommandOptionChoiceValue},
+        interaction::{
+            application_command::{CommandDataOption, CommandOptionValue},
+            Interaction, InteractionData, InteractionType,
+        },
+    },
+    http::interaction::{InteractionResponse, InteractionResponseData, InteractionResponseType},
+    id::{marker::ChannelMarker, Id},
+};
+
+/// How long to wait for suggestions before answering with none. Discord
+/// drops autocomplete responses after 3 seconds, so this leaves time for
+/// the response to reach it.
+pub const AUTOCOMPLETE_DEADLINE: Duration = Duration::from_millis(2500);
+
+/// The most choices Discord will show.
+const MAX_CHOICES: usize = 25;
+
+/// The longest choice value Discord accepts, in characters.
+const MAX_CHOICE_LENGTH: usize = 100;
+
+/// What a user has typed so far into an option with autocomplete enabled.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AutocompleteRequest {
+    /// The name of the command being used.
+    pub command: String,
+    /// The name of the option being typed into.
+    pub option: String,
+    /// What has been typed into the option so far.
+    pub value: String,
+    /// The channel the command is being used in.
+    pub channel_id: Option<Id<ChannelMarker>>,
+}
+
+/// Finds the option being typed into, including within subcommands.
+fn focused_option(options: &[CommandDataOption]) -> Option<(&str, &str)> {
+    options.iter().find_map(|option| match &option.value {
+        CommandOptionValue::Focused(value, _) => Some((option.name.as_str(), value.as_str())),
+        CommandOptionValue::SubCommand(options) | CommandOptionValue::SubCommandGroup(options) => {
+            focused_option(options)
+        }
+        _ => None,
+    })
+}
+
+impl AutocompleteRequest {
+    /// Reads what is being typed from an autocomplete interaction. Returns
+    /// None for any other kind of interaction.
+    pub fn from_interaction(interaction: &Interaction) -> Option<Self> {
+        if interaction.kind != InteractionType::ApplicationCommandAutocomplete {
+            return None;
+        }
+        let Some(InteractionData::ApplicationCommand(command)) = &interaction.data else {
+            return None;
+        };
+        let (option, value) = focused_option(&command.options)?;
+
+        Some(Self {
+            command: command.name.clone(),
+            option: option.to_string(),
+            value: value.to_string(),
+            channel_id: interaction.channel.as_ref().map(|channel| channel.id),
+        })
+    }
+}
+
+/// The response to an autocomplete interaction offering these choices,
+/// keeping only as many as Discord will show.
+pub fn autocomplete_result(mut choices: Vec<CommandOptionChoice>) -> InteractionResponse {
+    choices.truncate(MAX_CHOICES);
+    InteractionResponse {
+        kind: InteractionResponseType::ApplicationCommandAutocompleteResult,
+        data: Some(InteractionResponseData {
+            choices: Some(choices),
+            ..Default::default()
+        }),
+    }
+}
+
+/// Answers an autocomplete interaction with the choices from the service.
+/// If the service fails, or does not answer within [AUTOCOMPLETE_DEADLINE],
+/// answers with no choices, so the user can still type the value in full.
+pub async fn autocomplete<A>(
+    autocomplete_service: A,
+    interaction: &Interaction,
+) -> InteractionResponse
+where
+    A: Service<AutocompleteRequest, Response = Vec<CommandOptionChoice>>,
+    A::Error: Display,
+{
+    let Some(request) = AutocompleteRequest::from_interaction(interaction) else {
+        return autocomplete_result(Vec::new());
+    };
+
+    let choices =
+        match tokio::time::timeout(AUTOCOMPLETE_DEADLINE, autocomplete_service.oneshot(request))
+            .await
+        {
+            Ok(Ok(choices)) => choices,
+            Ok(Err(e)) => {
+                tracing::error!("Autocomplete service failed: {e}");
+                Vec::new()
+            }
+            Err(_) => {
+                tracing::warn!("Autocomplete service missed the deadline");
+                Vec::new()
+            }
+        };
+
+    autocomplete_result(choices)
+}
+
+/// A service which suggests actors for the `url` option of commands such as
+/// /follow and /block, from the URLs of actors known to the channel which
+/// contain what has been typed. `known_actors` looks up the URLs of the
+/// actors a channel follows or has seen posts from, most relevant first.
+pub fn actor_url_autocomplete_service<K>(
+    known_actors: K,
+) -> impl Service<AutocompleteRequest, Response = Vec<CommandOptionChoice>, Error = K::Error> + Clone
+where
+    K: Service<Id<ChannelMarker>, Response = Vec<String>> + Clone,
+{
+    service_fn(move |request: AutocompleteRequest| {
+        let known_actors = known_actors.clone();
+        async move {
+            let Some(channel_id) = request.channel_id.filter(|_| request.option == "url") else {
+                return Ok(Vec::new());
+            };
+
+            let typed = request.value.to_lowercase();
+            let choices = known_actors
+                .oneshot(channel_id)
+                .await?
+                .into_iter()
+                // Longer URLs could not be sent back as the value
+                .filter(|url| url.chars().count() <= MAX_CHOICE_LENGTH)
+                .filter(|url| url.to_lowercase().contains(&typed))
+                .take(MAX_CHOICES)
+                .map(|url| CommandOptionChoice {
+                    name: url.clone(),
+                    name_localizations: None,
+                    value: CommandOptionChoiceValue::String(url),
+                })
+                .collect();
+            Ok(choices)
+        }
+    })
+}
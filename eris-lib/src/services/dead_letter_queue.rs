@@ -0,0 +1,287 @@
+use std::{
+    fmt::{Debug, Display},
+    time::{Duration, SystemTime, UNIX_EPOCH},
+};
+
+use futures_util::future::BoxFuture;
+use serde::{Deserialize, Serialize};
+use sqlx::{types::Json, PgPool};
+use thiserror::Error;
+use tower::{Service, ServiceExt};
+
+use crate::payloads::DiscordClientAction;
+
+use super::twilight_service::{FinalTwilightServiceError, TwilightServiceError};
+
+/// Why a [DiscordClientAction] failed, in a form that can be stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase", tag = "kind")]
+pub enum DeadLetterErrorKind {
+    /// The action failed validation, so it was never sent to Discord
+    Validation,
+    /// An attachment could not be downloaded, so the action was never sent
+    /// to Discord
+    AttachmentDownload,
+    /// Discord responded with an error status code
+    Status {
+        /// The HTTP status code of the response
+        status: u16,
+    },
+    /// Discord did not respond in time
+    TimedOut,
+    /// The request could not be made, such as because of a network error
+    Request,
+    /// Discord accepted the action, but its response could not be read.
+    /// Replaying the action may duplicate it.
+    Deserialization,
+    /// The request body could not be built, so the action was never sent to
+    /// Discord
+    RequestBody,
+    /// The scheduler stopped before the action was answered. It may or may
+    /// not have been sent to Discord.
+    SchedulerStopped,
+}
+
+impl From<&TwilightServiceError> for DeadLetterErrorKind {
+    fn from(error: &TwilightServiceError) -> Self {
+        match error {
+            TwilightServiceError::TwilightValidationError(_) => Self::Validation,
+            TwilightServiceError::AttachmentDownloadError(_) => Self::AttachmentDownload,
+            TwilightServiceError::TwilightClientError(e) => match e.kind() {
+                twilight_http::error::ErrorType::Response { status, .. } => Self::Status {
+                    status: status.get(),
+                },
+                twilight_http::error::ErrorType::ServiceUnavailable { .. } => {
+                    Self::Status { status: 503 }
+                }
+                twilight_http::error::ErrorType::RequestTimedOut => Self::TimedOut,
+                _ => Self::Request,
+            },
+            TwilightServiceError::DeserializationBodyError(_) => Self::Deserialization,
+            TwilightServiceError::RequestBodyError(_) => Self::RequestBody,
+            TwilightServiceError::SchedulerStopped => Self::SchedulerStopped,
+        }
+    }
+}
+
+/// A [DiscordClientAction] which could not be sent to Discord, kept so that
+/// an operator can inspect it and decide whether to replay or discard it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct DeadLetter {
+    /// The action which failed
+    pub action: DiscordClientAction,
+    /// Why the final attempt failed
+    pub error_kind: DeadLetterErrorKind,
+    /// The error message of the final attempt
+    pub error: String,
+    /// How many times the action was attempted
+    pub attempts: u32,
+    /// When the final attempt failed
+    pub failed_at: SystemTime,
+}
+
+impl DeadLetter {
+    /// Records that an action has just failed with this error.
+    pub fn new(action: DiscordClientAction, error: &FinalTwilightServiceError) -> Self {
+        Self {
+            action,
+            error_kind: (&error.error).into(),
+            error: error.error.to_string(),
+            attempts: error.attempts,
+            failed_at: SystemTime::now(),
+        }
+    }
+}
+
+/// The error that might be returned by a [DeadLetterQueue].
+#[derive(Debug, Error)]
+pub enum DeadLetterQueueError {
+    /// The database failed to execute a query
+    #[error("Dead-letter queue database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+}
+
+/// The error that might be returned when replaying a [DeadLetter].
+#[derive(Debug, Error)]
+pub enum ReplayError<E: Debug + Display> {
+    /// There is no dead letter with this id, it may already have been
+    /// replayed or discarded, or be being replayed
+    #[error("No dead letter with id {0}")]
+    NotFound(i64),
+    /// The dead letter could not be read or removed
+    #[error("{0}")]
+    DeadLetterQueueError(#[from] DeadLetterQueueError),
+    /// The service the action was replayed into failed, so the dead letter
+    /// was kept
+    #[error("Replay service failed: {0}")]
+    ServiceError(E),
+}
+
+/// A row of the dead letter table.
+type DeadLetterRow = (
+    i64,
+    Json<DiscordClientAction>,
+    Json<DeadLetterErrorKind>,
+    String,
+    i32,
+    f64,
+);
+
+const SELECT_DEAD_LETTERS: &str = "SELECT id, action, error_kind, error, attempts,
+        EXTRACT(EPOCH FROM failed_at)::FLOAT8
+    FROM eris_dead_letters";
+
+fn from_row(row: DeadLetterRow) -> (i64, DeadLetter) {
+    let (id, Json(action), Json(error_kind), error, attempts, failed_at) = row;
+    (
+        id,
+        DeadLetter {
+            action,
+            error_kind,
+            error,
+            attempts: attempts as u32,
+            failed_at: UNIX_EPOCH + Duration::from_secs_f64(failed_at),
+        },
+    )
+}
+
+/// A durable store of [DeadLetter]s in Postgres. As a [tower::Service], it
+/// stores each dead letter it is called with, so it can be given to
+/// [super::discord_client_action::discord_client_action_service]. Its
+/// methods let an operator list, inspect, replay or discard what was stored.
+#[derive(Debug, Clone)]
+pub struct DeadLetterQueue {
+    pool: PgPool,
+}
+
+impl DeadLetterQueue {
+    /// Create a dead-letter queue stored in the given pool, which must have
+    /// been migrated with the eris-data migrations.
+    pub fn new(pool: PgPool) -> Self {
+        Self { pool }
+    }
+
+    /// Lists dead letters, oldest first, starting after the given id.
+    pub async fn list(
+        &self,
+        after_id: Option<i64>,
+        limit: i64,
+    ) -> Result<Vec<(i64, DeadLetter)>, DeadLetterQueueError> {
+        let rows: Vec<DeadLetterRow> = sqlx::query_as(&format!(
+            "{SELECT_DEAD_LETTERS} WHERE id > $1 ORDER BY id LIMIT $2"
+        ))
+        .bind(after_id.unwrap_or(0))
+        .bind(limit)
+        .fetch_all(&self.pool)
+        .await?;
+        Ok(rows.into_iter().map(from_row).collect())
+    }
+
+    /// Returns the dead letter with this id, if it exists.
+    pub async fn inspect(&self, id: i64) -> Result<Option<DeadLetter>, DeadLetterQueueError> {
+        let row: Option<DeadLetterRow> =
+            sqlx::query_as(&format!("{SELECT_DEAD_LETTERS} WHERE id = $1"))
+                .bind(id)
+                .fetch_optional(&self.pool)
+                .await?;
+        Ok(row.map(|row| from_row(row).1))
+    }
+
+    /// Deletes the dead letter with this id without replaying it. Returns
+    /// false if it did not exist.
+    pub async fn discard(&self, id: i64) -> Result<bool, DeadLetterQueueError> {
+        let result = sqlx::query("DELETE FROM eris_dead_letters WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Sends the dead-lettered action to the given service, such as the
+    /// queue in front of
+    /// [super::discord_client_action::discord_client_action_service], and
+    /// deletes the dead letter once the service accepts it. If the action
+    /// fails again, it is dead-lettered again under a new id.
+    ///
+    /// The dead letter is deleted before the action is sent, in a
+    /// transaction which is only committed once the service accepts it, so
+    /// it is replayed at most once even if replayed concurrently, and kept
+    /// under its id if the service fails.
+    pub async fn replay<S>(&self, id: i64, service: S) -> Result<(), ReplayError<S::Error>>
+    where
+        S: Service<DiscordClientAction, Response = ()>,
+        S::Error: Debug + Display,
+    {
+        let mut transaction = self
+            .pool
+            .begin()
+            .await
+            .map_err(DeadLetterQueueError::from)?;
+        // Skipped rather than waited for if another replay has claimed it
+        let row: Option<DeadLetterRow> = sqlx::query_as(
+            "DELETE FROM eris_dead_letters
+                WHERE id = (SELECT id FROM eris_dead_letters WHERE id = $1 FOR UPDATE SKIP LOCKED)
+                RETURNING id, action, error_kind, error, attempts,
+                    EXTRACT(EPOCH FROM failed_at)::FLOAT8",
+        )
+        .bind(id)
+        .fetch_optional(&mut *transaction)
+        .await
+        .map_err(DeadLetterQueueError::from)?;
+        let (_, dead_letter) = from_row(row.ok_or(ReplayError::NotFound(id))?);
+
+        match service.oneshot(dead_letter.action).await {
+            Ok(()) => transaction.commit().await,
+            Err(error) => {
+                // Rolled back now rather than when dropped, so that it can
+                // be replayed again straight away
+                transaction
+                    .rollback()
+                    .await
+                    .map_err(DeadLetterQueueError::from)?;
+                return Err(ReplayError::ServiceError(error));
+            }
+        }
+        .map_err(DeadLetterQueueError::from)?;
+        Ok(())
+    }
+}
+
+impl Service<DeadLetter> for DeadLetterQueue {
+    type Response = ();
+
+    type Error = DeadLetterQueueError;
+
+    type Future = BoxFuture<'static, Result<(), DeadLetterQueueError>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: DeadLetter) -> Self::Future {
+        let pool = self.pool.clone();
+        Box::pin(async move {
+            let failed_at = req
+                .failed_at
+                .duration_since(UNIX_EPOCH)
+                .unwrap_or_default()
+                .as_secs_f64();
+            sqlx::query(
+                "INSERT INTO eris_dead_letters (action, error_kind, error, attempts, failed_at)
+                    VALUES ($1, $2, $3, $4, to_timestamp($5))",
+            )
+            .bind(Json(&req.action))
+            .bind(Json(req.error_kind))
+            .bind(&req.error)
+            .bind(req.attempts as i32)
+            .bind(failed_at)
+            .execute(&pool)
+            .await?;
+            Ok(())
+        })
+    }
+}
@@ -1,11 +1,13 @@
 use std::fmt::{Debug, Display};
 use thiserror::Error;
-use tower::{Service, ServiceExt};
-use twilight_model::id::{marker::ApplicationMarker, Id};
+use tower::{service_fn, Service, ServiceExt};
 
-use crate::payloads::{DiscordClientAction, DiscordServerAction};
+use crate::payloads::{DiscordClientAction, DiscordClientActionResponse, DiscordServerAction};
 
-use super::twilight_service::{twilight_service, TwilightServiceError, TwilightValidationError};
+use super::{
+    dead_letter_queue::DeadLetter,
+    twilight_service::{FinalTwilightServiceError, TwilightServiceError, TwilightValidationError},
+};
 
 /// An error that might be produced during the processing of a [DiscordClientAction].
 #[derive(Debug, Error)]
@@ -14,73 +16,117 @@ where
     Q: Service<DiscordServerAction>,
     Q::Error: Debug + Display,
 {
-    /// An error from the Twilight Discord client
+    /// An error from the Twilight Discord client, after any retries
     #[error("An error from the Twilight Discord client: {0}")]
-    TwilightServiceError(#[from] TwilightServiceError),
+    TwilightServiceError(#[from] FinalTwilightServiceError),
     /// An error from the provided server action queue
     #[error("An error occurred in the queue service: {0}")]
     DiscordServerActionQueueError(Q::Error),
 }
 
+/// Logs an error produced while processing a [DiscordClientAction].
+fn log_error<Q>(error: &DiscordClientActionServiceError<Q>)
+where
+    Q: Service<DiscordServerAction>,
+    Q::Error: Debug + Display,
+{
+    match error {
+        DiscordClientActionServiceError::TwilightServiceError(FinalTwilightServiceError {
+            error,
+            attempts,
+        }) => match error {
+            TwilightServiceError::TwilightValidationError(e) => match e {
+                TwilightValidationError::MessageValidationError(e) => {
+                    tracing::error!("twilight message validation error: {e}");
+                }
+                TwilightValidationError::ValidationError(e) => {
+                    tracing::error!("twilight validation error: {e}");
+                }
+            },
+            TwilightServiceError::AttachmentDownloadError(e) => {
+                tracing::error!("attachment download error: {e}");
+            }
+            TwilightServiceError::TwilightClientError(e) => {
+                tracing::error!("twilight client error after {attempts} attempts: {e}");
+            }
+            TwilightServiceError::DeserializationBodyError(e) => {
+                tracing::error!("error deserializing Discord response after {attempts} attempts: {e}");
+            }
+            TwilightServiceError::RequestBodyError(e) => {
+                tracing::error!("error building Discord request body: {e}");
+            }
+            TwilightServiceError::SchedulerStopped => {
+                tracing::error!("Discord scheduler stopped before the action was answered");
+            }
+        },
+        DiscordClientActionServiceError::DiscordServerActionQueueError(e) => {
+            tracing::error!("server action queue failed: {e}");
+        }
+    };
+}
+
 /// A service which receives a [DiscordClientAction] and sends it to Discord
-/// through a rate-limited [twilight_http::Client]. If the response is
+/// through the provided service, normally a
+/// [super::twilight_service::twilight_service] behind a
+/// [super::discord_scheduler::discord_scheduler]. If the response is
 /// meaningful, ships it out through the provided queue service.
-/// In case of an error, attempts to log the error using [tracing::error].
-pub fn discord_client_action_service<Q>(
-    twilight_client: twilight_http::Client,
-    application_id: Id<ApplicationMarker>,
-    mut server_action_queue_service: Q,
+/// In case of an error, attempts to log the error using [tracing::error],
+/// and sends actions which Discord did not accept to the provided
+/// dead-letter service, so that they can be replayed later.
+#[allow(clippy::result_large_err)]
+pub fn discord_client_action_service<T, Q, D>(
+    twilight_service: T,
+    server_action_queue_service: Q,
+    dead_letter_service: D,
 ) -> impl Service<DiscordClientAction, Response = (), Error = DiscordClientActionServiceError<Q>> + Clone
 where
+    T: Service<
+            DiscordClientAction,
+            Response = Option<DiscordClientActionResponse>,
+            Error = FinalTwilightServiceError,
+        > + Clone,
     Q: Service<DiscordServerAction, Response = ()>,
     Q: Clone,
     Q::Error: Debug + Display,
+    D: Service<DeadLetter, Response = ()> + Clone,
+    D::Error: Display,
 {
+    service_fn(move |action: DiscordClientAction| {
+        let twilight_service = twilight_service.clone();
+        let mut server_action_queue_service = server_action_queue_service.clone();
+        let dead_letter_service = dead_letter_service.clone();
+        async move {
+            let result: Result<(), DiscordClientActionServiceError<Q>> = async {
+                match twilight_service.oneshot(action.clone()).await {
+                    Ok(Some(discord_response)) => server_action_queue_service
+                        .ready()
+                        .await
+                        .map_err(DiscordClientActionServiceError::DiscordServerActionQueueError)?
+                        .call(DiscordServerAction::DiscordClientActionResponse(
+                            discord_response,
+                        ))
+                        .await
+                        .map_err(DiscordClientActionServiceError::DiscordServerActionQueueError),
+                    Ok(None) => Ok(()),
+                    Err(e) => Err(e.into()),
+                }
+            }
+            .await;
 
+            if let Err(e) = result {
+                log_error(&e);
 
-
-    
-    twilight_service(twilight_client, application_id)
-        .then(|result_option_response| async move {
-            match result_option_response {
-                Ok(Some(discord_response)) => server_action_queue_service
-                    .ready()
-                    .await
-                    .map_err(DiscordClientActionServiceError::DiscordServerActionQueueError)?
-                    .call(DiscordServerAction::DiscordClientActionResponse(
-                        discord_response,
-                    ))
-                    .await
-                    .map_err(DiscordClientActionServiceError::DiscordServerActionQueueError),
-                Ok(None) => Ok(()),
-                Err(e) => Err(e.into()),
-            }
-        })
-        .map_result(|result_response| match result_response {
-            Ok(()) => Ok(()),
-            Err(e) => {
-                match e {
-                    DiscordClientActionServiceError::TwilightServiceError(e) => match e {
-                        TwilightServiceError::TwilightValidationError(e) => match e {
-                            TwilightValidationError::MessageValidationError(e) => {
-                                tracing::error!("twilight message validation error: {e}");
-                            }
-                            TwilightValidationError::ValidationError(e) => {
-                                tracing::error!("twilight validation error: {e}");
-                            }
-                        },
-                        TwilightServiceError::TwilightClientError(e) => {
-                            tracing::error!("twilight client error: {e}");
-                        }
-                        TwilightServiceError::DeserializationBodyError(e) => {
-                            tracing::error!("error deserializing Discord response: {e}");
-                        }
-                    },
-                    DiscordClientActionServiceError::DiscordServerActionQueueError(e) => {
-                        tracing::error!("server action queue failed: {e}");
+                // If only the response failed to queue, Discord already has
+                // the action, and replaying it would duplicate it
+                if let DiscordClientActionServiceError::TwilightServiceError(e) = e {
+                    let dead_letter = DeadLetter::new(action, &e);
+                    if let Err(e) = dead_letter_service.oneshot(dead_letter).await {
+                        tracing::error!("failed to dead-letter Discord client action: {e}");
                     }
-                };
-                Ok(())
+                }
             }
-        })
+
+            Ok::<(), DiscordClientActionServiceError<Q>>(())
+        }
+    })
 }
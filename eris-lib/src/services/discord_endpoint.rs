@@ -4,19 +4,22 @@ use axum::response::IntoResponse;
 use http::{Request, StatusCode};
 use thiserror::Error;
 use tower::{Service, ServiceBuilder, ServiceExt};
-use twilight_model::application::interaction::Interaction;
+use twilight_model::application::{command::CommandOptionChoice, interaction::Interaction};
 
 use crate::{
     layers::{
         body_to_bytes::{body_to_bytes_layer_fn, BodyToBytesServiceError},
         deserialize_json::{deserialize_json_layer_fn, JsonDeserializationServiceError},
-        respond_to_interaction::{respond_to_interaction_layer_fn, InteractionResponseError},
+        respond_to_interaction::{
+            respond_to_interaction_layer_fn, InitialResponse, InteractionResponseError,
+        },
         verify_signature::{
             verify_discord_signature_layer, DiscordSignatureVerificationFailure,
             DiscordSignatureVerificationLayerError,
         },
     },
     payloads::DiscordServerAction,
+    services::autocomplete::AutocompleteRequest,
 };
 
 /// An error which might occur with the Discord endpoint.
@@ -48,10 +51,16 @@ where
 }
 
 /// A service which receives an HTTP request and returns a reply in the form of
-/// a (StatusCode, JsonValue) pair.
-pub fn discord_endpoint_service<B, Q>(
+/// a (StatusCode, JsonValue) pair. How each interaction is answered is chosen
+/// by `choose_response`, normally
+/// [crate::layers::respond_to_interaction::default_initial_response], and
+/// autocomplete suggestions come from `autocomplete_service`, such as
+/// [crate::services::autocomplete::actor_url_autocomplete_service].
+pub fn discord_endpoint_service<B, Q, F, A>(
     public_key: ed25519_dalek::PublicKey,
     server_action_queue_service: Q,
+    choose_response: F,
+    autocomplete_service: A,
 ) -> impl Service<Request<B>, Response = axum::response::Response, Error = DiscordEndpointError<B, Q>>
        + Clone
 where
@@ -60,13 +69,22 @@ where
     Q: Service<DiscordServerAction, Response = ()>,
     Q: Clone,
     Q::Error: Debug + Display,
+    F: Fn(&Interaction) -> InitialResponse + Clone,
+    A: Service<AutocompleteRequest, Response = Vec<CommandOptionChoice>> + Clone,
+    A::Error: Display,
 {
     ServiceBuilder::new()
         .layer_fn(body_to_bytes_layer_fn)
         .layer(verify_discord_signature_layer(public_key))
         .layer_fn(deserialize_json_layer_fn)
         .map_request(|request: Request<Interaction>| request.into_body())
-        .layer_fn(respond_to_interaction_layer_fn)
+        .layer_fn(|queue_service| {
+            respond_to_interaction_layer_fn(
+                queue_service,
+                choose_response.clone(),
+                autocomplete_service.clone(),
+            )
+        })
         .service(server_action_queue_service)
         .map_response(axum::response::IntoResponse::into_response)
         .map_err(|e| {
@@ -0,0 +1,535 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    num::{NonZeroU32, NonZeroUsize},
+    sync::{Arc, Mutex, Weak},
+    time::Duration,
+};
+
+use futures_util::future::BoxFuture;
+use tokio::{
+    sync::{oneshot, Notify},
+    time::Instant,
+};
+use tower::{Service, ServiceExt};
+use twilight_http::{api_error::ApiError, error::ErrorType};
+use twilight_model::id::{marker::ChannelMarker, Id};
+
+use crate::payloads::DiscordClientAction;
+
+use super::twilight_service::{FinalTwilightServiceError, TwilightServiceError};
+
+/// A Discord rate limit bucket, which [DiscordClientAction]s are queued in
+/// by a [DiscordSchedulerService].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum DiscordBucket {
+    /// Messages created, updated or deleted in a channel
+    Channel(Id<ChannelMarker>),
+    /// Responses to an interaction, by interaction token. These are not
+    /// subject to the global limit, and are sent before any channel's.
+    Interaction(String),
+}
+
+impl From<&DiscordClientAction> for DiscordBucket {
+    fn from(action: &DiscordClientAction) -> Self {
+        match action {
+            DiscordClientAction::CreateMessage(action) => Self::Channel(action.channel_id),
+            DiscordClientAction::CreateReply(action) => {
+                Self::Channel(action.message_location.channel_id)
+            }
+            DiscordClientAction::CreateFollowup(action) => {
+                Self::Interaction(action.interaction_token.clone())
+            }
+            DiscordClientAction::DeleteMessage(action) => {
+                Self::Channel(action.message_location.channel_id)
+            }
+            DiscordClientAction::DeleteInteractionResponse(action) => {
+                Self::Interaction(action.interaction_token.clone())
+            }
+            DiscordClientAction::UpdateInteractionResponse(action) => {
+                Self::Interaction(action.interaction_token.clone())
+            }
+            DiscordClientAction::UpdateMessage(action) => {
+                Self::Channel(action.message_location.channel_id)
+            }
+        }
+    }
+}
+
+/// At most `requests` requests in any window of `per`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RateLimit {
+    /// The number of requests allowed in each window
+    pub requests: NonZeroU32,
+    /// The length of each window
+    pub per: Duration,
+}
+
+/// The limits a [DiscordSchedulerService] keeps to. The defaults match the
+/// limits Discord usually applies; if Discord responds with a 429 anyway,
+/// the bucket waits as long as Discord asks, and the action is sent again
+/// up to `max_rate_limited` times.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DiscordRateLimits {
+    /// The limit across every channel bucket
+    pub global: RateLimit,
+    /// The limit for each channel bucket
+    pub channel: RateLimit,
+    /// The limit for each interaction bucket
+    pub interaction: RateLimit,
+    /// The most actions that may be waiting on Discord at once
+    pub max_in_flight: NonZeroUsize,
+    /// The most times an action is queued again after a 429, before the
+    /// 429 is returned to the caller
+    pub max_rate_limited: u32,
+}
+
+impl Default for DiscordRateLimits {
+    fn default() -> Self {
+        Self {
+            global: RateLimit {
+                requests: NonZeroU32::new(50).unwrap(),
+                per: Duration::from_secs(1),
+            },
+            channel: RateLimit {
+                requests: NonZeroU32::new(5).unwrap(),
+                per: Duration::from_secs(5),
+            },
+            interaction: RateLimit {
+                requests: NonZeroU32::new(5).unwrap(),
+                per: Duration::from_secs(2),
+            },
+            max_in_flight: NonZeroUsize::new(10).unwrap(),
+            max_rate_limited: 3,
+        }
+    }
+}
+
+/// A fixed window rate limiter. The window starts with the first request.
+#[derive(Debug)]
+struct Window {
+    limit: RateLimit,
+    remaining: u32,
+    resets_at: Instant,
+}
+
+impl Window {
+    fn new(limit: RateLimit) -> Self {
+        Self {
+            limit,
+            remaining: limit.requests.get(),
+            resets_at: Instant::now(),
+        }
+    }
+
+    /// When the next request can be made, or None if it can be made now.
+    fn blocked_until(&self, now: Instant) -> Option<Instant> {
+        (self.remaining == 0 && now < self.resets_at).then_some(self.resets_at)
+    }
+
+    fn take(&mut self, now: Instant) {
+        if now >= self.resets_at {
+            self.remaining = self.limit.requests.get();
+            self.resets_at = now + self.limit.per;
+        }
+        self.remaining = self.remaining.saturating_sub(1);
+    }
+
+    /// Whether requests made now would count towards the current window.
+    fn is_open(&self, now: Instant) -> bool {
+        now < self.resets_at
+    }
+
+    /// Blocks every request until Discord says it may be retried.
+    fn pause_until(&mut self, until: Instant) {
+        self.remaining = 0;
+        self.resets_at = self.resets_at.max(until);
+    }
+}
+
+/// An action waiting for its turn, and the caller waiting for its result.
+struct Pending<R> {
+    action: DiscordClientAction,
+    respond: oneshot::Sender<Result<R, FinalTwilightServiceError>>,
+    /// How many times Discord has rate limited the action so far
+    rate_limited: u32,
+}
+
+struct Bucket<R> {
+    queue: VecDeque<Pending<R>>,
+    window: Window,
+}
+
+struct SchedulerState<R> {
+    buckets: HashMap<DiscordBucket, Bucket<R>>,
+    /// The interaction buckets with queued actions, in the order they are
+    /// served
+    interactions: VecDeque<DiscordBucket>,
+    /// The channel buckets with queued actions, in the order they are served
+    channels: VecDeque<DiscordBucket>,
+    global: Window,
+    in_flight: usize,
+}
+
+impl<R> SchedulerState<R> {
+    /// Queues an action at the front or back of its bucket.
+    fn enqueue(&mut self, limits: &DiscordRateLimits, pending: Pending<R>, front: bool) {
+        let key = DiscordBucket::from(&pending.action);
+        let bucket = self.buckets.entry(key.clone()).or_insert_with(|| {
+            let limit = match key {
+                DiscordBucket::Channel(_) => limits.channel,
+                DiscordBucket::Interaction(_) => limits.interaction,
+            };
+            Bucket {
+                queue: VecDeque::new(),
+                window: Window::new(limit),
+            }
+        });
+
+        if bucket.queue.is_empty() {
+            match key {
+                DiscordBucket::Channel(_) => self.channels.push_back(key),
+                DiscordBucket::Interaction(_) => self.interactions.push_back(key),
+            }
+        }
+        if front {
+            bucket.queue.push_front(pending);
+        } else {
+            bucket.queue.push_back(pending);
+        }
+    }
+}
+
+/// Takes the next action from the first bucket in `order` which is not
+/// rate limited, moving that bucket to the back. If every bucket is rate
+/// limited, records when the first of them will be ready.
+fn next_ready<R>(
+    order: &mut VecDeque<DiscordBucket>,
+    buckets: &mut HashMap<DiscordBucket, Bucket<R>>,
+    now: Instant,
+    wake_at: &mut Option<Instant>,
+) -> Option<(DiscordBucket, Pending<R>)> {
+    let index = order.iter().position(|key| {
+        let bucket = &buckets[key];
+        match bucket.window.blocked_until(now) {
+            Some(ready_at) => {
+                *wake_at = Some(wake_at.map_or(ready_at, |wake_at| wake_at.min(ready_at)));
+                false
+            }
+            None => true,
+        }
+    })?;
+
+    let key = order.remove(index)?;
+    let bucket = buckets.get_mut(&key)?;
+    let pending = bucket.queue.pop_front()?;
+    if !bucket.queue.is_empty() {
+        order.push_back(key.clone());
+    }
+    Some((key, pending))
+}
+
+struct Shared<R> {
+    state: Mutex<SchedulerState<R>>,
+    limits: DiscordRateLimits,
+    wake: Arc<Notify>,
+}
+
+impl<R> Drop for Shared<R> {
+    fn drop(&mut self) {
+        // Lets the dispatcher see that nothing is left to schedule
+        self.wake.notify_one();
+    }
+}
+
+/// Parses how long Discord asked us to wait, and whether every bucket should
+/// wait, from a 429 response.
+fn rate_limited<R>(result: &Result<R, FinalTwilightServiceError>) -> Option<(Duration, bool)> {
+    let Err(FinalTwilightServiceError {
+        error: TwilightServiceError::TwilightClientError(e),
+        ..
+    }) = result
+    else {
+        return None;
+    };
+    match e.kind() {
+        ErrorType::Response {
+            error: ApiError::Ratelimited(ratelimited),
+            ..
+        } => Some((
+            Duration::try_from_secs_f64(ratelimited.retry_after).unwrap_or_default(),
+            ratelimited.global,
+        )),
+        _ => None,
+    }
+}
+
+/// Sends an action through the inner service, then either responds to the
+/// caller or, if Discord rate limited it fewer than `max_rate_limited`
+/// times, puts it back at the front of its bucket. Either way, a 429 pauses
+/// the bucket, or every channel bucket, for as long as Discord asked.
+async fn send<S>(service: S, shared: Arc<Shared<S::Response>>, mut pending: Pending<S::Response>)
+where
+    S: Service<DiscordClientAction, Error = FinalTwilightServiceError>,
+{
+    let result = service.oneshot(pending.action.clone()).await;
+
+    let mut state = shared.state.lock().expect("scheduler lock poisoned");
+    state.in_flight -= 1;
+    let respond = match rate_limited(&result) {
+        Some((retry_after, global)) => {
+            let bucket = DiscordBucket::from(&pending.action);
+            tracing::warn!(
+                ?bucket,
+                global,
+                "Rate limited by Discord for {retry_after:?}"
+            );
+            let until = Instant::now() + retry_after;
+            pending.rate_limited += 1;
+            let respond = if pending.rate_limited <= shared.limits.max_rate_limited {
+                state.enqueue(&shared.limits, pending, true);
+                None
+            } else {
+                tracing::error!(
+                    ?bucket,
+                    "Giving up on an action Discord keeps rate limiting"
+                );
+                Some(pending)
+            };
+            if global {
+                state.global.pause_until(until);
+            } else if let Some(bucket) = state.buckets.get_mut(&bucket) {
+                bucket.window.pause_until(until);
+            }
+            respond
+        }
+        None => Some(pending),
+    };
+    if let Some(pending) = respond {
+        // The caller may have stopped waiting
+        let _ = pending.respond.send(result);
+    }
+    drop(state);
+    shared.wake.notify_one();
+}
+
+/// Starts sending every action which is allowed to be sent now, returning
+/// when the next rate limited action will be allowed, if any are waiting.
+fn dispatch_ready<S>(service: &S, shared: &Arc<Shared<S::Response>>) -> Option<Instant>
+where
+    S: Service<DiscordClientAction, Error = FinalTwilightServiceError> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send + 'static,
+{
+    let mut state = shared.state.lock().expect("scheduler lock poisoned");
+    let state = &mut *state;
+    let now = Instant::now();
+    let mut wake_at = None;
+
+    while state.in_flight < shared.limits.max_in_flight.get() {
+        let next = match next_ready(
+            &mut state.interactions,
+            &mut state.buckets,
+            now,
+            &mut wake_at,
+        ) {
+            Some(next) => Some(next),
+            None => match state.global.blocked_until(now) {
+                Some(ready_at) => {
+                    if !state.channels.is_empty() {
+                        wake_at = Some(wake_at.map_or(ready_at, |wake_at| wake_at.min(ready_at)));
+                    }
+                    None
+                }
+                None => next_ready(&mut state.channels, &mut state.buckets, now, &mut wake_at),
+            },
+        };
+        let Some((key, pending)) = next else {
+            break;
+        };
+
+        if pending.respond.is_closed() {
+            tracing::debug!(bucket = ?key, "Caller stopped waiting, action not sent");
+            continue;
+        }
+
+        if let Some(bucket) = state.buckets.get_mut(&key) {
+            bucket.window.take(now);
+        }
+        if let DiscordBucket::Channel(_) = key {
+            state.global.take(now);
+        }
+        state.in_flight += 1;
+        tokio::spawn(send(service.clone(), shared.clone(), pending));
+    }
+
+    // Forget buckets once nothing is waiting and their window has closed
+    state
+        .buckets
+        .retain(|_, bucket| !bucket.queue.is_empty() || bucket.window.is_open(now));
+
+    wake_at
+}
+
+/// Sends actions as their buckets allow, until every handle to the scheduler
+/// has been dropped.
+async fn dispatch<S>(service: S, shared: Weak<Shared<S::Response>>, wake: Arc<Notify>)
+where
+    S: Service<DiscordClientAction, Error = FinalTwilightServiceError> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send + 'static,
+{
+    while let Some(shared) = shared.upgrade() {
+        let wake_at = dispatch_ready(&service, &shared);
+        drop(shared);
+
+        match wake_at {
+            Some(wake_at) => {
+                tokio::select! {
+                    _ = wake.notified() => {}
+                    _ = tokio::time::sleep_until(wake_at) => {}
+                }
+            }
+            None => wake.notified().await,
+        }
+    }
+}
+
+/// A [tower::Service] which queues each [DiscordClientAction] in its
+/// [DiscordBucket], and sends it through the inner service once the bucket,
+/// and for channels the global limit, allow it. Interaction responses are
+/// sent before any channel's actions, so a fan-out to many channels does not
+/// hold up replies to commands. Each bucket is served in order, and channel
+/// buckets take turns, so one busy channel does not starve the others.
+///
+/// This service is Clone, and every clone shares the same queues. It
+/// responds once the inner service does, so dropping the response future
+/// before the action is sent cancels it. If the background task stops first,
+/// such as because the runtime is shutting down, it responds with
+/// [TwilightServiceError::SchedulerStopped].
+pub struct DiscordSchedulerService<R>(Arc<Shared<R>>);
+
+// Derived Clone would require R: Clone
+impl<R> Clone for DiscordSchedulerService<R> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<R> std::fmt::Debug for DiscordSchedulerService<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("DiscordSchedulerService")
+            .field("limits", &self.0.limits)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R> DiscordSchedulerService<R> {
+    /// The number of actions waiting to be sent in this bucket.
+    pub fn depth(&self, bucket: &DiscordBucket) -> usize {
+        let state = self.0.state.lock().expect("scheduler lock poisoned");
+        state
+            .buckets
+            .get(bucket)
+            .map_or(0, |bucket| bucket.queue.len())
+    }
+
+    /// The number of actions waiting to be sent in every bucket which has
+    /// any.
+    pub fn depths(&self) -> HashMap<DiscordBucket, usize> {
+        let state = self.0.state.lock().expect("scheduler lock poisoned");
+        state
+            .buckets
+            .iter()
+            .filter(|(_, bucket)| !bucket.queue.is_empty())
+            .map(|(key, bucket)| (key.clone(), bucket.queue.len()))
+            .collect()
+    }
+
+    /// The number of actions which have been sent but not yet answered.
+    pub fn in_flight(&self) -> usize {
+        self.0
+            .state
+            .lock()
+            .expect("scheduler lock poisoned")
+            .in_flight
+    }
+}
+
+impl<R: Send + 'static> Service<DiscordClientAction> for DiscordSchedulerService<R> {
+    type Response = R;
+
+    type Error = FinalTwilightServiceError;
+
+    type Future = BoxFuture<'static, Result<R, FinalTwilightServiceError>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: DiscordClientAction) -> Self::Future {
+        let (respond, response) = oneshot::channel();
+        let pending = Pending {
+            action: req,
+            respond,
+            rate_limited: 0,
+        };
+        self.0
+            .state
+            .lock()
+            .expect("scheduler lock poisoned")
+            .enqueue(&self.0.limits, pending, false);
+        self.0.wake.notify_one();
+
+        // Keeps the scheduler running until this action is answered
+        let shared = self.0.clone();
+        Box::pin(async move {
+            let result = match response.await {
+                Ok(result) => result,
+                Err(_) => Err(FinalTwilightServiceError {
+                    error: TwilightServiceError::SchedulerStopped,
+                    attempts: 0,
+                }),
+            };
+            drop(shared);
+            result
+        })
+    }
+}
+
+/// Puts a [DiscordSchedulerService] in front of a service which sends
+/// [DiscordClientAction]s, such as
+/// [super::twilight_service::twilight_service], starting a background task
+/// which sends the queued actions. The task stops once the returned service,
+/// every clone of it, and every response it has yet to give are dropped.
+///
+/// The scheduler queues actions Discord rate limits again itself, so the
+/// inner service should not also retry them: give a
+/// [super::twilight_service::twilight_service] a
+/// [super::twilight_service::RetryConfig] with `retry_rate_limited` unset.
+pub fn discord_scheduler<S>(
+    service: S,
+    limits: DiscordRateLimits,
+) -> DiscordSchedulerService<S::Response>
+where
+    S: Service<DiscordClientAction, Error = FinalTwilightServiceError> + Clone + Send + 'static,
+    S::Future: Send,
+    S::Response: Send + 'static,
+{
+    let wake = Arc::new(Notify::new());
+    let shared = Arc::new(Shared {
+        state: Mutex::new(SchedulerState {
+            buckets: HashMap::new(),
+            interactions: VecDeque::new(),
+            channels: VecDeque::new(),
+            global: Window::new(limits.global),
+            in_flight: 0,
+        }),
+        limits,
+        wake: wake.clone(),
+    });
+    tokio::spawn(dispatch(service, Arc::downgrade(&shared), wake));
+    DiscordSchedulerService(shared)
+}
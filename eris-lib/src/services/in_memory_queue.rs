@@ -1,7 +1,27 @@
-use std::ops::{Deref, DerefMut};
+use std::{
+    collections::VecDeque,
+    future::Future,
+    num::NonZeroUsize,
+    ops::{Deref, DerefMut},
+    sync::{
+        atomic::{AtomicU64, AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    task::{ready as ready_poll, Context, Poll},
+    time::Duration,
+};
 
 use futures_util::future::{ready, Ready};
-use tokio::sync::mpsc::{error::SendError, unbounded_channel, UnboundedReceiver, UnboundedSender};
+use thiserror::Error;
+use tokio::{
+    sync::{
+        mpsc::{error::SendError, unbounded_channel, UnboundedReceiver, UnboundedSender},
+        oneshot, Notify, OwnedSemaphorePermit, Semaphore, TryAcquireError,
+    },
+    task::{JoinError, JoinHandle, JoinSet},
+    time::Instant,
+};
+use tokio_util::sync::PollSemaphore;
 use tower::{Service, ServiceExt};
 
 /// An in-memory background task queue service, wrapping a
@@ -66,29 +86,442 @@ pub fn in_memory_queue<T>() -> (InMemoryQueueService<T>, InMemoryQueueSubscripti
     (InMemoryQueueService(tx), InMemoryQueueSubscription(rx))
 }
 
-/// Start a service in the background which responds to events in the in-memory
-/// queue
-pub async fn subscribe_to_queue<S, T>(
+/// What a [BoundedQueueService] does with a new item when the queue is full.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OverflowPolicy {
+    /// `poll_ready` waits until there is space, applying backpressure to the
+    /// caller.
+    Wait,
+    /// The new item is returned in [BoundedQueueError::Full].
+    Reject,
+    /// The oldest queued item is discarded to make space for the new one.
+    DropOldest,
+}
+
+/// The error returned by a [BoundedQueueService].
+#[derive(Debug, Error)]
+pub enum BoundedQueueError<T: std::fmt::Debug> {
+    /// The subscription has been dropped, so nothing can be sent
+    #[error("The queue subscription has been dropped")]
+    Closed,
+    /// The subscription has been dropped, so this item was not sent
+    #[error("The queue subscription has been dropped, item not sent")]
+    NotSent(T),
+    /// The queue is full, so this item was not sent
+    #[error("The queue is full, item rejected")]
+    Full(T),
+}
+
+/// The state shared by every handle to a bounded queue.
+#[derive(Debug)]
+struct BoundedQueue<T> {
+    items: Mutex<VecDeque<T>>,
+    capacity: usize,
+    policy: OverflowPolicy,
+    /// One permit for each free slot, closed when the subscription is dropped
+    free_slots: Arc<Semaphore>,
+    item_available: Notify,
+    senders: AtomicUsize,
+    dropped: AtomicU64,
+}
+
+impl<T> BoundedQueue<T> {
+    fn depth(&self) -> usize {
+        self.items.lock().expect("queue lock poisoned").len()
+    }
+
+    /// Adds an item to the back of the queue, using up a free slot.
+    fn push(&self, item: T, slot: OwnedSemaphorePermit) {
+        slot.forget();
+        self.items
+            .lock()
+            .expect("queue lock poisoned")
+            .push_back(item);
+        self.item_available.notify_one();
+    }
+
+    /// Adds an item to the back of the queue, discarding the oldest item if
+    /// there is no free slot. The slot is taken while locked, as the
+    /// subscriber frees them while locked, so that there is only no free
+    /// slot when the queue is full. Returns the item if the queue is closed.
+    fn push_dropping_oldest(&self, item: T) -> Result<(), T> {
+        let mut items = self.items.lock().expect("queue lock poisoned");
+        match self.free_slots.try_acquire() {
+            Ok(slot) => slot.forget(),
+            Err(TryAcquireError::NoPermits) => {
+                items.pop_front();
+                self.dropped.fetch_add(1, Ordering::Relaxed);
+                tracing::warn!("Queue is full, dropped the oldest item");
+            }
+            Err(TryAcquireError::Closed) => return Err(item),
+        }
+        items.push_back(item);
+        drop(items);
+        self.item_available.notify_one();
+        Ok(())
+    }
+}
+
+/// An in-memory background task queue service which holds at most a fixed
+/// number of items, applying an [OverflowPolicy] when it is full. Unlike
+/// [InMemoryQueueService], `poll_ready` reflects the queue's capacity when
+/// the policy is [OverflowPolicy::Wait], so a slow subscriber slows down its
+/// producers instead of letting memory grow without limit.
+/// This service is Clone, so multiple services can insert into this queue at
+/// once; each clone reserves its own slot in `poll_ready`.
+#[derive(Debug)]
+pub struct BoundedQueueService<T> {
+    queue: Arc<BoundedQueue<T>>,
+    free_slots: PollSemaphore,
+    reserved_slot: Option<OwnedSemaphorePermit>,
+}
+
+impl<T> BoundedQueueService<T> {
+    /// The number of items waiting in the queue.
+    pub fn depth(&self) -> usize {
+        self.queue.depth()
+    }
+
+    /// The maximum number of items the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.queue.capacity
+    }
+
+    /// The number of items discarded by [OverflowPolicy::DropOldest].
+    pub fn dropped(&self) -> u64 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Clone for BoundedQueueService<T> {
+    fn clone(&self) -> Self {
+        self.queue.senders.fetch_add(1, Ordering::Relaxed);
+        Self {
+            queue: self.queue.clone(),
+            free_slots: self.free_slots.clone(),
+            reserved_slot: None,
+        }
+    }
+}
+
+impl<T> Drop for BoundedQueueService<T> {
+    fn drop(&mut self) {
+        if self.queue.senders.fetch_sub(1, Ordering::AcqRel) == 1 {
+            // Wake the subscriber so it can see the queue is finished
+            self.queue.item_available.notify_one();
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> Service<T> for BoundedQueueService<T> {
+    type Response = ();
+
+    type Error = BoundedQueueError<T>;
+
+    type Future = Ready<Result<(), BoundedQueueError<T>>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        if self.queue.free_slots.is_closed() {
+            return Poll::Ready(Err(BoundedQueueError::Closed));
+        }
+        if self.queue.policy == OverflowPolicy::Wait && self.reserved_slot.is_none() {
+            match ready_poll!(self.free_slots.poll_acquire(cx)) {
+                Some(slot) => self.reserved_slot = Some(slot),
+                None => return Poll::Ready(Err(BoundedQueueError::Closed)),
+            }
+        }
+        Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: T) -> Self::Future {
+        if self.queue.free_slots.is_closed() {
+            return ready(Err(BoundedQueueError::NotSent(req)));
+        }
+
+        if self.queue.policy == OverflowPolicy::DropOldest {
+            return ready(
+                self.queue
+                    .push_dropping_oldest(req)
+                    .map_err(BoundedQueueError::NotSent),
+            );
+        }
+
+        let slot = self
+            .reserved_slot
+            .take()
+            .or_else(|| self.free_slots.clone_inner().try_acquire_owned().ok());
+        match slot {
+            Some(slot) => {
+                self.queue.push(req, slot);
+                ready(Ok(()))
+            }
+            // Either full, or call was made without waiting for poll_ready
+            None => ready(Err(BoundedQueueError::Full(req))),
+        }
+    }
+}
+
+/// The receiving end of a bounded in-memory queue. There can only be
+/// one such subscription, so it is not Clone. Dropping it closes the queue.
+#[derive(Debug)]
+pub struct BoundedQueueSubscription<T>(Arc<BoundedQueue<T>>);
+
+impl<T> BoundedQueueSubscription<T> {
+    /// Waits for the next item, returning None once every
+    /// [BoundedQueueService] has been dropped and the queue is empty.
+    pub async fn recv(&mut self) -> Option<T> {
+        loop {
+            {
+                let mut items = self.0.items.lock().expect("queue lock poisoned");
+                if let Some(item) = items.pop_front() {
+                    // Freed while locked, so that a full queue always has no
+                    // free slots
+                    self.0.free_slots.add_permits(1);
+                    return Some(item);
+                }
+            }
+            if self.0.senders.load(Ordering::Acquire) == 0 {
+                return None;
+            }
+            self.0.item_available.notified().await;
+        }
+    }
+
+    /// The number of items waiting in the queue.
+    pub fn depth(&self) -> usize {
+        self.0.depth()
+    }
+
+    /// The maximum number of items the queue can hold.
+    pub fn capacity(&self) -> usize {
+        self.0.capacity
+    }
+
+    /// The number of items discarded by [OverflowPolicy::DropOldest].
+    pub fn dropped(&self) -> u64 {
+        self.0.dropped.load(Ordering::Relaxed)
+    }
+}
+
+impl<T> Drop for BoundedQueueSubscription<T> {
+    fn drop(&mut self) {
+        // Wakes any producers waiting for space
+        self.0.free_slots.close();
+    }
+}
+
+/// Create a bounded in-memory queue service holding at most `capacity`
+/// items, and a subscription handle to it.
+pub fn bounded_in_memory_queue<T>(
+    capacity: NonZeroUsize,
+    policy: OverflowPolicy,
+) -> (BoundedQueueService<T>, BoundedQueueSubscription<T>) {
+    let free_slots = Arc::new(Semaphore::new(capacity.get()));
+    let queue = Arc::new(BoundedQueue {
+        items: Mutex::new(VecDeque::with_capacity(capacity.get())),
+        capacity: capacity.get(),
+        policy,
+        free_slots: free_slots.clone(),
+        item_available: Notify::new(),
+        senders: AtomicUsize::new(1),
+        dropped: AtomicU64::new(0),
+    });
+    (
+        BoundedQueueService {
+            queue: queue.clone(),
+            free_slots: PollSemaphore::new(free_slots),
+            reserved_slot: None,
+        },
+        BoundedQueueSubscription(queue),
+    )
+}
+
+/// The receiving end of an in-memory queue, which can be passed to
+/// [subscribe_to_queue].
+pub trait QueueSubscription {
+    /// The type of item in the queue.
+    type Item;
+
+    /// Waits for the next item, returning None once the queue is closed.
+    /// This must be cancel safe, as [subscribe_to_queue] stops waiting for
+    /// an item when it is shut down.
+    fn recv(&mut self) -> impl Future<Output = Option<Self::Item>> + Send;
+}
+
+impl<T: Send> QueueSubscription for InMemoryQueueSubscription<T> {
+    type Item = T;
+
+    fn recv(&mut self) -> impl Future<Output = Option<T>> + Send {
+        self.0.recv()
+    }
+}
+
+impl<T: Send> QueueSubscription for BoundedQueueSubscription<T> {
+    type Item = T;
+
+    fn recv(&mut self) -> impl Future<Output = Option<T>> + Send {
+        BoundedQueueSubscription::recv(self)
+    }
+}
+
+/// What was left of a queue subscription once [subscribe_to_queue] stopped
+/// processing it.
+#[derive(Debug)]
+pub struct QueueShutdown<Q> {
+    /// The subscription, still holding any items which were never received.
+    pub subscription: Q,
+    /// The number of items which were still being processed when the
+    /// shutdown deadline passed, and were cancelled.
+    pub cancelled: usize,
+}
+
+/// A handle to a subscription started by [subscribe_to_queue]. Dropping the
+/// handle leaves the subscription running in the background until the queue
+/// is closed.
+#[derive(Debug)]
+pub struct QueueSubscriptionHandle<Q> {
+    shutdown: oneshot::Sender<Instant>,
+    task: JoinHandle<QueueShutdown<Q>>,
+}
+
+impl<Q> QueueSubscriptionHandle<Q> {
+    /// Stops receiving new items, then waits up to `timeout` for the items
+    /// already being processed to finish, cancelling any that don't.
+    pub async fn shutdown(self, timeout: Duration) -> Result<QueueShutdown<Q>, JoinError> {
+        // Fails if the queue has already closed, which is just as good
+        let _ = self.shutdown.send(Instant::now() + timeout);
+        self.task.await
+    }
+
+    /// Waits for the queue to close and every item to be processed.
+    pub async fn join(self) -> Result<QueueShutdown<Q>, JoinError> {
+        self.task.await
+    }
+
+    /// Whether the subscription has stopped.
+    pub fn is_finished(&self) -> bool {
+        self.task.is_finished()
+    }
+}
+
+/// Waits for the service to be ready, then for the next item in the queue.
+/// An item is still received if the service fails, so that it is not
+/// retried forever.
+async fn next_item<S, Q>(service: &mut S, subscription: &mut Q) -> Option<Result<Q::Item, S::Error>>
+where
+    S: Service<Q::Item>,
+    Q: QueueSubscription,
+{
+    let ready = service.ready().await.map(|_| ());
+    let item = subscription.recv().await?;
+    Some(ready.map(|()| item))
+}
+
+fn log_join_error(result: Result<(), JoinError>) {
+    if let Err(e) = result {
+        tracing::error!("Queue subscription service panicked: {e}");
+    }
+}
+
+/// Receives items and processes them, at most `concurrency_limit` at a
+/// time, until the queue closes or a shutdown deadline is received.
+async fn run_subscription<S, Q>(
     mut service: S,
-    mut subscription: InMemoryQueueSubscription<T>,
-) where
-    S: Service<T, Response = ()> + Send + 'static,
+    mut subscription: Q,
+    concurrency_limit: NonZeroUsize,
+    mut shutdown: oneshot::Receiver<Instant>,
+) -> QueueShutdown<Q>
+where
+    S: Service<Q::Item, Response = ()> + Send + 'static,
     S::Error: std::fmt::Display + Send,
     S::Future: Send,
-    T: Send + 'static,
+    Q: QueueSubscription + Send + 'static,
+    Q::Item: Send + 'static,
 {
-    tokio::spawn(async move {
-        while let Some(t) = subscription.recv().await {
-            match service.ready().await {
-                Ok(service) => {
-                    if let Err(e) = service.call(t).await {
-                        tracing::error!("Queue subscription service returned an error: {e}");
+    let mut in_flight = JoinSet::new();
+    let mut deadline = None;
+    // False once the handle has been used or dropped
+    let mut listening = true;
+
+    while deadline.is_none() {
+        tokio::select! {
+            received = &mut shutdown, if listening => {
+                listening = false;
+                deadline = received.ok();
+            }
+            Some(result) = in_flight.join_next() => log_join_error(result),
+            next = next_item(&mut service, &mut subscription),
+                if in_flight.len() < concurrency_limit.get() =>
+            {
+                match next {
+                    Some(Ok(item)) => {
+                        let response = service.call(item);
+                        in_flight.spawn(async move {
+                            if let Err(e) = response.await {
+                                tracing::error!("Queue subscription service returned an error: {e}");
+                            }
+                        });
                     }
-                }
-                Err(e) => {
-                    tracing::error!("Queue subscription service failed to ready: {e}");
+                    Some(Err(e)) => {
+                        tracing::error!("Queue subscription service failed to ready: {e}");
+                    }
+                    None => break,
                 }
             }
         }
-    });
+    }
+
+    // Finish what was already received, cutting it short at the deadline
+    let cancelled = loop {
+        tokio::select! {
+            joined = in_flight.join_next() => match joined {
+                Some(result) => log_join_error(result),
+                None => break 0,
+            },
+            received = &mut shutdown, if listening && deadline.is_none() => {
+                listening = false;
+                deadline = received.ok();
+            }
+            _ = tokio::time::sleep_until(deadline.unwrap_or_else(Instant::now)),
+                if deadline.is_some() =>
+            {
+                let cancelled = in_flight.len();
+                in_flight.shutdown().await;
+                break cancelled;
+            }
+        }
+    };
+
+    if cancelled > 0 {
+        tracing::warn!("Queue subscription shut down with {cancelled} items unfinished");
+    }
+    QueueShutdown {
+        subscription,
+        cancelled,
+    }
+}
+
+/// Start a service in the background which responds to events in the
+/// in-memory queue, processing up to `concurrency_limit` items at once.
+/// The returned handle can be used to shut the subscription down gracefully.
+pub fn subscribe_to_queue<S, Q>(
+    service: S,
+    subscription: Q,
+    concurrency_limit: NonZeroUsize,
+) -> QueueSubscriptionHandle<Q>
+where
+    S: Service<Q::Item, Response = ()> + Send + 'static,
+    S::Error: std::fmt::Display + Send,
+    S::Future: Send,
+    Q: QueueSubscription + Send + 'static,
+    Q::Item: Send + 'static,
+{
+    let (shutdown, shutdown_received) = oneshot::channel();
+    let task = tokio::spawn(run_subscription(
+        service,
+        subscription,
+        concurrency_limit,
+        shutdown_received,
+    ));
+    QueueSubscriptionHandle { shutdown, task }
 }
@@ -0,0 +1,426 @@
+use std::fmt::Display;
+
+use thiserror::Error;
+use tower::{service_fn, Service, ServiceExt};
+use twilight_model::{
+    application::{
+        command::CommandType,
+        interaction::{
+            application_command::{CommandData, CommandOptionValue},
+            modal::ModalInteractionData,
+            Interaction, InteractionData, InteractionType,
+        },
+    },
+    channel::Message,
+};
+use url::Url;
+
+use crate::payloads::{
+    DiscordClientAction, DiscordServerAction, PostAction, JOIN_HANDLE_INPUT_ID, JOIN_MODAL_ID,
+    MAX_HANDLE_LENGTH,
+};
+
+/// The reply when a handler fails. The details are logged rather than shown,
+/// as they may not mean anything to the user.
+const HANDLER_FAILED_REPLY: &str = "Sorry, something went wrong. Please try again later.";
+
+/// The URL of an actor, given as the `url` option of a slash command. Only
+/// absolute http or https URLs are accepted.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct ActorUrl(Url);
+
+impl ActorUrl {
+    /// Parses and validates a URL typed by a user.
+    pub fn parse(value: &str) -> Result<Self, RouteError> {
+        let invalid = || RouteError::InvalidUrl(value.to_string());
+        let url = Url::parse(value.trim()).map_err(|_| invalid())?;
+        if !matches!(url.scheme(), "http" | "https") || url.host().is_none() {
+            return Err(invalid());
+        }
+        Ok(Self(url))
+    }
+
+    /// The validated URL.
+    pub fn url(&self) -> &Url {
+        &self.0
+    }
+}
+
+/// A WebFinger handle picked by a user, without the instance domain. It is
+/// 1 to [MAX_HANDLE_LENGTH] ASCII letters, numbers or underscores, but may
+/// not be unique.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Handle(String);
+
+impl Handle {
+    /// Validates a handle typed by a user.
+    pub fn parse(value: &str) -> Result<Self, RouteError> {
+        let value = value.trim();
+        let valid_length = (1..=MAX_HANDLE_LENGTH).contains(&value.len());
+        let valid_chars = value.chars().all(|c| c.is_ascii_alphanumeric() || c == '_');
+        if valid_length && valid_chars {
+            Ok(Self(value.to_string()))
+        } else {
+            Err(RouteError::InvalidHandle(value.to_string()))
+        }
+    }
+
+    /// The validated handle.
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+/// A slash command from [crate::deploy::slash], with its options.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SlashCommand {
+    /// /block <url>
+    Block(ActorUrl),
+    /// /follow <url>
+    Follow(ActorUrl),
+    /// /join, once the user has picked a handle in the modal it opens
+    Join(Handle),
+    /// /unblock <url>
+    Unblock(ActorUrl),
+    /// /unfollow <url>
+    Unfollow(ActorUrl),
+}
+
+/// A message command from [crate::deploy::message].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MessageCommand {
+    /// "Block in channel"
+    BlockInChannel,
+    /// "Delete post"
+    DeletePost,
+    /// "Follow in channel"
+    FollowInChannel,
+    /// "Like"
+    Like,
+    /// "Post"
+    Post,
+    /// "Share"
+    Share,
+    /// "Unblock in channel"
+    UnblockInChannel,
+    /// "Unfollow in channel"
+    UnfollowInChannel,
+    /// "Unlike"
+    Unlike,
+}
+
+impl MessageCommand {
+    fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "Block in channel" => Some(Self::BlockInChannel),
+            "Delete post" => Some(Self::DeletePost),
+            "Follow in channel" => Some(Self::FollowInChannel),
+            "Like" => Some(Self::Like),
+            "Post" => Some(Self::Post),
+            "Share" => Some(Self::Share),
+            "Unblock in channel" => Some(Self::UnblockInChannel),
+            "Unfollow in channel" => Some(Self::UnfollowInChannel),
+            "Unlike" => Some(Self::Unlike),
+            _ => None,
+        }
+    }
+}
+
+/// A slash command to be handled, with the interaction it came from.
+#[derive(Debug, Clone)]
+pub struct SlashCommandRequest {
+    /// The interaction, for its token, channel and user.
+    pub interaction: Interaction,
+    /// The command used.
+    pub command: SlashCommand,
+}
+
+/// A message command to be handled, with the interaction it came from.
+#[derive(Debug, Clone)]
+pub struct MessageCommandRequest {
+    /// The interaction, for its token, channel and user.
+    pub interaction: Interaction,
+    /// The command used.
+    pub command: MessageCommand,
+    /// The message the command was used on.
+    pub target: Message,
+}
+
+/// A click on a component under a post, with the interaction it came from.
+#[derive(Debug, Clone)]
+pub struct PostComponentRequest {
+    /// The interaction, for its token, channel and user. Its message is the
+    /// post.
+    pub interaction: Interaction,
+    /// The action picked.
+    pub action: PostAction,
+}
+
+/// An interaction, parsed into the request for the handler it is routed to.
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub enum RoutedInteraction {
+    /// A slash command
+    SlashCommand(SlashCommandRequest),
+    /// A message command
+    MessageCommand(MessageCommandRequest),
+    /// A button or select menu under a post
+    PostComponent(PostComponentRequest),
+}
+
+/// Why an interaction could not be routed to a handler. Each of these can
+/// be explained to the user who caused it, with [RouteError::user_message].
+#[derive(Debug, Error, Clone, PartialEq, Eq)]
+pub enum RouteError {
+    /// Not a command or modal that Eris deploys, such as one that has since
+    /// been removed
+    #[error("Unknown command: {0}")]
+    UnknownCommand(String),
+    /// A required option was not given
+    #[error("Missing option: {0}")]
+    MissingOption(&'static str),
+    /// The `url` option was not an http or https URL
+    #[error("Invalid URL: {0}")]
+    InvalidUrl(String),
+    /// The handle picked was not valid
+    #[error("Invalid handle: {0}")]
+    InvalidHandle(String),
+    /// A message command did not include the message it was used on
+    #[error("Message command is missing its target message")]
+    MissingTargetMessage,
+    /// Not a component that Eris puts under posts, such as one from a
+    /// message sent by an older version
+    #[error("Unknown component: {0}")]
+    UnknownComponent(String),
+}
+
+impl RouteError {
+    /// The reply shown to the user who used the interaction.
+    pub fn user_message(&self) -> String {
+        match self {
+            Self::UnknownCommand(name) => format!(
+                "Sorry, Eris doesn't know the command \"{name}\". It may have been removed since it was added to this server."
+            ),
+            Self::MissingOption(name) => format!("The {name} option is required."),
+            Self::InvalidUrl(value) => format!(
+                "\"{value}\" is not a valid URL. Use the full address of an actor, starting with https://"
+            ),
+            Self::InvalidHandle(value) => format!(
+                "\"{value}\" can't be used as a handle. Handles are 1 to {MAX_HANDLE_LENGTH} letters, numbers or underscores."
+            ),
+            Self::MissingTargetMessage => {
+                "Discord didn't say which message the command was used on. Please try again."
+                    .to_string()
+            }
+            Self::UnknownComponent(_) => {
+                "Sorry, this doesn't do anything anymore. It may be from an older version of Eris."
+                    .to_string()
+            }
+        }
+    }
+}
+
+/// Reads the `url` option of a slash command.
+fn url_option(command: &CommandData) -> Result<ActorUrl, RouteError> {
+    command
+        .options
+        .iter()
+        .find(|option| option.name == "url")
+        .and_then(|option| match &option.value {
+            CommandOptionValue::String(value) => Some(value),
+            _ => None,
+        })
+        .ok_or(RouteError::MissingOption("url"))
+        .and_then(|value| ActorUrl::parse(value))
+}
+
+fn parse_slash_command(command: &CommandData) -> Result<SlashCommand, RouteError> {
+    match command.name.as_str() {
+        "block" => url_option(command).map(SlashCommand::Block),
+        "follow" => url_option(command).map(SlashCommand::Follow),
+        // The handle is picked in a modal, submitted as its own interaction
+        "join" => Err(RouteError::MissingOption("handle")),
+        "unblock" => url_option(command).map(SlashCommand::Unblock),
+        "unfollow" => url_option(command).map(SlashCommand::Unfollow),
+        name => Err(RouteError::UnknownCommand(name.to_string())),
+    }
+}
+
+fn parse_message_command(command: &CommandData) -> Result<(MessageCommand, Message), RouteError> {
+    let message_command = MessageCommand::from_name(&command.name)
+        .ok_or_else(|| RouteError::UnknownCommand(command.name.clone()))?;
+    let target = command
+        .target_id
+        .zip(command.resolved.as_ref())
+        .and_then(|(target_id, resolved)| resolved.messages.get(&target_id.cast()))
+        .ok_or(RouteError::MissingTargetMessage)?;
+    Ok((message_command, target.clone()))
+}
+
+fn parse_modal(modal: &ModalInteractionData) -> Result<SlashCommand, RouteError> {
+    if modal.custom_id != JOIN_MODAL_ID {
+        return Err(RouteError::UnknownCommand(modal.custom_id.clone()));
+    }
+    let handle = modal
+        .components
+        .iter()
+        .flat_map(|row| &row.components)
+        .find(|component| component.custom_id == JOIN_HANDLE_INPUT_ID)
+        .and_then(|component| component.value.as_deref())
+        .ok_or(RouteError::MissingOption("handle"))?;
+    Handle::parse(handle).map(SlashCommand::Join)
+}
+
+/// Parses an interaction into the request for the handler of its command,
+/// matching the commands built in [crate::deploy].
+pub fn route_interaction(interaction: Interaction) -> Result<RoutedInteraction, RouteError> {
+    match &interaction.data {
+        Some(InteractionData::ApplicationCommand(command)) => match command.kind {
+            CommandType::ChatInput => {
+                let command = parse_slash_command(command)?;
+                Ok(RoutedInteraction::SlashCommand(SlashCommandRequest {
+                    interaction,
+                    command,
+                }))
+            }
+            CommandType::Message => {
+                let (command, target) = parse_message_command(command)?;
+                Ok(RoutedInteraction::MessageCommand(MessageCommandRequest {
+                    interaction,
+                    command,
+                    target,
+                }))
+            }
+            _ => Err(RouteError::UnknownCommand(command.name.clone())),
+        },
+        Some(InteractionData::ModalSubmit(modal)) => {
+            let command = parse_modal(modal)?;
+            Ok(RoutedInteraction::SlashCommand(SlashCommandRequest {
+                interaction,
+                command,
+            }))
+        }
+        Some(InteractionData::MessageComponent(data)) => {
+            let action =
+                PostAction::from_component(data).map_err(|e| RouteError::UnknownComponent(e.0))?;
+            Ok(RoutedInteraction::PostComponent(PostComponentRequest {
+                interaction,
+                action,
+            }))
+        }
+        _ => Err(RouteError::UnknownCommand(format!(
+            "{:?}",
+            interaction.kind
+        ))),
+    }
+}
+
+/// Parses a server action into the request for the handler of its
+/// interaction. Component interactions whose action was already parsed
+/// when they were queued are routed as they are. Returns None for actions
+/// which are not interactions.
+pub fn route_server_action(
+    action: DiscordServerAction,
+) -> Option<Result<RoutedInteraction, RouteError>> {
+    match action {
+        DiscordServerAction::PostInteraction(interaction) => Some(route_interaction(interaction)),
+        DiscordServerAction::PostComponentInteraction {
+            interaction,
+            action,
+        } => Some(Ok(RoutedInteraction::PostComponent(PostComponentRequest {
+            interaction,
+            action,
+        }))),
+        DiscordServerAction::DiscordClientActionResponse(_) => None,
+    }
+}
+
+/// The interaction a server action is about, if it is about one.
+fn interaction(action: &DiscordServerAction) -> Option<&Interaction> {
+    match action {
+        DiscordServerAction::PostInteraction(interaction)
+        | DiscordServerAction::PostComponentInteraction { interaction, .. } => Some(interaction),
+        DiscordServerAction::DiscordClientActionResponse(_) => None,
+    }
+}
+
+/// A service which takes a server action queued by
+/// [crate::services::discord_endpoint], parses it with
+/// [route_server_action], and calls the handler service for its kind of
+/// command or component. Handlers reply through the client action service
+/// themselves. Server actions which are not interactions are ignored.
+///
+/// If the interaction cannot be routed, or its handler fails, the user is
+/// told so: commands by updating their deferred response, and components by
+/// an ephemeral follow-up, so that the post they are under is left as it
+/// was. The service only fails if that reply cannot be sent.
+pub fn interaction_router<S, M, C, A>(
+    slash_command_service: S,
+    message_command_service: M,
+    post_component_service: C,
+    client_action_service: A,
+) -> impl Service<DiscordServerAction, Response = (), Error = A::Error> + Clone
+where
+    S: Service<SlashCommandRequest, Response = ()> + Clone,
+    S::Error: Display,
+    M: Service<MessageCommandRequest, Response = ()> + Clone,
+    M::Error: Display,
+    C: Service<PostComponentRequest, Response = ()> + Clone,
+    C::Error: Display,
+    A: Service<DiscordClientAction, Response = ()> + Clone,
+{
+    service_fn(move |action: DiscordServerAction| {
+        let slash_command_service = slash_command_service.clone();
+        let message_command_service = message_command_service.clone();
+        let post_component_service = post_component_service.clone();
+        let client_action_service = client_action_service.clone();
+        async move {
+            let Some(interaction) = interaction(&action) else {
+                tracing::debug!("Ignoring server action which is not an interaction");
+                return Ok(());
+            };
+            let interaction_token = interaction.token.clone();
+            let is_component = interaction.kind == InteractionType::MessageComponent;
+
+            let reply = match route_server_action(action) {
+                Some(Ok(RoutedInteraction::SlashCommand(request))) => {
+                    slash_command_service.oneshot(request).await.err().map(|e| {
+                        tracing::error!("Slash command handler failed: {e}");
+                        HANDLER_FAILED_REPLY.to_string()
+                    })
+                }
+                Some(Ok(RoutedInteraction::MessageCommand(request))) => message_command_service
+                    .oneshot(request)
+                    .await
+                    .err()
+                    .map(|e| {
+                        tracing::error!("Message command handler failed: {e}");
+                        HANDLER_FAILED_REPLY.to_string()
+                    }),
+                Some(Ok(RoutedInteraction::PostComponent(request))) => post_component_service
+                    .oneshot(request)
+                    .await
+                    .err()
+                    .map(|e| {
+                        tracing::error!("Post component handler failed: {e}");
+                        HANDLER_FAILED_REPLY.to_string()
+                    }),
+                Some(Err(e)) => {
+                    tracing::warn!("Could not route interaction: {e}");
+                    Some(e.user_message())
+                }
+                None => None,
+            };
+
+            if let Some(reply) = reply {
+                let reply = if is_component {
+                    DiscordClientAction::interaction_followup_text(interaction_token, reply, true)
+                } else {
+                    DiscordClientAction::interaction_response_text(interaction_token, reply)
+                };
+                client_action_service.oneshot(reply).await?;
+            }
+            Ok(())
+        }
+    })
+}
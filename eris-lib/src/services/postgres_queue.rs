@@ -0,0 +1,283 @@
+use std::{marker::PhantomData, sync::Arc, time::Duration};
+
+use futures_util::future::BoxFuture;
+use serde::{de::DeserializeOwned, Serialize};
+use sqlx::{postgres::PgListener, types::Json, PgPool};
+use thiserror::Error;
+use tower::{Service, ServiceExt};
+
+/// The Postgres channel used to wake subscribers when an item is queued.
+const NOTIFY_CHANNEL: &str = "eris_queue";
+
+/// How long a subscriber waits for a notification before checking for items
+/// anyway, in case a notification was missed while reconnecting.
+const POLL_INTERVAL: Duration = Duration::from_secs(30);
+
+/// The error that might be returned when queueing an item in, or receiving
+/// an item from, a [PostgresQueueService].
+#[derive(Debug, Error)]
+pub enum PostgresQueueError {
+    /// The item could not be converted to or from JSON
+    #[error("Failed to (de)serialize queue item: {0}")]
+    SerializeError(#[from] serde_json::Error),
+    /// The database failed to execute a query
+    #[error("Queue database error: {0}")]
+    DatabaseError(#[from] sqlx::Error),
+    /// A claimed item could not be converted from JSON, such as one queued
+    /// by an older version of Eris. It will never be deliverable.
+    #[error("Queue item {id} could not be deserialized: {source}")]
+    MalformedItem {
+        /// The id of the claimed item
+        id: i64,
+        /// The item as stored
+        payload: serde_json::Value,
+        /// Why it could not be deserialized
+        source: serde_json::Error,
+    },
+}
+
+/// A durable background task queue service, storing each item as a row in
+/// Postgres so that it survives restarts and crashes. Like
+/// [super::in_memory_queue::InMemoryQueueService], this service is Clone, so
+/// multiple services can insert into this queue at once, and responds as
+/// soon as the item is stored. Items are stored in the `eris_queue_items`
+/// table, which is created by the eris-data migrations.
+///
+/// Items are delivered at least once: an item is only deleted after the
+/// subscriber's service has processed it successfully.
+#[derive(Debug)]
+pub struct PostgresQueueService<T> {
+    pool: PgPool,
+    queue: Arc<str>,
+    _item: PhantomData<fn(T)>,
+}
+
+// Derived Clone would require T: Clone
+impl<T> Clone for PostgresQueueService<T> {
+    fn clone(&self) -> Self {
+        Self {
+            pool: self.pool.clone(),
+            queue: self.queue.clone(),
+            _item: PhantomData,
+        }
+    }
+}
+
+impl<T: Serialize> Service<T> for PostgresQueueService<T> {
+    type Response = ();
+
+    type Error = PostgresQueueError;
+
+    type Future = BoxFuture<'static, Result<(), PostgresQueueError>>;
+
+    fn poll_ready(
+        &mut self,
+        _cx: &mut std::task::Context<'_>,
+    ) -> std::task::Poll<Result<(), Self::Error>> {
+        std::task::Poll::Ready(Ok(()))
+    }
+
+    fn call(&mut self, req: T) -> Self::Future {
+        let pool = self.pool.clone();
+        let queue = self.queue.clone();
+        let payload = serde_json::to_value(req);
+        Box::pin(async move {
+            let payload = payload?;
+            let mut transaction = pool.begin().await?;
+            sqlx::query("INSERT INTO eris_queue_items (queue, payload) VALUES ($1, $2)")
+                .bind(&*queue)
+                .bind(Json(payload))
+                .execute(&mut *transaction)
+                .await?;
+            // Delivered once the transaction commits, so the item is visible
+            sqlx::query("SELECT pg_notify($1, $2)")
+                .bind(NOTIFY_CHANNEL)
+                .bind(&*queue)
+                .execute(&mut *transaction)
+                .await?;
+            transaction.commit().await?;
+            Ok(())
+        })
+    }
+}
+
+/// The receiving end of a Postgres queue. Only one subscription should be
+/// active per queue name at a time, as unacknowledged items are redelivered
+/// when it starts, so it is not Clone.
+#[derive(Debug)]
+pub struct PostgresQueueSubscription<T> {
+    pool: PgPool,
+    queue: Arc<str>,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> PostgresQueueSubscription<T> {
+    /// Makes every item that was claimed but never acknowledged, such as
+    /// because the process crashed or the service failed, available again.
+    /// Returns the number of items redelivered.
+    pub async fn redeliver_unacknowledged(&self) -> Result<u64, PostgresQueueError> {
+        let result = sqlx::query(
+            "UPDATE eris_queue_items SET claimed_at = NULL
+                WHERE queue = $1 AND claimed_at IS NOT NULL",
+        )
+        .bind(&*self.queue)
+        .execute(&self.pool)
+        .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Claims the oldest unclaimed item, if there is one, returning its id
+    /// and payload. The item stays in the queue until it is acknowledged,
+    /// even if it fails with [PostgresQueueError::MalformedItem].
+    pub async fn claim(&self) -> Result<Option<(i64, T)>, PostgresQueueError> {
+        let claimed: Option<(i64, Json<serde_json::Value>)> = sqlx::query_as(
+            "UPDATE eris_queue_items SET claimed_at = now(), attempts = attempts + 1
+                WHERE id = (
+                    SELECT id FROM eris_queue_items
+                        WHERE queue = $1 AND claimed_at IS NULL
+                        ORDER BY id
+                        FOR UPDATE SKIP LOCKED
+                        LIMIT 1
+                )
+                RETURNING id, payload",
+        )
+        .bind(&*self.queue)
+        .fetch_optional(&self.pool)
+        .await?;
+
+        let Some((id, Json(payload))) = claimed else {
+            return Ok(None);
+        };
+        match serde_json::from_value(payload.clone()) {
+            Ok(item) => Ok(Some((id, item))),
+            Err(source) => Err(PostgresQueueError::MalformedItem {
+                id,
+                payload,
+                source,
+            }),
+        }
+    }
+
+    /// Removes a claimed item from the queue, so that it is never delivered
+    /// again.
+    pub async fn acknowledge(&self, id: i64) -> Result<(), PostgresQueueError> {
+        sqlx::query("DELETE FROM eris_queue_items WHERE id = $1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+}
+
+/// Create a durable queue service and a subscription handle to it, storing
+/// items in the given pool under this queue name. The pool's database must
+/// have been migrated with the eris-data migrations.
+pub fn postgres_queue<T>(
+    pool: PgPool,
+    queue: &str,
+) -> (PostgresQueueService<T>, PostgresQueueSubscription<T>) {
+    let queue: Arc<str> = queue.into();
+    (
+        PostgresQueueService {
+            pool: pool.clone(),
+            queue: queue.clone(),
+            _item: PhantomData,
+        },
+        PostgresQueueSubscription {
+            pool,
+            queue,
+            _item: PhantomData,
+        },
+    )
+}
+
+/// Claims and processes every available item, acknowledging each one the
+/// service handles successfully. Items which fail are left claimed, so they
+/// are redelivered the next time the subscription starts rather than being
+/// retried in a tight loop. Items which cannot be deserialized are deleted,
+/// as they would otherwise be redelivered forever, and logged in full.
+async fn drain_queue<S, T>(
+    service: &mut S,
+    subscription: &PostgresQueueSubscription<T>,
+) -> Result<(), PostgresQueueError>
+where
+    S: Service<T, Response = ()>,
+    S::Error: std::fmt::Display,
+    T: DeserializeOwned,
+{
+    loop {
+        let (id, item) = match subscription.claim().await {
+            Ok(Some(claimed)) => claimed,
+            Ok(None) => return Ok(()),
+            Err(PostgresQueueError::MalformedItem {
+                id,
+                payload,
+                source,
+            }) => {
+                tracing::error!(
+                    "Deleting queue item {id} which could not be deserialized: {source}, payload: {payload}"
+                );
+                subscription.acknowledge(id).await?;
+                continue;
+            }
+            Err(e) => return Err(e),
+        };
+
+        match service.ready().await {
+            Ok(service) => match service.call(item).await {
+                Ok(()) => subscription.acknowledge(id).await?,
+                Err(e) => {
+                    tracing::error!("Queue subscription service returned an error: {e}");
+                }
+            },
+            Err(e) => {
+                tracing::error!("Queue subscription service failed to ready: {e}");
+            }
+        }
+    }
+}
+
+/// Start a service in the background which responds to items in the
+/// Postgres queue. Items left unacknowledged by a previous subscription are
+/// redelivered first, then new items are processed as they are queued.
+pub async fn subscribe_to_postgres_queue<S, T>(
+    mut service: S,
+    subscription: PostgresQueueSubscription<T>,
+) -> Result<(), PostgresQueueError>
+where
+    S: Service<T, Response = ()> + Send + 'static,
+    S::Error: std::fmt::Display + Send,
+    S::Future: Send,
+    T: DeserializeOwned + Send + 'static,
+{
+    let mut listener = PgListener::connect_with(&subscription.pool).await?;
+    listener.listen(NOTIFY_CHANNEL).await?;
+
+    let redelivered = subscription.redeliver_unacknowledged().await?;
+    if redelivered > 0 {
+        tracing::warn!(
+            "Redelivering {redelivered} unacknowledged items from queue {}",
+            subscription.queue
+        );
+    }
+
+    tokio::spawn(async move {
+        loop {
+            if let Err(e) = drain_queue(&mut service, &subscription).await {
+                tracing::error!("Failed to receive from queue {}: {e}", subscription.queue);
+            }
+
+            // Wait until something is queued, checking periodically in case
+            // a notification is missed. The listener reconnects by itself.
+            match tokio::time::timeout(POLL_INTERVAL, listener.recv()).await {
+                Ok(Ok(_)) | Err(_) => {}
+                Ok(Err(e)) => {
+                    tracing::error!("Queue listener failed: {e}");
+                    tokio::time::sleep(Duration::from_secs(1)).await;
+                }
+            }
+        }
+    });
+
+    Ok(())
+}
@@ -1,17 +1,50 @@
-use std::sync::Arc;
+use std::{
+    future::Future,
+    net::{IpAddr, SocketAddr},
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
 
-use futures_util::future::{ready, Ready};
+use futures_util::{future::BoxFuture, TryFutureExt};
+use hyper::client::connect::dns::Name;
+use rand::Rng;
+use reqwest::{
+    dns::{Addrs, Resolve, Resolving},
+    redirect,
+};
 use thiserror::Error;
-use tower::{Service, retry::{Policy, RetryLayer}, ServiceBuilder};
-use twilight_http::request::AuditLogReason;
+use tower::{
+    retry::{Policy, RetryLayer},
+    service_fn, Service, ServiceBuilder, ServiceExt,
+};
+use twilight_http::{
+    api_error::ApiError,
+    error::ErrorType,
+    request::{
+        channel::message::CreateMessage as CreateMessageRequest, AuditLogReason, Request,
+        TryIntoRequest,
+    },
+    routing::Route,
+};
 use twilight_model::{
-    channel::Message,
-    id::{marker::ApplicationMarker, Id},
+    channel::{message::MessageFlags, Message},
+    http::attachment::Attachment,
+    id::{
+        marker::{ApplicationMarker, ChannelMarker},
+        Id,
+    },
 };
 
+use url::{Host, Url};
+
 use crate::payloads::{
-    CreateMessage, CreateReply, DeleteMessage, DiscordClientAction, DiscordClientActionResponse,
-    MessagePayload, UpdateInteractionResponse, UpdateMessage,
+    CreateFollowup, CreateMessage, CreateReply, DeleteInteractionResponse, DeleteMessage,
+    DiscordClientAction, DiscordClientActionResponse, MessageAttachment, MessagePayload,
+    UpdateInteractionResponse, UpdateMessage,
 };
 
 /// Wrapper around two very similar validation errors from [twilight_validate].
@@ -25,6 +58,24 @@ pub enum TwilightValidationError {
     ValidationError(#[from] twilight_validate::request::ValidationError),
 }
 
+/// Why an attachment given by URL could not be downloaded.
+#[derive(Debug, Error)]
+pub enum AttachmentDownloadError {
+    /// The URL could not be parsed
+    #[error("Invalid attachment URL: {0}")]
+    InvalidUrl(#[from] url::ParseError),
+    /// The request failed, timed out, or was answered with an error status
+    #[error("{0}")]
+    RequestError(#[from] reqwest::Error),
+    /// The attachment is larger than Discord accepts
+    #[error("Attachment is larger than {MAX_ATTACHMENT_BYTES} bytes")]
+    TooLarge,
+    /// The URL is not on the public internet, such as a loopback or private
+    /// address, so it is not fetched
+    #[error("Attachment URL {0} is not a public address")]
+    ForbiddenAddress(Url),
+}
+
 /// Errors that can occur when constructing, sending, or receiving a response
 /// to a Discord API call made via [twilight_http::Client].
 #[derive(Debug, Error)]
@@ -32,6 +83,10 @@ pub enum TwilightServiceError {
     /// An error occurred during message validation, HTTP request not sent
     #[error("Message validation error: {0}")]
     TwilightValidationError(#[from] TwilightValidationError),
+    /// An attachment could not be downloaded from its URL, HTTP request not
+    /// sent
+    #[error("Attachment download error: {0}")]
+    AttachmentDownloadError(#[from] AttachmentDownloadError),
     /// The HTTP request was attempted but did not succeed, either due to a
     /// network failure, or because Discord returned an error status code
     #[error("Twilight client error: {0}")]
@@ -40,73 +95,377 @@ pub enum TwilightServiceError {
     /// occurred while trying to deserialize the response from Discord.
     #[error("Error deserializing body from Discord response: {0}")]
     DeserializationBodyError(#[from] twilight_http::response::DeserializeBodyError),
+    /// The body Twilight built for the request could not be read or written
+    /// as JSON, so that fields it has no option for could be added, HTTP
+    /// request not sent
+    #[error("Error building the request body: {0}")]
+    RequestBodyError(#[from] serde_json::Error),
+    /// The [super::discord_scheduler::DiscordSchedulerService] stopped, such
+    /// as because the runtime is shutting down, before the action was
+    /// answered. It may or may not have been sent.
+    #[error("The Discord scheduler stopped before the action was answered")]
+    SchedulerStopped,
 }
 
-async fn create_message(
-    twilight_client: &twilight_http::Client,
-    create_message: &CreateMessage,
-) -> Result<Message, TwilightServiceError> {
-    let request = twilight_client.create_message(create_message.channel_id);
-    let response = match &create_message.message {
-        MessagePayload::Text(text) => {
-            request
-                .content(text)
-                .map_err(TwilightValidationError::MessageValidationError)?
-                .await?
+/// The largest attachment downloaded, which is the most Discord accepts
+/// without a boost.
+const MAX_ATTACHMENT_BYTES: usize = 25 * 1024 * 1024;
+
+/// How long downloading an attachment, including its body, may take.
+const ATTACHMENT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many redirects are followed when downloading an attachment.
+const MAX_ATTACHMENT_REDIRECTS: usize = 5;
+
+/// Whether an address is on the public internet, as opposed to a loopback,
+/// private, link-local or otherwise special address.
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_unspecified()
+                || ip.is_loopback()
+                || ip.is_private()
+                || ip.is_link_local()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // Shared address space, used for carrier-grade NAT
+                || (a == 100 && (64..128).contains(&b)))
         }
-        MessagePayload::Embed(embed) => {
-            request
-                .embeds(std::slice::from_ref(embed))
-                .map_err(TwilightValidationError::from)?
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                let first = ip.segments()[0];
+                !(ip.is_unspecified()
+                    || ip.is_loopback()
+                    || ip.is_multicast()
+                    // Unique local
+                    || (first & 0xfe00) == 0xfc00
+                    // Link-local
+                    || (first & 0xffc0) == 0xfe80)
+            }
+        },
+    }
+}
+
+/// Whether a URL names a host by an address which is not public. Hosts named
+/// by domain are checked by [PublicResolver] once they are resolved.
+fn has_forbidden_address(url: &Url) -> bool {
+    match url.host() {
+        Some(Host::Ipv4(ip)) => !is_public(IpAddr::V4(ip)),
+        Some(Host::Ipv6(ip)) => !is_public(IpAddr::V6(ip)),
+        Some(Host::Domain(_)) => false,
+        None => true,
+    }
+}
+
+/// Resolves domains to their public addresses only, so that attachment URLs
+/// cannot be used to reach services on the host or its private network.
+struct PublicResolver;
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((name.as_str(), 0))
                 .await?
+                .filter(|addr| is_public(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{} has no public address", name.as_str()).into());
+            }
+            let addrs: Addrs = Box::new(addrs.into_iter());
+            Ok(addrs)
+        })
+    }
+}
+
+/// The client attachments are downloaded with. It times out, only connects
+/// to public addresses, including after redirects, and ignores any proxy
+/// configured in the environment, which would resolve hosts itself.
+fn attachment_client() -> reqwest::Client {
+    let redirect_policy = redirect::Policy::custom(|attempt| {
+        if attempt.previous().len() >= MAX_ATTACHMENT_REDIRECTS {
+            attempt.error("too many redirects")
+        } else if has_forbidden_address(attempt.url()) {
+            attempt.error("redirected to an address which is not public")
+        } else {
+            attempt.follow()
         }
+    });
+    reqwest::Client::builder()
+        .timeout(ATTACHMENT_TIMEOUT)
+        .redirect(redirect_policy)
+        .dns_resolver(Arc::new(PublicResolver))
+        .no_proxy()
+        .build()
+        // As with reqwest::Client::new, this only fails if TLS cannot be
+        // initialized
+        .expect("attachment client should build")
+}
+
+/// Downloads an attachment, reading at most [MAX_ATTACHMENT_BYTES] of it.
+async fn download_attachment(
+    http_client: &reqwest::Client,
+    url: &str,
+) -> Result<Vec<u8>, AttachmentDownloadError> {
+    let url = Url::parse(url)?;
+    if has_forbidden_address(&url) {
+        return Err(AttachmentDownloadError::ForbiddenAddress(url));
+    }
+
+    let mut response = http_client.get(url).send().await?.error_for_status()?;
+    if response
+        .content_length()
+        .is_some_and(|length| length > MAX_ATTACHMENT_BYTES as u64)
+    {
+        return Err(AttachmentDownloadError::TooLarge);
+    }
+    let mut file = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        if file.len() + chunk.len() > MAX_ATTACHMENT_BYTES {
+            return Err(AttachmentDownloadError::TooLarge);
+        }
+        file.extend_from_slice(&chunk);
+    }
+    Ok(file)
+}
+
+/// Downloads or copies the attachments of a message, so they can be uploaded
+/// to Discord.
+async fn load_attachments(
+    http_client: &reqwest::Client,
+    payload: &MessagePayload,
+) -> Result<Vec<Attachment>, AttachmentDownloadError> {
+    let MessagePayload::Rich(rich) = payload else {
+        return Ok(Vec::new());
+    };
+
+    let mut attachments = Vec::with_capacity(rich.attachments.len());
+    for (id, attachment) in (0..).zip(&rich.attachments) {
+        let (file, filename, description) = match attachment {
+            MessageAttachment::Url {
+                url,
+                filename,
+                description,
+            } => (
+                download_attachment(http_client, url).await?,
+                filename,
+                description,
+            ),
+            MessageAttachment::Bytes {
+                bytes,
+                filename,
+                description,
+            } => (bytes.clone(), filename, description),
+        };
+        let mut attachment = Attachment::from_bytes(filename.clone(), file, id);
+        attachment.description = description.clone();
+        attachments.push(attachment);
+    }
+    Ok(attachments)
+}
+
+/// The message an action sends, if it sends one.
+fn message_payload(action: &DiscordClientAction) -> Option<&MessagePayload> {
+    match action {
+        DiscordClientAction::CreateMessage(action) => Some(&action.message),
+        DiscordClientAction::CreateReply(action) => Some(&action.message),
+        DiscordClientAction::CreateFollowup(action) => Some(&action.message),
+        DiscordClientAction::UpdateInteractionResponse(action) => Some(&action.message),
+        DiscordClientAction::UpdateMessage(action) => Some(&action.message),
+        DiscordClientAction::DeleteMessage(_)
+        | DiscordClientAction::DeleteInteractionResponse(_) => None,
+    }
+}
+
+/// Sets the fields of a message to be created from its payload.
+fn create_message_fields<'a>(
+    request: CreateMessageRequest<'a>,
+    payload: &'a MessagePayload,
+) -> Result<CreateMessageRequest<'a>, TwilightValidationError> {
+    let request = match payload {
+        MessagePayload::Text(text) => request.content(text)?,
+        MessagePayload::Embed(embed) => request.embeds(std::slice::from_ref(embed))?,
         MessagePayload::TextAndEmbed { text, embed } => {
+            request.content(text)?.embeds(std::slice::from_ref(embed))?
+        }
+        MessagePayload::Rich(rich) => {
+            let mut request = request.embeds(&rich.embeds)?.components(&rich.components)?;
+            if let Some(content) = &rich.content {
+                request = request.content(content)?;
+            }
+            if let Some(allowed_mentions) = &rich.allowed_mentions {
+                request = request.allowed_mentions(Some(allowed_mentions));
+            }
+            if let Some(flags) = rich.flags {
+                request = request.flags(flags);
+            }
             request
-                .content(text)
-                .map_err(TwilightValidationError::from)?
-                .embeds(std::slice::from_ref(embed))
-                .map_err(TwilightValidationError::from)?
-                .await?
         }
     };
+    Ok(request)
+}
 
+/// Sends a request to create a message. With an idempotency key, the key is
+/// sent as the message nonce and Discord is asked to enforce it, so that
+/// sending the same request again returns the message created the first time
+/// instead of creating another. Twilight has no option for this, so it is
+/// added to the body Twilight would have sent.
+async fn send_create_message(
+    twilight_client: &twilight_http::Client,
+    channel_id: Id<ChannelMarker>,
+    request: CreateMessageRequest<'_>,
+    attachments: &[Attachment],
+    idempotency_key: Option<u64>,
+) -> Result<Message, TwilightServiceError> {
+    let Some(idempotency_key) = idempotency_key else {
+        let request = request
+            .attachments(attachments)
+            .map_err(TwilightValidationError::from)?;
+        return Ok(request.await?.model().await?);
+    };
+
+    // Built without attachments, so that the body is JSON
+    let request = request.nonce(idempotency_key).try_into_request()?;
+    let body = request.body().unwrap_or(b"{}");
+    let mut fields: serde_json::Map<String, serde_json::Value> = serde_json::from_slice(body)?;
+    fields.insert("enforce_nonce".to_string(), true.into());
+
+    let request = if attachments.is_empty() {
+        Request::builder(&Route::CreateMessage {
+            channel_id: channel_id.get(),
+        })
+        .json(&fields)?
+        .build()
+    } else {
+        // Sent as a form, with the fields as its JSON payload
+        let descriptions: Vec<_> = attachments
+            .iter()
+            .map(|attachment| {
+                let mut description = serde_json::json!({
+                    "id": attachment.id,
+                    "filename": attachment.filename,
+                });
+                if let Some(alt_text) = &attachment.description {
+                    description["description"] = alt_text.as_str().into();
+                }
+                description
+            })
+            .collect();
+        fields.insert("attachments".to_string(), descriptions.into());
+        let payload_json = serde_json::to_vec(&fields)?;
+        twilight_client
+            .create_message(channel_id)
+            .attachments(attachments)
+            .map_err(TwilightValidationError::from)?
+            .payload_json(&payload_json)
+            .try_into_request()?
+    };
+
+    let response = twilight_client.request::<Message>(request).await?;
     let message = response.model().await?;
     Ok(message)
 }
 
+async fn create_message(
+    twilight_client: &twilight_http::Client,
+    attachments: &[Attachment],
+    create_message: &CreateMessage,
+) -> Result<Message, TwilightServiceError> {
+    let request = create_message_fields(
+        twilight_client.create_message(create_message.channel_id),
+        &create_message.message,
+    )?;
+
+    send_create_message(
+        twilight_client,
+        create_message.channel_id,
+        request,
+        attachments,
+        create_message.idempotency_key,
+    )
+    .await
+}
+
 async fn create_reply(
     twilight_client: &twilight_http::Client,
+    attachments: &[Attachment],
     create_reply: &CreateReply,
 ) -> Result<Message, TwilightServiceError> {
     let request = twilight_client
         .create_message(create_reply.message_location.channel_id)
         .reply(create_reply.message_location.message_id)
         .fail_if_not_exists(false);
-    let response = match &create_reply.message {
+    let request = create_message_fields(request, &create_reply.message)?;
+
+    send_create_message(
+        twilight_client,
+        create_reply.message_location.channel_id,
+        request,
+        attachments,
+        create_reply.idempotency_key,
+    )
+    .await
+}
+
+async fn create_followup(
+    twilight_client: &twilight_http::Client,
+    attachments: &[Attachment],
+    application_id: Id<ApplicationMarker>,
+    create_followup: &CreateFollowup,
+) -> Result<Message, TwilightServiceError> {
+    let interaction_client = twilight_client.interaction(application_id);
+
+    let mut request = interaction_client.create_followup(&create_followup.interaction_token);
+    let mut flags = MessageFlags::empty();
+
+    match &create_followup.message {
         MessagePayload::Text(text) => {
-            request
+            request = request
                 .content(text)
-                .map_err(TwilightValidationError::from)?
-                .await?
+                .map_err(TwilightValidationError::from)?;
         }
         MessagePayload::Embed(embed) => {
-            request
+            request = request
                 .embeds(std::slice::from_ref(embed))
-                .map_err(TwilightValidationError::from)?
-                .await?
+                .map_err(TwilightValidationError::from)?;
         }
         MessagePayload::TextAndEmbed { text, embed } => {
-            request
+            request = request
                 .content(text)
                 .map_err(TwilightValidationError::from)?
                 .embeds(std::slice::from_ref(embed))
+                .map_err(TwilightValidationError::from)?;
+        }
+        MessagePayload::Rich(rich) => {
+            request = request
+                .embeds(&rich.embeds)
                 .map_err(TwilightValidationError::from)?
-                .await?
+                .components(&rich.components)
+                .map_err(TwilightValidationError::from)?
+                .attachments(attachments)
+                .map_err(TwilightValidationError::from)?
+                .allowed_mentions(rich.allowed_mentions.as_ref());
+            if let Some(content) = &rich.content {
+                request = request
+                    .content(content)
+                    .map_err(TwilightValidationError::from)?;
+            }
+            if let Some(rich_flags) = rich.flags {
+                flags |= rich_flags;
+            }
         }
     };
 
-    let message = response.model().await?;
-    Ok(message)
+    if create_followup.ephemeral {
+        flags |= MessageFlags::EPHEMERAL;
+    }
+    if !flags.is_empty() {
+        request = request.flags(flags);
+    }
+
+    Ok(request.await?.model().await?)
 }
 
 async fn delete_message(
@@ -128,8 +487,21 @@ async fn delete_message(
     Ok(())
 }
 
+async fn delete_interaction_response(
+    twilight_client: &twilight_http::Client,
+    application_id: Id<ApplicationMarker>,
+    delete_interaction_response: &DeleteInteractionResponse,
+) -> Result<(), TwilightServiceError> {
+    twilight_client
+        .interaction(application_id)
+        .delete_response(&delete_interaction_response.interaction_token)
+        .await?;
+    Ok(())
+}
+
 async fn update_interaction_response(
     twilight_client: &twilight_http::Client,
+    attachments: &[Attachment],
     application_id: Id<ApplicationMarker>,
     update_interaction_response: &UpdateInteractionResponse,
 ) -> Result<(), TwilightServiceError> {
@@ -159,12 +531,29 @@ async fn update_interaction_response(
                 .map_err(TwilightValidationError::from)?
                 .await?
         }
+        MessagePayload::Rich(rich) => {
+            // Interaction responses cannot have their flags updated
+            let mut request = request
+                .content(rich.content.as_deref())
+                .map_err(TwilightValidationError::from)?
+                .embeds(Some(&rich.embeds))
+                .map_err(TwilightValidationError::from)?
+                .components(Some(&rich.components))
+                .map_err(TwilightValidationError::from)?
+                .attachments(attachments)
+                .map_err(TwilightValidationError::from)?;
+            if let Some(allowed_mentions) = &rich.allowed_mentions {
+                request = request.allowed_mentions(Some(allowed_mentions));
+            }
+            request.await?
+        }
     };
     Ok(())
 }
 
 async fn update_message(
     twilight_client: &twilight_http::Client,
+    attachments: &[Attachment],
     update_message: &UpdateMessage,
 ) -> Result<(), TwilightServiceError> {
     let request = twilight_client.update_message(
@@ -193,104 +582,337 @@ async fn update_message(
                 .map_err(TwilightValidationError::from)?
                 .await?
         }
+        MessagePayload::Rich(rich) => {
+            // Replaces the whole message, including any existing attachments and
+            // components
+            let mut request = request
+                .content(rich.content.as_deref())
+                .map_err(TwilightValidationError::from)?
+                .embeds(Some(&rich.embeds))
+                .map_err(TwilightValidationError::from)?
+                .components(Some(&rich.components))
+                .map_err(TwilightValidationError::from)?
+                .attachments(attachments)
+                .map_err(TwilightValidationError::from)?;
+            if let Some(allowed_mentions) = &rich.allowed_mentions {
+                request = request.allowed_mentions(Some(allowed_mentions));
+            }
+            if let Some(flags) = rich.flags {
+                request = request.flags(flags);
+            }
+            request.await?
+        }
     };
     Ok(())
 }
 
+/// How [twilight_service] retries actions which fail in a way that may be
+/// temporary.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RetryConfig {
+    /// The most times an action is attempted, including the first attempt
+    pub max_attempts: NonZeroU32,
+    /// The longest to wait before the first retry. Each retry waits up to
+    /// twice as long as the one before, picked at random so that retries
+    /// from many actions are spread out.
+    pub initial_backoff: Duration,
+    /// The longest to wait before any retry, unless Discord asks for longer
+    pub max_backoff: Duration,
+    /// Whether actions Discord rate limits with a 429 are retried once it
+    /// says they may be. Set this to false when the service runs behind a
+    /// [super::discord_scheduler::discord_scheduler], which queues rate
+    /// limited actions again itself.
+    pub retry_rate_limited: bool,
+}
 
-#[derive(Debug, Clone, Copy)]
-struct RetryOnServerError;
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_attempts: NonZeroU32::new(4).unwrap(),
+            initial_backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(10),
+            retry_rate_limited: true,
+        }
+    }
+}
 
-impl<Res> Policy<Arc<DiscordClientAction>, Res, TwilightServiceError> for RetryOnServerError {
-    type Future = Ready<Self>;
+/// The [TwilightServiceError] from the last attempt at a
+/// [DiscordClientAction], once it will not be retried again.
+#[derive(Debug, Error)]
+#[error("{error} (after {attempts} attempts)")]
+pub struct FinalTwilightServiceError {
+    /// The error from the last attempt
+    pub error: TwilightServiceError,
+    /// How many times the action was attempted
+    pub attempts: u32,
+}
 
-    fn retry(&self, _req: &Arc<DiscordClientAction>, result: Result<&Res, &TwilightServiceError>) -> Option<Self::Future> {
-        let Err(e) = result else {
-            return Some(ready(RetryOnServerError));
-        };
+/// An action, its attachments, and how many times it has been attempted so
+/// far.
+#[derive(Debug, Clone)]
+struct Attempt {
+    action: Arc<DiscordClientAction>,
+    attachments: Arc<[Attachment]>,
+    attempts: Arc<AtomicU32>,
+}
+
+/// Whether sending this action twice has the same effect as sending it once.
+fn is_idempotent(action: &DiscordClientAction) -> bool {
+    match action {
+        // Without a nonce, each attempt that reaches Discord creates another
+        // message
+        DiscordClientAction::CreateMessage(CreateMessage {
+            idempotency_key, ..
+        })
+        | DiscordClientAction::CreateReply(CreateReply {
+            idempotency_key, ..
+        }) => idempotency_key.is_some(),
+        // Follow-ups cannot have a nonce
+        DiscordClientAction::CreateFollowup(_) => false,
+        DiscordClientAction::DeleteMessage(_)
+        | DiscordClientAction::DeleteInteractionResponse(_)
+        | DiscordClientAction::UpdateInteractionResponse(_)
+        | DiscordClientAction::UpdateMessage(_) => true,
+    }
+}
+
+/// How long a 503 response asked us to wait, if it said.
+fn retry_after_header(response: &hyper::Response<hyper::Body>) -> Option<Duration> {
+    let seconds = response
+        .headers()
+        .get(http::header::RETRY_AFTER)?
+        .to_str()
+        .ok()?
+        .parse()
+        .ok()?;
+    Duration::try_from_secs_f64(seconds).ok()
+}
+
+#[derive(Debug, Clone, Copy)]
+struct RetryOnServerError(RetryConfig);
 
-        match e {
+impl RetryOnServerError {
+    /// A random wait of up to `initial_backoff * 2^(attempts - 1)`.
+    fn backoff(&self, attempts: u32) -> Duration {
+        let ceiling = self
+            .0
+            .initial_backoff
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)))
+            .min(self.0.max_backoff);
+        ceiling.mul_f64(rand::thread_rng().gen_range(0.0..=1.0))
+    }
+
+    /// How long to wait before attempting the action again, or None if it
+    /// should not be retried after this error.
+    fn delay(
+        &self,
+        action: &DiscordClientAction,
+        attempts: u32,
+        error: &TwilightServiceError,
+    ) -> Option<Duration> {
+        let e = match error {
             TwilightServiceError::TwilightValidationError(_) => {
                 // Client error, cannot retry
-                None
+                return None;
             }
-            TwilightServiceError::TwilightClientError(e) => {
-                match e.kind() {
-                    twilight_http::error::ErrorType::Response { body: _, error: _, status } => {
-                        if status.is_server_error() {
-                            // Something went wrong on Discord's side, retry
-                            Some(ready(RetryOnServerError))
-                        } else {
-                            // Not a 5xx error, don't retry
-                            None
-                        }
-                    }
-                    twilight_http::error::ErrorType::ServiceUnavailable { response: _ } => {
-                        // 503 error, retry
-                        Some(ready(RetryOnServerError))
-                    }
-                    twilight_http::error::ErrorType::RequestTimedOut => {
-                        // Network goblins, retry
-                        Some(ready(RetryOnServerError))
-                    }
-                    _ =>  {
-                        // Unknown error, not safe to retry
-                        None
-                    }
-                }
+            TwilightServiceError::AttachmentDownloadError(_) => {
+                // Attachments are downloaded once, before the first attempt
+                return None;
             }
+            TwilightServiceError::TwilightClientError(e) => e,
             TwilightServiceError::DeserializationBodyError(_) => {
                 // Discord responded with a 2xx status code
                 // but we couldn't deserialize the body
                 // NOT safe to retry, request may not have been idempotent
+                return None;
+            }
+            TwilightServiceError::RequestBodyError(_) => {
+                // Building the request again gives the same body
+                return None;
+            }
+            TwilightServiceError::SchedulerStopped => {
+                // Only returned by the scheduler, in front of this service
+                return None;
+            }
+        };
+
+        match e.kind() {
+            ErrorType::Response {
+                error: ApiError::Ratelimited(ratelimited),
+                ..
+            } => {
+                // Discord refused without acting, and said when to retry,
+                // unless the scheduler in front of us will do it
+                if !self.0.retry_rate_limited {
+                    return None;
+                }
+                Duration::try_from_secs_f64(ratelimited.retry_after).ok()
+            }
+            ErrorType::ServiceUnavailable { response } => {
+                // 503 error, Discord did not act, retry
+                Some(retry_after_header(response).unwrap_or_else(|| self.backoff(attempts)))
+            }
+            ErrorType::Response { status, .. } if status.is_server_error() => {
+                // Something went wrong on Discord's side, possibly after
+                // acting on the request, so only retry if that is harmless
+                is_idempotent(action).then(|| self.backoff(attempts))
+            }
+            ErrorType::RequestTimedOut => {
+                // Network goblins, Discord may or may not have acted on the
+                // request, so only retry if that is harmless
+                is_idempotent(action).then(|| self.backoff(attempts))
+            }
+            _ => {
+                // Unknown error, not safe to retry
                 None
             }
         }
     }
+}
+
+impl<Res> Policy<Attempt, Res, TwilightServiceError> for RetryOnServerError {
+    type Future = BoxFuture<'static, Self>;
+
+    fn retry(
+        &self,
+        req: &Attempt,
+        result: Result<&Res, &TwilightServiceError>,
+    ) -> Option<Self::Future> {
+        let Err(e) = result else {
+            return None;
+        };
 
-    fn clone_request(&self, req: &Arc<DiscordClientAction>) -> Option<Arc<DiscordClientAction>> {
+        let attempts = req.attempts.load(Ordering::Relaxed);
+        if attempts >= self.0.max_attempts.get() {
+            return None;
+        }
+
+        let delay = self.delay(&req.action, attempts, e)?;
+        tracing::warn!(
+            "Discord client action failed on attempt {attempts}, retrying in {delay:?}: {e}"
+        );
+        let policy = *self;
+        Some(Box::pin(async move {
+            tokio::time::sleep(delay).await;
+            policy
+        }))
+    }
+
+    fn clone_request(&self, req: &Attempt) -> Option<Attempt> {
         Some(req.clone())
     }
 }
 
 /// Returns a [tower::Service] which processes [DiscordClientAction]s and
 /// sometimes returns a [DiscordClientActionResponse] for additional
-/// processing. If it errors, returns the offending action as well as the error.
+/// processing. Actions which fail in a way that may be temporary are retried
+/// according to the [RetryConfig], except that messages without an
+/// idempotency key are never created again if Discord may already have
+/// created them. If it errors, returns the
+/// error from the last attempt and how many attempts were made.
+///
+/// Attachments given by URL are downloaded once, before the first attempt,
+/// and are not retried. Only public addresses are fetched, with a timeout,
+/// and attachments larger than Discord accepts are refused.
 pub fn twilight_service(
     twilight_client: twilight_http::Client,
     application_id: Id<ApplicationMarker>,
+    retry_config: RetryConfig,
 ) -> impl Service<
     DiscordClientAction,
     Response = Option<DiscordClientActionResponse>,
-    Error = TwilightServiceError,
+    Error = FinalTwilightServiceError,
+    Future = impl Future<
+        Output = Result<Option<DiscordClientActionResponse>, FinalTwilightServiceError>,
+    > + Send,
 > + Clone {
     let twilight_client = Arc::new(twilight_client);
+    // Downloads attachments given by URL
+    let http_client = attachment_client();
 
-    ServiceBuilder::new()
-    .map_request(|action| Arc::new(action))
-    .layer(RetryLayer::new(RetryOnServerError))
-    .service_fn(move |request: Arc<DiscordClientAction>| {
-        let twilight_client = twilight_client.clone();
-        async move {
-            match request.as_ref() {
-                DiscordClientAction::CreateMessage(req) => create_message(&twilight_client, &req)
-                    .await
-                    .map(|message| Some(DiscordClientActionResponse::MessageCreated(message))),
-                DiscordClientAction::CreateReply(req) => create_reply(&twilight_client, &req)
-                    .await
-                    .map(|message| Some(DiscordClientActionResponse::MessageCreated(message))),
-                DiscordClientAction::DeleteMessage(req) => delete_message(&twilight_client, &req)
-                    .await
-                    .map(|_| Option::None),
-                DiscordClientAction::UpdateInteractionResponse(req) => {
-                    update_interaction_response(&twilight_client, application_id, &req)
+    let retrying_service = ServiceBuilder::new()
+        .layer(RetryLayer::new(RetryOnServerError(retry_config)))
+        .service_fn(move |request: Attempt| {
+            let twilight_client = twilight_client.clone();
+            async move {
+                request.attempts.fetch_add(1, Ordering::Relaxed);
+                match request.action.as_ref() {
+                    DiscordClientAction::CreateMessage(req) => {
+                        create_message(&twilight_client, &request.attachments, req)
+                            .await
+                            .map(|message| {
+                                Some(DiscordClientActionResponse::MessageCreated(message))
+                            })
+                    }
+                    DiscordClientAction::CreateReply(req) => {
+                        create_reply(&twilight_client, &request.attachments, req)
+                            .await
+                            .map(|message| {
+                                Some(DiscordClientActionResponse::MessageCreated(message))
+                            })
+                    }
+                    DiscordClientAction::CreateFollowup(req) => {
+                        create_followup(&twilight_client, &request.attachments, application_id, req)
+                            .await
+                            .map(|message| {
+                                Some(DiscordClientActionResponse::MessageCreated(message))
+                            })
+                    }
+                    DiscordClientAction::DeleteMessage(req) => {
+                        delete_message(&twilight_client, req)
+                            .await
+                            .map(|_| Option::None)
+                    }
+                    DiscordClientAction::DeleteInteractionResponse(req) => {
+                        delete_interaction_response(&twilight_client, application_id, req)
+                            .await
+                            .map(|_| Option::None)
+                    }
+                    DiscordClientAction::UpdateInteractionResponse(req) => {
+                        update_interaction_response(
+                            &twilight_client,
+                            &request.attachments,
+                            application_id,
+                            req,
+                        )
                         .await
                         .map(|_| Option::None)
+                    }
+                    DiscordClientAction::UpdateMessage(req) => {
+                        update_message(&twilight_client, &request.attachments, req)
+                            .await
+                            .map(|_| Option::None)
+                    }
                 }
-                DiscordClientAction::UpdateMessage(req) => update_message(&twilight_client, &req)
-                    .await
-                    .map(|_| Option::None),
             }
+        });
+
+    service_fn(move |action: DiscordClientAction| {
+        let http_client = http_client.clone();
+        let retrying_service = retrying_service.clone();
+        async move {
+            let attachments = match message_payload(&action) {
+                Some(payload) => load_attachments(&http_client, payload).await,
+                None => Ok(Vec::new()),
+            }
+            .map_err(|error| FinalTwilightServiceError {
+                error: error.into(),
+                attempts: 0,
+            })?;
+
+            let attempts = Arc::new(AtomicU32::new(0));
+            let request = Attempt {
+                action: Arc::new(action),
+                attachments: attachments.into(),
+                attempts: attempts.clone(),
+            };
+            retrying_service
+                .oneshot(request)
+                .map_err(move |error| FinalTwilightServiceError {
+                    error,
+                    attempts: attempts.load(Ordering::Relaxed),
+                })
+                .await
         }
     })
 }
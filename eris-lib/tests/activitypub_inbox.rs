@@ -0,0 +1,324 @@
+//! Checks that inboxes only receive activities signed by their actor, and
+//! that an activity delivered twice is only handled once, unless handling
+//! it failed.
+
+use std::{
+    collections::HashSet,
+    future::Future,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use activitypub_federation::{
+    activity_queue::send_activity,
+    config::FederationConfig,
+    http_signatures::{generate_actor_keypair, Keypair},
+    traits::ActivityHandler,
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::Utc;
+use eris_lib::{
+    model::{
+        activity::InboxActivity,
+        federation::{ErisData, ModelError, SharedService, StoredObject},
+        follow::Follow,
+        foreign_actor::{ActorType, ForeignActor},
+        urls::UrlLayout,
+        user::User,
+    },
+    services::{
+        activity_router::{activity_router, ActivityHandlers},
+        activitypub_inbox::activitypub_inbox_service,
+    },
+};
+use http::{Method, Request, StatusCode};
+use hyper::Body;
+use sha2::{Digest, Sha256};
+use tower::{make::Shared, service_fn, Service, ServiceExt};
+use twilight_model::id::Id;
+use url::Url;
+
+fn user(urls: &UrlLayout, keypair: &Keypair) -> User {
+    User {
+        id: urls.user(1),
+        discord_user_id: Id::new(1),
+        handle: "heron".to_string(),
+        display_name: None,
+        summary: None,
+        manually_approves_followers: false,
+        inbox: urls.user_collection(1, "inbox"),
+        outbox: urls.user_collection(1, "outbox"),
+        followers: urls.user_collection(1, "followers"),
+        following: urls.user_collection(1, "following"),
+        liked: urls.user_collection(1, "liked"),
+        shared_inbox: urls.shared_inbox(),
+        public_key_pem: keypair.public_key.clone(),
+        private_key_pem: Some(keypair.private_key.clone()),
+    }
+}
+
+/// A foreign user, who sends the activities, and the local user they are
+/// sent to.
+struct Actors {
+    sender: User,
+    recipient: User,
+}
+
+impl Actors {
+    fn new() -> Self {
+        let sender_urls = UrlLayout::new("mastodon.example").unwrap();
+        let urls = UrlLayout::new("eris.example").unwrap();
+        Self {
+            sender: user(&sender_urls, &generate_actor_keypair().unwrap()),
+            recipient: user(&urls, &generate_actor_keypair().unwrap()),
+        }
+    }
+
+    /// The sender, as stored by the recipient's instance.
+    fn stored_sender(&self) -> ForeignActor {
+        ForeignActor {
+            id: self.sender.id.clone(),
+            kind: ActorType::Person,
+            preferred_username: Some(self.sender.handle.clone()),
+            name: None,
+            url: None,
+            inbox: self.sender.inbox.clone(),
+            shared_inbox: None,
+            public_key_pem: self.sender.public_key_pem.clone(),
+            last_refreshed_at: Utc::now().naive_utc(),
+        }
+    }
+}
+
+/// Data which stores nothing and ignores every activity.
+fn empty_data() -> ErisData {
+    ErisData {
+        object_store: SharedService::new(service_fn(|_| async { Ok(None) })),
+        activity_service: SharedService::new(service_fn(|_| async { Ok(()) })),
+    }
+}
+
+/// Routes activities to the handlers, recording the ids of those handled.
+fn recording_router(
+    handlers: ActivityHandlers,
+) -> impl Service<
+    InboxActivity,
+    Response = (),
+    Error = ModelError,
+    Future = impl Future<Output = Result<(), ModelError>> + Send,
+> + Clone {
+    let recorded = Arc::new(Mutex::new(HashSet::new()));
+    let seen = recorded.clone();
+    let deduplicate = service_fn(move |id: Url| {
+        let first = !seen.lock().unwrap().contains(&id);
+        async move { Ok::<_, ModelError>(first) }
+    });
+    let record = service_fn(move |activity: InboxActivity| {
+        let first = recorded.lock().unwrap().insert(activity.id().clone());
+        async move { Ok::<_, ModelError>(first) }
+    });
+    activity_router(deduplicate, record, handlers)
+}
+
+/// The recipient's instance, which stores both actors, and records the
+/// follows it handles.
+async fn receiving_config(
+    actors: &Actors,
+    follows: Arc<Mutex<Vec<Follow>>>,
+) -> FederationConfig<ErisData> {
+    let recipient = actors.recipient.clone();
+    let sender = actors.stored_sender();
+    let object_store = service_fn(move |id: Url| {
+        let object = if id == recipient.id {
+            Some(StoredObject::User(recipient.clone()))
+        } else if id == sender.id {
+            Some(StoredObject::ForeignActor(sender.clone()))
+        } else {
+            None
+        };
+        async move { Ok(object) }
+    });
+
+    let handlers = ActivityHandlers {
+        follow: SharedService::new(service_fn(move |follow: Follow| {
+            follows.lock().unwrap().push(follow);
+            async { Ok(()) }
+        })),
+        ..Default::default()
+    };
+
+    FederationConfig::builder()
+        .domain("eris.example")
+        .app_data(ErisData {
+            object_store: SharedService::new(object_store),
+            activity_service: SharedService::new(recording_router(handlers)),
+        })
+        .build()
+        .await
+        .unwrap()
+}
+
+#[tokio::test]
+async fn signed_activities_are_handled_once() {
+    let actors = Actors::new();
+    let follows = Arc::new(Mutex::new(Vec::new()));
+    let config = receiving_config(&actors, follows.clone()).await;
+    let inbox = activitypub_inbox_service::<Body>(config, UrlLayout::new("eris.example").unwrap());
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(Shared::new(inbox));
+    let address: SocketAddr = server.local_addr();
+    tokio::spawn(server);
+
+    // In debug mode, activities are signed and sent before send_activity
+    // returns, and may be sent to localhost
+    let sending_config = FederationConfig::builder()
+        .domain("mastodon.example")
+        .app_data(empty_data())
+        .debug(true)
+        .build()
+        .await
+        .unwrap();
+    let follow = Follow::new(
+        Url::parse("https://mastodon.example/activities/1").unwrap(),
+        actors.sender.id.clone(),
+        actors.recipient.id.clone(),
+    );
+    let inbox_url = Url::parse(&format!(
+        "http://localhost:{}/users/1/inbox",
+        address.port()
+    ))
+    .unwrap();
+    for _ in 0..2 {
+        send_activity(
+            follow.clone(),
+            &actors.sender,
+            vec![inbox_url.clone()],
+            &sending_config.to_request_data(),
+        )
+        .await
+        .unwrap();
+    }
+
+    assert_eq!(*follows.lock().unwrap(), vec![follow]);
+}
+
+#[tokio::test]
+async fn unverifiable_requests_are_rejected() {
+    let actors = Actors::new();
+    let follows = Arc::new(Mutex::new(Vec::new()));
+    let config = receiving_config(&actors, follows.clone()).await;
+    let inbox = activitypub_inbox_service::<Body>(config, UrlLayout::new("eris.example").unwrap());
+    let body = serde_json::to_string(&Follow::new(
+        Url::parse("https://mastodon.example/activities/1").unwrap(),
+        actors.sender.id.clone(),
+        actors.recipient.id.clone(),
+    ))
+    .unwrap();
+
+    let post = |path: &str, content_type: &str, signed: bool| {
+        let mut request = Request::builder()
+            .method(Method::POST)
+            .uri(path)
+            .header("Content-Type", content_type);
+        if signed {
+            request = request.header("Digest", "SHA-256=bm90IHRoZSBib2R5").header(
+                "Signature",
+                "keyId=\"https://mastodon.example/users/1#main-key\"",
+            );
+        }
+        request.body(Body::from(body.clone())).unwrap()
+    };
+    let status = |request| {
+        let inbox = inbox.clone();
+        async move { inbox.oneshot(request).await.unwrap().status() }
+    };
+
+    let json = "application/activity+json";
+    assert_eq!(
+        status(post("/users/1/inbox", json, false)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(post("/users/1/inbox", json, true)).await,
+        StatusCode::UNAUTHORIZED
+    );
+    assert_eq!(
+        status(post("/inbox", "text/plain", true)).await,
+        StatusCode::UNSUPPORTED_MEDIA_TYPE
+    );
+    assert_eq!(
+        status(post("/users/2/inbox", json, true)).await,
+        StatusCode::NOT_FOUND
+    );
+    assert_eq!(
+        status(post("/users/1/outbox", json, true)).await,
+        StatusCode::NOT_FOUND
+    );
+    let get = Request::builder()
+        .uri("/inbox")
+        .body(Body::empty())
+        .unwrap();
+    assert_eq!(status(get).await, StatusCode::METHOD_NOT_ALLOWED);
+    assert!(follows.lock().unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn activities_are_handled_again_after_failing() {
+    let attempts = Arc::new(Mutex::new(0));
+    let handled = attempts.clone();
+    let handlers = ActivityHandlers {
+        follow: SharedService::new(service_fn(move |_: Follow| {
+            let mut attempts = handled.lock().unwrap();
+            *attempts += 1;
+            let result = if *attempts == 1 {
+                Err(ModelError::InvalidObject("the handler failed"))
+            } else {
+                Ok(())
+            };
+            async move { result }
+        })),
+        ..Default::default()
+    };
+    let router = recording_router(handlers);
+    let follow = InboxActivity::Follow(Follow::new(
+        Url::parse("https://mastodon.example/activities/1").unwrap(),
+        Url::parse("https://mastodon.example/users/1").unwrap(),
+        Url::parse("https://eris.example/users/1").unwrap(),
+    ));
+
+    assert!(router.clone().oneshot(follow.clone()).await.is_err());
+    router.clone().oneshot(follow.clone()).await.unwrap();
+    router.oneshot(follow).await.unwrap();
+    assert_eq!(*attempts.lock().unwrap(), 2);
+}
+
+#[tokio::test]
+async fn activities_from_unreachable_actors_are_retried() {
+    let actors = Actors::new();
+    let follows = Arc::new(Mutex::new(Vec::new()));
+    let config = receiving_config(&actors, follows.clone()).await;
+    let inbox = activitypub_inbox_service::<Body>(config, UrlLayout::new("eris.example").unwrap());
+
+    // The actor is not stored, and its server cannot be reached
+    let body = serde_json::to_string(&Follow::new(
+        Url::parse("https://unreachable.invalid/activities/1").unwrap(),
+        Url::parse("https://unreachable.invalid/users/1").unwrap(),
+        actors.recipient.id.clone(),
+    ))
+    .unwrap();
+    let digest = STANDARD.encode(Sha256::digest(body.as_bytes()));
+    let request = Request::builder()
+        .method(Method::POST)
+        .uri("/users/1/inbox")
+        .header("Content-Type", "application/activity+json")
+        .header("Digest", format!("SHA-256={digest}"))
+        .header(
+            "Signature",
+            "keyId=\"https://unreachable.invalid/users/1#main-key\"",
+        )
+        .body(Body::from(body))
+        .unwrap();
+
+    let response = inbox.oneshot(request).await.unwrap();
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+    assert!(follows.lock().unwrap().is_empty());
+}
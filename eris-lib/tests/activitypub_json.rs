@@ -0,0 +1,228 @@
+//! Checks that the ActivityPub model reads the JSON-LD that Mastodon sends,
+//! and that what it keeps serializes back to the same values.
+
+use activitypub_federation::{
+    config::{Data, FederationConfig},
+    traits::{ActivityHandler, Object},
+};
+use eris_lib::model::{
+    activity::InboxActivity,
+    federation::{ErisData, SharedService},
+    foreign_actor::{ActorJson, ActorType, ForeignActor},
+    post::{AttachmentType, NoteJson, Post},
+    urls::{LocalInbox, UrlLayout},
+};
+use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
+use tower::service_fn;
+use twilight_model::id::Id;
+
+fn fixture(name: &str) -> Value {
+    let path = format!(
+        "{}/tests/fixtures/mastodon/{name}.json",
+        env!("CARGO_MANIFEST_DIR")
+    );
+    let json = std::fs::read_to_string(&path).unwrap_or_else(|e| panic!("{path}: {e}"));
+    serde_json::from_str(&json).unwrap()
+}
+
+/// Checks that every value kept in `kept` is the same in `original`, which
+/// may have more fields.
+fn assert_kept_from(kept: &Value, original: &Value, path: &str) {
+    match (kept, original) {
+        (Value::Object(kept), Value::Object(original)) => {
+            for (key, value) in kept {
+                let original_value = original
+                    .get(key)
+                    .unwrap_or_else(|| panic!("{path}.{key} was not in the original"));
+                assert_kept_from(value, original_value, &format!("{path}.{key}"));
+            }
+        }
+        (Value::Array(kept), Value::Array(original)) => {
+            assert_eq!(kept.len(), original.len(), "{path} changed length");
+            for (i, (kept, original)) in kept.iter().zip(original).enumerate() {
+                assert_kept_from(kept, original, &format!("{path}[{i}]"));
+            }
+        }
+        _ => assert_eq!(kept, original, "{path} changed"),
+    }
+}
+
+/// Parses a fixture, and checks that it serializes to values from the
+/// fixture which parse and serialize again to the same JSON.
+fn round_trip<T: Serialize + DeserializeOwned>(name: &str) -> T {
+    let original = fixture(name);
+    let parsed: T = serde_json::from_value(original.clone()).unwrap();
+    let serialized = serde_json::to_value(&parsed).unwrap();
+    assert_kept_from(&serialized, &original, name);
+
+    let reparsed: T = serde_json::from_value(serialized.clone()).unwrap();
+    assert_eq!(serde_json::to_value(&reparsed).unwrap(), serialized);
+    reparsed
+}
+
+async fn data() -> Data<ErisData> {
+    let app_data = ErisData {
+        object_store: SharedService::new(service_fn(|_| async { Ok(None) })),
+        activity_service: SharedService::new(service_fn(|_| async { Ok(()) })),
+    };
+    FederationConfig::builder()
+        .domain("eris.example")
+        .app_data(app_data)
+        .build()
+        .await
+        .unwrap()
+        .to_request_data()
+}
+
+#[test]
+fn activities_round_trip_to_their_variants() {
+    let cases = [
+        ("create_note", "Create"),
+        ("update_note", "Update"),
+        ("delete_note", "Delete"),
+        ("delete_actor", "Delete"),
+        ("follow", "Follow"),
+        ("accept_follow", "Accept"),
+        ("reject_follow", "Reject"),
+        ("undo_like", "Undo"),
+        ("undo_follow", "Undo"),
+        ("like", "Like"),
+        ("announce", "Announce"),
+        ("block", "Block"),
+    ];
+
+    for (name, kind) in cases {
+        let activity: InboxActivity = round_trip(name);
+        let variant = match activity {
+            InboxActivity::Create(_) => "Create",
+            InboxActivity::Update(_) => "Update",
+            InboxActivity::Delete(_) => "Delete",
+            InboxActivity::Follow(_) => "Follow",
+            InboxActivity::Accept(_) => "Accept",
+            InboxActivity::Reject(_) => "Reject",
+            InboxActivity::Undo(_) => "Undo",
+            InboxActivity::Like(_) => "Like",
+            InboxActivity::Announce(_) => "Announce",
+            InboxActivity::Block(_) => "Block",
+        };
+        assert_eq!(variant, kind, "{name}");
+    }
+}
+
+#[tokio::test]
+async fn mastodon_person_becomes_foreign_actor() {
+    let data = data().await;
+    let json: ActorJson = round_trip("person");
+    let expected_domain = json.id.inner().clone();
+    ForeignActor::verify(&json, &expected_domain, &data)
+        .await
+        .unwrap();
+
+    let actor = ForeignActor::from_json(json, &data).await.unwrap();
+    assert_eq!(actor.kind, ActorType::Person);
+    assert_eq!(actor.preferred_username.as_deref(), Some("alice"));
+    assert_eq!(
+        actor.shared_inbox.as_ref().map(|url| url.as_str()),
+        Some("https://mastodon.example/inbox")
+    );
+
+    let serialized = serde_json::to_value(actor.into_json(&data).await.unwrap()).unwrap();
+    assert_kept_from(&serialized, &fixture("person"), "person");
+}
+
+#[tokio::test]
+async fn mastodon_note_becomes_post_with_attachments() {
+    let data = data().await;
+    let json: NoteJson = round_trip("note");
+    let post = Post::from_json(json, &data).await.unwrap();
+
+    let image = post.image.as_ref().unwrap();
+    assert_eq!(
+        image.url.as_str(),
+        "https://files.mastodon.example/media_attachments/files/heron.jpg"
+    );
+    assert_eq!(
+        image.name.as_deref(),
+        Some("A grey heron standing in shallow water")
+    );
+    let video = post.video.as_ref().unwrap();
+    assert_eq!(video.media_type.as_deref(), Some("video/mp4"));
+
+    let note = post.clone().into_json(&data).await.unwrap();
+    let kinds: Vec<_> = note.attachment.iter().map(|a| a.kind).collect();
+    assert_eq!(kinds, [AttachmentType::Image, AttachmentType::Video]);
+
+    let reparsed = Post::from_json(note, &data).await.unwrap();
+    assert_eq!(reparsed, post);
+}
+
+#[tokio::test]
+async fn activities_verify_their_actor() {
+    let data = data().await;
+    for name in ["create_note", "accept_follow", "undo_like", "delete_note"] {
+        let activity: InboxActivity = serde_json::from_value(fixture(name)).unwrap();
+        activity.verify(&data).await.unwrap();
+    }
+
+    // A post published by someone other than its author
+    let mut forged = fixture("create_note");
+    forged["actor"] = "https://mastodon.example/users/mallory".into();
+    let activity: InboxActivity = serde_json::from_value(forged).unwrap();
+    assert!(activity.verify(&data).await.is_err());
+
+    // A post on another server deleted by this actor
+    let mut forged = fixture("delete_note");
+    forged["object"]["id"] = "https://eris.example/users/1/posts/3".into();
+    let activity: InboxActivity = serde_json::from_value(forged).unwrap();
+    assert!(activity.verify(&data).await.is_err());
+
+    // Another actor on the same server deleted by this actor
+    let mut forged = fixture("delete_note");
+    forged["object"]["id"] = "https://mastodon.example/users/bob".into();
+    forged["object"]["formerType"] = "Person".into();
+    let activity: InboxActivity = serde_json::from_value(forged).unwrap();
+    assert!(activity.verify(&data).await.is_err());
+}
+
+#[test]
+fn url_layout_matches_the_book() {
+    let urls = UrlLayout::new("eris.example").unwrap();
+    assert_eq!(urls.instance().as_str(), "https://eris.example/");
+    assert_eq!(urls.shared_inbox().as_str(), "https://eris.example/inbox");
+    assert_eq!(
+        urls.user_collection(1, "followers").as_str(),
+        "https://eris.example/users/1/followers"
+    );
+    assert_eq!(
+        urls.channel(Id::new(100), Id::new(200)).as_str(),
+        "https://eris.example/channels/100/200"
+    );
+    assert_eq!(
+        urls.image(1, 3, 5).as_str(),
+        "https://eris.example/users/1/posts/3/attachments/images/5"
+    );
+    assert_eq!(
+        urls.video(1, 3, 6).as_str(),
+        "https://eris.example/users/1/posts/3/attachments/videos/6"
+    );
+}
+
+#[test]
+fn inboxes_are_parsed_from_their_urls() {
+    let urls = UrlLayout::new("eris.example").unwrap();
+    assert_eq!(
+        urls.parse_inbox(&urls.shared_inbox()),
+        Some(LocalInbox::Shared)
+    );
+    assert_eq!(
+        urls.parse_inbox(&urls.user_collection(1, "inbox")),
+        Some(LocalInbox::User(1))
+    );
+    assert_eq!(
+        urls.parse_inbox(&urls.channel_collection(Id::new(100), Id::new(200), "inbox")),
+        Some(LocalInbox::Channel(Id::new(100), Id::new(200)))
+    );
+    assert_eq!(urls.parse_inbox(&urls.user_collection(1, "outbox")), None);
+    assert_eq!(urls.parse_inbox(&urls.user(1)), None);
+}
@@ -0,0 +1,114 @@
+//! Checks the status and JSON-LD with which objects of the instance are
+//! served, including the tombstones of deleted ones.
+
+use activitypub_federation::config::FederationConfig;
+use chrono::{TimeZone, Utc};
+use eris_lib::{
+    model::{
+        federation::{ErisData, SharedService, StoredObject},
+        image::Image,
+        tombstone::Tombstone,
+        urls::UrlLayout,
+    },
+    services::activitypub_object::activitypub_object_service,
+};
+use http::{header, Method, Request, StatusCode};
+use serde_json::Value;
+use tower::{service_fn, ServiceExt};
+use url::Url;
+
+async fn get(method: Method, path: &str) -> (StatusCode, Option<String>, Value) {
+    let urls = UrlLayout::new("eris.example").unwrap();
+    let store_urls = urls.clone();
+    let object_store = service_fn(move |id: Url| {
+        let deleted_post = store_urls.post(1, 1);
+        let image = store_urls.image(1, 2, 3);
+        async move {
+            if id == deleted_post {
+                let deleted = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+                Ok(Some(StoredObject::Tombstone(Tombstone::new(
+                    id, "Note", deleted,
+                ))))
+            } else if id == image {
+                Ok(Some(StoredObject::Image(Image {
+                    kind: Default::default(),
+                    id,
+                    url: Url::parse("https://cdn.example/heron.png").unwrap(),
+                    media_type: Some("image/png".to_string()),
+                    name: None,
+                })))
+            } else if id.host_str() == Some("mastodon.example") {
+                // An object stored from another server
+                let deleted = Utc.with_ymd_and_hms(2023, 8, 1, 12, 0, 0).unwrap();
+                Ok(Some(StoredObject::Tombstone(Tombstone::new(
+                    id, "Note", deleted,
+                ))))
+            } else {
+                Ok(None)
+            }
+        }
+    });
+    let app_data = ErisData {
+        object_store: SharedService::new(object_store),
+        activity_service: SharedService::new(service_fn(|_| async { Ok(()) })),
+    };
+    let config = FederationConfig::builder()
+        .domain("eris.example")
+        .app_data(app_data)
+        .build()
+        .await
+        .unwrap();
+
+    let request = Request::builder()
+        .method(method)
+        .uri(path)
+        .body(())
+        .unwrap();
+    let response = activitypub_object_service(config, urls)
+        .oneshot(request)
+        .await
+        .unwrap();
+    let status = response.status();
+    let content_type = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .map(|value| value.to_str().unwrap().to_string());
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let json = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    (status, content_type, json)
+}
+
+#[tokio::test]
+async fn deleted_objects_are_gone_with_their_tombstone() {
+    let (status, content_type, json) = get(Method::GET, "/users/1/posts/1").await;
+    assert_eq!(status, StatusCode::GONE);
+    assert_eq!(content_type.as_deref(), Some("application/activity+json"));
+    assert_eq!(json["type"], "Tombstone");
+    assert_eq!(json["id"], "https://eris.example/users/1/posts/1");
+    assert_eq!(json["formerType"], "Note");
+    assert_eq!(json["deleted"], "2023-08-01T12:00:00Z");
+    assert_eq!(json["@context"][0], "https://www.w3.org/ns/activitystreams");
+}
+
+#[tokio::test]
+async fn objects_are_served_and_unknown_ids_are_not_found() {
+    let (status, _, json) = get(Method::GET, "/users/1/posts/2/attachments/images/3").await;
+    assert_eq!(status, StatusCode::OK);
+    assert_eq!(json["type"], "Image");
+    assert_eq!(json["url"], "https://cdn.example/heron.png");
+
+    let (status, _, _) = get(Method::GET, "/users/1/posts/3").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = get(Method::POST, "/users/1/posts/1").await;
+    assert_eq!(status, StatusCode::METHOD_NOT_ALLOWED);
+}
+
+#[tokio::test]
+async fn objects_of_other_servers_are_not_served() {
+    let (status, _, _) = get(Method::GET, "//mastodon.example/users/alice/statuses/1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+
+    let (status, _, _) = get(Method::GET, "/users/alice/statuses/1").await;
+    assert_eq!(status, StatusCode::NOT_FOUND);
+}
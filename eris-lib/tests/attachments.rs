@@ -0,0 +1,86 @@
+//! Checks that attachments are only downloaded from public addresses, once,
+//! before anything is sent to Discord.
+
+mod common;
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+};
+
+use axum::{extract::State, http::StatusCode, routing::any, Router};
+use common::{client, retry_config, serve};
+use eris_lib::{
+    payloads::{
+        CreateMessage, DiscordClientAction, MessageAttachment, MessagePayload, RichMessage,
+    },
+    services::twilight_service::{twilight_service, AttachmentDownloadError, TwilightServiceError},
+};
+use tower::ServiceExt;
+use twilight_model::id::Id;
+
+/// A server which counts the requests it is sent, standing in for both
+/// Discord and the host of an attachment.
+async fn counting_server() -> (SocketAddr, Arc<AtomicU32>) {
+    let requests = Arc::new(AtomicU32::new(0));
+    let app = Router::new()
+        .fallback(any(|State(requests): State<Arc<AtomicU32>>| async move {
+            requests.fetch_add(1, Ordering::SeqCst);
+            StatusCode::SERVICE_UNAVAILABLE
+        }))
+        .with_state(requests.clone());
+    (serve(app), requests)
+}
+
+fn message_with_attachment(url: String) -> DiscordClientAction {
+    DiscordClientAction::CreateMessage(CreateMessage {
+        channel_id: Id::new(10),
+        idempotency_key: Some(1),
+        message: MessagePayload::Rich(RichMessage {
+            attachments: vec![MessageAttachment::Url {
+                url,
+                filename: "heron.png".to_string(),
+                description: None,
+            }],
+            ..Default::default()
+        }),
+    })
+}
+
+#[tokio::test]
+async fn attachments_on_private_addresses_are_not_downloaded() {
+    let (addr, requests) = counting_server().await;
+    let service = twilight_service(client(addr), Id::new(1), retry_config(true));
+
+    let by_address = format!("http://{addr}/heron.png");
+    let error = match service
+        .clone()
+        .oneshot(message_with_attachment(by_address))
+        .await
+    {
+        Ok(_) => panic!("loopback addresses are not fetched"),
+        Err(error) => error,
+    };
+    assert!(matches!(
+        error.error,
+        TwilightServiceError::AttachmentDownloadError(AttachmentDownloadError::ForbiddenAddress(_))
+    ));
+    assert_eq!(error.attempts, 0);
+
+    // Resolves to loopback, which the resolver leaves out
+    let by_name = format!("http://localhost:{}/heron.png", addr.port());
+    let error = match service.oneshot(message_with_attachment(by_name)).await {
+        Ok(_) => panic!("loopback addresses are not fetched"),
+        Err(error) => error,
+    };
+    assert!(matches!(
+        error.error,
+        TwilightServiceError::AttachmentDownloadError(AttachmentDownloadError::RequestError(_))
+    ));
+    assert_eq!(error.attempts, 0);
+
+    assert_eq!(requests.load(Ordering::SeqCst), 0);
+}
@@ -0,0 +1,37 @@
+//! Serves a fake Discord API on a local port, and builds the twilight client
+//! and retry configuration which talk to it.
+
+#![allow(dead_code)]
+
+use std::{net::SocketAddr, num::NonZeroU32, time::Duration};
+
+use axum::Router;
+use eris_lib::services::twilight_service::RetryConfig;
+
+/// Serves `app` on a free local port, returning its address.
+pub fn serve(app: Router) -> SocketAddr {
+    let server = axum::Server::bind(&"127.0.0.1:0".parse().unwrap()).serve(app.into_make_service());
+    let addr = server.local_addr();
+    tokio::spawn(server);
+    addr
+}
+
+/// A client which sends every request to the fake Discord at `addr`, with
+/// no rate limiter of its own.
+pub fn client(addr: SocketAddr) -> twilight_http::Client {
+    twilight_http::Client::builder()
+        .proxy(addr.to_string(), true)
+        .ratelimiter(None)
+        .token("Bot fake".to_string())
+        .build()
+}
+
+/// Three attempts, 10 milliseconds apart.
+pub fn retry_config(retry_rate_limited: bool) -> RetryConfig {
+    RetryConfig {
+        max_attempts: NonZeroU32::new(3).unwrap(),
+        initial_backoff: Duration::from_millis(10),
+        max_backoff: Duration::from_millis(10),
+        retry_rate_limited,
+    }
+}
@@ -0,0 +1,89 @@
+//! Checks that actions Discord rate limits are handled by the scheduler
+//! alone, and only sent again a limited number of times.
+
+mod common;
+
+use std::{
+    net::SocketAddr,
+    num::NonZeroU32,
+    sync::{
+        atomic::{AtomicU32, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use axum::{
+    extract::State, http::StatusCode, response::IntoResponse, routing::delete, Json, Router,
+};
+use common::{client, retry_config, serve};
+use eris_lib::{
+    payloads::{DeleteMessage, DiscordClientAction, MessageLocation},
+    services::{
+        discord_scheduler::{discord_scheduler, DiscordRateLimits, RateLimit},
+        twilight_service::{twilight_service, TwilightServiceError},
+    },
+};
+use serde_json::json;
+use tower::ServiceExt;
+use twilight_model::id::Id;
+
+/// A fake Discord which rate limits every request, counting them.
+async fn rate_limiting_discord() -> (SocketAddr, Arc<AtomicU32>) {
+    let requests = Arc::new(AtomicU32::new(0));
+    let app = Router::new()
+        .route(
+            "/api/v10/channels/:channel_id/messages/:message_id",
+            delete(|State(requests): State<Arc<AtomicU32>>| async move {
+                requests.fetch_add(1, Ordering::SeqCst);
+                (
+                    StatusCode::TOO_MANY_REQUESTS,
+                    Json(json!({
+                        "message": "You are being rate limited.",
+                        "retry_after": 0.01,
+                        "global": false,
+                    })),
+                )
+                    .into_response()
+            }),
+        )
+        .with_state(requests.clone());
+    (serve(app), requests)
+}
+
+#[tokio::test]
+async fn rate_limited_actions_are_requeued_a_limited_number_of_times() {
+    let (addr, requests) = rate_limiting_discord().await;
+    let limits = DiscordRateLimits {
+        channel: RateLimit {
+            requests: NonZeroU32::new(5).unwrap(),
+            per: Duration::from_millis(10),
+        },
+        max_rate_limited: 2,
+        ..Default::default()
+    };
+    let scheduler = discord_scheduler(
+        twilight_service(client(addr), Id::new(1), retry_config(false)),
+        limits,
+    );
+
+    let action = DiscordClientAction::DeleteMessage(DeleteMessage {
+        message_location: MessageLocation {
+            channel_id: Id::new(10),
+            message_id: Id::new(20),
+        },
+        reason: None,
+    });
+    let error = match scheduler.oneshot(action).await {
+        Ok(_) => panic!("every request is rate limited"),
+        Err(error) => error,
+    };
+
+    // Sent once, then queued again twice, without the inner service retrying
+    assert!(matches!(
+        error.error,
+        TwilightServiceError::TwilightClientError(_)
+    ));
+    assert_eq!(error.attempts, 1);
+    assert_eq!(requests.load(Ordering::SeqCst), 3);
+}
@@ -0,0 +1,154 @@
+//! Checks that retried message creations are deduplicated by their
+//! idempotency key, against a fake Discord API which enforces nonces the way
+//! Discord does.
+
+mod common;
+
+use std::{
+    collections::HashMap,
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+};
+
+use axum::{extract::State, http::StatusCode, response::IntoResponse, routing::post, Json, Router};
+use common::{client, retry_config, serve};
+use eris_lib::{
+    payloads::{DiscordClientAction, DiscordClientActionResponse},
+    services::twilight_service::twilight_service,
+};
+use serde_json::{json, Value};
+use tower::ServiceExt;
+use twilight_model::id::Id;
+
+/// A fake Discord which creates every message it is sent, but fails the
+/// first response in each channel as if it had timed out on the way back.
+#[derive(Debug, Default)]
+struct FakeDiscord {
+    messages: Vec<Value>,
+    nonces: HashMap<String, usize>,
+    failed_channels: Vec<String>,
+}
+
+fn message_json(id: usize, channel_id: &str, content: &str) -> Value {
+    json!({
+        "id": (id + 1).to_string(),
+        "channel_id": channel_id,
+        "author": {
+            "id": "1",
+            "username": "eris",
+            "discriminator": "0000",
+            "avatar": null,
+        },
+        "content": content,
+        "timestamp": "2023-08-01T00:00:00.000000+00:00",
+        "edited_timestamp": null,
+        "tts": false,
+        "mention_everyone": false,
+        "mentions": [],
+        "mention_roles": [],
+        "attachments": [],
+        "embeds": [],
+        "pinned": false,
+        "type": 0,
+    })
+}
+
+async fn create_message(
+    State(discord): State<Arc<Mutex<FakeDiscord>>>,
+    axum::extract::Path(channel_id): axum::extract::Path<String>,
+    Json(body): Json<Value>,
+) -> impl IntoResponse {
+    let mut discord = discord.lock().unwrap();
+
+    let nonce = body.get("nonce").map(Value::to_string);
+    let enforced = body.get("enforce_nonce") == Some(&Value::Bool(true));
+    let existing = nonce
+        .as_ref()
+        .filter(|_| enforced)
+        .and_then(|nonce| discord.nonces.get(nonce).copied());
+
+    let id = match existing {
+        Some(id) => id,
+        None => {
+            let id = discord.messages.len();
+            let content = body["content"].as_str().unwrap_or_default();
+            discord
+                .messages
+                .push(message_json(id, &channel_id, content));
+            if let Some(nonce) = nonce {
+                discord.nonces.insert(nonce, id);
+            }
+            id
+        }
+    };
+
+    if !discord.failed_channels.contains(&channel_id) {
+        discord.failed_channels.push(channel_id);
+        return (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(json!({ "message": "upstream timed out", "code": 0 })),
+        )
+            .into_response();
+    }
+    Json(discord.messages[id].clone()).into_response()
+}
+
+async fn fake_discord() -> (SocketAddr, Arc<Mutex<FakeDiscord>>) {
+    let discord = Arc::new(Mutex::new(FakeDiscord::default()));
+    let app = Router::new()
+        .route(
+            "/api/v10/channels/:channel_id/messages",
+            post(create_message),
+        )
+        .with_state(discord.clone());
+    (serve(app), discord)
+}
+
+#[tokio::test]
+async fn retried_create_with_idempotency_key_creates_one_message() {
+    let (addr, discord) = fake_discord().await;
+    let service = twilight_service(client(addr), Id::new(1), retry_config(true));
+
+    let action =
+        DiscordClientAction::create_text_message(Id::new(10), "hello").with_idempotency_key(42);
+    let response = service.oneshot(action).await.expect("retry should succeed");
+
+    let Some(DiscordClientActionResponse::MessageCreated(message)) = response else {
+        panic!("expected a created message");
+    };
+    assert_eq!(message.id, Id::new(1));
+    assert_eq!(discord.lock().unwrap().messages.len(), 1);
+}
+
+#[tokio::test]
+async fn create_without_idempotency_key_is_not_retried() {
+    let (addr, discord) = fake_discord().await;
+    let service = twilight_service(client(addr), Id::new(1), retry_config(true));
+
+    let action = DiscordClientAction::create_text_message(Id::new(10), "hello");
+    let error = match service.oneshot(action).await {
+        Ok(_) => panic!("the first response always fails"),
+        Err(error) => error,
+    };
+
+    assert_eq!(error.attempts, 1);
+    assert_eq!(discord.lock().unwrap().messages.len(), 1);
+}
+
+#[tokio::test]
+async fn different_idempotency_keys_create_different_messages() {
+    let (addr, discord) = fake_discord().await;
+    let service = twilight_service(client(addr), Id::new(1), retry_config(true));
+
+    for key in [1, 2] {
+        let action = DiscordClientAction::create_text_reply(Id::new(10), Id::new(5), "hello")
+            .with_idempotency_key(key);
+        service
+            .clone()
+            .oneshot(action)
+            .await
+            .expect("retry should succeed");
+    }
+
+    assert_eq!(discord.lock().unwrap().messages.len(), 2);
+}
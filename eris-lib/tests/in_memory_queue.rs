@@ -0,0 +1,227 @@
+//! Checks the overflow policies of the bounded in-memory queue, and how
+//! subscriptions to it process and shut down.
+
+use std::{
+    convert::Infallible,
+    future::Future,
+    num::NonZeroUsize,
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use eris_lib::services::in_memory_queue::{
+    bounded_in_memory_queue, subscribe_to_queue, BoundedQueueError, OverflowPolicy,
+};
+use tokio::time::{sleep, timeout};
+use tower::{service_fn, Service, ServiceExt};
+
+fn capacity(capacity: usize) -> NonZeroUsize {
+    NonZeroUsize::new(capacity).unwrap()
+}
+
+/// Runs a future to completion on the current thread.
+fn block_on<F: Future>(future: F) -> F::Output {
+    tokio::runtime::Builder::new_current_thread()
+        .build()
+        .unwrap()
+        .block_on(future)
+}
+
+#[tokio::test]
+async fn waiting_producers_wait_for_space() {
+    let (mut queue, mut subscription) = bounded_in_memory_queue(capacity(2), OverflowPolicy::Wait);
+    for item in [1, 2] {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+    assert_eq!(queue.depth(), 2);
+
+    // Full, so not ready until an item is received
+    assert!(timeout(Duration::from_millis(50), queue.ready())
+        .await
+        .is_err());
+    assert_eq!(subscription.recv().await, Some(1));
+    queue.ready().await.unwrap().call(3).await.unwrap();
+    assert_eq!(subscription.depth(), 2);
+    assert_eq!(queue.dropped(), 0);
+}
+
+#[tokio::test]
+async fn rejecting_producers_get_their_item_back() {
+    let (mut queue, mut subscription) =
+        bounded_in_memory_queue(capacity(2), OverflowPolicy::Reject);
+    for item in [1, 2] {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+
+    let rejected = queue.ready().await.unwrap().call(3).await;
+    assert!(matches!(rejected, Err(BoundedQueueError::Full(3))));
+    assert_eq!(queue.depth(), 2);
+    assert_eq!(subscription.recv().await, Some(1));
+    assert_eq!(subscription.recv().await, Some(2));
+    assert_eq!(subscription.depth(), 0);
+}
+
+#[tokio::test]
+async fn dropping_producers_replace_the_oldest_item() {
+    let (mut queue, mut subscription) =
+        bounded_in_memory_queue(capacity(2), OverflowPolicy::DropOldest);
+    for item in [1, 2, 3] {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+
+    assert_eq!(queue.depth(), 2);
+    assert_eq!(queue.dropped(), 1);
+    assert_eq!(subscription.recv().await, Some(2));
+    assert_eq!(subscription.recv().await, Some(3));
+
+    drop(queue);
+    assert_eq!(subscription.recv().await, None);
+}
+
+#[test]
+fn dropping_producers_only_drop_from_a_full_queue() {
+    const PRODUCERS: usize = 3;
+    const SENT: usize = 100_000;
+    let (queue, mut subscription) =
+        bounded_in_memory_queue(capacity(1), OverflowPolicy::DropOldest);
+    // Threads rather than tasks, so that producers and the subscriber
+    // interleave as much as possible
+    let receiver = std::thread::spawn(move || {
+        block_on(async move {
+            let mut received = 0;
+            while subscription.recv().await.is_some() {
+                received += 1;
+            }
+            received
+        })
+    });
+
+    let producers: Vec<_> = (0..PRODUCERS)
+        .map(|_| {
+            let mut queue = queue.clone();
+            std::thread::spawn(move || {
+                block_on(async move {
+                    for item in 0..SENT {
+                        queue.ready().await.unwrap().call(item).await.unwrap();
+                        assert!(queue.depth() <= queue.capacity());
+                    }
+                })
+            })
+        })
+        .collect();
+    for producer in producers {
+        producer.join().unwrap();
+    }
+    let dropped = queue.dropped() as usize;
+    drop(queue);
+    assert_eq!(receiver.join().unwrap() + dropped, PRODUCERS * SENT);
+}
+
+/// A service which takes `duration` to process each item, counting the items
+/// started, finished, and the most processed at once.
+#[derive(Debug, Default)]
+struct Counters {
+    started: AtomicUsize,
+    finished: AtomicUsize,
+    in_flight: AtomicUsize,
+    most_in_flight: AtomicUsize,
+}
+
+fn counting_service(
+    duration: Duration,
+) -> (
+    Arc<Counters>,
+    impl Service<usize, Response = (), Error = Infallible, Future = impl Send> + Send + 'static,
+) {
+    let counters = Arc::new(Counters::default());
+    let service = service_fn({
+        let counters = counters.clone();
+        move |_item: usize| {
+            let counters = counters.clone();
+            async move {
+                counters.started.fetch_add(1, Ordering::SeqCst);
+                let in_flight = counters.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                counters
+                    .most_in_flight
+                    .fetch_max(in_flight, Ordering::SeqCst);
+                sleep(duration).await;
+                counters.in_flight.fetch_sub(1, Ordering::SeqCst);
+                counters.finished.fetch_add(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+    });
+    (counters, service)
+}
+
+/// Waits until this many items have been started.
+async fn started(counters: &Counters, items: usize) {
+    timeout(Duration::from_secs(5), async {
+        while counters.started.load(Ordering::SeqCst) < items {
+            sleep(Duration::from_millis(5)).await;
+        }
+    })
+    .await
+    .unwrap();
+}
+
+#[tokio::test]
+async fn subscriptions_process_at_most_their_limit_at_once() {
+    let (mut queue, subscription) = bounded_in_memory_queue(capacity(10), OverflowPolicy::Wait);
+    let (counters, service) = counting_service(Duration::from_millis(20));
+    for item in 0..10 {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+    drop(queue);
+
+    let handle = subscribe_to_queue(service, subscription, capacity(3));
+    let shutdown = handle.join().await.unwrap();
+    assert_eq!(shutdown.cancelled, 0);
+    assert_eq!(counters.finished.load(Ordering::SeqCst), 10);
+    assert_eq!(counters.most_in_flight.load(Ordering::SeqCst), 3);
+}
+
+#[tokio::test]
+async fn shutdown_stops_receiving_and_finishes_what_was_received() {
+    let (mut queue, subscription) = bounded_in_memory_queue(capacity(10), OverflowPolicy::Wait);
+    let (counters, service) = counting_service(Duration::from_millis(100));
+    for item in 0..5 {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+
+    let handle = subscribe_to_queue(service, subscription, capacity(2));
+    started(&counters, 2).await;
+    let mut shutdown = handle.shutdown(Duration::from_secs(5)).await.unwrap();
+    assert_eq!(shutdown.cancelled, 0);
+    assert_eq!(counters.started.load(Ordering::SeqCst), 2);
+    assert_eq!(counters.finished.load(Ordering::SeqCst), 2);
+
+    // The rest are left in the queue
+    assert_eq!(shutdown.subscription.depth(), 3);
+    assert_eq!(shutdown.subscription.recv().await, Some(2));
+}
+
+#[tokio::test]
+async fn shutdown_cancels_what_is_unfinished_at_the_deadline() {
+    let (mut queue, subscription) = bounded_in_memory_queue(capacity(10), OverflowPolicy::Wait);
+    let (counters, service) = counting_service(Duration::from_secs(60));
+    for item in 0..3 {
+        queue.ready().await.unwrap().call(item).await.unwrap();
+    }
+
+    let handle = subscribe_to_queue(service, subscription, capacity(2));
+    started(&counters, 2).await;
+    let shutdown = timeout(
+        Duration::from_secs(5),
+        handle.shutdown(Duration::from_millis(50)),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(shutdown.cancelled, 2);
+    assert_eq!(counters.finished.load(Ordering::SeqCst), 0);
+    assert_eq!(shutdown.subscription.depth(), 1);
+}
@@ -0,0 +1,184 @@
+//! Checks how interactions are parsed into the requests for their handlers,
+//! and how the user is told when they cannot be handled.
+
+use std::sync::{Arc, Mutex};
+
+use eris_lib::{
+    payloads::{
+        DiscordClientAction, DiscordServerAction, MessagePayload, PostAction, MAX_HANDLE_LENGTH,
+    },
+    services::interaction_router::{
+        interaction_router, route_interaction, route_server_action, ActorUrl, Handle,
+        MessageCommandRequest, PostComponentRequest, RouteError, RoutedInteraction, SlashCommand,
+        SlashCommandRequest,
+    },
+};
+use serde_json::{json, Value};
+use tower::{service_fn, ServiceExt};
+use twilight_model::application::interaction::Interaction;
+
+fn interaction(kind: u8, data: Value) -> Interaction {
+    serde_json::from_value(json!({
+        "id": "1",
+        "application_id": "2",
+        "type": kind,
+        "token": "token",
+        "version": 1,
+        "channel_id": "3",
+        "guild_id": "4",
+        "data": data,
+    }))
+    .unwrap()
+}
+
+fn slash_command(name: &str, options: Value) -> Interaction {
+    interaction(
+        2,
+        json!({ "id": "5", "name": name, "type": 1, "options": options }),
+    )
+}
+
+fn join_modal_submit(handle: &str) -> Interaction {
+    interaction(
+        5,
+        json!({
+            "custom_id": "join",
+            "components": [{
+                "type": 1,
+                "components": [{ "type": 4, "custom_id": "handle", "value": handle }],
+            }],
+        }),
+    )
+}
+
+fn button(custom_id: &str) -> Interaction {
+    interaction(3, json!({ "custom_id": custom_id, "component_type": 2 }))
+}
+
+#[test]
+fn actor_urls_must_be_absolute_http_urls() {
+    let url = ActorUrl::parse(" https://mastodon.example/users/heron ").unwrap();
+    assert_eq!(url.url().as_str(), "https://mastodon.example/users/heron");
+    assert!(ActorUrl::parse("http://mastodon.example/@heron").is_ok());
+
+    for value in [
+        "",
+        "heron",
+        "/users/heron",
+        "ftp://mastodon.example/heron",
+        "mailto:heron@mastodon.example",
+    ] {
+        assert_eq!(
+            ActorUrl::parse(value),
+            Err(RouteError::InvalidUrl(value.to_string()))
+        );
+    }
+}
+
+#[test]
+fn handles_are_short_ascii_words() {
+    assert_eq!(Handle::parse(" heron_1 ").unwrap().as_str(), "heron_1");
+    assert!(Handle::parse(&"h".repeat(MAX_HANDLE_LENGTH)).is_ok());
+
+    let too_long = "h".repeat(MAX_HANDLE_LENGTH + 1);
+    for value in ["", "grey heron", "héron", "heron@eris.example", &too_long] {
+        assert_eq!(
+            Handle::parse(value),
+            Err(RouteError::InvalidHandle(value.to_string()))
+        );
+    }
+}
+
+#[test]
+fn slash_commands_and_modals_are_routed_with_their_options() {
+    let follow = slash_command(
+        "follow",
+        json!([{ "name": "url", "type": 3, "value": "https://mastodon.example/users/heron" }]),
+    );
+    let Ok(RoutedInteraction::SlashCommand(request)) = route_interaction(follow) else {
+        panic!("follow is a slash command");
+    };
+    assert_eq!(
+        request.command,
+        SlashCommand::Follow(ActorUrl::parse("https://mastodon.example/users/heron").unwrap())
+    );
+
+    let Ok(RoutedInteraction::SlashCommand(request)) =
+        route_interaction(join_modal_submit("heron"))
+    else {
+        panic!("the join modal completes /join");
+    };
+    assert_eq!(
+        request.command,
+        SlashCommand::Join(Handle::parse("heron").unwrap())
+    );
+
+    assert!(matches!(
+        route_interaction(slash_command("block", json!([]))),
+        Err(RouteError::MissingOption("url"))
+    ));
+    assert!(matches!(
+        route_interaction(join_modal_submit("grey heron")),
+        Err(RouteError::InvalidHandle(_))
+    ));
+    assert!(matches!(
+        route_interaction(slash_command("boost", json!([]))),
+        Err(RouteError::UnknownCommand(name)) if name == "boost"
+    ));
+}
+
+#[test]
+fn post_components_are_routed_with_their_action() {
+    let Ok(RoutedInteraction::PostComponent(request)) = route_interaction(button("share")) else {
+        panic!("share is a post component");
+    };
+    assert_eq!(request.action, PostAction::Share);
+    assert!(matches!(
+        route_interaction(button("boost")),
+        Err(RouteError::UnknownComponent(id)) if id == "boost"
+    ));
+
+    let queued = DiscordServerAction::PostComponentInteraction {
+        interaction: button("follow_author"),
+        action: PostAction::FollowAuthor,
+    };
+    assert!(matches!(
+        route_server_action(queued),
+        Some(Ok(RoutedInteraction::PostComponent(PostComponentRequest {
+            action: PostAction::FollowAuthor,
+            ..
+        })))
+    ));
+}
+
+#[tokio::test]
+async fn failed_components_are_answered_with_an_ephemeral_followup() {
+    let sent = Arc::new(Mutex::new(Vec::new()));
+    let recorded = sent.clone();
+    let client_action_service = service_fn(move |action: DiscordClientAction| {
+        recorded.lock().unwrap().push(action);
+        async { Ok::<_, std::convert::Infallible>(()) }
+    });
+    let router = interaction_router(
+        service_fn(|_: SlashCommandRequest| async { Ok::<_, &str>(()) }),
+        service_fn(|_: MessageCommandRequest| async { Ok::<_, &str>(()) }),
+        service_fn(|_: PostComponentRequest| async { Err::<(), _>("storage is down") }),
+        client_action_service,
+    );
+
+    router
+        .oneshot(DiscordServerAction::PostComponentInteraction {
+            interaction: button("like"),
+            action: PostAction::Like,
+        })
+        .await
+        .unwrap();
+
+    let sent = sent.lock().unwrap();
+    let [DiscordClientAction::CreateFollowup(followup)] = sent.as_slice() else {
+        panic!("expected a single follow-up, got {sent:?}");
+    };
+    assert!(followup.ephemeral);
+    assert_eq!(followup.interaction_token, "token");
+    assert!(matches!(followup.message, MessagePayload::Text(_)));
+}